@@ -0,0 +1,145 @@
+use crate::domain::anatomy::{
+    AnatomyJobStatus, AnatomyStep, StepLog, StepProgress, StepStatus, SubjectAnatomy,
+};
+use crate::domain::error::{LogErr, NWError};
+use crate::services::anatomy_pipeline_service::{
+    load_subject, run_anatomy_job, subject_mesh, AnatomyJobOptions, JobMonitor,
+};
+use crate::services::meshing_service::TISSUES;
+use crate::state::jobs::{AnatomyJob, JobState};
+use crate::state::workspace::WorkspaceState;
+use log::info;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Reports a job's steps to the frontend as "anatomy-job-progress" and
+/// "anatomy-job-log" events, and keeps them for [`get_anatomy_job`].
+struct EventMonitor<'a> {
+    app: &'a AppHandle,
+    job: &'a AnatomyJob,
+}
+
+impl JobMonitor for EventMonitor<'_> {
+    fn progress(&self, step: AnatomyStep, status: StepStatus, detail: &str) {
+        let progress = StepProgress {
+            step,
+            status,
+            detail: detail.to_string(),
+        };
+        self.job.record_progress(progress.clone());
+        let _ = self.app.emit("anatomy-job-progress", &progress);
+    }
+
+    fn log(&self, step: AnatomyStep, line: &str) {
+        let log = StepLog {
+            step,
+            line: line.to_string(),
+        };
+        self.job.record_log(log.clone());
+        let _ = self.app.emit("anatomy-job-log", &log);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.job.is_cancelled()
+    }
+}
+
+/// Put the subject's mesh paths in the workspace and emit
+/// "anatomy-loaded" with the layers `anatomy` holds.
+fn publish_anatomy(
+    app: &AppHandle,
+    subject_dir: &Path,
+    anatomy: &SubjectAnatomy,
+) -> Result<(), NWError> {
+    let existing = |tissue: &str| {
+        let path = subject_mesh(subject_dir, tissue);
+        path.is_file().then(|| path.to_string_lossy().to_string())
+    };
+    let workspace = app.state::<WorkspaceState>();
+    let mut ws = workspace.write()?;
+    ws.anatomy.subject_dir = Some(subject_dir.to_string_lossy().to_string());
+    ws.anatomy.cortex = existing("grey_matter");
+    ws.anatomy.scalp = existing("skull");
+    drop(ws);
+
+    let loaded = [
+        anatomy.skull.is_some(),
+        anatomy.csf.is_some(),
+        anatomy.grey_matter.is_some(),
+        anatomy.white_matter.is_some(),
+    ];
+    let layers: Vec<&str> = TISSUES
+        .iter()
+        .zip(loaded)
+        .filter(|(_, loaded)| *loaded)
+        .map(|((name, _), _)| *name)
+        .collect();
+    let _ = app.emit(
+        "anatomy-loaded",
+        json!({ "layers": layers, "voxel_volumes": [] }),
+    );
+    Ok(())
+}
+
+/// Start the anatomy pipeline (see
+/// [`run_anatomy_job`]) on a background thread and return at once. When
+/// it has loaded the meshes, the subject folder, the MRI and the backend
+/// go into the workspace and "anatomy-loaded" is emitted;
+/// "anatomy-job-finished" follows either way.
+#[tauri::command]
+pub fn start_anatomy_job(
+    options: AnatomyJobOptions,
+    jobs: State<'_, JobState>,
+    app: AppHandle,
+) -> Result<(), NWError> {
+    let job = jobs.start_anatomy()?;
+    std::thread::spawn(move || {
+        let monitor = EventMonitor {
+            app: &app,
+            job: &job,
+        };
+        let result = run_anatomy_job(&options, &monitor).and_then(|(paths, anatomy)| {
+            {
+                let workspace = app.state::<WorkspaceState>();
+                let mut ws = workspace.write()?;
+                // A precomputed subject may come without its MRI.
+                if let Some(mri) = &options.mri {
+                    ws.anatomy.mri = Some(mri.clone());
+                }
+                ws.anatomy_backend = Some(options.backend.clone());
+            }
+            publish_anatomy(&app, &paths.subject_dir, &anatomy)
+        });
+        let result = result.log_err("anatomy job");
+        job.finish(result.err().map(|e| e.to_string()));
+        let _ = app.emit("anatomy-job-finished", job.status());
+    });
+    Ok(())
+}
+
+/// Show the meshes of a subject folder the pipeline has already been run
+/// on, without starting a job, as when a project is reopened.
+#[tauri::command(async)]
+pub fn load_subject_meshes(subject_dir: String, app: AppHandle) -> Result<(), NWError> {
+    let subject_dir = PathBuf::from(subject_dir);
+    let anatomy =
+        load_subject(&subject_dir, &|line| info!("{line}")).log_err("load_subject_meshes")?;
+    publish_anatomy(&app, &subject_dir, &anatomy)
+}
+
+/// Ask the running anatomy job to stop; its current subprocess is killed
+/// and the steps already done are kept for the next run to resume from.
+#[tauri::command]
+pub fn cancel_anatomy_job(jobs: State<'_, JobState>) -> Result<(), NWError> {
+    if let Some(job) = jobs.anatomy()? {
+        job.cancel();
+    }
+    Ok(())
+}
+
+/// The anatomy job last started, if any.
+#[tauri::command]
+pub fn get_anatomy_job(jobs: State<'_, JobState>) -> Result<Option<AnatomyJobStatus>, NWError> {
+    Ok(jobs.anatomy()?.map(|job| job.status()))
+}
@@ -0,0 +1,107 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::annotation::{Annotations, ChannelPair, TimeSegment};
+use crate::domain::error::NWError;
+use crate::state::history::Change;
+use crate::state::session::SessionState;
+use tauri::{Emitter, State};
+
+#[tauri::command]
+pub fn get_annotations(session: State<SessionState>) -> Result<Annotations, NWError> {
+    Ok(session.read()?.annotations.clone())
+}
+
+/// Mark `[start, end]` seconds as bad. Overlapping or touching segments are merged.
+#[tauri::command]
+pub fn add_bad_segment(
+    start: f64,
+    end: f64,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Annotations, NWError> {
+    if !start.is_finite() || !end.is_finite() {
+        return Err(NWError::InvalidInput(
+            "segment bounds must be finite".into(),
+        ));
+    }
+    let mut inner = session.write()?;
+    let before = Change::Annotations(inner.annotations.clone());
+    let segments = &mut inner.annotations.bad_segments;
+    segments.push(TimeSegment::new(start, end));
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    let mut merged: Vec<TimeSegment> = Vec::with_capacity(segments.len());
+    for s in segments.drain(..) {
+        match merged.last_mut() {
+            Some(last) if s.start <= last.end => last.end = last.end.max(s.end),
+            _ => merged.push(s),
+        }
+    }
+    *segments = merged;
+    inner.history.record("Add bad segment", before);
+    let annotations = inner.annotations.clone();
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("annotations-changed", annotations.clone());
+    emit_history(&app, history);
+    Ok(annotations)
+}
+
+#[tauri::command]
+pub fn remove_bad_segment(
+    index: usize,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Annotations, NWError> {
+    let mut inner = session.write()?;
+    let before = Change::Annotations(inner.annotations.clone());
+    let segments = &mut inner.annotations.bad_segments;
+    if index >= segments.len() {
+        return Err(NWError::InvalidInput(format!(
+            "no bad segment at index {index}"
+        )));
+    }
+    segments.remove(index);
+    inner.history.record("Remove bad segment", before);
+    let annotations = inner.annotations.clone();
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("annotations-changed", annotations.clone());
+    emit_history(&app, history);
+    Ok(annotations)
+}
+
+/// Mark channel S`source`-D`detector` as bad (or good again).
+#[tauri::command]
+pub fn set_bad_channel(
+    source: usize,
+    detector: usize,
+    bad: bool,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Annotations, NWError> {
+    let mut inner = session.write()?;
+    let before = Change::Annotations(inner.annotations.clone());
+    let pair = ChannelPair { source, detector };
+    let channels = &mut inner.annotations.bad_channels;
+    match (channels.binary_search(&pair), bad) {
+        (Err(at), true) => channels.insert(at, pair),
+        (Ok(at), false) => {
+            channels.remove(at);
+        }
+        _ => return Ok(inner.annotations.clone()),
+    }
+    let label = if bad {
+        format!("Mark S{source}-D{detector} bad")
+    } else {
+        format!("Mark S{source}-D{detector} good")
+    };
+    inner.history.record(label, before);
+    let annotations = inner.annotations.clone();
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("annotations-changed", annotations.clone());
+    emit_history(&app, history);
+    Ok(annotations)
+}
@@ -0,0 +1,95 @@
+use crate::commands::projection_commands::project;
+use crate::domain::atlas::{AtlasInfo, ChannelRegions};
+use crate::domain::error::{LogErr, NWError};
+use crate::domain::nirs_view::NirsView;
+use crate::io::atlas_importer::load_atlas;
+use crate::io::timeseries_exporter::write_regions_delimited;
+use crate::services::atlas_service::{self, AtlasRoi};
+use crate::services::projection_service::ProjectionMethod;
+use crate::services::timeseries_service::region_rows;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::{Emitter, State};
+
+/// Use the atlas volume at `path` (MGH/MGZ) for labelling channels. Its
+/// lookup table is looked up beside it.
+#[tauri::command]
+pub fn set_atlas(path: String, workspace: State<WorkspaceState>) -> Result<AtlasInfo, NWError> {
+    let info = load_atlas(&path).log_err("set_atlas")?.to_info();
+    let mut ws = workspace.write()?;
+    ws.anatomy.atlas = Some(path);
+    ws.channel_regions.clear();
+    Ok(info)
+}
+
+/// Project the channels onto the cortex and list the atlas regions within
+/// `radius` mm of each. The table is kept for ROIs and exports.
+#[tauri::command]
+pub fn label_channels_with_atlas(
+    radius: f64,
+    method: ProjectionMethod,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<ChannelRegions>, NWError> {
+    let path = workspace
+        .read()?
+        .anatomy
+        .atlas
+        .clone()
+        .ok_or_else(|| NWError::InvalidInput("no atlas is loaded".into()))?;
+    let projection = project(method, &session, &workspace)?;
+    let atlas = load_atlas(&path)?;
+    let table = atlas_service::label_channels(&atlas, &projection.channels, radius)?;
+    workspace.write()?.channel_regions = table.clone();
+    let _ = app.emit("channel-regions-changed", &table);
+    Ok(table)
+}
+
+#[tauri::command]
+pub fn get_channel_regions(
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<ChannelRegions>, NWError> {
+    Ok(workspace.read()?.channel_regions.clone())
+}
+
+/// The atlas regions as ROIs: each with the channels it covers at least
+/// `min_percent` of.
+#[tauri::command]
+pub fn get_atlas_rois(
+    min_percent: f64,
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<AtlasRoi>, NWError> {
+    Ok(atlas_service::atlas_rois(
+        &workspace.read()?.channel_regions,
+        min_percent,
+    ))
+}
+
+/// Write the channel→region table as CSV (`.csv`) or TSV.
+#[tauri::command]
+pub fn export_channel_regions(
+    path: String,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<(), NWError> {
+    let inner = session.read()?;
+    let ws = workspace.read()?;
+    if ws.channel_regions.is_empty() {
+        return Err(NWError::InvalidInput(
+            "channels have not been labelled from an atlas".into(),
+        ));
+    }
+    let rows = region_rows(
+        &NirsView::new(inner.entry()?),
+        0,
+        &ws.channel_regions,
+        |_| true,
+    );
+    let delim = if path.to_ascii_lowercase().ends_with(".csv") {
+        ','
+    } else {
+        '\t'
+    };
+    write_regions_delimited(&path, &rows, delim).log_err("export_channel_regions")
+}
@@ -0,0 +1,59 @@
+use crate::commands::file_commands::open_in_session;
+use crate::domain::dataset::{Dataset, DatasetRecording, RecordingFilter};
+use crate::domain::error::NWError;
+use crate::domain::summary::SnirfSummary;
+use crate::services::dataset_service::index_dataset;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::State;
+
+/// Index every recording below the folder `path` and make it the current
+/// dataset. Runs off the main thread: every file's header is read.
+#[tauri::command(async)]
+pub fn open_dataset(path: String, workspace: State<WorkspaceState>) -> Result<Dataset, NWError> {
+    let dataset = index_dataset(&path)?;
+    workspace.write()?.dataset = Some(dataset.clone());
+    Ok(dataset)
+}
+
+/// Recordings of the current dataset matching `filter`.
+#[tauri::command]
+pub fn list_recordings(
+    filter: Option<RecordingFilter>,
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<DatasetRecording>, NWError> {
+    let ws = workspace.read()?;
+    let dataset = ws
+        .dataset
+        .as_ref()
+        .ok_or_else(|| NWError::InvalidInput("no dataset is open".into()))?;
+    Ok(dataset
+        .filter(&filter.unwrap_or_default())
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+/// Load recording `id` of the current dataset into the session.
+#[tauri::command]
+pub fn load_dataset_recording(
+    id: usize,
+    workspace: State<WorkspaceState>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let path = {
+        let ws = workspace.read()?;
+        let dataset = ws
+            .dataset
+            .as_ref()
+            .ok_or_else(|| NWError::InvalidInput("no dataset is open".into()))?;
+        dataset
+            .recordings
+            .get(id)
+            .ok_or_else(|| NWError::InvalidInput(format!("no recording with id {id}")))?
+            .path
+            .clone()
+    };
+    open_in_session(&path, &session, &app)
+}
@@ -0,0 +1,137 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::error::NWError;
+use crate::domain::snirf::Event;
+use crate::io::event_table_importer::read_event_table;
+use crate::services::event_service::{
+    apply_event_edit, build_events, merge_events, EventEdit, EventImport, EventImportOptions,
+};
+use crate::services::trigger_service::{
+    auxiliary_info, extract_trigger_events, AuxiliaryInfo, TriggerOptions,
+};
+use crate::state::history::Change;
+use crate::state::session::SessionState;
+use std::path::Path;
+use tauri::{Emitter, State};
+
+/// Events of the first NIRS entry.
+#[tauri::command]
+pub fn get_events(session: State<SessionState>) -> Result<Vec<Event>, NWError> {
+    Ok(session.read()?.entry()?.events.clone())
+}
+
+/// Read the event log at `path` and build its markers without changing the
+/// session. Logs without a condition column are named after the file.
+fn read_import(
+    path: &str,
+    options: &EventImportOptions,
+    session: &SessionState,
+) -> Result<EventImport, NWError> {
+    let table = read_event_table(path)?;
+    let fallback = Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("events");
+    build_events(&table, options, session.read()?.entry()?, fallback)
+}
+
+/// What [`import_events`] would add, for review before committing.
+#[tauri::command]
+pub fn preview_event_import(
+    path: String,
+    options: EventImportOptions,
+    session: State<SessionState>,
+) -> Result<EventImport, NWError> {
+    read_import(&path, &options, &session)
+}
+
+/// Merge the events of a BIDS `events.tsv` or CSV/TSV log into the first
+/// entry's stims.
+#[tauri::command]
+pub fn import_events(
+    path: String,
+    options: EventImportOptions,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<Event>, NWError> {
+    let import = read_import(&path, &options, &session)?;
+
+    let mut inner = session.write()?;
+    let entry = inner.entry_mut()?;
+    let before = Change::Events(entry.events.clone());
+    merge_events(&mut entry.events, import.events, options.mode);
+    let events = entry.events.clone();
+    let file = Path::new(&path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(path);
+    inner
+        .history
+        .record(format!("Import events from {file}"), before);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("events-changed", events.clone());
+    emit_history(&app, history);
+    Ok(events)
+}
+
+/// Apply one edit to the first entry's events (add, move or delete a
+/// marker; rename, merge, split, recode or delete a condition). The edited
+/// events are what the exporters and analyses see from then on.
+#[tauri::command]
+pub fn edit_events(
+    edit: EventEdit,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<Event>, NWError> {
+    let mut inner = session.write()?;
+    let entry = inner.entry_mut()?;
+    let before = Change::Events(entry.events.clone());
+    apply_event_edit(entry, &edit)?;
+    let events = entry.events.clone();
+    inner.history.record(edit.label(), before);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("events-changed", events.clone());
+    emit_history(&app, history);
+    Ok(events)
+}
+
+/// The first entry's auxiliary signals, for choosing trigger channels.
+#[tauri::command]
+pub fn get_auxiliaries(session: State<SessionState>) -> Result<Vec<AuxiliaryInfo>, NWError> {
+    Ok(auxiliary_info(session.read()?.entry()?))
+}
+
+/// The events [`extract_triggers`] would add, for review before committing.
+#[tauri::command]
+pub fn preview_triggers(
+    options: TriggerOptions,
+    session: State<SessionState>,
+) -> Result<Vec<Event>, NWError> {
+    extract_trigger_events(session.read()?.entry()?, &options)
+}
+
+/// Derive events from trigger pulses in auxiliary signals and merge them
+/// into the first entry's stims.
+#[tauri::command]
+pub fn extract_triggers(
+    options: TriggerOptions,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<Event>, NWError> {
+    let mut inner = session.write()?;
+    let entry = inner.entry_mut()?;
+    let extracted = extract_trigger_events(entry, &options)?;
+    let before = Change::Events(entry.events.clone());
+    merge_events(&mut entry.events, extracted, options.mode);
+    let events = entry.events.clone();
+    inner.history.record("Extract trigger events", before);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("events-changed", events.clone());
+    emit_history(&app, history);
+    Ok(events)
+}
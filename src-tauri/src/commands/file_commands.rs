@@ -1,21 +1,86 @@
+use crate::commands::history_commands::emit_history;
 use crate::domain::error::{LogErr, NWError};
 use crate::domain::summary::SnirfSummary;
-use crate::services::session_service::{load_snirf, LoadResult};
+use crate::services::session_service::{load_snirf, save_bids_sidecars, save_snirf, LoadResult};
+use crate::services::timeseries_service::{export_timeseries, TimeseriesFormat};
+use crate::state::selection::SelectionState;
 use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
 use tauri::{Emitter, State};
 
+/// Open a recording: SNIRF, or a NIRx folder (any of its files), Homer
+/// `.nirs`, Hitachi ETG CSV or Shimadzu text export, told apart by content
+/// and extension.
 #[tauri::command]
 pub fn import_snirf(
     path: String,
     session: State<SessionState>,
     app: tauri::AppHandle,
 ) -> Result<SnirfSummary, NWError> {
-    let result: LoadResult = load_snirf(&path)?;
+    open_in_session(&path, &session, &app)
+}
+
+/// Load the recording at `path` into the session and notify the frontend.
+pub(crate) fn open_in_session(
+    path: &str,
+    session: &SessionState,
+    app: &tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let result: LoadResult = load_snirf(path)?;
 
     session.load(result.snirf, result.channel_indices);
+    let history = session.read()?.history.status();
 
     let _ = app.emit("snirf-loaded", result.summary.clone());
+    emit_history(app, history);
     Ok(result.summary)
 }
 
-// TODO : export_snirf
+/// Write the loaded recording to `path` as SNIRF, including derived blocks
+/// and the provenance of each.
+#[tauri::command]
+pub fn export_snirf(path: String, session: State<SessionState>) -> Result<(), NWError> {
+    let inner = session.read()?;
+    save_snirf(inner.snirf()?, &path)
+}
+
+/// Write the BIDS-NIRS sidecar files (`_nirs.json`, `_channels.tsv`,
+/// `_optodes.tsv`, `_coordsystem.json`, `_events.tsv`) for the loaded
+/// recording into `dir`. `prefix` is the BIDS file name stem, e.g.
+/// `sub-01_task-rest`; it defaults to the SNIRF file's name.
+#[tauri::command]
+pub fn export_bids_sidecars(
+    dir: String,
+    prefix: Option<String>,
+    session: State<SessionState>,
+) -> Result<Vec<String>, NWError> {
+    let inner = session.read()?;
+    save_bids_sidecars(inner.snirf()?, &inner.annotations, &dir, prefix.as_deref())
+}
+
+/// Export channels of a block as CSV, TSV, Parquet or Homer `.nirs`.
+/// `block` defaults to the active block and `channels` to the selected
+/// channels (every channel when none are selected). Returns the files
+/// written: the data and, except for `.nirs`, an events table beside it.
+#[tauri::command]
+pub fn export_timeseries_data(
+    path: String,
+    format: TimeseriesFormat,
+    block: Option<usize>,
+    channels: Option<Vec<usize>>,
+    session: State<SessionState>,
+    selection: State<SelectionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<String>, NWError> {
+    let (block, channels) = {
+        let sel = selection.read();
+        (
+            block.unwrap_or(sel.active_block),
+            channels.unwrap_or_else(|| sel.selected_channels.clone()),
+        )
+    };
+    let regions = workspace.read()?.channel_regions.clone();
+    let inner = session.read()?;
+    export_timeseries(inner.entry()?, block, &channels, &regions, format, &path)
+        .log_err("export_timeseries_data")
+}
@@ -0,0 +1,98 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::anatomy::NamedPoint;
+use crate::domain::error::{LogErr, NWError};
+use crate::io::mesh_importer::load_mesh;
+use crate::io::position_exporter::PositionFormat;
+use crate::services::head_position_service::{
+    self, OptodeLabel, OptodePlacement, PositionSet, PositionSystem,
+};
+use crate::state::history::Change;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::{Emitter, State};
+
+/// Generate the positions of `system` on the loaded scalp mesh from its
+/// fiducials (Nz, Iz, LPA, RPA, in mm) and keep them for labelling and
+/// placing optodes.
+#[tauri::command]
+pub fn compute_head_positions(
+    fiducials: Vec<NamedPoint>,
+    system: PositionSystem,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<NamedPoint>, NWError> {
+    let path = workspace
+        .read()?
+        .anatomy
+        .scalp
+        .clone()
+        .ok_or_else(|| NWError::InvalidInput("no scalp mesh is loaded".into()))?;
+    let scalp = load_mesh(&path).map_err(NWError::Parse)?;
+    let positions = head_position_service::head_positions(&scalp, &fiducials, system)?;
+    workspace.write()?.head_positions = positions.clone();
+    let _ = app.emit("head-positions-changed", &positions);
+    Ok(positions)
+}
+
+#[tauri::command]
+pub fn get_head_positions(workspace: State<WorkspaceState>) -> Result<Vec<NamedPoint>, NWError> {
+    Ok(workspace.read()?.head_positions.clone())
+}
+
+/// The nearest head position to each optode, as placed on the head.
+#[tauri::command]
+pub fn label_optodes(
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<OptodeLabel>, NWError> {
+    let inner = session.read()?;
+    let ws = workspace.read()?;
+    head_position_service::label_optodes(inner.entry()?, &ws.probe_transform, &ws.head_positions)
+}
+
+/// Move optodes onto named head positions (`S1` → `F3`…).
+#[tauri::command]
+pub fn place_optodes(
+    placements: Vec<OptodePlacement>,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<(), NWError> {
+    let (transform, positions) = {
+        let ws = workspace.read()?;
+        (ws.probe_transform.clone(), ws.head_positions.clone())
+    };
+    let mut inner = session.write()?;
+    let before = Change::probe(&inner)?;
+    head_position_service::place_optodes(inner.entry_mut()?, &transform, &positions, &placements)?;
+    inner.history.record("Place optodes", before);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("probe-changed", ());
+    emit_history(&app, history);
+    Ok(())
+}
+
+/// Write the optodes or the computed head positions as `.elc`, `.sfp` or
+/// BIDS `optodes.tsv`.
+#[tauri::command]
+pub fn export_head_positions(
+    path: String,
+    set: PositionSet,
+    format: PositionFormat,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<(), NWError> {
+    let inner = session.read()?;
+    let ws = workspace.read()?;
+    head_position_service::export_head_positions(
+        inner.entry().ok(),
+        &ws.probe_transform,
+        &ws.head_positions,
+        set,
+        format,
+        &path,
+    )
+    .log_err("export_head_positions")
+}
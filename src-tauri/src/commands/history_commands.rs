@@ -0,0 +1,100 @@
+use crate::domain::annotation::Annotations;
+use crate::domain::error::NWError;
+use crate::domain::summary::SnirfSummary;
+use crate::state::history::{ChangeScope, HistoryStatus};
+use crate::state::selection::SelectionState;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::{Emitter, State};
+
+/// Tell the frontend what can be undone or redone now.
+pub(crate) fn emit_history(app: &tauri::AppHandle, status: HistoryStatus) {
+    let _ = app.emit("history-changed", status);
+}
+
+#[tauri::command]
+pub fn get_history(session: State<SessionState>) -> Result<HistoryStatus, NWError> {
+    Ok(session.read()?.history.status())
+}
+
+#[tauri::command]
+pub fn undo(
+    session: State<SessionState>,
+    selection: State<SelectionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<HistoryStatus, NWError> {
+    step_history(true, &session, &selection, &workspace, &app)
+}
+
+#[tauri::command]
+pub fn redo(
+    session: State<SessionState>,
+    selection: State<SelectionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<HistoryStatus, NWError> {
+    step_history(false, &session, &selection, &workspace, &app)
+}
+
+/// Undo (or redo) one change and emit the event of whatever it touched, as
+/// if the original operation had just happened.
+fn step_history(
+    undo: bool,
+    session: &SessionState,
+    selection: &SelectionState,
+    workspace: &WorkspaceState,
+    app: &tauri::AppHandle,
+) -> Result<HistoryStatus, NWError> {
+    let mut inner = session.write()?;
+    let mut ws = workspace.write()?;
+    let transform = &mut ws.probe_transform;
+    let scope = if undo {
+        inner.undo(transform)?
+    } else {
+        inner.redo(transform)?
+    };
+    drop(ws);
+    let status = inner.history.status();
+    let summary = inner.snirf().ok().map(SnirfSummary::from_snirf);
+    let annotations: Annotations = inner.annotations.clone();
+    let events = inner.entry().map(|e| e.events.clone()).unwrap_or_default();
+    let n_blocks = inner.entry().map(|e| e.data_blocks.len()).unwrap_or(0);
+    drop(inner);
+
+    // Removing blocks may leave the active block pointing past the end.
+    let clamped = {
+        let mut sel = selection.write();
+        if sel.active_block >= n_blocks && n_blocks > 0 {
+            sel.active_block = n_blocks - 1;
+            Some(sel.active_block)
+        } else {
+            None
+        }
+    };
+
+    match (scope, summary) {
+        (Some(ChangeScope::Recording), Some(summary)) => {
+            let _ = app.emit("snirf-loaded", summary);
+            let _ = app.emit("annotations-changed", annotations);
+        }
+        (Some(ChangeScope::Blocks), Some(summary)) => {
+            let _ = app.emit("blocks-changed", summary);
+        }
+        (Some(ChangeScope::Annotations), _) => {
+            let _ = app.emit("annotations-changed", annotations);
+        }
+        (Some(ChangeScope::Events), _) => {
+            let _ = app.emit("events-changed", events);
+        }
+        (Some(ChangeScope::Probe), _) => {
+            let _ = app.emit("probe-changed", ());
+        }
+        _ => {}
+    }
+    if let Some(index) = clamped {
+        let _ = app.emit("block-changed", index);
+    }
+    emit_history(app, status.clone());
+    Ok(status)
+}
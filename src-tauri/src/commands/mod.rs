@@ -1,6 +1,24 @@
+pub mod anatomy_commands;
+pub mod annotation_commands;
+pub mod atlas_commands;
+pub mod dataset_commands;
+pub mod event_commands;
 pub mod file_commands;
+pub mod head_position_commands;
+pub mod history_commands;
 pub mod info_commands;
+pub mod pipeline_commands;
 pub mod probe_commands;
+pub mod photon_commands;
+pub mod processing_commands;
+pub mod project_commands;
+pub mod projection_commands;
+pub mod reconstruction_commands;
+pub mod registration_commands;
+pub mod resample_commands;
 pub mod selection_commands;
+pub mod sensitivity_commands;
+pub mod spatial_filter_commands;
 pub mod spectral_commands;
 pub mod timeseries_commands;
+pub mod workspace_commands;
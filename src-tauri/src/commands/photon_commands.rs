@@ -0,0 +1,139 @@
+use crate::domain::error::{LogErr, NWError};
+use crate::domain::optics::{OpticalProperties, Simulation};
+use crate::domain::probe::OptodeLayout;
+use crate::io::anatomy_importer::load_head_labels_volume;
+use crate::io::{mcx_exporter, mcx_importer};
+use crate::photon::SimulationConfig;
+use crate::services::head_position_service::optode_positions;
+use crate::services::photon_service::{self, FluenceSummary};
+use crate::services::sensitivity_service;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Simulate photon migration from each of `optodes` through the labelled
+/// head volume at `volume`, `media[label]` giving each tissue's optical
+/// properties. Runs off the main thread; the fluence is kept for the
+/// sensitivity analysis.
+#[tauri::command(async)]
+pub fn simulate_photons(
+    volume: String,
+    media: Vec<OpticalProperties>,
+    optodes: Vec<String>,
+    config: SimulationConfig,
+    session: State<'_, SessionState>,
+    workspace: State<'_, WorkspaceState>,
+) -> Result<Vec<FluenceSummary>, NWError> {
+    let labels = load_head_labels_volume(&PathBuf::from(&volume)).map_err(NWError::Parse)?;
+    let transform = workspace.read()?.probe_transform.clone();
+    let placed = optode_positions(session.read()?.entry()?, &transform)?;
+    let results = photon_service::simulate_optodes(&labels, &media, &placed, &optodes, &config)
+        .log_err("simulate_photons")?;
+    let summaries = results
+        .iter()
+        .map(|(name, beam, fluence)| photon_service::summarize(name, fluence, beam))
+        .collect();
+    let mut ws = workspace.write()?;
+    ws.sensitivity = None;
+    ws.simulation = Some(Simulation {
+        volume,
+        media,
+        fluence: results
+            .into_iter()
+            .map(|(name, _, fluence)| (name, fluence))
+            .collect(),
+    });
+    Ok(summaries)
+}
+
+/// Write the labelled head volume at `volume` and an MCX session for every
+/// optode the channels use to `dir` (see
+/// [`export_mcx`](mcx_exporter::export_mcx)), to simulate on a machine
+/// with MCX and a GPU. Returns the files written.
+#[tauri::command]
+pub fn export_mcx(
+    volume: String,
+    media: Vec<OpticalProperties>,
+    config: SimulationConfig,
+    dir: String,
+    session: State<'_, SessionState>,
+    workspace: State<'_, WorkspaceState>,
+) -> Result<Vec<String>, NWError> {
+    let labels = load_head_labels_volume(&PathBuf::from(&volume)).map_err(NWError::Parse)?;
+    let transform = workspace.read()?.probe_transform.clone();
+    let inner = session.read()?;
+    let layout = OptodeLayout::from_snirf(inner.snirf()?);
+    let optodes = photon_service::mcx_optodes(
+        &labels,
+        &media,
+        inner.entry()?,
+        &transform,
+        &layout,
+        &sensitivity_service::channel_optodes(&layout),
+        &config,
+    )
+    .log_err("export_mcx")?;
+    mcx_exporter::export_mcx(&labels, &media, &optodes, &config, Path::new(&dir))
+        .log_err("export_mcx")
+}
+
+/// Load MCX fluence outputs (`<optode>.jnii` or `.mc2`, as the sessions
+/// from [`export_mcx`] name them) simulated in the head volume at `volume`
+/// with `media`. They join the fluence already simulated there, for the
+/// sensitivity analysis to use.
+#[tauri::command(async)]
+pub fn import_mcx_fluence(
+    volume: String,
+    media: Vec<OpticalProperties>,
+    paths: Vec<String>,
+    session: State<'_, SessionState>,
+    workspace: State<'_, WorkspaceState>,
+) -> Result<Vec<FluenceSummary>, NWError> {
+    let labels = load_head_labels_volume(&PathBuf::from(&volume)).map_err(NWError::Parse)?;
+    let transform = workspace.read()?.probe_transform.clone();
+    let mut imported = Vec::with_capacity(paths.len());
+    {
+        let inner = session.read()?;
+        for path in &paths {
+            let optode = Path::new(path)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| NWError::InvalidInput(format!("'{path}' names no optode")))?;
+            let mcx = mcx_importer::import_mcx_fluence(path).log_err("import_mcx_fluence")?;
+            let (beam, fluence) = photon_service::mcx_fluence(
+                &labels,
+                &media,
+                inner.entry()?,
+                &transform,
+                optode,
+                mcx,
+            )
+            .log_err("import_mcx_fluence")?;
+            imported.push((optode.to_string(), beam, fluence));
+        }
+    }
+    let summaries = imported
+        .iter()
+        .map(|(name, beam, fluence)| photon_service::summarize(name, fluence, beam))
+        .collect();
+
+    let mut ws = workspace.write()?;
+    let mut simulation = match ws.simulation.take() {
+        Some(sim) if sim.volume == volume && sim.media == media => sim,
+        _ => Simulation {
+            volume,
+            media,
+            fluence: BTreeMap::new(),
+        },
+    };
+    simulation.fluence.extend(
+        imported
+            .into_iter()
+            .map(|(name, _, fluence)| (name, fluence)),
+    );
+    ws.sensitivity = None;
+    ws.simulation = Some(simulation);
+    Ok(summaries)
+}
@@ -0,0 +1,103 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::error::NWError;
+use crate::domain::provenance::ProvenanceRecord;
+use crate::domain::summary::SnirfSummary;
+use crate::services::pipeline_service::{
+    run_batch, run_pipeline, run_step, BatchResult, Pipeline, ProcessingStep,
+};
+use crate::state::history::Change;
+use crate::state::session::SessionState;
+use tauri::{Emitter, State};
+
+/// Run one step on data block `block`, append the derived block with its
+/// provenance, and notify the frontend. Shared by the single-step commands.
+pub(crate) fn apply_step(
+    block: usize,
+    step: ProcessingStep,
+    session: &SessionState,
+    app: &tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let mut inner = session.write()?;
+    let bad_segments = inner.annotations.bad_segments.clone();
+    let before = Change::appended(&inner)?;
+    run_step(inner.entry_mut()?, block, &step, &bad_segments, None)?;
+    inner.sync_channel_indices();
+    inner.record_appended(step.label(), before);
+    let summary = SnirfSummary::from_snirf(inner.snirf()?);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("blocks-changed", summary.clone());
+    emit_history(app, history);
+    Ok(summary)
+}
+
+/// Run a single processing step on data block `block`.
+#[tauri::command]
+pub fn run_processing_step(
+    block: usize,
+    step: ProcessingStep,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    apply_step(block, step, &session, &app)
+}
+
+/// Run every step of `pipeline` starting from data block `block`; each step
+/// appends one block.
+#[tauri::command]
+pub fn run_processing_pipeline(
+    block: usize,
+    pipeline: Pipeline,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let mut inner = session.write()?;
+    let bad_segments = inner.annotations.bad_segments.clone();
+    let before = Change::appended(&inner)?;
+    let result = run_pipeline(inner.entry_mut()?, block, &pipeline, &bad_segments);
+    // Steps that succeeded before a failure still appended their blocks.
+    inner.sync_channel_indices();
+    inner.record_appended(format!("Pipeline {}", pipeline.name), before);
+    let summary = SnirfSummary::from_snirf(inner.snirf()?);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("blocks-changed", summary.clone());
+    emit_history(&app, history);
+    result.map(|_| summary)
+}
+
+#[tauri::command]
+pub fn load_pipeline(path: String) -> Result<Pipeline, NWError> {
+    Pipeline::load(&path)
+}
+
+#[tauri::command]
+pub fn save_pipeline(path: String, pipeline: Pipeline) -> Result<(), NWError> {
+    pipeline.save(&path)
+}
+
+/// The pipeline that produced data block `block`, rebuilt from provenance.
+#[tauri::command]
+pub fn get_block_pipeline(block: usize, session: State<SessionState>) -> Result<Pipeline, NWError> {
+    let inner = session.read()?;
+    Pipeline::from_provenance(&inner.entry()?.provenance, block)
+}
+
+#[tauri::command]
+pub fn get_provenance(session: State<SessionState>) -> Result<Vec<ProvenanceRecord>, NWError> {
+    let inner = session.read()?;
+    Ok(inner.entry()?.provenance.clone())
+}
+
+/// Apply `pipeline` to each SNIRF file in `inputs`, writing the results to
+/// `output_dir`. Runs off the main thread; the session is not touched.
+#[tauri::command(async)]
+pub fn run_pipeline_batch(
+    pipeline: Pipeline,
+    inputs: Vec<String>,
+    output_dir: String,
+) -> Vec<BatchResult> {
+    run_batch(&pipeline, &inputs, &output_dir)
+}
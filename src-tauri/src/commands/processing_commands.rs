@@ -0,0 +1,39 @@
+use crate::commands::pipeline_commands::apply_step;
+use crate::domain::error::NWError;
+use crate::domain::summary::SnirfSummary;
+use crate::services::detrend_service::{BaselineMode, DetrendMethod};
+use crate::services::pipeline_service::ProcessingStep;
+use crate::state::session::SessionState;
+use tauri::State;
+
+/// Detrend every measurement of data block `block` and append the result as a
+/// new block. Bad segments are excluded from the trend fit.
+#[tauri::command]
+pub fn detrend_data_block(
+    block: usize,
+    method: DetrendMethod,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    apply_step(block, ProcessingStep::Detrend { method }, &session, &app)
+}
+
+/// Normalise data block `block` to the mean of `[start, end]` seconds and
+/// append the result as a new block. Bad segments do not count towards the
+/// baseline mean.
+#[tauri::command]
+pub fn baseline_correct_block(
+    block: usize,
+    start: f64,
+    end: f64,
+    mode: Option<BaselineMode>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let step = ProcessingStep::Baseline {
+        start,
+        end,
+        mode: mode.unwrap_or_default(),
+    };
+    apply_step(block, step, &session, &app)
+}
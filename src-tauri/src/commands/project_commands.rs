@@ -0,0 +1,125 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::error::NWError;
+use crate::services::project_service::{
+    leaf_pipelines, open_recording, Project, RecordingRef, PROJECT_EXTENSION,
+};
+use crate::state::selection::SelectionState;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use std::path::Path;
+use tauri::{Emitter, State};
+
+/// Save the session as a project file. `view` is the frontend's own view
+/// state, stored untouched. Returns the path written, with the project
+/// extension added if it was missing.
+#[tauri::command]
+pub fn save_project(
+    path: String,
+    view: serde_json::Value,
+    session: State<SessionState>,
+    selection: State<SelectionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<String, NWError> {
+    let path = if Path::new(&path).extension().is_some() {
+        path
+    } else {
+        format!("{path}.{PROJECT_EXTENSION}")
+    };
+
+    let inner = session.read()?;
+    let (recording, pipelines, events) = match inner.snirf.as_ref() {
+        Some(snirf) => {
+            let entry = inner.entry()?;
+            let provenance = entry.provenance.clone();
+            let pipelines = leaf_pipelines(&provenance)?;
+            let recording = RecordingRef {
+                path: snirf.file_descriptor.filepath.clone(),
+                provenance,
+            };
+            (Some(recording), pipelines, Some(entry.events.clone()))
+        }
+        None => (None, Vec::new(), None),
+    };
+    let annotations = inner.annotations.clone();
+    drop(inner);
+
+    let (active_block, selected_channels) = {
+        let sel = selection.read();
+        (sel.active_block, sel.selected_channels.clone())
+    };
+
+    let mut ws = workspace.write()?;
+    let project = Project {
+        recording,
+        active_block,
+        selected_channels,
+        annotations,
+        events,
+        pipelines,
+        anatomy: ws.anatomy.clone(),
+        anatomy_backend: ws.anatomy_backend.clone(),
+        probe_transform: ws.probe_transform.clone(),
+        probe_settings: ws.probe_settings.clone(),
+        view,
+        ..Project::default()
+    };
+    project.save(&path)?;
+    ws.project_path = Some(path.clone().into());
+    Ok(path)
+}
+
+/// Open a project file: reload its recording, recompute the derived blocks
+/// and restore annotations, selection and workspace settings. The project is
+/// returned (and emitted as "project-opened") with absolute paths so the
+/// frontend can reload anatomy and apply its view state.
+#[tauri::command]
+pub fn open_project(
+    path: String,
+    session: State<SessionState>,
+    selection: State<SelectionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Project, NWError> {
+    let project = Project::load(&path)?;
+
+    let loaded = match &project.recording {
+        Some(recording) => {
+            let result = open_recording(recording)?;
+            session.load(result.snirf, result.channel_indices);
+            let mut inner = session.write()?;
+            inner.annotations = project.annotations.clone();
+            if let Some(events) = &project.events {
+                inner.entry_mut()?.events = events.clone();
+            }
+            drop(inner);
+            Some(result.summary)
+        }
+        None => None,
+    };
+    let n_blocks = loaded.as_ref().map_or(0, |s| s.data_blocks.len());
+
+    let active_block = {
+        let mut sel = selection.write();
+        sel.active_block = project.active_block.min(n_blocks.saturating_sub(1));
+        sel.selected_channels = project.selected_channels.clone();
+        sel.active_block
+    };
+
+    {
+        let mut ws = workspace.write()?;
+        ws.project_path = Some(path.into());
+        ws.anatomy = project.anatomy.clone();
+        ws.anatomy_backend = project.anatomy_backend.clone();
+        ws.probe_transform = project.probe_transform.clone();
+        ws.probe_settings = project.probe_settings.clone();
+    }
+
+    if let Some(summary) = loaded {
+        let _ = app.emit("snirf-loaded", summary);
+        let _ = app.emit("annotations-changed", project.annotations.clone());
+        let _ = app.emit("block-changed", active_block);
+        emit_history(&app, session.read()?.history.status());
+    }
+    let _ = app.emit("project-opened", project.clone());
+    Ok(project)
+}
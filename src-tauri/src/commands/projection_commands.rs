@@ -0,0 +1,65 @@
+use crate::domain::error::NWError;
+use crate::domain::probe::OptodeLayout;
+use crate::io::mesh_importer::load_mesh;
+use crate::services::projection_service::{self, ProbeProjection, ProjectionMethod};
+use crate::services::registration_service::mm_per_unit;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use nalgebra::Point3;
+use tauri::State;
+
+/// Project the optodes onto the loaded scalp mesh and, if a cortex mesh is
+/// loaded, the channels onto the grey matter, with each channel's
+/// scalp-to-cortex depth. The probe is placed by its workspace transform;
+/// a non-zero `projection_target` is used as the head centre.
+#[tauri::command]
+pub fn project_probe(
+    method: ProjectionMethod,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<ProbeProjection, NWError> {
+    project(method, &session, &workspace)
+}
+
+/// [`project_probe`] on the current session and workspace.
+pub(crate) fn project(
+    method: ProjectionMethod,
+    session: &SessionState,
+    workspace: &WorkspaceState,
+) -> Result<ProbeProjection, NWError> {
+    let (scalp_path, cortex_path, transform, target) = {
+        let ws = workspace.read()?;
+        (
+            ws.anatomy
+                .scalp
+                .clone()
+                .ok_or_else(|| NWError::InvalidInput("no scalp mesh is loaded".into()))?,
+            ws.anatomy.cortex.clone(),
+            ws.probe_transform.clone(),
+            ws.probe_settings.projection_target,
+        )
+    };
+    let (layout, mm) = {
+        let inner = session.read()?;
+        let snirf = inner.snirf()?;
+        (
+            OptodeLayout::from_snirf(snirf),
+            mm_per_unit(&inner.entry()?.metadata)?,
+        )
+    };
+
+    let scalp = load_mesh(&scalp_path).map_err(NWError::Parse)?;
+    let cortex = cortex_path
+        .map(|p| load_mesh(&p).map_err(NWError::Parse))
+        .transpose()?;
+    let centre = (target != nalgebra::Vector3::zeros()).then(|| Point3::from(target));
+    projection_service::project_probe(
+        &layout,
+        mm,
+        &transform,
+        &scalp,
+        cortex.as_ref(),
+        method,
+        centre,
+    )
+}
@@ -0,0 +1,51 @@
+use crate::domain::error::{LogErr, NWError};
+use crate::io::mesh_importer::load_mesh;
+use crate::services::reconstruction_service::{
+    self, CortexImage, ReconstructionInput, ReconstructionOptions,
+};
+use crate::state::selection::SelectionState;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::{Emitter, State};
+
+/// Reconstruct ΔHbO/ΔHbR images from the active block at `input` (or the
+/// given betas) with the workspace sensitivity, and show them on the
+/// grey-matter mesh. The result is also emitted as
+/// `reconstruction-changed` for the viewport. Runs off the main thread.
+#[tauri::command(async)]
+pub fn reconstruct_image(
+    input: ReconstructionInput,
+    options: ReconstructionOptions,
+    session: State<'_, SessionState>,
+    selection: State<'_, SelectionState>,
+    workspace: State<'_, WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<CortexImage, NWError> {
+    let block = selection.read().active_block;
+    // Copied out so the workspace is not locked while the session is: other
+    // commands lock the session first.
+    let (sensitivity, path) = {
+        let ws = workspace.read()?;
+        let sensitivity = ws.sensitivity.clone().ok_or(NWError::NoData)?;
+        let path = ws
+            .anatomy
+            .cortex
+            .clone()
+            .ok_or_else(|| NWError::InvalidInput("no grey-matter mesh is loaded".into()))?;
+        (sensitivity, path)
+    };
+    let cortex = load_mesh(&path).map_err(NWError::Parse)?;
+    let inner = session.read()?;
+    let image = reconstruction_service::reconstruct(
+        &sensitivity,
+        inner.entry()?,
+        inner.block(block)?,
+        &input,
+        &cortex,
+        &options,
+    )
+    .log_err("reconstruct_image")?;
+    drop(inner);
+    let _ = app.emit("reconstruction-changed", &image);
+    Ok(image)
+}
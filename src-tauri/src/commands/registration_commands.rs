@@ -0,0 +1,68 @@
+use crate::commands::history_commands::emit_history;
+use crate::domain::anatomy::NamedPoint;
+use crate::domain::error::NWError;
+use crate::domain::scene::Transform;
+use crate::services::registration_service::{self, Registration, TransformModel};
+use crate::state::history::Change;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use tauri::{Emitter, State};
+
+/// The probe's landmarks (Nz, Iz, LPA, RPA, Cz …) in millimetres.
+#[tauri::command]
+pub fn get_probe_landmarks(session: State<SessionState>) -> Result<Vec<NamedPoint>, NWError> {
+    registration_service::probe_landmarks(session.read()?.entry()?)
+}
+
+/// A first guess at the fiducials on the loaded scalp mesh.
+#[tauri::command]
+pub fn detect_scalp_landmarks(
+    workspace: State<WorkspaceState>,
+) -> Result<Vec<NamedPoint>, NWError> {
+    let path = workspace
+        .read()?
+        .anatomy
+        .scalp
+        .clone()
+        .ok_or_else(|| NWError::InvalidInput("no scalp mesh is loaded".into()))?;
+    registration_service::detect_landmarks_on_mesh(&path)
+}
+
+/// Fit the probe landmarks to `targets` without moving the probe, to show
+/// the residuals before applying.
+#[tauri::command]
+pub fn preview_registration(
+    targets: Vec<NamedPoint>,
+    model: TransformModel,
+    session: State<SessionState>,
+) -> Result<Registration, NWError> {
+    registration_service::register(session.read()?.entry()?, &targets, model)
+}
+
+/// Fit the probe landmarks to `targets` and move the optodes into head
+/// space. The manual probe transform is reset, as the registered positions
+/// already sit on the head; it remains available for fine-tuning. Undo
+/// restores both.
+#[tauri::command]
+pub fn register_probe(
+    targets: Vec<NamedPoint>,
+    model: TransformModel,
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Registration, NWError> {
+    let mut inner = session.write()?;
+    let registration = registration_service::register(inner.entry()?, &targets, model)?;
+    let mut ws = workspace.write()?;
+    let before = Change::probe_and_transform(&inner, &ws.probe_transform)?;
+    registration_service::apply_registration(inner.entry_mut()?, &registration)?;
+    ws.probe_transform = Transform::default();
+    drop(ws);
+    inner.history.record("Register probe to head", before);
+    let history = inner.history.status();
+    drop(inner);
+
+    let _ = app.emit("probe-changed", ());
+    emit_history(&app, history);
+    Ok(registration)
+}
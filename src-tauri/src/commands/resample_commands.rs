@@ -0,0 +1,44 @@
+use crate::commands::pipeline_commands::apply_step;
+use crate::domain::error::NWError;
+use crate::domain::summary::SnirfSummary;
+use crate::services::pipeline_service::ProcessingStep;
+use crate::services::resample_service::ResampleMethod;
+use crate::state::session::SessionState;
+use tauri::State;
+
+/// Derive a uniformly sampled copy of data block `block` and append it to the
+/// entry, leaving the original untouched. `target_rate` defaults to the
+/// block's median rate.
+#[tauri::command]
+pub fn regularize_time_base(
+    block: usize,
+    target_rate: Option<f64>,
+    method: Option<ResampleMethod>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let step = ProcessingStep::Regularize {
+        target_rate,
+        method: method.unwrap_or_default(),
+    };
+    apply_step(block, step, &session, &app)
+}
+
+/// Derive a copy of data block `block` at `target_rate` Hz (polyphase
+/// anti-aliased) and append it to the entry. With `include_aux` the auxiliary
+/// signals are resampled too and added alongside the originals, suffixed with
+/// their new rate; copies at that rate from an earlier call are replaced.
+#[tauri::command]
+pub fn resample_data_block(
+    block: usize,
+    target_rate: f64,
+    include_aux: Option<bool>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let step = ProcessingStep::Resample {
+        target_rate,
+        include_aux: include_aux.unwrap_or(true),
+    };
+    apply_step(block, step, &session, &app)
+}
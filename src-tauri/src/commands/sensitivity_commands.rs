@@ -0,0 +1,135 @@
+use crate::domain::error::{LogErr, NWError};
+use crate::domain::optics::{OpticalProperties, Simulation};
+use crate::domain::probe::OptodeLayout;
+use crate::io::anatomy_importer::load_head_labels_volume;
+use crate::io::mesh_importer::load_mesh;
+use crate::io::{sensitivity_exporter, sensitivity_importer};
+use crate::photon::SimulationConfig;
+use crate::services::head_position_service::optode_positions;
+use crate::services::photon_service;
+use crate::services::sensitivity_service::{
+    self, ChannelSensitivitySummary, CortexSensitivity, DEFAULT_THRESHOLD,
+};
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tauri::{Emitter, State};
+
+/// Compute every channel's sensitivity over the labelled head volume at
+/// `volume` from the fluence of its source and detector. Optodes already
+/// simulated in the same volume with the same `media` are reused; the rest
+/// are simulated with `config`. Runs off the main thread.
+#[tauri::command(async)]
+pub fn compute_sensitivity(
+    volume: String,
+    media: Vec<OpticalProperties>,
+    config: SimulationConfig,
+    threshold: Option<f64>,
+    session: State<'_, SessionState>,
+    workspace: State<'_, WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<ChannelSensitivitySummary>, NWError> {
+    // The probe is copied out so no lock is held while simulating.
+    let transform = workspace.read()?.probe_transform.clone();
+    let (layout, placed) = {
+        let inner = session.read()?;
+        (
+            OptodeLayout::from_snirf(inner.snirf()?),
+            optode_positions(inner.entry()?, &transform)?,
+        )
+    };
+    let cached = |sim: &Simulation| sim.volume == volume && sim.media == media;
+    let missing: Vec<String> = {
+        let ws = workspace.read()?;
+        let previous = ws.simulation.as_ref().filter(|sim| cached(sim));
+        sensitivity_service::channel_optodes(&layout)
+            .into_iter()
+            .filter(|name| !previous.is_some_and(|sim| sim.fluence.contains_key(name)))
+            .collect()
+    };
+    // Simulate without holding the workspace, so a failure leaves the
+    // cached fluence as it was.
+    let simulated = if missing.is_empty() {
+        Vec::new()
+    } else {
+        let labels = load_head_labels_volume(&PathBuf::from(&volume)).map_err(NWError::Parse)?;
+        photon_service::simulate_optodes(&labels, &media, &placed, &missing, &config)
+            .log_err("compute_sensitivity")?
+    };
+
+    {
+        let mut ws = workspace.write()?;
+        if !ws.simulation.as_ref().is_some_and(cached) {
+            ws.simulation = None;
+        }
+        let simulation = ws.simulation.get_or_insert_with(|| Simulation {
+            volume: volume.clone(),
+            media: media.clone(),
+            fluence: BTreeMap::new(),
+        });
+        for (name, _, fluence) in simulated {
+            simulation.fluence.insert(name, fluence);
+        }
+    }
+    // Only read from here on, so the workspace stays readable meanwhile.
+    let sensitivity = {
+        let ws = workspace.read()?;
+        let simulation = ws.simulation.as_ref().ok_or(NWError::NoData)?;
+        sensitivity_service::compute_sensitivity(
+            &layout,
+            simulation,
+            threshold.unwrap_or(DEFAULT_THRESHOLD),
+        )
+        .log_err("compute_sensitivity")?
+    };
+    let summaries = sensitivity_service::summarize(&sensitivity);
+    workspace.write()?.sensitivity = Some(sensitivity);
+    let _ = app.emit("sensitivity-changed", &summaries);
+    Ok(summaries)
+}
+
+/// The summed sensitivity of `channels` on the grey-matter mesh, averaged
+/// over `radius` mm around each vertex.
+#[tauri::command]
+pub fn get_cortex_sensitivity(
+    channels: Vec<usize>,
+    radius: f64,
+    workspace: State<WorkspaceState>,
+) -> Result<CortexSensitivity, NWError> {
+    let ws = workspace.read()?;
+    let sensitivity = ws.sensitivity.as_ref().ok_or(NWError::NoData)?;
+    let path = ws
+        .anatomy
+        .cortex
+        .as_ref()
+        .ok_or_else(|| NWError::InvalidInput("no grey-matter mesh is loaded".into()))?;
+    let cortex = load_mesh(path).map_err(NWError::Parse)?;
+    sensitivity_service::cortex_sensitivity(sensitivity, &cortex, &channels, radius)
+}
+
+/// Save the sensitivities as sparse HDF5 (see
+/// [`export_sensitivity`](sensitivity_exporter::export_sensitivity)).
+#[tauri::command]
+pub fn export_sensitivity(path: String, workspace: State<WorkspaceState>) -> Result<(), NWError> {
+    let ws = workspace.read()?;
+    let sensitivity = ws.sensitivity.as_ref().ok_or(NWError::NoData)?;
+    sensitivity_exporter::export_sensitivity(sensitivity, &path).log_err("export_sensitivity")
+}
+
+/// Load sensitivities saved by [`export_sensitivity`] or another tool in
+/// the same layout, replacing the workspace's. Channels are matched to
+/// the recording by source and detector name.
+#[tauri::command]
+pub fn import_sensitivity(
+    path: String,
+    workspace: State<WorkspaceState>,
+    app: tauri::AppHandle,
+) -> Result<Vec<ChannelSensitivitySummary>, NWError> {
+    let sensitivity =
+        sensitivity_importer::import_sensitivity(&path).log_err("import_sensitivity")?;
+    let summaries = sensitivity_service::summarize(&sensitivity);
+    workspace.write()?.sensitivity = Some(sensitivity);
+    let _ = app.emit("sensitivity-changed", &summaries);
+    Ok(summaries)
+}
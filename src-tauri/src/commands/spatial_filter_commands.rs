@@ -0,0 +1,69 @@
+use crate::commands::pipeline_commands::apply_step;
+use crate::domain::error::NWError;
+use crate::domain::summary::SnirfSummary;
+use crate::services::pipeline_service::{fit_segment, ProcessingStep};
+use crate::services::spatial_filter_service::{ica_decompose, IcaDecomposition};
+use crate::state::session::SessionState;
+use tauri::State;
+
+/// Remove the `n_components` strongest principal components from data block
+/// `block` and append the result as a new block. With `fit_start`/`fit_end`
+/// the components are estimated on that interval only (e.g. rest) and then
+/// removed from the whole recording.
+#[tauri::command]
+pub fn pca_filter_data_block(
+    block: usize,
+    n_components: usize,
+    fit_start: Option<f64>,
+    fit_end: Option<f64>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let step = ProcessingStep::Pca {
+        n_components,
+        fit_start,
+        fit_end,
+    };
+    apply_step(block, step, &session, &app)
+}
+
+/// Independent components of data block `block` — time courses and spatial
+/// maps — for the user to inspect before rejecting any.
+#[tauri::command]
+pub fn get_ica_components(
+    block: usize,
+    n_components: Option<usize>,
+    fit_start: Option<f64>,
+    fit_end: Option<f64>,
+    session: State<SessionState>,
+) -> Result<IcaDecomposition, NWError> {
+    let inner = session.read()?;
+    ica_decompose(
+        inner.block(block)?,
+        n_components,
+        fit_segment(fit_start, fit_end),
+        &inner.annotations.bad_segments,
+    )
+}
+
+/// Remove the ICA components with ids in `reject` (as returned by
+/// `get_ica_components` with the same arguments) and append the result as a
+/// new block.
+#[tauri::command]
+pub fn reject_ica_components(
+    block: usize,
+    reject: Vec<usize>,
+    n_components: Option<usize>,
+    fit_start: Option<f64>,
+    fit_end: Option<f64>,
+    session: State<SessionState>,
+    app: tauri::AppHandle,
+) -> Result<SnirfSummary, NWError> {
+    let step = ProcessingStep::IcaReject {
+        reject,
+        n_components,
+        fit_start,
+        fit_end,
+    };
+    apply_step(block, step, &session, &app)
+}
@@ -1,5 +1,8 @@
 use crate::domain::nirs_view::NirsView;
-use crate::dsp::{compute_fft_spectrum, compute_welch_psd, WindowType};
+use crate::dsp::{
+    compute_fft_spectrum, compute_psd, Detrend, PsdMethod, PsdOptions, Scaling, WindowType,
+};
+use crate::services::resample_service::uniform_signal;
 use crate::state::AppState;
 use serde::{Deserialize, Serialize};
 
@@ -12,23 +15,54 @@ pub struct SpectrumDTO {
     pub magnitudes: Vec<f64>,
 }
 
-/// Returns FFT spectra for all selected channels.
+/// Returns spectra for all selected channels.
+///
+/// `method` is `"fft"` (amplitude spectrum), `"psd"`/`"welch"` or `"multitaper"`.
+/// The PSD options (`detrend`, `scaling`, `segment_len`, `nw`) are ignored for FFT.
 /// Falls back to a synthetic test signal when no SNIRF file is loaded.
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
 pub fn get_spectrums(
     method: Option<String>,
     window: Option<String>,
+    detrend: Option<String>,
+    scaling: Option<String>,
+    segment_len: Option<usize>,
+    nw: Option<f64>,
     state: tauri::State<AppState>,
 ) -> Result<Vec<SpectrumDTO>, String> {
-    let use_psd = method
+    let window_type = WindowType::from_name(window.as_deref().unwrap_or("hann"));
+    let psd_method = match method
         .as_deref()
         .unwrap_or("fft")
-        .eq_ignore_ascii_case("psd");
-    let window_type = match window.as_deref().unwrap_or("hann") {
-        "hamming" => WindowType::Hamming,
-        "blackman" => WindowType::Blackman,
-        _ => WindowType::Hann,
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "psd" | "welch" => {
+            let seg = segment_len.unwrap_or(256).max(4);
+            Some(PsdMethod::Welch {
+                segment_len: seg,
+                overlap: seg / 2,
+                window: window_type,
+            })
+        }
+        "multitaper" => {
+            let nw = nw.unwrap_or(4.0).max(1.0);
+            Some(PsdMethod::Multitaper {
+                nw,
+                tapers: (2.0 * nw).floor() as usize - 1,
+            })
+        }
+        _ => None,
     };
+    let psd_options = psd_method.map(|method| PsdOptions {
+        method,
+        detrend: Detrend::from_name(detrend.as_deref().unwrap_or("constant")),
+        scaling: match scaling.as_deref() {
+            Some("spectrum") => Scaling::Spectrum,
+            _ => Scaling::Density,
+        },
+    });
     let nirs = state.nirs.read().map_err(|e| e.to_string())?;
     let selection = state.selection.read().map_err(|e| e.to_string())?;
 
@@ -48,7 +82,6 @@ pub fn get_spectrums(
     let block_idx = selection
         .active_block
         .min(view.block_count().saturating_sub(1));
-    let sample_rate = view.sampling_rate_at(block_idx);
     let channels = view.channels_at(block_idx).to_vec();
     let channels = channels.as_slice();
 
@@ -70,7 +103,9 @@ pub fn get_spectrums(
                 Some(b) => b,
                 None => continue,
             };
-            let signal = &block.measurements[meas_idx].data;
+            // Irregular time bases are regridded first; the estimators assume uniform sampling.
+            let (signal, sample_rate) =
+                uniform_signal(&block.time, &block.measurements[meas_idx].data);
             let label = {
                 let m = &block.measurements[meas_idx];
                 if m.data_type_label.is_empty() {
@@ -84,15 +119,9 @@ pub fn get_spectrums(
                     m.data_type_label.clone()
                 }
             };
-            let result = if use_psd {
-                let seg = 256.min(signal.len()).max(4);
-                if signal.len() >= seg {
-                    compute_welch_psd(signal, sample_rate, seg, seg / 2, window_type)
-                } else {
-                    compute_fft_spectrum(signal, sample_rate, window_type)
-                }
-            } else {
-                compute_fft_spectrum(signal, sample_rate, window_type)
+            let result = match &psd_options {
+                Some(options) => compute_psd(&signal, sample_rate, options),
+                None => compute_fft_spectrum(&signal, sample_rate, window_type),
             };
             out.push(SpectrumDTO {
                 channel_id: ch.id,
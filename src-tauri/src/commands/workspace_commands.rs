@@ -0,0 +1,64 @@
+use crate::domain::anatomy::AnatomySource;
+use crate::domain::error::NWError;
+use crate::domain::probe::OptodeLayout;
+use crate::state::session::SessionState;
+use crate::state::workspace::WorkspaceState;
+use nalgebra::Vector3;
+use tauri::State;
+
+/// The probe's 3D layout with the workspace's placement and display
+/// settings; `None` until a recording is loaded.
+#[tauri::command]
+pub fn get_optode_layout_3d(
+    session: State<SessionState>,
+    workspace: State<WorkspaceState>,
+) -> Result<Option<OptodeLayout>, NWError> {
+    let inner = session.read()?;
+    let Ok(snirf) = inner.snirf() else {
+        return Ok(None);
+    };
+    let mut layout = OptodeLayout::from_snirf(snirf);
+    let ws = workspace.read()?;
+    layout.transform = ws.probe_transform.clone();
+    layout.settings = ws.probe_settings.clone();
+    Ok(Some(layout))
+}
+
+/// Placement of the probe on the anatomy, as edited in the scene inspector.
+#[tauri::command]
+pub fn set_optode_layout_transform(
+    position: Vector3<f64>,
+    rotation: Vector3<f64>,
+    scale: Vector3<f64>,
+    workspace: State<WorkspaceState>,
+) -> Result<(), NWError> {
+    let mut ws = workspace.write()?;
+    ws.probe_transform.position = position;
+    ws.probe_transform.rotation = rotation;
+    ws.probe_transform.scale = scale;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_optode_layout_settings(
+    spread_factor: f64,
+    optode_radius: f64,
+    workspace: State<WorkspaceState>,
+) -> Result<(), NWError> {
+    let mut ws = workspace.write()?;
+    ws.probe_settings.spread_factor = spread_factor;
+    ws.probe_settings.optode_radius = optode_radius;
+    Ok(())
+}
+
+/// Remember the file an anatomy layer was loaded from (`None` to forget it),
+/// so the project can reload it.
+#[tauri::command]
+pub fn set_anatomy_path(
+    source: AnatomySource,
+    path: Option<String>,
+    workspace: State<WorkspaceState>,
+) -> Result<(), NWError> {
+    *workspace.write()?.anatomy.get_mut(source) = path;
+    Ok(())
+}
@@ -14,6 +14,144 @@ pub struct SubjectAnatomy {
     pub labels_mgz_path: Option<std::path::PathBuf>,
 }
 
+/// A labelled point on the head (a fiducial, a 10-10 position…), in
+/// millimetres.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NamedPoint {
+    pub label: String,
+    pub position: [f64; 3],
+}
+
+/// Files the subject anatomy was loaded from, so a project can reload them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AnatomyPaths {
+    pub mri: Option<String>,
+    pub cortex: Option<String>,
+    pub scalp: Option<String>,
+    /// Labelled atlas volume for naming the regions under channels.
+    pub atlas: Option<String>,
+    /// Subject folder the anatomy pipeline wrote head_labels.mgz and the
+    /// tissue meshes to.
+    #[serde(default)]
+    pub subject_dir: Option<String>,
+}
+
+/// Which anatomy file a path in [`AnatomyPaths`] refers to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnatomySource {
+    Mri,
+    Cortex,
+    Scalp,
+    Atlas,
+}
+
+impl AnatomyPaths {
+    pub fn get_mut(&mut self, source: AnatomySource) -> &mut Option<String> {
+        match source {
+            AnatomySource::Mri => &mut self.mri,
+            AnatomySource::Cortex => &mut self.cortex,
+            AnatomySource::Scalp => &mut self.scalp,
+            AnatomySource::Atlas => &mut self.atlas,
+        }
+    }
+}
+
+pub const FASTSURFER_IMAGE: &str = "deepmi/fastsurfer:latest";
+
+/// How the MRI gets segmented.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SegmentationBackend {
+    /// FastSurfer in a Docker container.
+    Container {
+        image: String,
+        /// Run on the GPU (`--gpus all`); FastSurfer falls back to the CPU
+        /// otherwise, which takes hours.
+        gpu: bool,
+    },
+    /// A local FastSurfer install, by the path of its run_fastsurfer.sh.
+    Native { command: String },
+    /// A subject FreeSurfer or FastSurfer has already segmented: the
+    /// folder holding mri/aseg.mgz, T1.mgz and brainmask.mgz. The later
+    /// steps write into it.
+    Precomputed { subject_dir: String },
+}
+
+impl Default for SegmentationBackend {
+    fn default() -> Self {
+        SegmentationBackend::Container {
+            image: FASTSURFER_IMAGE.to_string(),
+            gpu: true,
+        }
+    }
+}
+
+/// A step of the anatomy pipeline, in the order they run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AnatomyStep {
+    /// Check the tools and inputs the steps to run need.
+    Verify,
+    /// Segment the MRI (aseg.mgz, T1.mgz, brainmask.mgz).
+    Segment,
+    /// Label the head's tissues (head_labels.mgz).
+    Voxelise,
+    /// Mesh each tissue (surf/<tissue>.obj).
+    Meshify,
+    /// Read the meshes back into the scene.
+    Load,
+}
+
+impl AnatomyStep {
+    pub const ALL: [AnatomyStep; 5] = [
+        AnatomyStep::Verify,
+        AnatomyStep::Segment,
+        AnatomyStep::Voxelise,
+        AnatomyStep::Meshify,
+        AnatomyStep::Load,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    /// Its outputs were already there.
+    Skipped,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StepProgress {
+    pub step: AnatomyStep,
+    pub status: StepStatus,
+    /// Why it was skipped or failed; empty otherwise.
+    pub detail: String,
+}
+
+/// A line a step printed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StepLog {
+    pub step: AnatomyStep,
+    pub line: String,
+}
+
+/// Where the anatomy job stands, for a view opened while it runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AnatomyJobStatus {
+    pub running: bool,
+    /// The latest progress of each step.
+    pub steps: Vec<StepProgress>,
+    /// The last lines logged.
+    pub log: Vec<StepLog>,
+    /// Why the job stopped, if it did not finish.
+    pub error: Option<String>,
+}
+
 // Voxelizaed Head Anatomy: 5 tissues
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VoxelAnatomy {
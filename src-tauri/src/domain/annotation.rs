@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// A closed time interval `[start, end]` in seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TimeSegment {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl TimeSegment {
+    /// Segment with `start <= end` regardless of argument order.
+    pub fn new(a: f64, b: f64) -> Self {
+        TimeSegment {
+            start: a.min(b),
+            end: a.max(b),
+        }
+    }
+
+    pub fn contains(&self, t: f64) -> bool {
+        t >= self.start && t <= self.end
+    }
+}
+
+/// A source–detector pair, by 1-based SNIRF optode indices.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChannelPair {
+    pub source: usize,
+    pub detector: usize,
+}
+
+/// User markings on the loaded recording that processing steps must honour.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Annotations {
+    /// Stretches of data excluded from fits and averages (motion, disconnects…).
+    pub bad_segments: Vec<TimeSegment>,
+    /// Channels excluded from analysis (poor coupling, saturation…), sorted.
+    #[serde(default)]
+    pub bad_channels: Vec<ChannelPair>,
+}
+
+impl Annotations {
+    pub fn is_bad_channel(&self, source: usize, detector: usize) -> bool {
+        self.bad_channels
+            .binary_search(&ChannelPair { source, detector })
+            .is_ok()
+    }
+}
+
+/// `true` for every sample whose time lies outside all `bad` segments.
+pub fn good_sample_mask(time: &[f64], bad: &[TimeSegment]) -> Vec<bool> {
+    time.iter()
+        .map(|&t| !bad.iter().any(|s| s.contains(t)))
+        .collect()
+}
@@ -0,0 +1,76 @@
+use std::collections::{BTreeSet, HashMap};
+
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+/// A labelled brain atlas (AAL, Brodmann, FreeSurfer `aparc+aseg`…): one
+/// integer label per voxel and a name per label.
+pub struct Atlas {
+    pub name: String,
+    /// [nx, ny, nz]
+    pub dims: [usize; 3],
+    /// Voxel-to-RAS world transform.
+    pub vox2ras: na::Matrix4<f64>,
+    /// Flat label array: labels[x + y*nx + z*nx*ny]; 0 is background.
+    pub labels: Vec<u32>,
+    pub label_names: HashMap<u32, String>,
+}
+
+impl Atlas {
+    pub fn label_at(&self, [i, j, k]: [usize; 3]) -> u32 {
+        let [nx, ny, _] = self.dims;
+        self.labels[i + j * nx + k * nx * ny]
+    }
+
+    /// The region name of `label`, or `Label <n>` when the atlas has no
+    /// lookup table entry for it.
+    pub fn label_name(&self, label: u32) -> String {
+        self.label_names
+            .get(&label)
+            .cloned()
+            .unwrap_or_else(|| format!("Label {label}"))
+    }
+
+    pub fn to_info(&self) -> AtlasInfo {
+        let present: BTreeSet<u32> = self.labels.iter().copied().filter(|&l| l != 0).collect();
+        AtlasInfo {
+            name: self.name.clone(),
+            dims: self.dims,
+            regions: present.len(),
+            named_regions: present
+                .iter()
+                .filter(|l| self.label_names.contains_key(l))
+                .count(),
+        }
+    }
+}
+
+/// Lightweight metadata sent to the frontend on load.
+#[derive(Serialize, Clone, Debug)]
+pub struct AtlasInfo {
+    pub name: String,
+    pub dims: [usize; 3],
+    /// Distinct non-zero labels in the volume.
+    pub regions: usize,
+    /// Labels with a name from a lookup table.
+    pub named_regions: usize,
+}
+
+/// An atlas region under a channel.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegionOverlap {
+    pub label: u32,
+    pub name: String,
+    /// Share of the labelled voxels within the sensitivity radius, %.
+    pub percent: f64,
+}
+
+/// The atlas regions around a channel's cortical projection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelRegions {
+    pub channel: usize,
+    /// The channel on the cortex, mm.
+    pub position: [f64; 3],
+    /// By decreasing overlap.
+    pub regions: Vec<RegionOverlap>,
+}
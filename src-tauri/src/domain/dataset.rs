@@ -0,0 +1,145 @@
+use crate::domain::summary::SnirfSummary;
+use serde::{Deserialize, Serialize};
+
+/// BIDS entities parsed from a file name such as
+/// `sub-01_ses-02_task-rest_run-1_nirs.snirf`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct BidsEntities {
+    pub subject: Option<String>,
+    pub session: Option<String>,
+    pub task: Option<String>,
+    pub acquisition: Option<String>,
+    pub run: Option<String>,
+    /// The part after the last entity, e.g. `nirs`.
+    pub suffix: Option<String>,
+}
+
+impl BidsEntities {
+    pub fn from_filename(name: &str) -> Self {
+        let stem = name.split('.').next().unwrap_or(name);
+        let mut entities = BidsEntities::default();
+        for part in stem.split('_') {
+            let value = |v: &str| Some(v.to_string());
+            match part.split_once('-') {
+                Some(("sub", v)) => entities.subject = value(v),
+                Some(("ses", v)) => entities.session = value(v),
+                Some(("task", v)) => entities.task = value(v),
+                Some(("acq", v)) => entities.acquisition = value(v),
+                Some(("run", v)) => entities.run = value(v),
+                Some(_) => {}
+                None => entities.suffix = value(part),
+            }
+        }
+        entities
+    }
+
+    /// `sub-<label>[_ses-<label>][_acq-<label>]`: the prefix of files shared
+    /// by every task and run of a session, such as `_optodes.tsv`.
+    pub fn session_prefix(&self) -> Option<String> {
+        let subject = self.subject.as_ref()?;
+        let mut prefix = format!("sub-{subject}");
+        if let Some(session) = &self.session {
+            prefix.push_str(&format!("_ses-{session}"));
+        }
+        if let Some(acq) = &self.acquisition {
+            prefix.push_str(&format!("_acq-{acq}"));
+        }
+        Some(prefix)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationLevel {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Outcome of checking one recording: readable, non-empty, BIDS-conformant.
+#[derive(Serialize, Clone, Debug)]
+pub struct Validation {
+    pub level: ValidationLevel,
+    pub messages: Vec<String>,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Validation {
+            level: ValidationLevel::Ok,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl Validation {
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.level = self.level.max(ValidationLevel::Warning);
+        self.messages.push(message.into());
+    }
+
+    pub fn fail(&mut self, message: impl Into<String>) {
+        self.level = ValidationLevel::Error;
+        self.messages.push(message.into());
+    }
+}
+
+/// One SNIRF file found in a dataset folder.
+#[derive(Serialize, Clone)]
+pub struct DatasetRecording {
+    /// Position in [`Dataset::recordings`].
+    pub id: usize,
+    pub path: String,
+    /// Relative to the dataset root.
+    pub relative_path: String,
+    pub entities: BidsEntities,
+    /// `None` when the file could not be read.
+    pub summary: Option<SnirfSummary>,
+    pub validation: Validation,
+}
+
+/// An indexed folder of recordings, BIDS-structured or not.
+#[derive(Serialize, Clone)]
+pub struct Dataset {
+    pub root: String,
+    /// `Name` from `dataset_description.json`.
+    pub name: Option<String>,
+    /// Whether the root has a `dataset_description.json`.
+    pub is_bids: bool,
+    pub recordings: Vec<DatasetRecording>,
+}
+
+/// Criteria for [`Dataset::filter`]; fields left `None` match anything.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RecordingFilter {
+    pub subject: Option<String>,
+    pub session: Option<String>,
+    pub task: Option<String>,
+    pub run: Option<String>,
+    /// Skip recordings that failed validation.
+    #[serde(default)]
+    pub valid_only: bool,
+}
+
+impl RecordingFilter {
+    pub fn matches(&self, recording: &DatasetRecording) -> bool {
+        let e = &recording.entities;
+        let field = |want: &Option<String>, have: &Option<String>| {
+            want.as_ref().map_or(true, |w| have.as_ref() == Some(w))
+        };
+        field(&self.subject, &e.subject)
+            && field(&self.session, &e.session)
+            && field(&self.task, &e.task)
+            && field(&self.run, &e.run)
+            && !(self.valid_only && recording.validation.level == ValidationLevel::Error)
+    }
+}
+
+impl Dataset {
+    pub fn filter(&self, filter: &RecordingFilter) -> Vec<&DatasetRecording> {
+        self.recordings
+            .iter()
+            .filter(|r| filter.matches(r))
+            .collect()
+    }
+}
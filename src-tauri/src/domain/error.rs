@@ -24,10 +24,14 @@ pub enum NWError {
     BlockOutOfRange(usize),
     #[error("Channel {0} not found")]
     ChannelNotFound(usize),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
     #[error("Parse error {0}")]
-    Parse,
+    Parse(String),
     #[error("State lock poisoned")]
     LockPoisoned,
+    #[error("Cancelled")]
+    Cancelled,
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
     #[error("{0}")]
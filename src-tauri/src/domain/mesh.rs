@@ -1,4 +1,7 @@
-use nalgebra::{Vector2, Vector3};
+use kiddo::SquaredEuclidean;
+use nalgebra::{Point3, Vector2, Vector3};
+use parry3d::query::{PointQuery, Ray, RayCast};
+use parry3d::shape::FeatureId;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -46,6 +49,32 @@ impl std::fmt::Debug for MeshTopology {
     }
 }
 
+impl MeshTopology {
+    /// The search structures over `geometry`'s vertices and triangles.
+    pub fn build(geometry: &MeshGeometry) -> Self {
+        let positions: Vec<Vector3<f64>> = geometry.verts.iter().map(|v| v.position).collect();
+        let trimesh = if !positions.is_empty() && geometry.indices.len() >= 3 {
+            let parry_verts: Vec<parry3d::math::Vector> = positions
+                .iter()
+                .map(|v| parry3d::math::Vector::new(v.x as f32, v.y as f32, v.z as f32))
+                .collect();
+            let tri_indices: Vec<[u32; 3]> = geometry
+                .indices
+                .chunks(3)
+                .filter(|c| c.len() == 3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect();
+            parry3d::shape::TriMesh::new(parry_verts, tri_indices).ok()
+        } else {
+            None
+        };
+        Self {
+            trimesh,
+            kdtree: build_kdtree(&positions),
+        }
+    }
+}
+
 impl Default for MeshTopology {
     fn default() -> Self {
         Self {
@@ -69,6 +98,114 @@ pub struct Mesh {
     pub topology: MeshTopology,
 }
 
+/// Where a query met the surface.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceHit {
+    pub point: Point3<f64>,
+    /// The mesh vertex nearest to `point`.
+    pub vertex: usize,
+    /// The triangle `point` lies on, when the mesh has a `TriMesh`.
+    pub face: Option<u32>,
+}
+
+fn parry_point(p: &Point3<f64>) -> parry3d::math::Vector {
+    parry3d::math::Vector::new(p.x as f32, p.y as f32, p.z as f32)
+}
+
+fn face_index(feature: FeatureId, n_faces: usize) -> Option<u32> {
+    match feature {
+        // Back faces of a triangle mesh are numbered after the front ones.
+        FeatureId::Face(i) if n_faces > 0 => Some(i % n_faces as u32),
+        _ => None,
+    }
+}
+
+impl Mesh {
+    /// A mesh built in memory rather than loaded from a file.
+    pub fn from_geometry(name: &str, geometry: MeshGeometry) -> Self {
+        Mesh {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            filepath: String::new(),
+            topology: MeshTopology::build(&geometry),
+            geometry,
+        }
+    }
+
+    fn n_faces(&self) -> usize {
+        self.geometry.indices.len() / 3
+    }
+
+    /// Index of the vertex nearest to `p`.
+    pub fn nearest_vertex(&self, p: &Point3<f64>) -> Option<usize> {
+        if self.geometry.verts.is_empty() {
+            return None;
+        }
+        let nearest = self
+            .topology
+            .kdtree
+            .nearest_one::<SquaredEuclidean>(&[p.x, p.y, p.z]);
+        Some(nearest.item as usize)
+    }
+
+    /// Indices of the vertices within `radius` of `p`.
+    pub fn vertices_within(&self, p: &Point3<f64>, radius: f64) -> Vec<usize> {
+        self.topology
+            .kdtree
+            .within::<SquaredEuclidean>(&[p.x, p.y, p.z], radius * radius)
+            .into_iter()
+            .map(|n| n.item as usize)
+            .collect()
+    }
+
+    fn hit(&self, point: Point3<f64>, face: Option<u32>) -> Option<SurfaceHit> {
+        Some(SurfaceHit {
+            vertex: self.nearest_vertex(&point)?,
+            point,
+            face,
+        })
+    }
+
+    /// The point of the surface closest to `p`; the nearest vertex when the
+    /// mesh has no `TriMesh`.
+    pub fn closest_point(&self, p: &Point3<f64>) -> Option<SurfaceHit> {
+        match &self.topology.trimesh {
+            Some(trimesh) => {
+                let (projection, feature) =
+                    trimesh.project_local_point_and_get_feature(&parry_point(p));
+                let q = projection.point;
+                let point = Point3::new(q.x as f64, q.y as f64, q.z as f64);
+                self.hit(point, face_index(feature, self.n_faces()))
+            }
+            None => {
+                let vertex = self.nearest_vertex(p)?;
+                let point = Point3::from(self.geometry.verts[vertex].position);
+                self.hit(point, None)
+            }
+        }
+    }
+
+    /// First crossing of the surface by the ray from `origin` along `dir`
+    /// (not necessarily unit), and its distance from `origin` in units of
+    /// `dir`. `None` without a `TriMesh` or a hit.
+    pub fn cast_ray(
+        &self,
+        origin: &Point3<f64>,
+        dir: &Vector3<f64>,
+        max_distance: f64,
+    ) -> Option<(SurfaceHit, f64)> {
+        let trimesh = self.topology.trimesh.as_ref()?;
+        let ray = Ray::new(
+            parry_point(origin),
+            parry3d::math::Vector::new(dir.x as f32, dir.y as f32, dir.z as f32),
+        );
+        let hit = trimesh.cast_local_ray_and_get_normal(&ray, max_distance as f32, false)?;
+        let toi = hit.time_of_impact as f64;
+        let surface = self.hit(origin + dir * toi, face_index(hit.feature, self.n_faces()))?;
+        Some((surface, toi))
+    }
+}
+
 // =========================
 // Helper: build KD-tree from vertex positions
 // =========================
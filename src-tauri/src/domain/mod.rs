@@ -14,6 +14,18 @@ pub use scene::{ObjectMeta, SceneObject, Transform};
 pub mod probe;
 pub use probe::{ChannelConnection, Optode3D, OptodeLayout, ProbeDisplaySettings};
 pub mod anatomy;
-pub use anatomy::SubjectAnatomy;
+pub use anatomy::{AnatomyPaths, AnatomySource, NamedPoint, SubjectAnatomy};
 pub mod voxel;
 pub use voxel::VoxelVolume;
+pub mod atlas;
+pub use atlas::{Atlas, AtlasInfo, ChannelRegions, RegionOverlap};
+pub mod optics;
+pub use optics::{ChannelSensitivity, Fluence, OpticalProperties, Sensitivity, Simulation};
+pub mod timebase;
+pub use timebase::TimeBase;
+pub mod annotation;
+pub use annotation::{Annotations, TimeSegment};
+pub mod provenance;
+pub use provenance::ProvenanceRecord;
+pub mod dataset;
+pub use dataset::{BidsEntities, Dataset, DatasetRecording, RecordingFilter};
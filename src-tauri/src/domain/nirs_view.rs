@@ -1,4 +1,5 @@
 use crate::domain::snirf::{DataBlock, Measurement, NirsEntry, Optode};
+use crate::domain::timebase::{median_sampling_rate, TimeBase};
 use std::collections::BTreeMap;
 
 // A channel is a unqiue par of source and detector
@@ -145,12 +146,7 @@ impl<'a> NirsView<'a> {
     }
 
     pub fn sampling_rate(&self) -> f64 {
-        let t = self.time();
-        if t.len() >= 2 {
-            1.0 / (t[1] - t[0])
-        } else {
-            0.0
-        }
+        median_sampling_rate(self.time())
     }
 
     pub fn duration(&self) -> f64 {
@@ -185,12 +181,12 @@ impl<'a> NirsView<'a> {
     }
 
     pub fn sampling_rate_at(&self, idx: usize) -> f64 {
-        let t = self.time_at(idx);
-        if t.len() >= 2 {
-            1.0 / (t[1] - t[0])
-        } else {
-            0.0
-        }
+        median_sampling_rate(self.time_at(idx))
+    }
+
+    /// Full sampling statistics (median rate, jitter, gaps) of block `idx`.
+    pub fn time_base_at(&self, idx: usize) -> TimeBase {
+        TimeBase::analyze(self.time_at(idx))
     }
 
     pub fn duration_at(&self, idx: usize) -> f64 {
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+/// Optical properties of one tissue label, MCX conventions: coefficients
+/// in 1/mm.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct OpticalProperties {
+    /// Absorption coefficient μa.
+    pub mua: f64,
+    /// Scattering coefficient μs.
+    pub mus: f64,
+    /// Anisotropy of the Henyey-Greenstein phase function.
+    pub g: f64,
+    /// Refractive index.
+    pub n: f64,
+}
+
+impl OpticalProperties {
+    /// Air, for the background label.
+    pub const AIR: OpticalProperties = OpticalProperties {
+        mua: 0.0,
+        mus: 0.0,
+        g: 1.0,
+        n: 1.0,
+    };
+}
+
+/// Photon fluence in voxel space, one volume per time gate. Values are
+/// fluence rates per unit source power, 1/(mm²·s), as MCX writes them
+/// with normalisation on.
+#[derive(Clone, Debug)]
+pub struct Fluence {
+    /// [nx, ny, nz]
+    pub dims: [usize; 3],
+    /// Voxel-to-RAS world transform of the volume simulated.
+    pub vox2ras: na::Matrix4<f64>,
+    /// Start of the first gate, s.
+    pub t0: f64,
+    /// Gate width, s.
+    pub gate_width: f64,
+    pub gates: usize,
+    /// The first tissue voxel on the beam, where the optode also detects.
+    pub entry: [usize; 3],
+    /// data[x + y*nx + z*nx*ny + gate*nx*ny*nz]
+    pub data: Vec<f32>,
+}
+
+impl Fluence {
+    pub fn voxel_count(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    /// One gate's volume.
+    pub fn gate(&self, gate: usize) -> &[f32] {
+        let n = self.voxel_count();
+        &self.data[gate * n..(gate + 1) * n]
+    }
+
+    /// Time-integrated (continuous-wave) fluence, 1/mm².
+    pub fn cw(&self) -> Vec<f32> {
+        let n = self.voxel_count();
+        let mut out = vec![0.0f32; n];
+        for gate in 0..self.gates {
+            for (o, v) in out.iter_mut().zip(self.gate(gate)) {
+                *o += v * self.gate_width as f32;
+            }
+        }
+        out
+    }
+}
+
+/// The fluence last simulated from the optodes, and what it was simulated
+/// in.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    /// The labelled head volume.
+    pub volume: String,
+    /// Optical properties by label.
+    pub media: Vec<OpticalProperties>,
+    /// By optode name.
+    pub fluence: BTreeMap<String, Fluence>,
+}
+
+/// One channel's sensitivity to absorption in each voxel (its Jacobian,
+/// the "banana" between source and detector), sparse.
+#[derive(Clone, Debug)]
+pub struct ChannelSensitivity {
+    pub channel: usize,
+    pub source: String,
+    pub detector: String,
+    /// Voxel offsets, x + y*nx + z*nx*ny, ascending.
+    pub voxels: Vec<u32>,
+    /// -∂ln(I)/∂μa per voxel: the partial path length there, mm.
+    pub values: Vec<f32>,
+}
+
+/// Channel sensitivities over a voxel volume.
+#[derive(Clone, Debug)]
+pub struct Sensitivity {
+    /// [nx, ny, nz]
+    pub dims: [usize; 3],
+    /// Voxel-to-RAS world transform.
+    pub vox2ras: na::Matrix4<f64>,
+    pub channels: Vec<ChannelSensitivity>,
+}
@@ -2,7 +2,7 @@ use crate::domain::nirs_view::NirsView;
 use crate::domain::scene::Transform;
 use crate::domain::snirf::Snirf;
 use nalgebra::Vector3;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tauri::ipc::Channel;
 
 /// A single optode (source or detector) with its 3D position.
@@ -22,7 +22,7 @@ pub struct ChannelConnection {
 }
 
 /// Display/layout parameters the frontend uses to position and scale the probe.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProbeDisplaySettings {
     pub spread_factor: f64,
     pub optode_radius: f64,
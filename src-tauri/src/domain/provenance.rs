@@ -0,0 +1,79 @@
+use crate::domain::annotation::TimeSegment;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `metaDataTags` entry under which the processing history is stored in
+/// exported SNIRF files, as a JSON array of [`ProvenanceRecord`]s.
+pub const PROVENANCE_TAG: &str = "NIRWizardProvenance";
+
+pub const SOFTWARE_NAME: &str = "NIRWizard";
+pub const SOFTWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How one derived data block was produced.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProvenanceRecord {
+    /// Index of the block this step produced.
+    pub output_block: usize,
+    /// Index of the block it was computed from.
+    pub input_block: usize,
+    /// Step kind, e.g. `"bandpass"`.
+    pub step: String,
+    /// The full step definition, so the step can be replayed.
+    pub parameters: serde_json::Value,
+    /// Name of the pipeline that ran the step, if any.
+    pub pipeline: Option<String>,
+    /// Bad segments the step excluded from its fits, needed to replay it.
+    #[serde(default)]
+    pub bad_segments: Vec<TimeSegment>,
+    pub software: String,
+    pub version: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl ProvenanceRecord {
+    /// Record of a step run now by this build of the software.
+    pub fn new(
+        output_block: usize,
+        input_block: usize,
+        step: &str,
+        parameters: serde_json::Value,
+        pipeline: Option<&str>,
+        bad_segments: &[TimeSegment],
+    ) -> Self {
+        ProvenanceRecord {
+            output_block,
+            input_block,
+            step: step.to_string(),
+            parameters,
+            pipeline: pipeline.map(str::to_string),
+            bad_segments: bad_segments.to_vec(),
+            software: SOFTWARE_NAME.to_string(),
+            version: SOFTWARE_VERSION.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    /// The record of the step that produced `block`, if it is a derived block.
+    pub fn for_block(records: &[ProvenanceRecord], block: usize) -> Option<&ProvenanceRecord> {
+        records.iter().rev().find(|r| r.output_block == block)
+    }
+
+    /// Records from the first raw block up to `block`, in execution order.
+    pub fn chain(records: &[ProvenanceRecord], block: usize) -> Vec<&ProvenanceRecord> {
+        let mut chain = Vec::new();
+        let mut current = block;
+        while let Some(record) = Self::for_block(records, current) {
+            chain.push(record);
+            if record.input_block == current {
+                break;
+            }
+            current = record.input_block;
+        }
+        chain.reverse();
+        chain
+    }
+}
@@ -1,4 +1,7 @@
+use crate::domain::provenance::ProvenanceRecord;
+use crate::domain::timebase::TimeBase;
 use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 pub struct Snirf {
@@ -24,6 +27,8 @@ pub struct NirsEntry {
     pub probe: Probe,
     pub events: Vec<Event>,
     pub auxiliaries: Vec<AuxiliaryData>,
+    /// Processing history of derived data blocks.
+    pub provenance: Vec<ProvenanceRecord>,
 }
 
 // =========================
@@ -58,6 +63,27 @@ pub struct Measurement {
     pub module_index: Option<f64>,
 }
 
+impl Measurement {
+    /// Copy of this measurement's description carrying a different timeseries.
+    /// Used when a processing step derives a new block from an existing one.
+    pub fn with_data(&self, data: Vec<f64>) -> Measurement {
+        Measurement {
+            source_index: self.source_index,
+            detector_index: self.detector_index,
+            wavelength_index: self.wavelength_index,
+            data_type: self.data_type,
+            data_type_label: self.data_type_label.clone(),
+            data_type_index: self.data_type_index,
+            data_unit: self.data_unit.clone(),
+            data,
+            wavelength_actual: self.wavelength_actual,
+            source_power: self.source_power,
+            detector_gain: self.detector_gain,
+            module_index: self.module_index,
+        }
+    }
+}
+
 pub struct DataBlock {
     pub time: Vec<f64>,
     pub measurements: Vec<Measurement>,
@@ -119,12 +145,14 @@ pub struct Optode {
 // =========================
 // Events / Markers / Triggers
 // =========================
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EventMarker {
     pub onset: f64,    // seconds
     pub duration: f64, // seconds
     pub value: f64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Event {
     pub name: String,
     pub markers: Vec<EventMarker>,
@@ -139,6 +167,9 @@ pub struct AuxiliaryData {
     pub data: Vec<f64>,
     pub time: Vec<f64>,
     pub time_offset: Option<f64>,
+    /// Name of the signal this one was resampled from, for copies made by a
+    /// resampling step. Not saved to SNIRF.
+    pub resampled_from: Option<String>,
 }
 
 impl fmt::Display for Snirf {
@@ -178,13 +209,9 @@ impl fmt::Display for NirsEntry {
             let n_measurements = block.measurements.len();
             let n_timepoints = block.time.len();
 
-            let (duration, sr) = if block.time.len() >= 2 {
-                let dur = block.time.last().copied().unwrap_or(0.0);
-                let sr = 1.0 / (block.time[1] - block.time[0]);
-                (dur, sr)
-            } else {
-                (0.0, 0.0)
-            };
+            let time_base = TimeBase::analyze(&block.time);
+            let duration = block.time.last().copied().unwrap_or(0.0);
+            let sr = time_base.sampling_rate;
 
             writeln!(
                 f,
@@ -196,6 +223,16 @@ impl fmt::Display for NirsEntry {
                 duration,
                 sr,
             )?;
+            if !time_base.is_uniform {
+                writeln!(
+                    f,
+                    "      irregular time base: jitter ±{:.2} ms (max {:.2} ms), {} gaps, {} out-of-order",
+                    time_base.jitter_std * 1e3,
+                    time_base.jitter_max * 1e3,
+                    time_base.gaps.len(),
+                    time_base.non_increasing,
+                )?;
+            }
 
             // Show data types present
             let mut type_counts: std::collections::BTreeMap<String, usize> =
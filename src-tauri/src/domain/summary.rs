@@ -1,5 +1,7 @@
 use crate::domain::nirs_view::{ChannelIndex, DataKind};
+use crate::domain::provenance::ProvenanceRecord;
 use crate::domain::snirf::Snirf;
+use crate::domain::timebase::TimeBase;
 use serde::Serialize;
 
 #[derive(Serialize, Clone)]
@@ -10,6 +12,9 @@ pub struct BlockSummary {
     pub timepoints: usize,
     pub sampling_rate: f64,
     pub duration: f64,
+    pub time_base: TimeBase,
+    /// Processing step that derived this block; `None` for recorded data.
+    pub step: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -35,25 +40,23 @@ impl SnirfSummary {
             .enumerate()
             .map(|(i, block)| {
                 let ci = ChannelIndex::build(block);
-                let sr = if block.time.len() >= 2 {
-                    1.0 / (block.time[1] - block.time[0])
-                } else {
-                    0.0
-                };
+                let time_base = TimeBase::analyze(&block.time);
                 BlockSummary {
                     index: i,
                     data_kind: DataKind::detect(block).as_str().to_string(),
                     channels: ci.len(),
                     timepoints: block.time.len(),
-                    sampling_rate: sr,
+                    sampling_rate: time_base.sampling_rate,
                     duration: block.time.last().copied().unwrap_or(0.0),
+                    time_base,
+                    step: ProvenanceRecord::for_block(&entry.provenance, i).map(|r| r.step.clone()),
                 }
             })
             .collect();
 
         let first = blocks.first();
         SnirfSummary {
-            filename: snirf.file_descriptor.clone(),
+            filename: snirf.file_descriptor.filename.clone(),
             format_version: snirf.format_version.clone(),
             channels: first.map(|b| b.channels).unwrap_or(0),
             sources: entry.probe.sources.len(),
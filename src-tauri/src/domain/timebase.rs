@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+/// An interval counts as a gap when it is this many times the median interval.
+pub const GAP_FACTOR: f64 = 1.5;
+
+/// Largest |dt - median| (relative to the median) still treated as uniform.
+pub const UNIFORM_TOLERANCE: f64 = 0.01;
+
+/// A stretch of the time vector where samples were dropped.
+#[derive(Serialize, Clone, Debug)]
+pub struct TimeGap {
+    /// Index of the last sample before the gap.
+    pub index: usize,
+    pub start: f64,
+    pub end: f64,
+    /// Samples that would have been recorded at the median rate.
+    pub missing_samples: usize,
+}
+
+/// Sampling statistics of a time vector.
+///
+/// Devices with jitter or dropped samples make `1 / (t[1] - t[0])` unreliable,
+/// so every rate in the app comes from the median interval instead.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TimeBase {
+    /// 1 / median interval, Hz.
+    pub sampling_rate: f64,
+    pub median_interval: f64,
+    pub mean_interval: f64,
+    /// Standard deviation of the intervals, excluding gaps (s).
+    pub jitter_std: f64,
+    /// Largest |dt - median| excluding gaps (s).
+    pub jitter_max: f64,
+    pub gaps: Vec<TimeGap>,
+    /// Number of intervals that are zero or negative (duplicate / out-of-order stamps).
+    pub non_increasing: usize,
+    /// No gaps, no out-of-order samples and jitter within `UNIFORM_TOLERANCE`.
+    pub is_uniform: bool,
+}
+
+impl TimeBase {
+    pub fn analyze(time: &[f64]) -> Self {
+        if time.len() < 2 {
+            return TimeBase {
+                is_uniform: true,
+                ..Default::default()
+            };
+        }
+        let intervals: Vec<f64> = time.windows(2).map(|w| w[1] - w[0]).collect();
+        let median = median_positive(&intervals);
+        if median <= 0.0 {
+            return TimeBase {
+                non_increasing: intervals.len(),
+                ..Default::default()
+            };
+        }
+
+        let mut gap_intervals = Vec::new();
+        let mut non_increasing = 0;
+        let (mut sum, mut sum_sq, mut count) = (0.0, 0.0, 0usize);
+        let mut jitter_max = 0.0f64;
+        for (i, &dt) in intervals.iter().enumerate() {
+            if dt <= 0.0 {
+                non_increasing += 1;
+            } else if dt > GAP_FACTOR * median {
+                gap_intervals.push(i);
+            } else {
+                sum += dt;
+                sum_sq += dt * dt;
+                count += 1;
+                jitter_max = jitter_max.max((dt - median).abs());
+            }
+        }
+        let mean = if count > 0 {
+            sum / count as f64
+        } else {
+            median
+        };
+        // The mean interval is a better yardstick than the median here:
+        // alternating jitter pulls the median towards one of the two values.
+        let gaps: Vec<TimeGap> = gap_intervals
+            .into_iter()
+            .map(|i| TimeGap {
+                index: i,
+                start: time[i],
+                end: time[i + 1],
+                missing_samples: ((intervals[i] / mean).round() as usize).saturating_sub(1),
+            })
+            .collect();
+        let jitter_std = if count > 1 {
+            ((sum_sq - count as f64 * mean * mean) / (count - 1) as f64)
+                .max(0.0)
+                .sqrt()
+        } else {
+            0.0
+        };
+
+        TimeBase {
+            sampling_rate: 1.0 / median,
+            median_interval: median,
+            mean_interval: mean,
+            jitter_std,
+            jitter_max,
+            is_uniform: gaps.is_empty()
+                && non_increasing == 0
+                && jitter_max <= UNIFORM_TOLERANCE * median,
+            gaps,
+            non_increasing,
+        }
+    }
+}
+
+/// Sampling rate from the median interval; 0.0 for fewer than two samples.
+pub fn median_sampling_rate(time: &[f64]) -> f64 {
+    let intervals: Vec<f64> = time.windows(2).map(|w| w[1] - w[0]).collect();
+    let median = median_positive(&intervals);
+    if median > 0.0 {
+        1.0 / median
+    } else {
+        0.0
+    }
+}
+
+fn median_positive(values: &[f64]) -> f64 {
+    let mut v: Vec<f64> = values.iter().copied().filter(|x| *x > 0.0).collect();
+    if v.is_empty() {
+        return 0.0;
+    }
+    v.sort_unstable_by(|a, b| a.total_cmp(b));
+    let mid = v.len() / 2;
+    if v.len() % 2 == 0 {
+        0.5 * (v[mid - 1] + v[mid])
+    } else {
+        v[mid]
+    }
+}
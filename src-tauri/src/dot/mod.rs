@@ -0,0 +1,3 @@
+mod tikhonov;
+
+pub use tikhonov::{ModelRow, Regularization, SparseRow, Tikhonov};
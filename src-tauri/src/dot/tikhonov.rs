@@ -0,0 +1,443 @@
+use crate::domain::error::NWError;
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use serde::Deserialize;
+
+/// Candidate α for the L-curve and GCV searches, log-spaced.
+const ALPHA_MIN_LOG10: f64 = -8.0;
+const ALPHA_MAX_LOG10: f64 = 0.0;
+const ALPHA_STEPS: usize = 81;
+
+/// How the Tikhonov parameter is chosen. α is λ relative to the largest
+/// eigenvalue of Ã·Ãᵀ, so it does not depend on the units of the model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Regularization {
+    Fixed {
+        alpha: f64,
+    },
+    /// The corner (largest curvature) of the L-curve.
+    #[default]
+    LCurve,
+    /// Generalised cross-validation.
+    Gcv,
+}
+
+/// A sparse row of a sensitivity matrix: `values[i]` at column
+/// `columns[i]`, columns ascending.
+#[derive(Clone, Debug, Default)]
+pub struct SparseRow {
+    pub columns: Vec<u32>,
+    pub values: Vec<f64>,
+}
+
+/// One measurement of the forward model: `coefficients[k]` times the
+/// sensitivity row `row` for unknown component k. With ΔOD data the
+/// components are HbO and HbR and the coefficients their extinction at the
+/// measurement's wavelength; with concentration data there is one
+/// component and a coefficient of 1.
+#[derive(Clone, Debug)]
+pub struct ModelRow {
+    pub row: usize,
+    pub coefficients: Vec<f64>,
+}
+
+/// Tikhonov inversion of y = A·x in measurement space,
+/// x = L⁻²Aᵀ(ÃÃᵀ + λI)⁻¹y with Ã = AL⁻¹. There are far fewer
+/// measurements than unknowns, so only the measurements × measurements
+/// matrix ÃÃᵀ is ever formed; A stays sparse.
+///
+/// L is the identity, or with spatially variant regularisation
+/// diag(AᵀA) + β·max(diag(AᵀA)) under a square root, which evens out the
+/// fall-off of sensitivity with depth (Pogue et al. 1999).
+pub struct Tikhonov {
+    jacobian: Vec<SparseRow>,
+    model: Vec<ModelRow>,
+    unknowns: usize,
+    /// L⁻² per component and unknown.
+    weights: Vec<Vec<f64>>,
+    eigenvalues: DVector<f64>,
+    eigenvectors: DMatrix<f64>,
+}
+
+/// Solution norm ‖L·x‖ and residual norm ‖A·x − y‖ at one λ, from the
+/// projections `beta` of y onto the eigenvectors.
+fn norms(eigenvalues: &DVector<f64>, beta: &DVector<f64>, lambda: f64) -> (f64, f64) {
+    let (mut solution, mut residual) = (0.0, 0.0);
+    for (s2, b) in eigenvalues.iter().zip(beta.iter()) {
+        let d = s2 + lambda;
+        solution += s2 * b * b / (d * d);
+        residual += (lambda * b / d).powi(2);
+    }
+    (solution.sqrt(), residual.sqrt())
+}
+
+fn alpha_grid() -> impl Iterator<Item = f64> {
+    let step = (ALPHA_MAX_LOG10 - ALPHA_MIN_LOG10) / (ALPHA_STEPS - 1) as f64;
+    (0..ALPHA_STEPS).map(move |i| 10f64.powf(ALPHA_MIN_LOG10 + i as f64 * step))
+}
+
+impl Tikhonov {
+    /// Factor the model. `jacobian` rows index unknowns below `unknowns`;
+    /// every model row must have the same number of components. `spatial`
+    /// is β for spatially variant regularisation.
+    pub fn new(
+        jacobian: Vec<SparseRow>,
+        model: Vec<ModelRow>,
+        unknowns: usize,
+        spatial: Option<f64>,
+    ) -> Result<Tikhonov, NWError> {
+        let components = model.first().map(|m| m.coefficients.len()).unwrap_or(0);
+        if model.is_empty() || components == 0 {
+            return Err(NWError::InvalidInput(
+                "no measurements to reconstruct".into(),
+            ));
+        }
+        if model
+            .iter()
+            .any(|m| m.row >= jacobian.len() || m.coefficients.len() != components)
+        {
+            return Err(NWError::Internal("malformed forward model".into()));
+        }
+        if jacobian.iter().any(|r| {
+            r.columns.len() != r.values.len() || r.columns.iter().any(|c| *c as usize >= unknowns)
+        }) {
+            return Err(NWError::Internal("sensitivity row out of range".into()));
+        }
+        if let Some(beta) = spatial {
+            if !(beta.is_finite() && beta > 0.0) {
+                return Err(NWError::InvalidInput(format!(
+                    "invalid spatial regularisation β {beta}"
+                )));
+            }
+        }
+
+        // Each unknown's (row, value) pairs, compressed by column.
+        let mut start = vec![0usize; unknowns + 1];
+        for r in &jacobian {
+            for c in &r.columns {
+                start[*c as usize + 1] += 1;
+            }
+        }
+        for i in 0..unknowns {
+            start[i + 1] += start[i];
+        }
+        let mut fill = start.clone();
+        let mut entries = vec![(0usize, 0.0f64); start[unknowns]];
+        for (i, r) in jacobian.iter().enumerate() {
+            for (c, v) in r.columns.iter().zip(&r.values) {
+                let at = &mut fill[*c as usize];
+                entries[*at] = (i, *v);
+                *at += 1;
+            }
+        }
+        let column = |u: usize| &entries[start[u]..start[u + 1]];
+
+        let weights: Vec<Vec<f64>> = match spatial {
+            None => vec![vec![1.0; unknowns]; components],
+            Some(beta) => {
+                // Rows sharing a sensitivity row contribute the sum of their
+                // squared coefficients.
+                let mut scale = vec![vec![0.0; jacobian.len()]; components];
+                for m in &model {
+                    for (k, e) in m.coefficients.iter().enumerate() {
+                        scale[k][m.row] += e * e;
+                    }
+                }
+                let diag: Vec<Vec<f64>> = scale
+                    .iter()
+                    .map(|s| {
+                        (0..unknowns)
+                            .map(|u| column(u).iter().map(|(i, v)| s[*i] * v * v).sum())
+                            .collect()
+                    })
+                    .collect();
+                let max = diag.iter().flatten().copied().fold(0.0, f64::max);
+                if max <= 0.0 {
+                    return Err(NWError::InvalidInput("the sensitivity is zero".into()));
+                }
+                diag.iter()
+                    .map(|d| d.iter().map(|v| 1.0 / (v + beta * max)).collect())
+                    .collect()
+            }
+        };
+
+        // P_k = J·W_k·Jᵀ over the sensitivity rows, then
+        // (ÃÃᵀ)_rs = Σ_k e_rk·e_sk·P_k(row r, row s).
+        let n = jacobian.len();
+        let mut products = vec![DMatrix::<f64>::zeros(n, n); components];
+        for (p, w) in products.iter_mut().zip(&weights) {
+            for (u, w) in w.iter().enumerate() {
+                let col = column(u);
+                for (a, (i, vi)) in col.iter().enumerate() {
+                    for (j, vj) in &col[a..] {
+                        p[(*i, *j)] += vi * vj * w;
+                    }
+                }
+            }
+        }
+        // Rows within a column are ascending, so only the upper triangle
+        // was filled.
+        for p in &mut products {
+            p.fill_lower_triangle_with_upper_triangle();
+        }
+        let m = model.len();
+        let gram = DMatrix::from_fn(m, m, |r, s| {
+            let (a, b) = (&model[r], &model[s]);
+            (0..components)
+                .map(|k| a.coefficients[k] * b.coefficients[k] * products[k][(a.row, b.row)])
+                .sum()
+        });
+        let eigen = SymmetricEigen::new(gram);
+        if eigen.eigenvalues.iter().all(|v| *v <= 0.0) {
+            return Err(NWError::InvalidInput(
+                "the measurements are insensitive to every unknown".into(),
+            ));
+        }
+
+        Ok(Tikhonov {
+            jacobian,
+            model,
+            unknowns,
+            weights,
+            eigenvalues: eigen.eigenvalues.map(|v: f64| v.max(0.0)),
+            eigenvectors: eigen.eigenvectors,
+        })
+    }
+
+    pub fn measurements(&self) -> usize {
+        self.model.len()
+    }
+
+    fn largest_eigenvalue(&self) -> f64 {
+        self.eigenvalues.iter().copied().fold(0.0, f64::max)
+    }
+
+    /// α for `y` by `method`.
+    pub fn select(&self, y: &DVector<f64>, method: Regularization) -> Result<f64, NWError> {
+        if y.len() != self.measurements() {
+            return Err(NWError::Internal(
+                "data length does not match the model".into(),
+            ));
+        }
+        let beta = self.eigenvectors.tr_mul(y);
+        let s_max = self.largest_eigenvalue();
+        match method {
+            Regularization::Fixed { alpha } => {
+                if alpha.is_finite() && alpha > 0.0 {
+                    Ok(alpha)
+                } else {
+                    Err(NWError::InvalidInput(format!("invalid α {alpha}")))
+                }
+            }
+            Regularization::Gcv => {
+                let m = self.measurements() as f64;
+                let score = |alpha: f64| {
+                    let lambda = alpha * s_max;
+                    let (_, residual) = norms(&self.eigenvalues, &beta, lambda);
+                    let dof: f64 = self.eigenvalues.iter().map(|s2| s2 / (s2 + lambda)).sum();
+                    residual * residual / (m - dof).powi(2)
+                };
+                Ok(alpha_grid()
+                    .map(|a| (a, score(a)))
+                    .filter(|(_, s)| s.is_finite())
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(a, _)| a)
+                    .unwrap_or(1e-2))
+            }
+            Regularization::LCurve => {
+                let points: Vec<(f64, f64, f64)> = alpha_grid()
+                    .map(|a| {
+                        let (eta, rho) = norms(&self.eigenvalues, &beta, a * s_max);
+                        (
+                            a.log10(),
+                            rho.max(f64::MIN_POSITIVE).ln(),
+                            eta.max(f64::MIN_POSITIVE).ln(),
+                        )
+                    })
+                    .collect();
+                // Curvature of (log ρ, log η) against log α, by central
+                // differences on the evenly spaced grid. Going from small
+                // to large α the curve runs down its steep leg and turns
+                // left onto the flat one, so the corner is the largest
+                // (anticlockwise) curvature.
+                let corner = points
+                    .windows(3)
+                    .map(|w| {
+                        let h = w[1].0 - w[0].0;
+                        let (r1, e1) =
+                            ((w[2].1 - w[0].1) / (2.0 * h), (w[2].2 - w[0].2) / (2.0 * h));
+                        let r2 = (w[2].1 - 2.0 * w[1].1 + w[0].1) / (h * h);
+                        let e2 = (w[2].2 - 2.0 * w[1].2 + w[0].2) / (h * h);
+                        let kappa = (r1 * e2 - e1 * r2) / (r1 * r1 + e1 * e1).powf(1.5);
+                        (10f64.powf(w[1].0), kappa)
+                    })
+                    .filter(|(_, k)| k.is_finite())
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                Ok(corner.map(|(a, _)| a).unwrap_or(1e-2))
+            }
+        }
+    }
+
+    /// The regularised solution for `y` at `alpha`, one vector of unknowns
+    /// per component.
+    pub fn solve(&self, y: &DVector<f64>, alpha: f64) -> Result<Vec<Vec<f64>>, NWError> {
+        if y.len() != self.measurements() {
+            return Err(NWError::Internal(
+                "data length does not match the model".into(),
+            ));
+        }
+        let lambda = alpha * self.largest_eigenvalue();
+        let beta = self.eigenvectors.tr_mul(y);
+        let filtered = DVector::from_iterator(
+            beta.len(),
+            beta.iter()
+                .zip(self.eigenvalues.iter())
+                .map(|(b, s2)| b / (s2 + lambda)),
+        );
+        let c = &self.eigenvectors * filtered;
+
+        let components = self.weights.len();
+        let mut q = vec![vec![0.0; self.jacobian.len()]; components];
+        for (m, cr) in self.model.iter().zip(c.iter()) {
+            for (k, e) in m.coefficients.iter().enumerate() {
+                q[k][m.row] += e * cr;
+            }
+        }
+        let mut x = vec![vec![0.0; self.unknowns]; components];
+        for (k, xk) in x.iter_mut().enumerate() {
+            for (i, r) in self.jacobian.iter().enumerate() {
+                let qi = q[k][i];
+                if qi == 0.0 {
+                    continue;
+                }
+                for (col, v) in r.columns.iter().zip(&r.values) {
+                    xk[*col as usize] += v * qi;
+                }
+            }
+            for (xu, w) in xk.iter_mut().zip(&self.weights[k]) {
+                *xu *= w;
+            }
+        }
+        Ok(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square, diagonal sensitivity `d` with one component.
+    fn diagonal(d: &[f64], spatial: Option<f64>) -> Tikhonov {
+        let jacobian = d
+            .iter()
+            .enumerate()
+            .map(|(i, v)| SparseRow {
+                columns: vec![i as u32],
+                values: vec![*v],
+            })
+            .collect();
+        let model = (0..d.len())
+            .map(|row| ModelRow {
+                row,
+                coefficients: vec![1.0],
+            })
+            .collect();
+        Tikhonov::new(jacobian, model, d.len(), spatial).unwrap()
+    }
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-9, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn identity_shrinks_the_data() {
+        let t = diagonal(&[1.0; 3], None);
+        let y = DVector::from_vec(vec![1.5, -3.0, 6.0]);
+        let x = t.solve(&y, 0.5).unwrap();
+        assert_close(&x[0], &[1.0, -2.0, 4.0]);
+    }
+
+    #[test]
+    fn diagonal_solve_is_closed_form() {
+        let d = [1.0, 2.0, 0.5];
+        let t = diagonal(&d, None);
+        let y = DVector::from_vec(vec![1.0, 1.0, 1.0]);
+        // λ = α·max(d²) = 0.1 · 4.
+        let x = t.solve(&y, 0.1).unwrap();
+        let expected: Vec<f64> = d.iter().map(|d| d / (d * d + 0.4)).collect();
+        assert_close(&x[0], &expected);
+    }
+
+    #[test]
+    fn minimum_norm_solution_of_an_underdetermined_row() {
+        let jacobian = vec![SparseRow {
+            columns: vec![0, 1],
+            values: vec![1.0, 1.0],
+        }];
+        let model = vec![ModelRow {
+            row: 0,
+            coefficients: vec![2.0, 1.0],
+        }];
+        // Components a and b: y = 2·(a₀ + a₁) + (b₀ + b₁), whose
+        // minimum-norm solution follows the coefficients.
+        let t = Tikhonov::new(jacobian, model, 2, None).unwrap();
+        let x = t.solve(&DVector::from_vec(vec![10.0]), 1e-12).unwrap();
+        assert_close(&x[0], &[2.0, 2.0]);
+        assert_close(&x[1], &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn spatial_regularisation_weights_by_sensitivity() {
+        let t = diagonal(&[1.0, 2.0], Some(1.0));
+        // L⁻² = 1 / (d² + β·max d²) = [1/5, 1/8], so ÃÃᵀ = diag(1/5, 1/2)
+        // and λ = 0.2 · 1/2.
+        let x = t.solve(&DVector::from_vec(vec![1.0, 1.0]), 0.2).unwrap();
+        assert_close(&x[0], &[0.2 / 0.3, 0.25 / 0.6]);
+
+        let row = SparseRow {
+            columns: vec![0],
+            values: vec![1.0],
+        };
+        let model = vec![ModelRow {
+            row: 0,
+            coefficients: vec![1.0],
+        }];
+        assert!(Tikhonov::new(vec![row], model, 1, Some(0.0)).is_err());
+    }
+
+    #[test]
+    fn gcv_and_l_curve_recover_the_noise_to_signal_ratio() {
+        // x ~ N(0, 1) seen through singular values from 1 down to 0.001
+        // with noise σ = 0.1: the best λ, which GCV estimates, is σ²/1.
+        let mut state = 7u64;
+        let mut gaussian = || {
+            let mut uniform = || {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                ((state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+            };
+            let (u, v) = (uniform(), uniform());
+            (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+        };
+        let n = 300;
+        let d: Vec<f64> = (0..n)
+            .map(|i| 10f64.powf(-3.0 * i as f64 / (n - 1) as f64))
+            .collect();
+        let y = DVector::from_iterator(n, d.iter().map(|d| d * gaussian() + 0.1 * gaussian()));
+        let t = diagonal(&d, None);
+
+        let alpha = t.select(&y, Regularization::Gcv).unwrap();
+        assert!((5e-3..2e-2).contains(&alpha), "GCV α = {alpha}");
+        // The L-curve corner lands near it too.
+        let alpha = t.select(&y, Regularization::LCurve).unwrap();
+        assert!((5e-3..5e-2).contains(&alpha), "L-curve α = {alpha}");
+        assert_eq!(
+            t.select(&y, Regularization::Fixed { alpha: 0.3 }).unwrap(),
+            0.3
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+/// Trend removed from each segment before spectral estimation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detrend {
+    None,
+    /// Subtract the mean.
+    #[default]
+    Constant,
+    /// Subtract the least-squares line.
+    Linear,
+}
+
+impl Detrend {
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Detrend::None,
+            "linear" => Detrend::Linear,
+            _ => Detrend::Constant,
+        }
+    }
+}
+
+pub fn detrend(data: &[f64], kind: Detrend) -> Vec<f64> {
+    let n = data.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    match kind {
+        Detrend::None => data.to_vec(),
+        Detrend::Constant => {
+            let mean = data.iter().sum::<f64>() / n as f64;
+            data.iter().map(|x| x - mean).collect()
+        }
+        Detrend::Linear => {
+            let (slope, intercept) = linear_fit(data);
+            data.iter()
+                .enumerate()
+                .map(|(i, x)| x - (intercept + slope * i as f64))
+                .collect()
+        }
+    }
+}
+
+/// Least-squares `(slope, intercept)` of `data` against its sample index.
+pub fn linear_fit(data: &[f64]) -> (f64, f64) {
+    let n = data.len() as f64;
+    if data.len() < 2 {
+        return (0.0, data.first().copied().unwrap_or(0.0));
+    }
+    let x_mean = (n - 1.0) / 2.0;
+    let y_mean = data.iter().sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for (i, y) in data.iter().enumerate() {
+        let dx = i as f64 - x_mean;
+        sxy += dx * (y - y_mean);
+        sxx += dx * dx;
+    }
+    let slope = sxy / sxx;
+    (slope, y_mean - slope * x_mean)
+}
+
+// =============================================================================
+// Masked trend models
+// =============================================================================
+//
+// Each function fits a slow trend to the samples where `mask[i]` is true and
+// evaluates it at every sample, so excluded stretches (bad segments) neither
+// distort the fit nor are left without a value.
+
+/// Least-squares polynomial of `order` in time. Fitted in a Legendre basis on
+/// the rescaled time axis, which keeps high orders well conditioned.
+/// Returns `None` when fewer than `order + 1` samples are usable.
+pub fn polynomial_trend(time: &[f64], x: &[f64], mask: &[bool], order: usize) -> Option<Vec<f64>> {
+    let n = time.len().min(x.len()).min(mask.len());
+    let m = order + 1;
+    if mask[..n].iter().filter(|&&k| k).count() < m {
+        return None;
+    }
+    let (t_min, t_max) = time[..n]
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &t| {
+            (lo.min(t), hi.max(t))
+        });
+    let span = (t_max - t_min).max(f64::EPSILON);
+    let basis = |t: f64| legendre(2.0 * (t - t_min) / span - 1.0, order);
+
+    let mut ata = DMatrix::<f64>::zeros(m, m);
+    let mut atb = DVector::<f64>::zeros(m);
+    for i in (0..n).filter(|&i| mask[i]) {
+        let p = basis(time[i]);
+        for r in 0..m {
+            atb[r] += p[r] * x[i];
+            for c in 0..m {
+                ata[(r, c)] += p[r] * p[c];
+            }
+        }
+    }
+    let coef = ata.lu().solve(&atb)?;
+    Some(
+        time[..n]
+            .iter()
+            .map(|&t| basis(t).iter().zip(coef.iter()).map(|(p, c)| p * c).sum())
+            .collect(),
+    )
+}
+
+fn legendre(u: f64, order: usize) -> Vec<f64> {
+    let mut p = Vec::with_capacity(order + 1);
+    p.push(1.0);
+    if order >= 1 {
+        p.push(u);
+    }
+    for k in 1..order {
+        let next = ((2 * k + 1) as f64 * u * p[k] - k as f64 * p[k - 1]) / (k + 1) as f64;
+        p.push(next);
+    }
+    p
+}
+
+/// Least-squares cubic B-spline with uniform knots every `knot_spacing` seconds.
+/// A light second-difference penalty bridges knot intervals that contain no
+/// usable samples. Returns `None` if no samples are usable.
+pub fn spline_trend(time: &[f64], x: &[f64], mask: &[bool], knot_spacing: f64) -> Option<Vec<f64>> {
+    let n = time.len().min(x.len()).min(mask.len());
+    if !mask[..n].iter().any(|&k| k) || knot_spacing <= 0.0 {
+        return None;
+    }
+    let (t_min, t_max) = time[..n]
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &t| {
+            (lo.min(t), hi.max(t))
+        });
+    let segments = (((t_max - t_min) / knot_spacing).ceil() as usize).max(1);
+    let step = (t_max - t_min).max(f64::EPSILON) / segments as f64;
+    let m = segments + 3;
+
+    // (first basis index, the four non-zero basis weights) at time t.
+    let basis = |t: f64| -> (usize, [f64; 4]) {
+        let s = ((t - t_min) / step).clamp(0.0, segments as f64);
+        let j = (s.floor() as usize).min(segments - 1);
+        let f = s - j as f64;
+        let f2 = f * f;
+        let f3 = f2 * f;
+        (
+            j,
+            [
+                (1.0 - f).powi(3) / 6.0,
+                (3.0 * f3 - 6.0 * f2 + 4.0) / 6.0,
+                (-3.0 * f3 + 3.0 * f2 + 3.0 * f + 1.0) / 6.0,
+                f3 / 6.0,
+            ],
+        )
+    };
+
+    let mut ata = DMatrix::<f64>::zeros(m, m);
+    let mut atb = DVector::<f64>::zeros(m);
+    for i in (0..n).filter(|&i| mask[i]) {
+        let (j, w) = basis(time[i]);
+        for r in 0..4 {
+            atb[j + r] += w[r] * x[i];
+            for c in 0..4 {
+                ata[(j + r, j + c)] += w[r] * w[c];
+            }
+        }
+    }
+    let lambda = 1e-6 * (0..m).map(|i| ata[(i, i)]).sum::<f64>() / m as f64 + 1e-12;
+    for k in 0..m.saturating_sub(2) {
+        let d = [1.0, -2.0, 1.0];
+        for r in 0..3 {
+            for c in 0..3 {
+                ata[(k + r, k + c)] += lambda * d[r] * d[c];
+            }
+        }
+    }
+    let coef = ata.lu().solve(&atb)?;
+    Some(
+        time[..n]
+            .iter()
+            .map(|&t| {
+                let (j, w) = basis(t);
+                (0..4).map(|r| w[r] * coef[j + r]).sum()
+            })
+            .collect(),
+    )
+}
+
+/// Savitzky–Golay smooth of `x` (window `window_len` samples, polynomial
+/// `order`) used as a trend. Windows that are fully usable use the fixed
+/// convolution kernel; windows touching masked samples or the edges fall back
+/// to a weighted local fit over the usable samples.
+pub fn savitzky_golay_trend(x: &[f64], mask: &[bool], window_len: usize, order: usize) -> Vec<f64> {
+    let n = x.len().min(mask.len());
+    let half = (window_len.max(order + 2) / 2).max(1);
+    let kernel = local_fit_weights(half, order, |_| true);
+
+    // Running count of masked-out samples for O(1) "window is clean" checks.
+    let mut bad_before = vec![0usize; n + 1];
+    for i in 0..n {
+        bad_before[i + 1] = bad_before[i] + usize::from(!mask[i]);
+    }
+
+    (0..n)
+        .map(|i| {
+            if i >= half && i + half < n && bad_before[i + half + 1] == bad_before[i - half] {
+                return kernel
+                    .as_ref()
+                    .map(|k| {
+                        k.iter()
+                            .zip(&x[i - half..=i + half])
+                            .map(|(w, v)| w * v)
+                            .sum()
+                    })
+                    .unwrap_or(x[i]);
+            }
+            let usable = |offset: isize| {
+                let j = i as isize + offset;
+                j >= 0 && (j as usize) < n && mask[j as usize]
+            };
+            match local_fit_weights(half, order, usable) {
+                Some(w) => w
+                    .iter()
+                    .enumerate()
+                    .filter(|(k, _)| usable(*k as isize - half as isize))
+                    .map(|(k, w)| w * x[i + k - half])
+                    .sum(),
+                None => x[i],
+            }
+        })
+        .collect()
+}
+
+/// Weights `w[k]` (k = 0..=2·half, offset k - half) such that `Σ w[k]·x[k]` is
+/// the value at offset 0 of the least-squares polynomial through the usable
+/// offsets. `None` if too few offsets are usable for the order.
+fn local_fit_weights(
+    half: usize,
+    order: usize,
+    usable: impl Fn(isize) -> bool,
+) -> Option<Vec<f64>> {
+    let len = 2 * half + 1;
+    let m = order + 1;
+    let rows: Vec<(usize, Vec<f64>)> = (0..len)
+        .filter(|&k| usable(k as isize - half as isize))
+        .map(|k| (k, legendre((k as f64 - half as f64) / half as f64, order)))
+        .collect();
+    if rows.len() < m {
+        return None;
+    }
+    let mut ata = DMatrix::<f64>::zeros(m, m);
+    for (_, p) in &rows {
+        for r in 0..m {
+            for c in 0..m {
+                ata[(r, c)] += p[r] * p[c];
+            }
+        }
+    }
+    // value at 0 = p(0)ᵀ (AᵀA)⁻¹ Aᵀ x  →  weights = A (AᵀA)⁻¹ p(0)
+    let p0 = DVector::from_vec(legendre(0.0, order));
+    let g = ata.lu().solve(&p0)?;
+    let mut w = vec![0.0; len];
+    for (k, p) in &rows {
+        w[*k] = p.iter().zip(g.iter()).map(|(a, b)| a * b).sum();
+    }
+    Some(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64], tolerance: f64) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!((a - e).abs() <= tolerance, "sample {i}: {a} != {e}");
+        }
+    }
+
+    /// A quadratic in time at 10 Hz, with a step of 50 over samples 40..60
+    /// that the mask leaves out.
+    fn quadratic_with_bad_stretch() -> (Vec<f64>, Vec<f64>, Vec<f64>, Vec<bool>) {
+        let time: Vec<f64> = (0..100).map(|i| i as f64 / 10.0).collect();
+        let trend: Vec<f64> = time.iter().map(|t| 2.0 - 0.5 * t + 0.1 * t * t).collect();
+        let mask: Vec<bool> = (0..100).map(|i| !(40..60).contains(&i)).collect();
+        let x = trend
+            .iter()
+            .zip(&mask)
+            .map(|(v, &good)| if good { *v } else { v + 50.0 })
+            .collect();
+        (time, trend, x, mask)
+    }
+
+    #[test]
+    fn linear_detrend_removes_a_line() {
+        let data: Vec<f64> = (0..7).map(|i| 3.0 + 0.25 * i as f64).collect();
+        assert_eq!(linear_fit(&data), (0.25, 3.0));
+        assert_close(&detrend(&data, Detrend::Linear), &[0.0; 7], 1e-12);
+        assert_close(
+            &detrend(&[1.0, 2.0, 6.0], Detrend::Constant),
+            &[-2.0, -1.0, 3.0],
+            1e-12,
+        );
+        assert_eq!(detrend(&[1.0, 2.0], Detrend::None), vec![1.0, 2.0]);
+        assert_eq!(Detrend::from_name("LINEAR"), Detrend::Linear);
+        assert_eq!(Detrend::from_name("anything"), Detrend::Constant);
+    }
+
+    #[test]
+    fn polynomial_trend_ignores_masked_samples() {
+        let (time, trend, x, mask) = quadratic_with_bad_stretch();
+        let fitted = polynomial_trend(&time, &x, &mask, 2).unwrap();
+        assert_close(&fitted, &trend, 1e-9);
+        // Three usable samples are too few for a cubic.
+        let few: Vec<bool> = (0..100).map(|i| i < 3).collect();
+        assert!(polynomial_trend(&time, &x, &few, 3).is_none());
+    }
+
+    #[test]
+    fn spline_trend_bridges_a_masked_gap() {
+        let (time, _, _, mask) = quadratic_with_bad_stretch();
+        // A line is in the spline space and has no curvature to penalise.
+        let line: Vec<f64> = time.iter().map(|t| 1.0 + 0.3 * t).collect();
+        let mut x = line.clone();
+        for (v, &good) in x.iter_mut().zip(&mask) {
+            if !good {
+                *v = -100.0;
+            }
+        }
+        let fitted = spline_trend(&time, &x, &mask, 1.0).unwrap();
+        assert_close(&fitted, &line, 1e-6);
+        assert!(spline_trend(&time, &x, &[false; 100], 1.0).is_none());
+    }
+
+    #[test]
+    fn savitzky_golay_keeps_a_polynomial_of_its_order() {
+        let (_, trend, x, mask) = quadratic_with_bad_stretch();
+        let smoothed = savitzky_golay_trend(&x, &mask, 11, 2);
+        // Windows centred on 43..57 hold fewer than three usable samples,
+        // too few for a quadratic, and keep the sample as it is.
+        let fits_quadratic = |i: usize| !(43..57).contains(&i);
+        for (i, ((s, t), v)) in smoothed.iter().zip(&trend).zip(&x).enumerate() {
+            let expected = if fits_quadratic(i) { t } else { v };
+            assert!((s - expected).abs() < 1e-9, "sample {i}: {s} != {expected}");
+        }
+    }
+}
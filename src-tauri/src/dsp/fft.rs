@@ -0,0 +1,28 @@
+use realfft::{RealFftPlanner, RealToComplex};
+use std::sync::{Arc, Mutex, OnceLock};
+
+// One planner for the whole process. `RealFftPlanner` memoises every plan it
+// creates, so repeated spectra of the same length reuse the twiddle tables
+// instead of replanning on each call.
+static PLANNER: OnceLock<Mutex<RealFftPlanner<f64>>> = OnceLock::new();
+
+fn planner() -> std::sync::MutexGuard<'static, RealFftPlanner<f64>> {
+    PLANNER
+        .get_or_init(|| Mutex::new(RealFftPlanner::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Forward real-to-complex FFT of length `n` from the shared plan cache.
+pub fn forward_plan(n: usize) -> Arc<dyn RealToComplex<f64>> {
+    planner().plan_fft_forward(n)
+}
+
+/// Squared magnitude of the one-sided spectrum of `input` (consumed as scratch).
+pub fn power_spectrum(input: &mut [f64]) -> Vec<f64> {
+    let fft = forward_plan(input.len());
+    let mut output = fft.make_output_vec();
+    fft.process(input, &mut output)
+        .expect("buffer lengths come from the plan");
+    output.iter().map(|c| c.norm_sqr()).collect()
+}
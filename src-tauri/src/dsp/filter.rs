@@ -0,0 +1,164 @@
+use std::f64::consts::PI;
+
+/// One biquad section, `b = [b0, b1, b2]`, `a = [1, a1, a2]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biquad {
+    pub b: [f64; 3],
+    pub a: [f64; 3],
+}
+
+impl Biquad {
+    /// Steady-state gain for a constant input.
+    fn dc_gain(&self) -> f64 {
+        let den: f64 = self.a.iter().sum();
+        if den.abs() < f64::EPSILON {
+            0.0
+        } else {
+            self.b.iter().sum::<f64>() / den
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Response {
+    LowPass,
+    HighPass,
+}
+
+/// Digital Butterworth low-pass of `order` at `cutoff` Hz, as second-order sections.
+pub fn butterworth_lowpass(order: usize, cutoff: f64, fs: f64) -> Vec<Biquad> {
+    butterworth(order, cutoff, fs, Response::LowPass)
+}
+
+/// Digital Butterworth high-pass of `order` at `cutoff` Hz, as second-order sections.
+pub fn butterworth_highpass(order: usize, cutoff: f64, fs: f64) -> Vec<Biquad> {
+    butterworth(order, cutoff, fs, Response::HighPass)
+}
+
+/// Bilinear transform of the analog prototype with pre-warping, so the -3 dB
+/// point lands exactly on `cutoff`. Each section is normalised to unit gain in
+/// the passband (DC for low-pass, Nyquist for high-pass).
+fn butterworth(order: usize, cutoff: f64, fs: f64, response: Response) -> Vec<Biquad> {
+    if order == 0 || !(cutoff > 0.0 && cutoff < fs / 2.0) {
+        return Vec::new();
+    }
+    let warped = 2.0 * fs * (PI * cutoff / fs).tan();
+    let k = 2.0 * fs;
+
+    // Analog pole of the normalised prototype, scaled to the cutoff, then
+    // mapped to z. Only the upper half-plane poles are needed (conjugates pair up).
+    let digital_pole = |theta: f64| -> (f64, f64) {
+        let (re, im) = (theta.cos(), theta.sin());
+        let (sr, si) = match response {
+            Response::LowPass => (warped * re, warped * im),
+            Response::HighPass => {
+                // warped / p  with |p| = 1  →  warped * conj(p)
+                (warped * re, -warped * im)
+            }
+        };
+        // z = (k + s) / (k - s)
+        let (nr, ni) = (k + sr, si);
+        let (dr, di) = (k - sr, -si);
+        let d = dr * dr + di * di;
+        ((nr * dr + ni * di) / d, (ni * dr - nr * di) / d)
+    };
+    let zero = match response {
+        Response::LowPass => -1.0,
+        Response::HighPass => 1.0,
+    };
+
+    let mut sections = Vec::with_capacity(order.div_ceil(2));
+    for i in 0..order / 2 {
+        let theta = PI * (2 * i + order + 1) as f64 / (2 * order) as f64;
+        let (pr, pi) = digital_pole(theta);
+        sections.push(Biquad {
+            b: [1.0, -2.0 * zero, 1.0],
+            a: [1.0, -2.0 * pr, pr * pr + pi * pi],
+        });
+    }
+    if order % 2 == 1 {
+        let (pr, _) = digital_pole(PI);
+        sections.push(Biquad {
+            b: [1.0, -zero, 0.0],
+            a: [1.0, -pr, 0.0],
+        });
+    }
+
+    for s in &mut sections {
+        let gain = match response {
+            Response::LowPass => s.dc_gain(),
+            Response::HighPass => (s.b[0] - s.b[1] + s.b[2]) / (s.a[0] - s.a[1] + s.a[2]),
+        };
+        if gain.abs() > f64::EPSILON {
+            s.b.iter_mut().for_each(|b| *b /= gain);
+        }
+    }
+    sections
+}
+
+/// Causal filtering through the cascade (direct form II transposed), starting
+/// from the steady state for a constant input equal to `x[0]`.
+pub fn sosfilt(sections: &[Biquad], x: &[f64]) -> Vec<f64> {
+    let mut y = x.to_vec();
+    let mut level = x.first().copied().unwrap_or(0.0);
+    for s in sections {
+        // Steady state: y = G·level, z2 = b2·level − a2·y, z1 = y − b0·level.
+        let g = s.dc_gain();
+        let mut z2 = s.b[2] * level - s.a[2] * g * level;
+        let mut z1 = g * level - s.b[0] * level;
+        for v in y.iter_mut() {
+            let input = *v;
+            let out = s.b[0] * input + z1;
+            z1 = s.b[1] * input - s.a[1] * out + z2;
+            z2 = s.b[2] * input - s.a[2] * out;
+            *v = out;
+        }
+        level *= g;
+    }
+    y
+}
+
+/// Zero-phase filtering: forward and backward passes over an odd (point-
+/// symmetric) extension of the signal, which suppresses edge transients.
+/// The extension is long enough for the slowest pole to decay.
+/// Non-finite samples are not supported; the caller should interpolate first.
+pub fn filtfilt(sections: &[Biquad], x: &[f64]) -> Vec<f64> {
+    let n = x.len();
+    if n < 2 || sections.is_empty() {
+        return x.to_vec();
+    }
+    let pad = (3 * (2 * sections.len() + 1))
+        .max(settling_samples(sections))
+        .min(n - 1);
+    let mut ext = Vec::with_capacity(n + 2 * pad);
+    ext.extend((1..=pad).rev().map(|i| 2.0 * x[0] - x[i]));
+    ext.extend_from_slice(x);
+    ext.extend((1..=pad).map(|i| 2.0 * x[n - 1] - x[n - 1 - i]));
+
+    let mut y = sosfilt(sections, &ext);
+    y.reverse();
+    let mut y = sosfilt(sections, &y);
+    y.reverse();
+    y[pad..pad + n].to_vec()
+}
+
+/// Samples for the impulse response of the cascade to decay by 10⁻⁴.
+fn settling_samples(sections: &[Biquad]) -> usize {
+    let radius = sections
+        .iter()
+        .map(|s| {
+            // roots of z² + a1·z + a2
+            let disc = s.a[1] * s.a[1] - 4.0 * s.a[2];
+            if disc < 0.0 {
+                s.a[2].sqrt()
+            } else {
+                let r = disc.sqrt();
+                ((-s.a[1] + r) / 2.0).abs().max(((-s.a[1] - r) / 2.0).abs())
+            }
+        })
+        .fold(0.0, f64::max);
+    if radius <= 0.0 || radius >= 1.0 {
+        return 0;
+    }
+    (1e-4f64.ln() / radius.ln()).ceil() as usize
+}
@@ -0,0 +1,267 @@
+use super::pca::{covariance, usable_columns};
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+
+/// Eigenvalues below this fraction of the largest are treated as rank
+/// deficiency and dropped before whitening.
+const RANK_TOLERANCE: f64 = 1e-10;
+
+/// Independent components of a multichannel recording (rows = channels,
+/// columns = samples), such that `data ≈ mean + mixing · sources`.
+#[derive(Clone, Debug)]
+pub struct Ica {
+    pub mean: DVector<f64>,
+    /// components × channels; `sources = unmixing · (data - mean)`.
+    pub unmixing: DMatrix<f64>,
+    /// channels × components; column `i` is the spatial map of component `i`.
+    pub mixing: DMatrix<f64>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Symmetric FastICA with the log-cosh contrast (Hyvärinen 1999), fitted on
+/// the samples where `fit_mask` is true.
+///
+/// The data are whitened onto at most `n_components` principal components
+/// first. Initialisation is deterministic, so the same input always yields the
+/// same components in the same order: sorted by decreasing variance they
+/// explain, each with the largest entry of its spatial map positive.
+pub fn fast_ica(
+    data: &DMatrix<f64>,
+    fit_mask: &[bool],
+    n_components: usize,
+    max_iterations: usize,
+    tolerance: f64,
+) -> Option<Ica> {
+    let columns = usable_columns(data, fit_mask);
+    let (mean, cov) = covariance(data, &columns)?;
+    let eigen = SymmetricEigen::new(cov);
+
+    let mut order: Vec<usize> = (0..eigen.eigenvalues.len()).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    let largest = eigen.eigenvalues[order[0]];
+    if largest <= 0.0 {
+        return None;
+    }
+    order.retain(|&i| eigen.eigenvalues[i] > RANK_TOLERANCE * largest);
+    order.truncate(n_components.max(1));
+    let c = order.len();
+
+    // Whitening K (c × channels) and its pseudo-inverse (channels × c).
+    let whitening = DMatrix::from_fn(c, data.nrows(), |r, ch| {
+        eigen.eigenvectors[(ch, order[r])] / eigen.eigenvalues[order[r]].sqrt()
+    });
+    let dewhitening = DMatrix::from_fn(data.nrows(), c, |ch, r| {
+        eigen.eigenvectors[(ch, order[r])] * eigen.eigenvalues[order[r]].sqrt()
+    });
+    let centered = DMatrix::from_fn(data.nrows(), columns.len(), |r, j| {
+        data[(r, columns[j])] - mean[r]
+    });
+    let z = &whitening * centered;
+    let m = z.ncols() as f64;
+
+    let mut w = symmetric_decorrelation(&initial_rotation(c));
+    let mut converged = false;
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        iterations += 1;
+        let wz = &w * &z;
+        let g = wz.map(f64::tanh);
+        let g_prime_mean = DVector::from_fn(c, |i, _| {
+            g.row(i).iter().map(|v| 1.0 - v * v).sum::<f64>() / m
+        });
+        let mut w_new = (&g * z.transpose()) / m;
+        for i in 0..c {
+            let scaled = w.row(i) * g_prime_mean[i];
+            let mut row = w_new.row_mut(i);
+            row -= &scaled;
+        }
+        let w_new = symmetric_decorrelation(&w_new);
+
+        let change = (&w_new * w.transpose())
+            .diagonal()
+            .iter()
+            .map(|d| (d.abs() - 1.0).abs())
+            .fold(0.0, f64::max);
+        w = w_new;
+        if change < tolerance {
+            converged = true;
+            break;
+        }
+    }
+
+    let mut unmixing = &w * &whitening;
+    let mut mixing = &dewhitening * w.transpose();
+
+    // Order by explained variance (sources have unit variance, so that is the
+    // squared norm of the spatial map) and fix the sign ambiguity.
+    let power: Vec<f64> = (0..c).map(|i| mixing.column(i).norm_squared()).collect();
+    let mut rank: Vec<usize> = (0..c).collect();
+    rank.sort_by(|&a, &b| power[b].total_cmp(&power[a]));
+    unmixing = DMatrix::from_fn(c, data.nrows(), |r, ch| unmixing[(rank[r], ch)]);
+    mixing = DMatrix::from_fn(data.nrows(), c, |ch, r| mixing[(ch, rank[r])]);
+    for i in 0..c {
+        let peak = mixing
+            .column(i)
+            .iter()
+            .copied()
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.0);
+        if peak < 0.0 {
+            mixing.column_mut(i).neg_mut();
+            unmixing.row_mut(i).neg_mut();
+        }
+    }
+
+    Some(Ica {
+        mean,
+        unmixing,
+        mixing,
+        iterations,
+        converged,
+    })
+}
+
+impl Ica {
+    pub fn n_components(&self) -> usize {
+        self.unmixing.nrows()
+    }
+
+    /// Component time courses (components × samples). Samples with a
+    /// non-finite channel value are NaN.
+    pub fn sources(&self, data: &DMatrix<f64>) -> DMatrix<f64> {
+        let mut s = DMatrix::from_element(self.n_components(), data.ncols(), f64::NAN);
+        for j in 0..data.ncols() {
+            let x = data.column(j);
+            if x.iter().all(|v| v.is_finite()) {
+                s.set_column(j, &(&self.unmixing * (x - &self.mean)));
+            }
+        }
+        s
+    }
+
+    /// `data` with the contribution of the `reject`ed components removed.
+    /// Samples containing non-finite values are returned unchanged.
+    pub fn remove_components(&self, data: &DMatrix<f64>, reject: &[usize]) -> DMatrix<f64> {
+        let reject: Vec<usize> = reject
+            .iter()
+            .copied()
+            .filter(|&i| i < self.n_components())
+            .collect();
+        let a = self.mixing.select_columns(&reject);
+        let w = self.unmixing.select_rows(&reject);
+        let projector = a * w;
+        let mut out = data.clone();
+        for j in 0..data.ncols() {
+            let x = data.column(j);
+            if x.iter().all(|v| v.is_finite()) {
+                let removed = &projector * (x - &self.mean);
+                let mut column = out.column_mut(j);
+                column -= &removed;
+            }
+        }
+        out
+    }
+}
+
+/// `(W Wᵀ)^{-1/2} W` — the nearest matrix with orthonormal rows.
+fn symmetric_decorrelation(w: &DMatrix<f64>) -> DMatrix<f64> {
+    let eigen = SymmetricEigen::new(w * w.transpose());
+    let inv_sqrt = DMatrix::from_diagonal(&eigen.eigenvalues.map(|v| 1.0 / v.max(1e-300).sqrt()));
+    &eigen.eigenvectors * inv_sqrt * eigen.eigenvectors.transpose() * w
+}
+
+/// Fixed pseudo-random starting matrix (xorshift), so results are reproducible.
+fn initial_rotation(c: usize) -> DMatrix<f64> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    DMatrix::from_fn(c, c, |_, _| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sawtooth and a sine mixed into three channels, and the sources.
+    fn mixture() -> (DMatrix<f64>, [Vec<f64>; 2]) {
+        let samples = 2000;
+        let saw: Vec<f64> = (0..samples)
+            .map(|j| (j as f64 * 0.037) % 1.0 - 0.5)
+            .collect();
+        let sine: Vec<f64> = (0..samples).map(|j| (j as f64 * 0.011).sin()).collect();
+        let mixing = [[2.0, 0.5], [1.0, 1.0], [0.2, 1.5]];
+        let offsets = [1.0, -2.0, 0.5];
+        let data = DMatrix::from_fn(3, samples, |r, j| {
+            offsets[r] + mixing[r][0] * saw[j] + mixing[r][1] * sine[j]
+        });
+        (data, [saw, sine])
+    }
+
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let mean = |x: &[f64]| x.iter().sum::<f64>() / x.len() as f64;
+        let (ma, mb) = (mean(a), mean(b));
+        let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+        for (x, y) in a.iter().zip(b) {
+            ab += (x - ma) * (y - mb);
+            aa += (x - ma) * (x - ma);
+            bb += (y - mb) * (y - mb);
+        }
+        ab / (aa * bb).sqrt()
+    }
+
+    #[test]
+    fn unmixes_two_sources_from_three_channels() {
+        let (data, sources) = mixture();
+        let mask = vec![true; data.ncols()];
+        let ica = fast_ica(&data, &mask, 3, 500, 1e-8).unwrap();
+        // Three channels of two sources are rank two.
+        assert_eq!(ica.n_components(), 2);
+        assert!(ica.converged);
+
+        let found = ica.sources(&data);
+        let row = |i: usize| found.row(i).iter().copied().collect::<Vec<_>>();
+        let saw = (0..2)
+            .max_by(|&a, &b| {
+                let r = |i| correlation(&row(i), &sources[0]).abs();
+                r(a).total_cmp(&r(b))
+            })
+            .unwrap();
+        assert!(correlation(&row(saw), &sources[0]).abs() > 0.99);
+        assert!(correlation(&row(1 - saw), &sources[1]).abs() > 0.99);
+
+        // Taking the sawtooth out leaves the sine on its offsets.
+        let cleaned = ica.remove_components(&data, &[saw]);
+        let mixing = [0.5, 1.0, 1.5];
+        let offsets = [1.0, -2.0, 0.5];
+        for r in 0..3 {
+            for j in (0..data.ncols()).step_by(97) {
+                let expected = offsets[r] + mixing[r] * sources[1][j];
+                assert!(
+                    (cleaned[(r, j)] - expected).abs() < 0.02,
+                    "channel {r}, sample {j}: {} != {expected}",
+                    cleaned[(r, j)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fit_is_deterministic() {
+        let (data, _) = mixture();
+        let mask = vec![true; data.ncols()];
+        let a = fast_ica(&data, &mask, 2, 500, 1e-8).unwrap();
+        let b = fast_ica(&data, &mask, 2, 500, 1e-8).unwrap();
+        assert_eq!(a.unmixing, b.unmixing);
+        // Ordered by the power of the spatial map, largest entry positive.
+        let power = |i| a.mixing.column(i).norm_squared();
+        assert!(power(0) >= power(1));
+        for i in 0..2 {
+            let column = a.mixing.column(i);
+            let peak = column.iter().max_by(|x, y| x.abs().total_cmp(&y.abs()));
+            assert!(*peak.unwrap() > 0.0);
+        }
+    }
+}
@@ -1,6 +1,27 @@
+mod detrend;
+mod dpss;
+mod fft;
+mod filter;
+mod ica;
+mod motion;
+mod pca;
+mod resample;
 mod spectrogram;
 mod spectrum;
 mod window;
 
-pub use spectrum::{compute_fft_spectrum, compute_welch_psd, SpectrumResult};
+pub use detrend::{detrend, polynomial_trend, savitzky_golay_trend, spline_trend, Detrend};
+pub use dpss::dpss;
+pub use filter::{butterworth_highpass, butterworth_lowpass, filtfilt, sosfilt, Biquad};
+pub use ica::{fast_ica, Ica};
+pub use motion::tddr;
+pub use pca::Pca;
+pub use resample::{
+    interpolate, polyphase_filter, rational_approximation, resample_poly, uniform_grid,
+    Interpolation,
+};
+pub use spectrum::{
+    compute_fft_spectrum, compute_multitaper_psd, compute_psd, compute_welch_psd, PsdMethod,
+    PsdOptions, Scaling, SpectrumResult,
+};
 pub use window::WindowType;
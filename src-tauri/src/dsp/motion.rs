@@ -0,0 +1,79 @@
+use super::filter::{butterworth_lowpass, filtfilt};
+
+/// Low-pass cutoff separating the slow component TDDR corrects from the fast
+/// (cardiac) component it leaves untouched.
+const TDDR_CUTOFF_HZ: f64 = 0.5;
+/// Tukey biweight tuning constant (95% efficiency for Gaussian data).
+const TUKEY_C: f64 = 4.685;
+
+/// Temporal Derivative Distribution Repair (Fishburn et al., 2019).
+///
+/// Motion artefacts show up as outliers in the sample-to-sample derivative.
+/// The derivative of the low-frequency part of `x` is reweighted with an
+/// iteratively re-estimated Tukey biweight, integrated back, and the
+/// high-frequency part is added again. No parameters need tuning.
+pub fn tddr(x: &[f64], fs: f64) -> Vec<f64> {
+    let n = x.len();
+    if n < 3 {
+        return x.to_vec();
+    }
+    let mean = x.iter().sum::<f64>() / n as f64;
+
+    let low = if fs > 2.0 * TDDR_CUTOFF_HZ {
+        filtfilt(&butterworth_lowpass(3, TDDR_CUTOFF_HZ, fs), x)
+    } else {
+        x.to_vec()
+    };
+    let high: Vec<f64> = x.iter().zip(&low).map(|(a, b)| a - b).collect();
+    let deriv: Vec<f64> = low.windows(2).map(|w| w[1] - w[0]).collect();
+
+    let mut weights = vec![1.0; deriv.len()];
+    let mut mu = f64::INFINITY;
+    let tolerance = f64::EPSILON.sqrt();
+    for _ in 0..50 {
+        let mu0 = mu;
+        let wsum: f64 = weights.iter().sum();
+        if wsum <= 0.0 {
+            break;
+        }
+        mu = weights.iter().zip(&deriv).map(|(w, d)| w * d).sum::<f64>() / wsum;
+        let dev: Vec<f64> = deriv.iter().map(|d| (d - mu).abs()).collect();
+        let sigma = 1.4826 * median(&dev);
+        if sigma <= 0.0 {
+            break;
+        }
+        for (w, d) in weights.iter_mut().zip(&dev) {
+            let r = d / (sigma * TUKEY_C);
+            *w = if r < 1.0 { (1.0 - r * r).powi(2) } else { 0.0 };
+        }
+        if (mu - mu0).abs() < tolerance * mu.abs().max(mu0.abs()) {
+            break;
+        }
+    }
+
+    let mut corrected = Vec::with_capacity(n);
+    corrected.push(0.0);
+    for (w, d) in weights.iter().zip(&deriv) {
+        let last = corrected[corrected.len() - 1];
+        corrected.push(last + w * (d - mu));
+    }
+    let offset = mean - corrected.iter().sum::<f64>() / n as f64;
+    corrected
+        .iter()
+        .zip(&high)
+        .map(|(c, h)| c + offset + h)
+        .collect()
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut v = values.to_vec();
+    v.sort_unstable_by(|a, b| a.total_cmp(b));
+    let mid = v.len() / 2;
+    if v.is_empty() {
+        0.0
+    } else if v.len() % 2 == 0 {
+        0.5 * (v[mid - 1] + v[mid])
+    } else {
+        v[mid]
+    }
+}
@@ -0,0 +1,158 @@
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+
+/// Principal components of a multichannel recording (rows = channels,
+/// columns = samples).
+#[derive(Clone, Debug)]
+pub struct Pca {
+    /// Per-channel mean of the fitted samples.
+    pub mean: DVector<f64>,
+    /// Unit eigenvectors of the channel covariance, one per column, ordered by
+    /// decreasing variance.
+    pub components: DMatrix<f64>,
+    /// Variance along each component (eigenvalues), same order.
+    pub variance: Vec<f64>,
+}
+
+impl Pca {
+    /// Fit on the samples (columns) where `fit_mask` is true. Samples with a
+    /// non-finite value in any channel are skipped. `None` if fewer than two
+    /// samples remain.
+    pub fn fit(data: &DMatrix<f64>, fit_mask: &[bool]) -> Option<Pca> {
+        let columns = usable_columns(data, fit_mask);
+        let (mean, cov) = covariance(data, &columns)?;
+        let eigen = SymmetricEigen::new(cov);
+
+        let mut order: Vec<usize> = (0..eigen.eigenvalues.len()).collect();
+        order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+        let components = DMatrix::from_fn(data.nrows(), order.len(), |r, c| {
+            eigen.eigenvectors[(r, order[c])]
+        });
+        let variance = order
+            .iter()
+            .map(|&i| eigen.eigenvalues[i].max(0.0))
+            .collect();
+        Some(Pca {
+            mean,
+            components,
+            variance,
+        })
+    }
+
+    /// Fraction of the total variance carried by each component.
+    pub fn explained_variance_ratio(&self) -> Vec<f64> {
+        let total: f64 = self.variance.iter().sum();
+        if total <= 0.0 {
+            return vec![0.0; self.variance.len()];
+        }
+        self.variance.iter().map(|v| v / total).collect()
+    }
+
+    /// `data` with its projection onto the first `k` components removed.
+    /// Samples containing non-finite values are returned unchanged.
+    pub fn remove_components(&self, data: &DMatrix<f64>, k: usize) -> DMatrix<f64> {
+        let k = k.min(self.components.ncols());
+        let u = self.components.columns(0, k);
+        let projector = u * u.transpose();
+        let mut out = data.clone();
+        for j in 0..data.ncols() {
+            let x = data.column(j);
+            if x.iter().all(|v| v.is_finite()) {
+                let removed = &projector * (x - &self.mean);
+                let mut column = out.column_mut(j);
+                column -= &removed;
+            }
+        }
+        out
+    }
+}
+
+/// Columns where `mask` is true and every channel is finite.
+pub(crate) fn usable_columns(data: &DMatrix<f64>, mask: &[bool]) -> Vec<usize> {
+    (0..data.ncols())
+        .filter(|&j| mask.get(j).copied().unwrap_or(false))
+        .filter(|&j| data.column(j).iter().all(|v| v.is_finite()))
+        .collect()
+}
+
+/// Channel mean and (unbiased) covariance over the given columns.
+pub(crate) fn covariance(
+    data: &DMatrix<f64>,
+    columns: &[usize],
+) -> Option<(DVector<f64>, DMatrix<f64>)> {
+    if columns.len() < 2 || data.nrows() == 0 {
+        return None;
+    }
+    let channels = data.nrows();
+    let mut mean = DVector::<f64>::zeros(channels);
+    for &j in columns {
+        mean += data.column(j);
+    }
+    mean /= columns.len() as f64;
+
+    let centered = DMatrix::from_fn(channels, columns.len(), |r, c| {
+        data[(r, columns[c])] - mean[r]
+    });
+    let cov = (&centered * centered.transpose()) / (columns.len() - 1) as f64;
+    Some((mean, cov))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two channels of 3·s ± n on offsets 10 and -5, with s and n zero-mean
+    /// and uncorrelated, then a NaN sample and a masked-out outlier.
+    fn sum_and_difference() -> (DMatrix<f64>, Vec<bool>) {
+        let s = [1.0, -1.0, 1.0, -1.0];
+        let n = [1.0, 1.0, -1.0, -1.0];
+        let mut data = DMatrix::from_fn(2, 6, |r, j| match (r, j) {
+            (0, 0..=3) => 10.0 + 3.0 * s[j] + n[j],
+            (1, 0..=3) => -5.0 + 3.0 * s[j] - n[j],
+            _ => 1000.0,
+        });
+        data[(1, 4)] = f64::NAN;
+        (data, vec![true, true, true, true, true, false])
+    }
+
+    #[test]
+    fn components_of_a_sum_and_difference() {
+        let (data, mask) = sum_and_difference();
+        let pca = Pca::fit(&data, &mask).unwrap();
+        assert_eq!(pca.mean.as_slice(), &[10.0, -5.0]);
+        // Variance (6s)²/2 along (1, 1)/√2 and (2n)²/2 along (1, -1)/√2,
+        // over three degrees of freedom.
+        assert!((pca.variance[0] - 24.0).abs() < 1e-9);
+        assert!((pca.variance[1] - 8.0 / 3.0).abs() < 1e-9);
+        let first = pca.components.column(0);
+        assert!((first[0].abs() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((first[0] - first[1]).abs() < 1e-9);
+        let ratio = pca.explained_variance_ratio();
+        assert!((ratio[0] - 0.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn removing_the_first_component_leaves_the_difference() {
+        let (data, mask) = sum_and_difference();
+        let pca = Pca::fit(&data, &mask).unwrap();
+        let cleaned = pca.remove_components(&data, 1);
+        let n = [1.0, 1.0, -1.0, -1.0];
+        for j in 0..4 {
+            assert!((cleaned[(0, j)] - (10.0 + n[j])).abs() < 1e-9);
+            assert!((cleaned[(1, j)] - (-5.0 - n[j])).abs() < 1e-9);
+        }
+        // The sample with a NaN is left alone.
+        assert_eq!(cleaned[(0, 4)], 1000.0);
+        assert!(cleaned[(1, 4)].is_nan());
+        // Removing every component leaves the mean.
+        let flat = pca.remove_components(&data, 5);
+        assert!((flat[(0, 2)] - 10.0).abs() < 1e-9);
+        assert!((flat[(1, 2)] + 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn too_few_samples_do_not_fit() {
+        let (data, _) = sum_and_difference();
+        let one = [true, false, false, false, false, false];
+        assert!(Pca::fit(&data, &one).is_none());
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Natural cubic spline.
+    Cubic,
+}
+
+/// Evenly spaced grid from `start` to `end` (inclusive, within half a step) at `rate` Hz.
+pub fn uniform_grid(start: f64, end: f64, rate: f64) -> Vec<f64> {
+    if rate <= 0.0 || end < start {
+        return Vec::new();
+    }
+    let step = 1.0 / rate;
+    let n = ((end - start) / step + 0.5).floor() as usize + 1;
+    (0..n).map(|i| start + i as f64 * step).collect()
+}
+
+/// Sample `(t, x)` at `t_new`. `t` must be strictly increasing; `t_new` ascending.
+/// Points outside `t` take the nearest edge value.
+pub fn interpolate(t: &[f64], x: &[f64], t_new: &[f64], method: Interpolation) -> Vec<f64> {
+    let n = t.len().min(x.len());
+    match n {
+        0 => return vec![0.0; t_new.len()],
+        1 => return vec![x[0]; t_new.len()],
+        _ => {}
+    }
+    let (t, x) = (&t[..n], &x[..n]);
+    let second = match method {
+        Interpolation::Linear => None,
+        Interpolation::Cubic => Some(natural_spline_second_derivatives(t, x)),
+    };
+
+    let mut k = 0;
+    t_new
+        .iter()
+        .map(|&tq| {
+            if tq <= t[0] {
+                return x[0];
+            }
+            if tq >= t[n - 1] {
+                return x[n - 1];
+            }
+            while k + 2 < n && t[k + 1] <= tq {
+                k += 1;
+            }
+            while k > 0 && t[k] > tq {
+                k -= 1;
+            }
+            let h = t[k + 1] - t[k];
+            let a = (t[k + 1] - tq) / h;
+            let b = 1.0 - a;
+            let linear = a * x[k] + b * x[k + 1];
+            match &second {
+                None => linear,
+                Some(m) => {
+                    linear + ((a * a * a - a) * m[k] + (b * b * b - b) * m[k + 1]) * h * h / 6.0
+                }
+            }
+        })
+        .collect()
+}
+
+/// Second derivatives of the natural cubic spline through `(t, x)`.
+fn natural_spline_second_derivatives(t: &[f64], x: &[f64]) -> Vec<f64> {
+    let n = t.len();
+    let mut m = vec![0.0; n];
+    if n < 3 {
+        return m;
+    }
+    // Thomas algorithm on the (diagonally dominant) interior system.
+    let mut c_prime = vec![0.0; n];
+    let mut d_prime = vec![0.0; n];
+    for i in 1..n - 1 {
+        let h0 = t[i] - t[i - 1];
+        let h1 = t[i + 1] - t[i];
+        let rhs = 6.0 * ((x[i + 1] - x[i]) / h1 - (x[i] - x[i - 1]) / h0);
+        let diag = 2.0 * (h0 + h1) - h0 * c_prime[i - 1];
+        c_prime[i] = h1 / diag;
+        d_prime[i] = (rhs - h0 * d_prime[i - 1]) / diag;
+    }
+    for i in (1..n - 1).rev() {
+        m[i] = d_prime[i] - c_prime[i] * m[i + 1];
+    }
+    m
+}
+
+/// Best rational approximation `up / down` of `ratio` with `down <= max_denominator`
+/// (continued fractions). Returns `(1, 1)` for non-positive ratios.
+pub fn rational_approximation(ratio: f64, max_denominator: usize) -> (usize, usize) {
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return (1, 1);
+    }
+    let (mut p0, mut q0, mut p1, mut q1) = (0u64, 1u64, 1u64, 0u64);
+    let mut x = ratio;
+    loop {
+        let a = x.floor() as u64;
+        let (p2, q2) = (a * p1 + p0, a * q1 + q0);
+        if q2 as usize > max_denominator {
+            break;
+        }
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+        let frac = x - a as f64;
+        if frac < 1e-12 {
+            break;
+        }
+        x = 1.0 / frac;
+    }
+    if q1 == 0 {
+        // ratio is larger than any fraction with denominator 1 we could reach
+        return (ratio.round().max(1.0) as usize, 1);
+    }
+    let g = gcd(p1 as usize, q1 as usize).max(1);
+    ((p1 as usize / g).max(1), q1 as usize / g)
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Anti-aliasing low-pass used by [`resample_poly`]: a Kaiser-windowed sinc
+/// with cutoff at the lower of the two Nyquist frequencies.
+pub fn polyphase_filter(up: usize, down: usize) -> Vec<f64> {
+    let max_rate = up.max(down);
+    let cutoff = 1.0 / max_rate as f64; // relative to the upsampled Nyquist
+    let half_len = 10 * max_rate;
+    let len = 2 * half_len + 1;
+    let beta = 5.0;
+
+    let mut h: Vec<f64> = (0..len)
+        .map(|k| {
+            let m = k as f64 - half_len as f64;
+            let r = m / half_len as f64;
+            let window = bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta);
+            cutoff * sinc(cutoff * m) * window
+        })
+        .collect();
+    // Unit DC gain for every polyphase branch (taps r, r+up, r+2·up, …), not
+    // just on average — otherwise large DC offsets such as raw intensities
+    // come out with a ripple at the output phase rate.
+    for phase in 0..up {
+        let sum: f64 = h.iter().skip(phase).step_by(up).sum();
+        h.iter_mut().skip(phase).step_by(up).for_each(|v| *v /= sum);
+    }
+    h
+}
+
+/// Resample `x` by the rational factor `up / down` with a polyphase FIR
+/// (upsample, low-pass, downsample — without materialising the zero-stuffed
+/// signal). Samples beyond either end are taken as the edge value, so DC
+/// offsets do not produce edge transients.
+///
+/// Output length is `ceil(len * up / down)`.
+pub fn resample_poly(x: &[f64], up: usize, down: usize) -> Vec<f64> {
+    let n = x.len();
+    if n == 0 || up == 0 || down == 0 {
+        return Vec::new();
+    }
+    let g = gcd(up, down);
+    let (up, down) = (up / g, down / g);
+    if up == 1 && down == 1 {
+        return x.to_vec();
+    }
+
+    let h = polyphase_filter(up, down);
+    let half_len = (h.len() - 1) / 2;
+    let n_out = (n * up).div_ceil(down);
+    let sample = |i: i64| x[i.clamp(0, n as i64 - 1) as usize];
+
+    (0..n_out)
+        .map(|m| {
+            // Position of the filter centre in the upsampled domain.
+            let p = (m * down + half_len) as i64;
+            let first = (p - h.len() as i64 + 1).div_euclid(up as i64)
+                + i64::from((p - h.len() as i64 + 1).rem_euclid(up as i64) != 0);
+            let last = p.div_euclid(up as i64);
+            (first..=last)
+                .map(|i| h[(p - i * up as i64) as usize] * sample(i))
+                .sum()
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind, order zero (power series).
+fn bessel_i0(x: f64) -> f64 {
+    let half_sq = x * x / 4.0;
+    let (mut sum, mut term) = (1.0, 1.0);
+    for k in 1..50 {
+        term *= half_sq / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
@@ -1,81 +1,326 @@
-use crate::dsp::window::{apply_window, WindowType};
-use realfft::RealFftPlanner;
+use crate::dsp::detrend::{detrend, Detrend};
+use crate::dsp::dpss::dpss;
+use crate::dsp::fft::power_spectrum;
+use crate::dsp::window::{window_coefficients, WindowType};
+use serde::{Deserialize, Serialize};
 
-#[derive(serde::Serialize)]
+#[derive(Serialize)]
 pub struct SpectrumResult {
     pub frequencies: Vec<f64>,
     pub magnitudes: Vec<f64>,
 }
 
+impl SpectrumResult {
+    fn empty() -> Self {
+        SpectrumResult {
+            frequencies: Vec::new(),
+            magnitudes: Vec::new(),
+        }
+    }
+}
+
+/// Units of a PSD estimate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    /// Power spectral density, units²/Hz. Integrating over frequency gives the variance.
+    #[default]
+    Density,
+    /// Power spectrum, units². A sinusoid of amplitude A peaks at A²/2.
+    Spectrum,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PsdMethod {
+    /// Averaged, overlapping, windowed periodograms.
+    Welch {
+        segment_len: usize,
+        overlap: usize,
+        window: WindowType,
+    },
+    /// Average of periodograms tapered by `tapers` Slepian sequences with
+    /// time-halfbandwidth product `nw`. `tapers` is usually `2·nw − 1`.
+    Multitaper { nw: f64, tapers: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PsdOptions {
+    pub method: PsdMethod,
+    pub detrend: Detrend,
+    pub scaling: Scaling,
+}
+
+impl Default for PsdOptions {
+    fn default() -> Self {
+        PsdOptions {
+            method: PsdMethod::Welch {
+                segment_len: 256,
+                overlap: 128,
+                window: WindowType::Hann,
+            },
+            detrend: Detrend::Constant,
+            scaling: Scaling::Density,
+        }
+    }
+}
+
+/// Single-sided amplitude spectrum. A sinusoid of amplitude A shows a peak of
+/// height A regardless of the window (coherent-gain corrected).
 pub fn compute_fft_spectrum(
     signal: &[f64],
     sample_rate: f64,
     window: WindowType,
 ) -> SpectrumResult {
     let n = signal.len();
-    let windowed = apply_window(signal, window);
-
-    let mut planner = RealFftPlanner::<f64>::new();
-    let fft = planner.plan_fft_forward(n);
-
-    let mut input = windowed;
-    let mut output = fft.make_output_vec();
-
-    fft.process(&mut input, &mut output).unwrap();
-
-    let magnitudes: Vec<f64> = output
-        .iter()
-        .map(|c| (c.re * c.re + c.im * c.im).sqrt() * 2.0 / n as f64)
-        .collect();
-    let freq_resolution = sample_rate / n as f64;
+    if n == 0 || sample_rate <= 0.0 {
+        return SpectrumResult::empty();
+    }
+    let w = window_coefficients(n, window);
+    let coherent_gain: f64 = w.iter().sum();
 
-    let frequencies: Vec<f64> = (0..magnitudes.len())
-        .map(|i| i as f64 * freq_resolution)
+    let mut input: Vec<f64> = signal.iter().zip(&w).map(|(x, w)| x * w).collect();
+    let mut magnitudes: Vec<f64> = power_spectrum(&mut input)
+        .into_iter()
+        .map(|p| p.sqrt() / coherent_gain)
         .collect();
+    one_sided(&mut magnitudes, n, 2.0);
 
     SpectrumResult {
-        frequencies,
+        frequencies: bin_frequencies(magnitudes.len(), n, sample_rate),
         magnitudes,
     }
 }
 
+/// Power spectral estimate with the method, detrending and scaling in `options`.
+pub fn compute_psd(signal: &[f64], sample_rate: f64, options: &PsdOptions) -> SpectrumResult {
+    match options.method {
+        PsdMethod::Welch {
+            segment_len,
+            overlap,
+            window,
+        } => compute_welch_psd(
+            signal,
+            sample_rate,
+            segment_len,
+            overlap,
+            window,
+            options.detrend,
+            options.scaling,
+        ),
+        PsdMethod::Multitaper { nw, tapers } => compute_multitaper_psd(
+            signal,
+            sample_rate,
+            nw,
+            tapers,
+            options.detrend,
+            options.scaling,
+        ),
+    }
+}
+
+/// Welch's averaged periodogram.
+///
+/// `segment_len` is clamped to the signal length and `overlap` to
+/// `segment_len - 1`, so short recordings degrade to a single periodogram
+/// instead of failing.
 pub fn compute_welch_psd(
     signal: &[f64],
     sample_rate: f64,
     segment_len: usize,
     overlap: usize, // typically segment_len / 2
     window: WindowType,
+    detrend_kind: Detrend,
+    scaling: Scaling,
 ) -> SpectrumResult {
+    let n = signal.len();
+    if n == 0 || sample_rate <= 0.0 {
+        return SpectrumResult::empty();
+    }
+    let segment_len = segment_len.clamp(1, n);
+    let overlap = overlap.min(segment_len - 1);
     let hop = segment_len - overlap;
-    let mut planner = RealFftPlanner::<f64>::new();
-    let fft = planner.plan_fft_forward(segment_len);
+    let n_segments = (n - segment_len) / hop + 1;
 
-    let n_segments = (signal.len() - overlap) / hop;
-    let n_bins = segment_len / 2 + 1;
-    let mut psd = vec![0.0f64; n_bins];
+    let w = window_coefficients(segment_len, window);
+    let scale = match scaling {
+        Scaling::Density => 1.0 / (sample_rate * w.iter().map(|x| x * x).sum::<f64>()),
+        Scaling::Spectrum => 1.0 / w.iter().sum::<f64>().powi(2),
+    };
 
+    let mut psd = vec![0.0f64; segment_len / 2 + 1];
     for i in 0..n_segments {
         let start = i * hop;
-        let segment = &signal[start..start + segment_len];
-        let windowed = apply_window(segment, window);
+        let segment = detrend(&signal[start..start + segment_len], detrend_kind);
+        let mut input: Vec<f64> = segment.iter().zip(&w).map(|(x, w)| x * w).collect();
+        for (acc, p) in psd.iter_mut().zip(power_spectrum(&mut input)) {
+            *acc += p;
+        }
+    }
 
-        let mut input = windowed;
-        let mut output = fft.make_output_vec();
-        fft.process(&mut input, &mut output).unwrap();
+    psd.iter_mut().for_each(|v| *v *= scale / n_segments as f64);
+    one_sided(&mut psd, segment_len, 2.0);
 
-        for (j, c) in output.iter().enumerate() {
-            psd[j] += (c.re * c.re + c.im * c.im) / (segment_len as f64 * sample_rate);
-        }
+    SpectrumResult {
+        frequencies: bin_frequencies(psd.len(), segment_len, sample_rate),
+        magnitudes: psd,
     }
+}
 
-    // Average across segments
-    psd.iter_mut().for_each(|v| *v /= n_segments as f64);
+/// Thomson multitaper estimate over the whole signal.
+///
+/// With `Scaling::Spectrum` the density is multiplied by the full analysis
+/// bandwidth `2W = 2·nw·fs/N`; a sinusoid spreads evenly over that band, so
+/// the plateau height approximates its power A²/2.
+pub fn compute_multitaper_psd(
+    signal: &[f64],
+    sample_rate: f64,
+    nw: f64,
+    tapers: usize,
+    detrend_kind: Detrend,
+    scaling: Scaling,
+) -> SpectrumResult {
+    let n = signal.len();
+    if n == 0 || sample_rate <= 0.0 {
+        return SpectrumResult::empty();
+    }
+    let tapers = dpss(n, nw, tapers.max(1));
+    let x = detrend(signal, detrend_kind);
 
-    let freq_resolution = sample_rate / segment_len as f64;
-    let frequencies: Vec<f64> = (0..n_bins).map(|i| i as f64 * freq_resolution).collect();
+    let mut psd = vec![0.0f64; n / 2 + 1];
+    for taper in &tapers {
+        let mut input: Vec<f64> = x.iter().zip(taper).map(|(x, v)| x * v).collect();
+        for (acc, p) in psd.iter_mut().zip(power_spectrum(&mut input)) {
+            *acc += p;
+        }
+    }
+
+    // Tapers have unit energy, so |X|² / fs is already a density.
+    let mut scale = 1.0 / (sample_rate * tapers.len() as f64);
+    if scaling == Scaling::Spectrum {
+        scale *= 2.0 * nw * sample_rate / n as f64;
+    }
+    psd.iter_mut().for_each(|v| *v *= scale);
+    one_sided(&mut psd, n, 2.0);
 
     SpectrumResult {
-        frequencies,
+        frequencies: bin_frequencies(psd.len(), n, sample_rate),
         magnitudes: psd,
     }
 }
+
+/// Fold negative frequencies into the positive half: every bin except DC
+/// (and Nyquist, for even `n`) is multiplied by `factor`.
+fn one_sided(bins: &mut [f64], n: usize, factor: f64) {
+    let last = if n % 2 == 0 {
+        bins.len() - 1
+    } else {
+        bins.len()
+    };
+    for v in bins.iter_mut().take(last).skip(1) {
+        *v *= factor;
+    }
+}
+
+fn bin_frequencies(n_bins: usize, n: usize, sample_rate: f64) -> Vec<f64> {
+    let resolution = sample_rate / n as f64;
+    (0..n_bins).map(|i| i as f64 * resolution).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    const FS: f64 = 100.0;
+
+    /// Amplitude-2 sine on bin 32 of a 256-point segment (12.5 Hz at 100 Hz).
+    fn sine(n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| 2.0 * (TAU * 12.5 * i as f64 / FS).sin())
+            .collect()
+    }
+
+    /// Unit-variance Gaussian noise (Box-Muller over a fixed LCG).
+    fn white_noise(n: usize) -> Vec<f64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut uniform = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        };
+        (0..n)
+            .map(|_| (-2.0 * uniform().ln()).sqrt() * (TAU * uniform()).cos())
+            .collect()
+    }
+
+    fn peak(result: &SpectrumResult) -> (f64, f64) {
+        let i = (0..result.magnitudes.len())
+            .max_by(|&a, &b| result.magnitudes[a].total_cmp(&result.magnitudes[b]))
+            .unwrap();
+        (result.frequencies[i], result.magnitudes[i])
+    }
+
+    fn welch(signal: &[f64], scaling: Scaling) -> SpectrumResult {
+        let hann = WindowType::Hann;
+        compute_welch_psd(signal, FS, 256, 128, hann, Detrend::Constant, scaling)
+    }
+
+    #[test]
+    fn sine_peak_under_both_scalings() {
+        let x = sine(4096);
+
+        let spectrum = welch(&x, Scaling::Spectrum);
+        let (f, p) = peak(&spectrum);
+        assert_eq!(f, 12.5);
+        assert!((p - 2.0).abs() < 1e-4, "A²/2 = 2, got {p}");
+
+        // The density integrates to the sine's power, A²/2.
+        let density = welch(&x, Scaling::Density);
+        let (f, _) = peak(&density);
+        assert_eq!(f, 12.5);
+        let df = density.frequencies[1];
+        let power: f64 = density.magnitudes.iter().sum::<f64>() * df;
+        assert!((power - 2.0).abs() < 0.01, "{power}");
+
+        let multitaper =
+            compute_multitaper_psd(&x, FS, 4.0, 7, Detrend::Constant, Scaling::Density);
+        let (f, _) = peak(&multitaper);
+        assert!((f - 12.5).abs() <= multitaper.frequencies[1]);
+        let power: f64 = multitaper.magnitudes.iter().sum::<f64>() * multitaper.frequencies[1];
+        assert!((power - 2.0).abs() < 0.02, "{power}");
+
+        let amplitude = compute_fft_spectrum(&x[..256], FS, WindowType::Hann);
+        let (f, a) = peak(&amplitude);
+        assert_eq!(f, 12.5);
+        assert!((a - 2.0).abs() < 1e-4, "{a}");
+    }
+
+    #[test]
+    fn white_noise_density_level() {
+        let x = white_noise(1 << 16);
+        let psd = welch(&x, Scaling::Density);
+        let last = psd.magnitudes.len() - 1;
+        // One-sided: 2σ²/fs everywhere but DC and Nyquist, which keep σ²/fs.
+        let level: f64 = psd.magnitudes[1..last].iter().sum::<f64>() / (last - 1) as f64;
+        assert!((level - 2.0 / FS).abs() < 0.002, "{level}");
+        let nyquist = psd.magnitudes[last];
+        assert!((nyquist - 1.0 / FS).abs() < 0.002, "{nyquist}");
+
+        let multitaper =
+            compute_multitaper_psd(&x[..4096], FS, 4.0, 7, Detrend::Constant, Scaling::Density);
+        let last = multitaper.magnitudes.len() - 1;
+        let level: f64 = multitaper.magnitudes[1..last].iter().sum::<f64>() / (last - 1) as f64;
+        assert!((level - 2.0 / FS).abs() < 0.002, "{level}");
+    }
+
+    #[test]
+    fn signal_shorter_than_overlap() {
+        let x = sine(10);
+        let psd = welch(&x, Scaling::Density);
+        assert_eq!(psd.magnitudes.len(), 6);
+        assert_eq!(psd.frequencies[1], 10.0);
+        assert!(psd.magnitudes.iter().all(|v| v.is_finite()));
+    }
+}
@@ -1,26 +1,42 @@
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum WindowType {
+    Rectangular,
     Hann,
     Hamming,
     Blackman,
 }
 
-pub fn apply_window(data: &[f64], window: WindowType) -> Vec<f64> {
-    let n = data.len() as f64;
-    data.iter()
-        .enumerate()
-        .map(|(i, &x)| {
-            let w = match window {
-                WindowType::Hann => 0.5 * (1.0 - (2.0 * PI * i as f64 / (n - 1.0)).cos()),
-                WindowType::Hamming => 0.54 - 0.46 * (2.0 * PI * i as f64 / (n - 1.0)).cos(),
-                WindowType::Blackman => {
-                    0.42 - 0.5 * (2.0 * PI * i as f64 / (n - 1.0)).cos()
-                        + 0.08 * (4.0 * PI * i as f64 / (n - 1.0)).cos()
-                }
-            };
-            x * w
+impl WindowType {
+    /// Parse the lowercase names the frontend sends. Unknown names fall back to Hann.
+    pub fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "rectangular" | "boxcar" | "none" => WindowType::Rectangular,
+            "hamming" => WindowType::Hamming,
+            "blackman" => WindowType::Blackman,
+            _ => WindowType::Hann,
+        }
+    }
+}
+
+/// Symmetric window coefficients of length `n`.
+pub fn window_coefficients(n: usize, window: WindowType) -> Vec<f64> {
+    if n <= 1 {
+        return vec![1.0; n];
+    }
+    let denom = (n - 1) as f64;
+    (0..n)
+        .map(|i| {
+            let x = 2.0 * PI * i as f64 / denom;
+            match window {
+                WindowType::Rectangular => 1.0,
+                WindowType::Hann => 0.5 * (1.0 - x.cos()),
+                WindowType::Hamming => 0.54 - 0.46 * x.cos(),
+                WindowType::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+            }
         })
         .collect()
 }
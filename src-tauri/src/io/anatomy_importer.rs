@@ -7,11 +7,8 @@ use nalgebra as na;
 use ndarray16::Array4;
 use neuroformats::{write_mgh, FsMgh, FsMghData};
 use std::collections::HashMap;
-use serde::Serialize;
 use std::collections::VecDeque;
-use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 // User interaction:
 
@@ -19,43 +16,20 @@ use std::process::Command;
 // ----> and a .snirf file
 // 2. FreeSurfer segmentation runs
 // 3. The resulting meshes are loaded into NIRWizard
-// 4. The user manually aligns the fNIRS probe
+// 4. The probe is registered to the scalp from its landmarks
+//    (registration_service); the manual transform is left for fine-tuning
 // 5. The user presses "Calculate Sensitivity"
 // 6. The sensitivity profile is calculated and visualized
 // 7. Projection mode becomes available letting the user
-// set a timepoint in the DataPlotter chart.
+// set a timepoint in the DataPlotter chart; the image at that timepoint
+// is reconstructed (reconstruction_service) and shown on the cortex.
 //
 
 // Practical NIRWizard :
 // Step 1: Menubar Anatomy-> Open MRI -> File Dialog ->
-// step 2: Calls load_mri(path: &String)
-
-//
-
-//
-#[derive(Serialize, Debug)]
-pub struct Config {
-    /// Absolute path to the input T1 NIfTI file.
-    mri_path: PathBuf,
-
-    /// Absolute path to the output root folder.
-    output_folder: PathBuf,
-
-    /// Subject ID — used as the sub-folder name inside output_folder.
-    subject_id: String,
-
-    /// Folder that contains license.txt (FreeSurfer license).
-    license_folder: PathBuf,
-
-    /// Folder containing the Python helper scripts.
-    scripts_folder: PathBuf,
-}
-
-impl Config {
-    fn subject_dir(&self) -> PathBuf {
-        self.output_folder.join(&self.subject_id)
-    }
-}
+// step 2: start_anatomy_job runs the pipeline in the background
+//         (anatomy_pipeline_service): verify, segment, voxelise, meshify
+//         and load, the file steps of which live here.
 
 // ------------------------------------------------------------------
 // Tissue label values
@@ -75,30 +49,6 @@ const GM_LABELS: &[i32] = &[
 ];
 const CSF_LABELS: &[i32] = &[4, 5, 14, 15, 24, 31, 43, 44, 63, 72];
 
-// ------------------------------------------------------------------
-// Generic helpers
-// ------------------------------------------------------------------
-/// Convert a Windows path to forward-slash form for Docker volume mounts.
-fn docker_path(p: &Path) -> String {
-    p.to_string_lossy().replace('\\', "/")
-}
-
-/// Run a subprocess with inherited stdio.  Streams output live to the terminal.
-fn run(label: &str, cmd: &mut Command) -> Result<(), String> {
-    println!("  [RUN] {label}");
-    let status = cmd
-        .status()
-        .map_err(|e| format!("[{label}] failed to launch process: {e}"))?;
-    if status.success() {
-        Ok(())
-    } else {
-        Err(format!(
-            "[{label}] exited with code {:?}",
-            status.code().unwrap_or(-1)
-        ))
-    }
-}
-
 // ------------------------------------------------------------------
 // MGH data extraction helpers
 // ------------------------------------------------------------------
@@ -200,150 +150,30 @@ fn compute_head_mask(t1: &Array4<f32>, threshold: f32) -> Vec<bool> {
     is_bg.into_iter().map(|b| !b).collect()
 }
 
-// ------------------------------------------------------------------
-// Step 1 — verify_setup
-// ------------------------------------------------------------------
-fn verify_setup(cfg: &Config) -> Result<(), String> {
-    println!("\n=== Step 1: Verify Setup ===");
-
-    // Docker daemon running?  (`docker info` connects to the daemon;
-    // `docker --version` only reads the local binary and always succeeds.)
-    run(
-        "docker info (daemon check)",
-        Command::new("docker").arg("info"),
-    )
-    .map_err(|e| format!("{e}\n  → Is Docker Desktop running? Start it and retry."))?;
-
-    // GPU accessible inside Docker?
-    run(
-        "nvidia-smi in Docker",
-        Command::new("docker").args([
-            "run",
-            "--gpus",
-            "all",
-            "--rm",
-            "nvidia/cuda:12.0.0-base-ubuntu22.04",
-            "nvidia-smi",
-        ]),
-    )
-    .map_err(|e| format!("{e}\n  → Enable GPU support in Docker Desktop."))?;
-
-    // FastSurfer image present locally?
-    run(
-        "inspect deepmi/fastsurfer:latest",
-        Command::new("docker").args(["image", "inspect", "deepmi/fastsurfer:latest"]),
-    )?;
-
-    // FreeSurfer license file present?
-    let license = cfg.license_folder.join("license.txt");
-    if !license.is_file() {
-        return Err(format!(
-            "FreeSurfer license not found at: {}",
-            license.display()
-        ));
-    }
-    println!("  [OK] license.txt found");
-
-    // Input MRI file present?
-    if !cfg.mri_path.is_file() {
-        return Err(format!("Input MRI not found: {}", cfg.mri_path.display()));
-    }
-    println!("  [OK] Input MRI found");
 
-    println!("  All checks passed.");
-    Ok(())
-}
-
-// ------------------------------------------------------------------
-// Step 2 — run_fastsurfer
-// ------------------------------------------------------------------
-
-fn run_fastsurfer(cfg: &Config) -> Result<(), String> {
-    println!("\n=== Step 2: Run FastSurfer ===");
-
-    // Idempotent — skip if aseg.mgz already exists.
-    if cfg.subject_dir().join("mri").join("aseg.mgz").is_file() {
-        println!("  aseg.mgz already exists — skipping FastSurfer.");
-        return Ok(());
-    }
-
-    let input_folder = cfg.mri_path.parent().ok_or("mri_path has no parent")?;
-    let filename = cfg
-        .mri_path
-        .file_name()
-        .ok_or("mri_path has no filename")?
-        .to_string_lossy()
-        .to_string();
-
-    fs::create_dir_all(&cfg.output_folder)
-        .map_err(|e| format!("Cannot create output folder: {e}"))?;
-
-    run(
-        "docker run deepmi/fastsurfer",
-        Command::new("docker").args([
-            "run",
-            "--gpus",
-            "all",
-            "-v",
-            &format!("{}:/data", docker_path(input_folder)),
-            "-v",
-            &format!("{}:/output", docker_path(&cfg.output_folder)),
-            "-v",
-            &format!("{}:/fs_license", docker_path(&cfg.license_folder)),
-            "--rm",
-            "--user",
-            "root",
-            "deepmi/fastsurfer:latest",
-            "--fs_license",
-            "/fs_license/license.txt",
-            "--t1",
-            &format!("/data/{filename}"),
-            "--sid",
-            &cfg.subject_id,
-            "--sd",
-            "/output",
-            "--3T",
-            "--threads",
-            "max",
-            "--allow_root",
-        ]),
-    )?;
-
-    println!("  FastSurfer complete.");
-    Ok(())
-}
-
-// ------------------------------------------------------------------
-// Step 3 — Build Labeled Voxel Representation
-// ------------------------------------------------------------------
 // ------------------------------------------------------------------
-// Step 4 — Build .Obj Meshes for Visualization.
+// Voxelise — Build Labeled Voxel Representation
 // ------------------------------------------------------------------
 
 //
 // Reads aseg.mgz, T1.mgz, brainmask.mgz directly — no Python or Docker needed.
 // Writes head_labels.mgz with the same grid geometry as the input volumes.
-fn run_voxelization(cfg: &Config) -> Result<(), String> {
-    println!("\n=== Step 3: Build Labeled Voxel Volume (native Rust) ===");
-
-    let out_path = cfg.subject_dir().join("mri").join("head_labels.mgz");
-    if out_path.is_file() {
-        println!("  head_labels.mgz already exists — skipping.");
-        return Ok(());
-    }
-
-    let aseg_path = cfg.subject_dir().join("mri").join("aseg.mgz");
-    let t1_path = cfg.subject_dir().join("mri").join("T1.mgz");
-    let bm_path = cfg.subject_dir().join("mri").join("brainmask.mgz");
-
-    println!("  Loading aseg.mgz ...");
+// Progress goes to `log`, a line at a time.
+pub fn build_head_labels(subject_dir: &Path, log: &dyn Fn(&str)) -> Result<(), String> {
+    let mri_dir = subject_dir.join("mri");
+    let out_path = mri_dir.join("head_labels.mgz");
+    let aseg_path = mri_dir.join("aseg.mgz");
+    let t1_path = mri_dir.join("T1.mgz");
+    let bm_path = mri_dir.join("brainmask.mgz");
+
+    log("Loading aseg.mgz ...");
     let aseg_mgh =
         FsMgh::from_file(&aseg_path).map_err(|e| format!("Cannot read aseg.mgz: {e}"))?;
 
-    println!("  Loading T1.mgz ...");
+    log("Loading T1.mgz ...");
     let t1_mgh = FsMgh::from_file(&t1_path).map_err(|e| format!("Cannot read T1.mgz: {e}"))?;
 
-    println!("  Loading brainmask.mgz ...");
+    log("Loading brainmask.mgz ...");
     let bm_mgh =
         FsMgh::from_file(&bm_path).map_err(|e| format!("Cannot read brainmask.mgz: {e}"))?;
 
@@ -352,14 +182,14 @@ fn run_voxelization(cfg: &Config) -> Result<(), String> {
     let bm_data = mgh_as_i32(&bm_mgh)?;
 
     let (nx, ny, nz, _) = aseg_data.dim();
-    println!("  Volume: {nx} × {ny} × {nz}");
+    log(&format!("Volume: {nx} × {ny} × {nz}"));
 
     // Head mask: flood-fill background from border, head = everything else.
-    println!("  Computing head mask (threshold = 15) ...");
+    log("Computing head mask (threshold = 15) ...");
     let head_mask = compute_head_mask(&t1_data, 15.0);
 
     // Build output label volume.
-    println!("  Mapping tissue labels ...");
+    log("Mapping tissue labels ...");
     let mut labels = Array4::<u8>::zeros((nx, ny, nz, 1));
 
     let flat_idx = |x: usize, y: usize, z: usize| x * ny * nz + y * nz + z;
@@ -398,12 +228,12 @@ fn run_voxelization(cfg: &Config) -> Result<(), String> {
         .iter()
         .enumerate()
     {
-        println!(
-            "  {:15}: {:>10} voxels  ({:.1}%)",
+        log(&format!(
+            "{:15}: {:>10} voxels  ({:.1}%)",
             name,
             counts[i],
             100.0 * counts[i] as f64 / total
-        );
+        ));
     }
 
     // Write output MGZ.  Reuse aseg header for geometry (same voxel grid),
@@ -422,84 +252,48 @@ fn run_voxelization(cfg: &Config) -> Result<(), String> {
         },
     };
 
-    println!("  Writing: {}", out_path.display());
+    log(&format!("Writing: {}", out_path.display()));
     write_mgh(&out_path, &out_mgh).map_err(|e| format!("Cannot write head_labels.mgz: {e}"))?;
 
-    println!("  [OK] head_labels.mgz written.");
+    log("[OK] head_labels.mgz written.");
     Ok(())
 }
 
-pub fn load_subject_anatomy(mri_path: &str) -> Result<SubjectAnatomy, String> {
-    let mri_path = PathBuf::from(mri_path);
-
-    // mri_path is at data_root/input/<file>.nii.gz
-    // so data_root is two levels up (parent of the input folder)
-    let data_root = mri_path
-        .parent() // .../input
-        .and_then(|p| p.parent()) // .../NIRWizard_DATA
-        .ok_or("mri_path must be inside an 'input' subfolder (e.g. data_root/input/file.nii.gz)")?
-        .to_path_buf();
-
-    // Derive subject_id from BIDS filename:
-    //   sub-116_ses-BL_T1w.nii.gz  →  sub-116
-    // Take only the first _-delimited BIDS entity (the sub- part).
-    let subject_id = {
-        let fname = mri_path
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("subject");
-        let stem = fname
-            .strip_suffix(".nii.gz")
-            .or_else(|| fname.strip_suffix(".nii"))
-            .unwrap_or(fname);
-        stem.split('_').next().unwrap_or(stem).to_string()
-    };
 
-    let cfg = Config {
-        subject_id,
-        mri_path: mri_path.clone(),
-        output_folder: data_root.join("output"),
-        license_folder: data_root.join("license"),
-        scripts_folder: data_root.join("scripts"),
-    };
-
-    println!("[anatomy] subject_id  : {}", cfg.subject_id);
-    println!("[anatomy] subject_dir  : {}", cfg.subject_dir().display());
-    println!("[anatomy] mri_path     : {}", cfg.mri_path.display());
-    println!("[anatomy] output_folder: {}", cfg.output_folder.display());
-
-    verify_setup(&cfg)?;
-    run_fastsurfer(&cfg)?;
-    // run_voxelization and meshify are future steps — skipped for now
+// ------------------------------------------------------------------
+// Load — the tissue meshes back from surf/
+// ------------------------------------------------------------------
 
-    // We have 5 files we are expecting in the subject dir / surf /
-    // "csf.obj", "grey_matter.obj", "white_matter.obj", "skull.obj", "scalp.obj"
-    // Load resulting OBJ files (each optional if not yet produced)
-    let surf_dir = cfg.subject_dir().join("surf");
+// The meshing step writes "skull.obj", "csf.obj", "grey_matter.obj" and
+// "white_matter.obj" to the subject dir / surf /; each is optional, a
+// mesh that is missing or fails to read is left out and reported to `log`.
+pub fn load_subject_anatomy(subject_dir: &Path, log: &dyn Fn(&str)) -> SubjectAnatomy {
+    let surf_dir = subject_dir.join("surf");
     let load_opt = |name: &str| -> Option<SceneObject> {
         let path = surf_dir.join(format!("{name}.obj"));
         if !path.is_file() {
+            log(&format!("{name}.obj not found — skipping."));
             return None;
         }
         let path_str = path.to_string_lossy().to_string();
         match crate::io::mesh_importer::load_mesh(&path_str) {
             Ok(mesh) => Some(SceneObject::new(name, mesh)),
             Err(e) => {
-                eprintln!("[anatomy] failed to load {name}.obj: {e}");
+                log(&format!("failed to load {name}.obj: {e}"));
                 None
             }
         }
     };
 
-    let labels_mgz = cfg.subject_dir().join("mri").join("head_labels.mgz");
+    let labels_mgz = subject_dir.join("mri").join("head_labels.mgz");
 
-    Ok(SubjectAnatomy {
+    SubjectAnatomy {
         skull: load_opt("skull"),
         csf: load_opt("csf"),
         grey_matter: load_opt("grey_matter"),
         white_matter: load_opt("white_matter"),
         labels_mgz_path: if labels_mgz.is_file() { Some(labels_mgz) } else { None },
-    })
+    }
 }
 
 // ------------------------------------------------------------------
@@ -507,7 +301,7 @@ pub fn load_subject_anatomy(mri_path: &str) -> Result<SubjectAnatomy, String> {
 // ------------------------------------------------------------------
 
 /// Build the vox2ras (4×4) from an MGH header using the FreeSurfer convention.
-fn mgh_vox2ras(header: &neuroformats::FsMghHeader, nx: usize, ny: usize, nz: usize) -> na::Matrix4<f64> {
+pub(crate) fn mgh_vox2ras(header: &neuroformats::FsMghHeader, nx: usize, ny: usize, nz: usize) -> na::Matrix4<f64> {
     // mdc_raw: [xras(3), yras(3), zras(3)] — each triple is a direction cosine vector
     let r = &header.mdc_raw;
     let xras = na::Vector3::new(r[0] as f64, r[1] as f64, r[2] as f64);
@@ -0,0 +1,101 @@
+use crate::domain::atlas::Atlas;
+use crate::domain::error::NWError;
+use crate::io::anatomy_importer::mgh_vox2ras;
+use nalgebra as na;
+use ndarray16::Array4;
+use neuroformats::FsMgh;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Parse a label lookup table: one region per line, `#` comments. Both
+/// FreeSurfer's `<id> <name> <r> <g> <b> <a>` and the AAL
+/// `<code> <name> <id>` layouts are read.
+pub fn parse_lookup_table(text: &str) -> HashMap<u32, String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (first, name) = (fields.first()?, fields.get(1)?);
+            let id = first.parse().ok().or_else(|| fields.last()?.parse().ok())?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// `aparc+aseg` for `…/aparc+aseg.mgz`.
+fn atlas_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    name.trim_end_matches(".mgz")
+        .trim_end_matches(".mgh")
+        .to_string()
+}
+
+/// The lookup table for the atlas at `path`: `<stem>.txt` beside it, else
+/// `FreeSurferColorLUT.txt` beside it or in `$FREESURFER_HOME`.
+fn find_lookup_table(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut candidates = vec![
+        dir.join(format!("{}.txt", atlas_stem(path))),
+        dir.join("FreeSurferColorLUT.txt"),
+    ];
+    if let Some(home) = std::env::var_os("FREESURFER_HOME") {
+        candidates.push(PathBuf::from(home).join("FreeSurferColorLUT.txt"));
+    }
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+fn flatten<T: Copy>(arr: &Array4<T>, to_label: impl Fn(T) -> u32) -> Vec<u32> {
+    let (nx, ny, nz, _) = arr.dim();
+    // labels[x + y*nx + z*nx*ny]
+    let mut labels = Vec::with_capacity(nx * ny * nz);
+    for k in 0..nz {
+        for j in 0..ny {
+            for i in 0..nx {
+                labels.push(to_label(arr[[i, j, k, 0]]));
+            }
+        }
+    }
+    labels
+}
+
+/// Load a labelled atlas from an MGH/MGZ volume (`aparc+aseg.mgz`, or an
+/// AAL/Brodmann volume converted with `mri_convert`). Region names come
+/// from the lookup table found beside it (see [`find_lookup_table`]).
+pub fn load_atlas(path: &str) -> Result<Atlas, NWError> {
+    let file = Path::new(path);
+    let mgh = FsMgh::from_file(file).map_err(|e| NWError::Parse(format!("{path}: {e}")))?;
+    let data = &mgh.data;
+    let (labels, (nx, ny, nz, _)) = if let Some(arr) = &data.mri_uchar {
+        (flatten(arr, u32::from), arr.dim())
+    } else if let Some(arr) = &data.mri_int {
+        (flatten(arr, |v| v.max(0) as u32), arr.dim())
+    } else if let Some(arr) = &data.mri_short {
+        (flatten(arr, |v| v.max(0) as u32), arr.dim())
+    } else if let Some(arr) = &data.mri_float {
+        (flatten(arr, |v| v.round().max(0.0) as u32), arr.dim())
+    } else {
+        return Err(NWError::Parse(format!("{path}: the volume holds no data")));
+    };
+
+    let vox2ras = if mgh.header.is_ras_good == 1 {
+        mgh_vox2ras(&mgh.header, nx, ny, nz)
+    } else {
+        na::Matrix4::identity()
+    };
+
+    let label_names = match find_lookup_table(file) {
+        Some(lut) => parse_lookup_table(&std::fs::read_to_string(lut)?),
+        None => HashMap::new(),
+    };
+
+    Ok(Atlas {
+        name: atlas_stem(file),
+        dims: [nx, ny, nz],
+        vox2ras,
+        labels,
+        label_names,
+    })
+}
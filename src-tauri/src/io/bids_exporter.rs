@@ -0,0 +1,322 @@
+use crate::domain::annotation::Annotations;
+use crate::domain::dataset::BidsEntities;
+use crate::domain::error::NWError;
+use crate::domain::snirf::{Measurement, MetadataTag, NirsEntry};
+use crate::domain::timebase::TimeBase;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+/// SNIRF `dataType` for processed data.
+const PROCESSED: i32 = 99999;
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn tag<'a>(metadata: &'a [MetadataTag], name: &str) -> Option<&'a str> {
+    metadata
+        .iter()
+        .find(|t| t.name == name)
+        .map(|t| t.value.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// BIDS writes missing values as `n/a`.
+fn or_na(value: Option<&str>) -> Value {
+    Value::String(value.unwrap_or("n/a").to_string())
+}
+
+/// Tab-separated table with a header row. Tabs and newlines inside a cell
+/// would break the format, so they are replaced by spaces.
+fn write_tsv(path: &Path, header: &[&str], rows: &[Vec<String>]) -> Result<(), NWError> {
+    let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    let mut text = header.join("\t");
+    text.push('\n');
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|c| clean(c)).collect();
+        text.push_str(&cells.join("\t"));
+        text.push('\n');
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<(), NWError> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| NWError::Internal(e.to_string()))?;
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// The entry's `LengthUnit`, as BIDS spells it.
+fn length_unit(metadata: &[MetadataTag]) -> &str {
+    match tag(metadata, "LengthUnit") {
+        Some("mm") | None => "mm",
+        Some("cm") => "cm",
+        Some("m") => "m",
+        Some(_) => "n/a",
+    }
+}
+
+fn wavelength(m: &Measurement, wavelengths: &[f64]) -> Option<f64> {
+    m.wavelength_actual.or_else(|| {
+        m.wavelength_index
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| wavelengths.get(i))
+            .copied()
+    })
+}
+
+/// BIDS channel `type` of a measurement.
+fn channel_type(m: &Measurement) -> &'static str {
+    if m.data_type != PROCESSED {
+        return match m.data_type {
+            1 => "NIRSCWAMPLITUDE",
+            _ => "MISC",
+        };
+    }
+    match m.data_type_label.trim() {
+        "HbO" => "NIRSCWHBO",
+        "HbR" => "NIRSCWHBR",
+        "dOD" => "NIRSCWOPTICALDENSITY",
+        _ => "MISC",
+    }
+}
+
+// =============================================================================
+// Public entry point
+// =============================================================================
+
+/// Write the BIDS-NIRS sidecars describing the first data block of `entry`
+/// into `dir`: `_nirs.json`, `_channels.tsv`, `_events.tsv` named after
+/// `prefix` (e.g. `sub-01_task-rest_run-1`), and `_optodes.tsv`,
+/// `_coordsystem.json` named after its subject/session part. Channels listed
+/// in `annotations.bad_channels` get status `bad`. Returns the files written.
+pub fn export_bids_sidecars(
+    entry: &NirsEntry,
+    annotations: &Annotations,
+    dir: &str,
+    prefix: &str,
+) -> Result<Vec<String>, NWError> {
+    if entry.data_blocks.is_empty() {
+        return Err(NWError::BlockOutOfRange(0));
+    }
+    let dir = Path::new(dir);
+    std::fs::create_dir_all(dir)?;
+
+    let entities = BidsEntities::from_filename(prefix);
+    let session_prefix = entities
+        .session_prefix()
+        .unwrap_or_else(|| prefix.to_string());
+    let mut written = Vec::new();
+    let mut out = |name: String| {
+        let path = dir.join(name);
+        written.push(path.to_string_lossy().into_owned());
+        path
+    };
+
+    write_json(
+        &out(format!("{prefix}_nirs.json")),
+        &nirs_json(entry, &entities),
+    )?;
+    write_channels(&out(format!("{prefix}_channels.tsv")), entry, annotations)?;
+    write_events(&out(format!("{prefix}_events.tsv")), entry)?;
+    write_optodes(&out(format!("{session_prefix}_optodes.tsv")), entry)?;
+    write_json(
+        &out(format!("{session_prefix}_coordsystem.json")),
+        &coordsystem_json(entry),
+    )?;
+    Ok(written)
+}
+
+// =============================================================================
+// Sidecars
+// =============================================================================
+
+fn nirs_json(entry: &NirsEntry, entities: &BidsEntities) -> Value {
+    let block = &entry.data_blocks[0];
+    let time_base = TimeBase::analyze(&block.time);
+    let duration = match (block.time.first(), block.time.last()) {
+        (Some(a), Some(b)) => b - a,
+        _ => 0.0,
+    };
+    let md = &entry.metadata;
+
+    let mut map = Map::new();
+    map.insert(
+        "TaskName".into(),
+        or_na(entities.task.as_deref().or(tag(md, "TaskName"))),
+    );
+    // Rounded so float noise in the time vector does not show up as 9.9999…
+    let rate = (time_base.sampling_rate * 1e6).round() / 1e6;
+    map.insert("SamplingFrequency".into(), json!(rate));
+    map.insert("NIRSChannelCount".into(), json!(block.measurements.len()));
+    map.insert(
+        "NIRSSourceOptodeCount".into(),
+        json!(entry.probe.sources.len()),
+    );
+    map.insert(
+        "NIRSDetectorOptodeCount".into(),
+        json!(entry.probe.detectors.len()),
+    );
+    map.insert("RecordingDuration".into(), json!(duration));
+    map.insert("Manufacturer".into(), or_na(tag(md, "ManufacturerName")));
+    map.insert("ManufacturersModelName".into(), or_na(tag(md, "Model")));
+    map.insert(
+        "PowerLineFrequency".into(),
+        tag(md, "PowerLineFrequency")
+            .and_then(|v| v.parse::<f64>().ok())
+            .map_or_else(|| or_na(None), |f| json!(f)),
+    );
+    map.insert("SoftwareFilters".into(), or_na(None));
+    Value::Object(map)
+}
+
+fn write_channels(
+    path: &Path,
+    entry: &NirsEntry,
+    annotations: &Annotations,
+) -> Result<(), NWError> {
+    let block = &entry.data_blocks[0];
+    let rows: Vec<Vec<String>> = block
+        .measurements
+        .iter()
+        .map(|m| {
+            let wl = wavelength(m, &entry.probe.wavelengths);
+            let name = match (wl, channel_type(m)) {
+                (Some(wl), _) => format!("S{}_D{} {wl}", m.source_index, m.detector_index),
+                (None, "NIRSCWHBO") => format!("S{}_D{} hbo", m.source_index, m.detector_index),
+                (None, "NIRSCWHBR") => format!("S{}_D{} hbr", m.source_index, m.detector_index),
+                (None, _) => format!("S{}_D{}", m.source_index, m.detector_index),
+            };
+            let bad = annotations.is_bad_channel(m.source_index, m.detector_index);
+            vec![
+                name,
+                channel_type(m).to_string(),
+                format!("S{}", m.source_index),
+                format!("D{}", m.detector_index),
+                wl.map_or_else(|| "n/a".to_string(), |w| w.to_string()),
+                m.data_unit
+                    .clone()
+                    .filter(|u| !u.is_empty())
+                    .unwrap_or_else(|| "n/a".to_string()),
+                if bad { "bad" } else { "good" }.to_string(),
+            ]
+        })
+        .collect();
+    write_tsv(
+        path,
+        &[
+            "name",
+            "type",
+            "source",
+            "detector",
+            "wavelength_nominal",
+            "units",
+            "status",
+        ],
+        &rows,
+    )
+}
+
+/// Optode positions; `n/a` for a probe with only a 2D layout, which the
+/// importers give a flat z = 0 in 3D.
+fn write_optodes(path: &Path, entry: &NirsEntry) -> Result<(), NWError> {
+    let probe = &entry.probe;
+    let has_3d = probe
+        .sources
+        .iter()
+        .chain(&probe.detectors)
+        .any(|o| o.pos_3d.z != 0.0);
+    let coordinate = |v: f64| {
+        if has_3d {
+            v.to_string()
+        } else {
+            "n/a".to_string()
+        }
+    };
+    let rows: Vec<Vec<String>> = probe
+        .sources
+        .iter()
+        .enumerate()
+        .map(|(i, o)| ("source", format!("S{}", i + 1), o))
+        .chain(
+            probe
+                .detectors
+                .iter()
+                .enumerate()
+                .map(|(i, o)| ("detector", format!("D{}", i + 1), o)),
+        )
+        .map(|(kind, name, o)| {
+            vec![
+                name,
+                kind.to_string(),
+                coordinate(o.pos_3d.x),
+                coordinate(o.pos_3d.y),
+                coordinate(o.pos_3d.z),
+            ]
+        })
+        .collect();
+    write_tsv(path, &["name", "type", "x", "y", "z"], &rows)
+}
+
+fn coordsystem_json(entry: &NirsEntry) -> Value {
+    let probe = &entry.probe;
+    let system = probe
+        .coordinate_system
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("Other");
+    let unit = length_unit(&entry.metadata);
+
+    let mut map = Map::new();
+    map.insert("NIRSCoordinateSystem".into(), json!(system));
+    map.insert("NIRSCoordinateUnits".into(), json!(unit));
+    if system == "Other" {
+        map.insert(
+            "NIRSCoordinateSystemDescription".into(),
+            or_na(probe.coordinate_system_description.as_deref()),
+        );
+    }
+
+    let landmarks: Map<String, Value> = probe
+        .landmarks
+        .iter()
+        .flatten()
+        .filter_map(|l| Some((l.label.clone(), json!(l.pos_3d?))))
+        .collect();
+    if !landmarks.is_empty() {
+        map.insert(
+            "AnatomicalLandmarkCoordinates".into(),
+            Value::Object(landmarks),
+        );
+        map.insert("AnatomicalLandmarkCoordinateSystem".into(), json!(system));
+        map.insert("AnatomicalLandmarkCoordinateUnits".into(), json!(unit));
+    }
+    Value::Object(map)
+}
+
+fn write_events(path: &Path, entry: &NirsEntry) -> Result<(), NWError> {
+    let mut markers: Vec<(&str, f64, f64, f64)> = entry
+        .events
+        .iter()
+        .flat_map(|e| {
+            e.markers
+                .iter()
+                .map(move |m| (e.name.as_str(), m.onset, m.duration, m.value))
+        })
+        .collect();
+    markers.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let rows: Vec<Vec<String>> = markers
+        .into_iter()
+        .map(|(name, onset, duration, value)| {
+            vec![
+                onset.to_string(),
+                duration.to_string(),
+                name.to_string(),
+                value.to_string(),
+            ]
+        })
+        .collect();
+    write_tsv(path, &["onset", "duration", "trial_type", "value"], &rows)
+}
@@ -0,0 +1,85 @@
+use crate::domain::error::NWError;
+use serde::Serialize;
+use std::path::Path;
+
+/// A delimited text table of events: a BIDS `events.tsv` or a CSV/TSV log
+/// written by experiment software (PsychoPy, E-Prime…). Cells are kept as
+/// text; interpreting columns is up to the caller.
+#[derive(Serialize, Clone, Debug)]
+pub struct EventTable {
+    pub columns: Vec<String>,
+    /// One row per line, padded to `columns.len()`.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl EventTable {
+    /// Index of the column named `name`, ignoring case.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Field separator: by extension for `.tsv`/`.csv`, otherwise whichever of
+/// tab, comma or semicolon the header line contains most often.
+fn delimiter(path: &Path, header: &str) -> char {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("tsv") => '\t',
+        Some("csv") => ',',
+        _ => ['\t', ',', ';']
+            .into_iter()
+            .max_by_key(|&d| header.matches(d).count())
+            .unwrap_or('\t'),
+    }
+}
+
+/// Split one line on `delim`, honouring double-quoted fields with `""`
+/// escapes as spreadsheet and PsychoPy CSVs write them.
+fn split_line(line: &str, delim: char) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delim && !quoted => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+/// Read an event table. The first non-empty line is the header; blank
+/// lines are skipped. Quoted fields may not span lines.
+pub fn read_event_table(path: &str) -> Result<EventTable, NWError> {
+    let text = std::fs::read_to_string(path)?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+
+    let header = lines
+        .next()
+        .ok_or_else(|| NWError::Parse(format!("{path}: file is empty")))?;
+    let delim = delimiter(Path::new(path), header);
+    let columns = split_line(header, delim);
+
+    let rows = lines
+        .map(|line| {
+            let mut row = split_line(line, delim);
+            row.resize(columns.len().max(row.len()), String::new());
+            row
+        })
+        .collect();
+    Ok(EventTable { columns, rows })
+}
@@ -0,0 +1,248 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{DataBlock, NirsEntry, Optode, Probe, Snirf};
+use crate::io::recording_importer::{
+    channel_column, channel_measurements, events_from_marks, metadata, numbered_probe, optode,
+    probe, read_text, single_entry, uniform_time, wavelength_bands, ColumnKind,
+};
+
+/// Centre-to-centre distance of neighbouring optodes in ETG holders, mm.
+const HOLDER_SPACING: f64 = 30.0;
+
+/// ETG probe holders (`Mode` 3x3, 4x4, 3x5, 3x11…): `rows`×`cols` optodes
+/// alternating emitters and detectors like a chessboard, the top-left one
+/// an emitter. Channels join neighbours and are numbered row by row: the
+/// horizontal pairs of a row, then the vertical pairs down to the next.
+/// Returns the sources, the detectors and each channel's 1-based pair.
+fn holder_layout(rows: usize, cols: usize) -> (Vec<Optode>, Vec<Optode>, Vec<(usize, usize)>) {
+    let mut sources = Vec::new();
+    let mut detectors = Vec::new();
+    // Per grid cell: (is source, 1-based index among its kind).
+    let mut cells = Vec::with_capacity(rows * cols);
+    for r in 0..rows {
+        for c in 0..cols {
+            let pos = Some([c as f64 * HOLDER_SPACING, -(r as f64) * HOLDER_SPACING]);
+            let is_source = (r + c) % 2 == 0;
+            let list = if is_source {
+                &mut sources
+            } else {
+                &mut detectors
+            };
+            list.push(optode(
+                if is_source { "S" } else { "D" },
+                list.len(),
+                pos,
+                None,
+            ));
+            cells.push((is_source, list.len()));
+        }
+    }
+    let pair = |a: usize, b: usize| {
+        let ((a_src, a_i), (_, b_i)) = (cells[a], cells[b]);
+        if a_src {
+            (a_i, b_i)
+        } else {
+            (b_i, a_i)
+        }
+    };
+    let mut pairs = Vec::new();
+    for r in 0..rows {
+        pairs.extend((0..cols.saturating_sub(1)).map(|c| pair(r * cols + c, r * cols + c + 1)));
+        if r + 1 < rows {
+            pairs.extend((0..cols).map(|c| pair(r * cols + c, (r + 1) * cols + c)));
+        }
+    }
+    (sources, detectors, pairs)
+}
+
+fn parse_mode(mode: &str) -> Option<(usize, usize)> {
+    let (r, c) = mode.trim().split_once(['x', 'X'])?;
+    Some((r.trim().parse().ok()?, c.trim().parse().ok()?))
+}
+
+/// Read a Hitachi ETG-4000/ETG-7000 CSV export: a `Header` block of
+/// `key,value…` lines, then a `Data` line, a column header starting with
+/// `Probe1` and one row per sample. Channel columns are `CHn(wavelength)`
+/// for intensity exports and `CHn(Oxy)`/`CHn(Deoxy)`/`CHn(Total)` for
+/// haemoglobin (mM·mm). The `Mark` column becomes events. Optode positions
+/// follow the holder layout given by `Mode`; other layouts get one
+/// numbered pair per channel.
+pub fn parse_hitachi_csv(path: &str) -> Result<Snirf, NWError> {
+    let text = read_text(path)?;
+    let rows: Vec<Vec<&str>> = text
+        .lines()
+        .map(|l| l.split(',').map(str::trim).collect())
+        .collect();
+    let value = |key: &str| {
+        rows.iter()
+            .find(|r| r[0].eq_ignore_ascii_case(key))
+            .and_then(|r| r.get(1).copied())
+            .filter(|v| !v.is_empty())
+    };
+
+    let header_at = rows
+        .iter()
+        .position(|r| r[0].starts_with("Probe") && r.iter().any(|c| channel_column(c).is_some()))
+        .ok_or_else(|| NWError::Parse(format!("{path}: no Probe1,CH1(…) data header")))?;
+    let header = &rows[header_at];
+    let data: Vec<&Vec<&str>> = rows[header_at + 1..]
+        .iter()
+        .filter(|r| r.len() >= header.len() && r[0].parse::<f64>().is_ok())
+        .collect();
+
+    let column = |c: usize| -> Vec<f64> {
+        data.iter()
+            .map(|r| r[c].parse().unwrap_or(f64::NAN))
+            .collect()
+    };
+    let columns: Vec<(usize, ColumnKind, Vec<f64>)> = header
+        .iter()
+        .enumerate()
+        .filter_map(|(c, h)| channel_column(h).map(|(ch, kind)| (ch, kind, column(c))))
+        .collect();
+    let n_channels = columns.iter().map(|(ch, _, _)| *ch).max().unwrap_or(0);
+
+    // Nominal wavelengths from `Wave[nm]`, else from the channel headers.
+    let nominal: Vec<f64> = rows
+        .iter()
+        .find(|r| r[0].eq_ignore_ascii_case("Wave[nm]"))
+        .map(|r| wavelength_bands(r[1..].iter().filter_map(|v| v.parse().ok())))
+        .unwrap_or_default();
+    let wavelengths = if nominal.is_empty() {
+        wavelength_bands(columns.iter().filter_map(|(_, kind, _)| match kind {
+            ColumnKind::Wavelength(nm) => Some(*nm),
+            ColumnKind::Hemoglobin(_) => None,
+        }))
+    } else {
+        nominal
+    };
+
+    let layout = value("Mode")
+        .and_then(parse_mode)
+        .map(|(r, c)| holder_layout(r, c))
+        .filter(|(_, _, pairs)| pairs.len() >= n_channels);
+    let (probe, pairs): (Probe, Vec<(usize, usize)>) = match layout {
+        Some((sources, detectors, pairs)) => {
+            (probe(wavelengths.clone(), sources, detectors), pairs)
+        }
+        None => (
+            numbered_probe(n_channels, wavelengths.clone(), HOLDER_SPACING),
+            (1..=n_channels).map(|k| (k, k)).collect(),
+        ),
+    };
+    let measurements =
+        channel_measurements(columns, &wavelengths, |ch| pairs[ch - 1], Some("mM*mm"));
+
+    let period = value("Sampling Period[s]")
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|p| *p > 0.0)
+        .ok_or_else(|| NWError::Parse(format!("{path}: no Sampling Period[s]")))?;
+    let time = uniform_time(data.len(), period);
+    let events = match header.iter().position(|h| h.eq_ignore_ascii_case("Mark")) {
+        Some(c) => {
+            let marks: Vec<&str> = data.iter().map(|r| r[c]).collect();
+            events_from_marks(&time, &marks, "Mark")
+        }
+        None => Vec::new(),
+    };
+
+    // `Date` is "2020/02/02 11:20".
+    let (date, clock) = match value("Date").and_then(|d| d.split_once(' ')) {
+        Some((date, clock)) => (Some(date.replace('/', "-")), Some(clock.to_string())),
+        None => (value("Date").map(str::to_string), None),
+    };
+    let metadata = metadata(
+        value("ID").map(str::to_string),
+        date,
+        clock,
+        "mm",
+        vec![
+            ("ManufacturerName", "Hitachi".to_string()),
+            ("SubjectName", value("Name").unwrap_or_default().to_string()),
+        ],
+    );
+
+    let entry = NirsEntry {
+        metadata,
+        data_blocks: vec![DataBlock { time, measurements }],
+        probe,
+        events,
+        auxiliaries: Vec::new(),
+        provenance: Vec::new(),
+    };
+    Ok(single_entry(path, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::recording_importer::tests::{fixture, fixture_dir};
+
+    const EXPORT: &str = "\
+Header,,,
+File Version,1.25
+ID,subj01
+Name,Jane
+Date,2020/02/02 11:20
+Mode,3x3
+Wave[nm],695,830
+Sampling Period[s],0.1
+Data
+Probe1,CH1(695.2),CH1(829.1),CH2(697.8),CH2(830.4),Mark,Time
+1,1.0,2.0,3.0,4.0,0,11:20:00
+2,1.1,2.1,3.1,4.1,1,11:20:00
+3,1.2,2.2,3.2,4.2,1,11:20:00
+4,1.3,2.3,3.3,4.3,0,11:20:00
+";
+
+    #[test]
+    fn reads_an_etg_export() {
+        let path = fixture(&fixture_dir("hitachi"), "run.csv", EXPORT);
+        let snirf = parse_hitachi_csv(&path).unwrap();
+        let entry = &snirf.nirs_entries[0];
+        let block = &entry.data_blocks[0];
+        assert_eq!(block.time, uniform_time(4, 0.1));
+
+        // 3x3 holder: CH1 joins S1 and D1, CH2 D1 and S2.
+        let channels: Vec<_> = block
+            .measurements
+            .iter()
+            .map(|m| {
+                (
+                    m.source_index,
+                    m.detector_index,
+                    m.wavelength_index,
+                    m.wavelength_actual,
+                )
+            })
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                (1, 1, Some(1), Some(695.2)),
+                (1, 1, Some(2), Some(829.1)),
+                (2, 1, Some(1), Some(697.8)),
+                (2, 1, Some(2), Some(830.4)),
+            ]
+        );
+        assert_eq!(block.measurements[3].data, [4.0, 4.1, 4.2, 4.3]);
+        assert_eq!(entry.probe.wavelengths, [695.0, 830.0]);
+        assert_eq!(entry.probe.sources.len(), 5);
+        assert_eq!(entry.probe.detectors.len(), 4);
+
+        let tag = |name: &str| {
+            entry
+                .metadata
+                .iter()
+                .find(|t| t.name == name)
+                .map(|t| t.value.as_str())
+        };
+        assert_eq!(tag("SubjectID"), Some("subj01"));
+        assert_eq!(tag("MeasurementDate"), Some("2020-02-02"));
+        assert_eq!(tag("MeasurementTime"), Some("11:20"));
+
+        assert_eq!(entry.events.len(), 1);
+        assert_eq!(entry.events[0].name, "Mark_1");
+        assert_eq!(entry.events[0].markers.len(), 1);
+        assert_eq!(entry.events[0].markers[0].onset, 0.1);
+    }
+}
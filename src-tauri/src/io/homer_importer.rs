@@ -0,0 +1,312 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{
+    AuxiliaryData, DataBlock, EventMarker, Measurement, NirsEntry, Optode, Snirf,
+};
+use crate::io::matlab::{read_mat, MatValue};
+use crate::io::recording_importer::{
+    group_events, hemoglobin_measurement, metadata, optode, probe, raw_measurement, single_entry,
+};
+
+fn missing(path: &str, what: &str) -> NWError {
+    NWError::Parse(format!("{path}: no `{what}` in this .nirs file"))
+}
+
+/// Columns of a samples×n matrix. Homer writes time along the rows, but
+/// hand-made files sometimes have it transposed.
+fn time_columns(value: &MatValue, samples: usize) -> Vec<Vec<f64>> {
+    let Some((rows, cols)) = value.dims() else {
+        return Vec::new();
+    };
+    if rows == samples {
+        (0..cols)
+            .filter_map(|c| value.column_at(c).map(<[f64]>::to_vec))
+            .collect()
+    } else if cols == samples {
+        value.to_rows()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Optodes `{prefix}1…{prefix}{count}` placed from `SD.{name}3D`/`SD.{name}`
+/// (3 columns) and `SD.{name}2D` (or a 2-column `SD.{name}`).
+fn optodes(sd: &MatValue, name: &str, prefix: &str, count: usize) -> Vec<Optode> {
+    let rows = |field: &str| sd.field(field).map(MatValue::to_rows).unwrap_or_default();
+    let main = rows(name);
+    let three = match rows(&format!("{name}3D")) {
+        r if !r.is_empty() => r,
+        _ => main.iter().filter(|r| r.len() >= 3).cloned().collect(),
+    };
+    let two = match rows(&format!("{name}2D")) {
+        r if !r.is_empty() => r,
+        _ => main.iter().filter(|r| r.len() == 2).cloned().collect(),
+    };
+    (0..count)
+        .map(|i| {
+            optode(
+                prefix,
+                i,
+                two.get(i).filter(|r| r.len() >= 2).map(|r| [r[0], r[1]]),
+                three
+                    .get(i)
+                    .filter(|r| r.len() >= 3)
+                    .map(|r| [r[0], r[1], r[2]]),
+            )
+        })
+        .collect()
+}
+
+/// Read a Homer2/Homer3 `.nirs` file: `t`, `d` and `SD` are required;
+/// `s`/`CondNames` become events, `aux` auxiliary signals, and a
+/// `procResult.dc` (HbO/HbR/HbT per channel) a second, processed block.
+pub fn parse_homer_nirs(path: &str) -> Result<Snirf, NWError> {
+    let variables = read_mat(path)?;
+    let var = |name: &str| variables.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+
+    let time = var("t")
+        .and_then(MatValue::values)
+        .ok_or_else(|| missing(path, "t"))?
+        .to_vec();
+    let d = var("d").ok_or_else(|| missing(path, "d"))?;
+    let sd = var("SD").ok_or_else(|| missing(path, "SD"))?;
+
+    let columns = time_columns(d, time.len());
+    let meas_list = sd
+        .field("MeasList")
+        .map(MatValue::to_rows)
+        .ok_or_else(|| missing(path, "SD.MeasList"))?;
+    if meas_list.len() != columns.len() || meas_list.iter().any(|r| r.len() < 4) {
+        return Err(NWError::Parse(format!(
+            "{path}: d has {} columns of {} samples but SD.MeasList {} rows",
+            columns.len(),
+            time.len(),
+            meas_list.len()
+        )));
+    }
+    let measurements: Vec<Measurement> = meas_list
+        .iter()
+        .zip(columns)
+        .map(|(row, data)| raw_measurement(row[0] as usize, row[1] as usize, row[3] as usize, data))
+        .collect();
+
+    let count = |field: &str, column: usize| {
+        let listed = meas_list
+            .iter()
+            .map(|r| r[column] as usize)
+            .max()
+            .unwrap_or(0);
+        sd.field(field)
+            .and_then(MatValue::values)
+            .and_then(|v| v.first())
+            .map_or(listed, |&n| (n as usize).max(listed))
+    };
+    let sources = optodes(sd, "SrcPos", "S", count("nSrcs", 0));
+    let detectors = optodes(sd, "DetPos", "D", count("nDets", 1));
+    let wavelengths = sd
+        .field("Lambda")
+        .and_then(MatValue::values)
+        .ok_or_else(|| missing(path, "SD.Lambda"))?
+        .to_vec();
+    let unit = sd
+        .field("SpatialUnit")
+        .and_then(MatValue::text)
+        .filter(|u| !u.is_empty())
+        .unwrap_or("mm");
+
+    // Stimuli: one column per condition, non-zero at onsets. Homer marks
+    // disabled stimuli with negative values; the value is kept as is.
+    let names: Vec<String> = match var("CondNames") {
+        Some(MatValue::Cell(items)) => items
+            .iter()
+            .map(|i| i.text().unwrap_or_default().to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let markers = var("s")
+        .map(|s| time_columns(s, time.len()))
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .flat_map(|(k, column)| {
+            let name = names
+                .get(k)
+                .filter(|n| !n.is_empty())
+                .cloned()
+                .unwrap_or_else(|| (k + 1).to_string());
+            let time = &time;
+            column
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, v)| v != 0.0)
+                .map(move |(i, value)| {
+                    (
+                        name.clone(),
+                        EventMarker {
+                            onset: time[i],
+                            duration: 0.0,
+                            value,
+                        },
+                    )
+                })
+        });
+    let events = group_events(markers);
+
+    let auxiliaries = var("aux")
+        .map(|a| time_columns(a, time.len()))
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, data)| AuxiliaryData {
+            name: format!("aux{}", i + 1),
+            unit: String::new(),
+            data,
+            time: time.clone(),
+            time_offset: None,
+            resampled_from: None,
+        })
+        .collect();
+
+    let mut data_blocks = vec![DataBlock {
+        time: time.clone(),
+        measurements,
+    }];
+    if let Some(block) = var("procResult")
+        .and_then(|p| p.field("dc"))
+        .and_then(|dc| hemoglobin_block(dc, &meas_list, &time))
+    {
+        data_blocks.push(block);
+    }
+
+    let entry = NirsEntry {
+        metadata: metadata(None, None, None, unit, Vec::new()),
+        data_blocks,
+        probe: probe(wavelengths, sources, detectors),
+        events,
+        auxiliaries,
+        provenance: Vec::new(),
+    };
+    Ok(single_entry(path, entry))
+}
+
+/// Homer's `procResult.dc` is samples×3×channels (HbO, HbR, HbT), which the
+/// MAT reader flattens to samples×(3·channels). Channels are the
+/// first-wavelength rows of the measurement list.
+fn hemoglobin_block(dc: &MatValue, meas_list: &[Vec<f64>], time: &[f64]) -> Option<DataBlock> {
+    let (rows, cols) = dc.dims()?;
+    let pairs: Vec<(usize, usize)> = meas_list
+        .iter()
+        .filter(|r| r[3] as usize == 1)
+        .map(|r| (r[0] as usize, r[1] as usize))
+        .collect();
+    if rows != time.len() || pairs.is_empty() || cols != 3 * pairs.len() {
+        return None;
+    }
+    let measurements = pairs
+        .iter()
+        .enumerate()
+        .flat_map(|(ch, &(src, det))| {
+            ["HbO", "HbR", "HbT"]
+                .into_iter()
+                .enumerate()
+                .map(move |(hb, label)| {
+                    let data = dc.column_at(3 * ch + hb).unwrap_or_default().to_vec();
+                    hemoglobin_measurement(src, det, label, None, data)
+                })
+        })
+        .collect();
+    Some(DataBlock {
+        time: time.to_vec(),
+        measurements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::matlab::write_mat;
+    use crate::io::recording_importer::tests::fixture_dir;
+    use nalgebra::{Vector2, Vector3};
+
+    #[test]
+    fn reads_a_written_nirs_file() {
+        let path = fixture_dir("homer").join("run.nirs");
+        let path = path.to_string_lossy();
+        let time = vec![0.0, 0.1, 0.2, 0.3];
+        // S1-D1 and S1-D2 at 690 nm, then at 830 nm.
+        let d: Vec<Vec<f64>> = (0..4)
+            .map(|c| (0..4).map(|i| (10 * c + i) as f64).collect())
+            .collect();
+        let meas_list = [
+            vec![1.0, 1.0, 1.0, 1.0],
+            vec![1.0, 2.0, 1.0, 1.0],
+            vec![1.0, 1.0, 1.0, 2.0],
+            vec![1.0, 2.0, 1.0, 2.0],
+        ];
+        let sd = MatValue::Struct(vec![
+            ("Lambda".into(), MatValue::row(vec![690.0, 830.0])),
+            ("SrcPos".into(), MatValue::from_rows(&[vec![0.0; 3]], 3)),
+            (
+                "DetPos".into(),
+                MatValue::from_rows(&[vec![30.0, 0.0, 5.0], vec![0.0, 30.0, 5.0]], 3),
+            ),
+            // Too narrow for 2D positions: ignored, not indexed past its end.
+            ("DetPos2D".into(), MatValue::column(vec![1.0, 2.0])),
+            ("nSrcs".into(), MatValue::scalar(1.0)),
+            ("nDets".into(), MatValue::scalar(2.0)),
+            ("MeasList".into(), MatValue::from_rows(&meas_list, 4)),
+            ("SpatialUnit".into(), MatValue::Char("cm".into())),
+        ]);
+        write_mat(
+            &path,
+            &[
+                ("t", MatValue::column(time.clone())),
+                ("d", MatValue::from_columns(&d, 4)),
+                ("SD", sd),
+                ("s", MatValue::column(vec![0.0, 1.0, 0.0, 0.0])),
+                (
+                    "CondNames",
+                    MatValue::Cell(vec![MatValue::Char("tap".into())]),
+                ),
+                ("aux", MatValue::column(vec![5.0, 6.0, 7.0, 8.0])),
+            ],
+        )
+        .unwrap();
+
+        let snirf = parse_homer_nirs(&path).unwrap();
+        let entry = &snirf.nirs_entries[0];
+        let block = &entry.data_blocks[0];
+        assert_eq!(block.time, time);
+        let channels: Vec<_> = block
+            .measurements
+            .iter()
+            .map(|m| (m.source_index, m.detector_index, m.wavelength_index))
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                (1, 1, Some(1)),
+                (1, 2, Some(1)),
+                (1, 1, Some(2)),
+                (1, 2, Some(2))
+            ]
+        );
+        for (m, column) in block.measurements.iter().zip(&d) {
+            assert_eq!(&m.data, column);
+        }
+
+        let probe = &entry.probe;
+        assert_eq!(probe.wavelengths, [690.0, 830.0]);
+        assert_eq!((probe.sources.len(), probe.detectors.len()), (1, 2));
+        assert_eq!(probe.detectors[1].pos_3d, Vector3::new(0.0, 30.0, 5.0));
+        // The 3D position seen from above, y flipped to the screen.
+        assert_eq!(probe.detectors[1].pos_2d, Vector2::new(0.0, -30.0));
+        let unit = entry.metadata.iter().find(|t| t.name == "LengthUnit");
+        assert_eq!(unit.map(|t| t.value.as_str()), Some("cm"));
+
+        assert_eq!(entry.events.len(), 1);
+        assert_eq!(entry.events[0].name, "tap");
+        assert_eq!(entry.events[0].markers[0].onset, 0.1);
+        assert_eq!(entry.auxiliaries[0].data, [5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(entry.auxiliaries[0].time, time);
+    }
+}
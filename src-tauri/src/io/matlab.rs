@@ -0,0 +1,639 @@
+use crate::domain::error::NWError;
+use log::warn;
+use std::io::Read;
+
+// Level 5 MAT-file data types and array classes.
+const MI_INT8: u32 = 1;
+const MI_UINT8: u32 = 2;
+const MI_INT16: u32 = 3;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_INT64: u32 = 12;
+const MI_UINT64: u32 = 13;
+const MI_MATRIX: u32 = 14;
+const MI_COMPRESSED: u32 = 15;
+const MI_UTF8: u32 = 16;
+const MI_UTF16: u32 = 17;
+
+const MX_CELL_CLASS: u32 = 1;
+const MX_STRUCT_CLASS: u32 = 2;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+const MX_SINGLE_CLASS: u32 = 7;
+const MX_UINT64_CLASS: u32 = 15;
+
+const HEADER_TEXT_LEN: usize = 116;
+const HEADER_LEN: usize = 128;
+
+/// Slot per struct field name: 31 characters and a NUL, which every MATLAB
+/// version reads.
+const FIELD_NAME_LEN: usize = 32;
+
+/// A MATLAB variable, as far as NIRS files need them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatValue {
+    /// Real double matrix, stored column-major like MATLAB.
+    Double {
+        rows: usize,
+        cols: usize,
+        data: Vec<f64>,
+    },
+    Char(String),
+    /// 1×1 struct.
+    Struct(Vec<(String, MatValue)>),
+    /// 1×n cell array.
+    Cell(Vec<MatValue>),
+}
+
+impl MatValue {
+    pub fn scalar(value: f64) -> Self {
+        MatValue::Double {
+            rows: 1,
+            cols: 1,
+            data: vec![value],
+        }
+    }
+
+    pub fn column(data: Vec<f64>) -> Self {
+        MatValue::Double {
+            rows: data.len(),
+            cols: 1,
+            data,
+        }
+    }
+
+    pub fn row(data: Vec<f64>) -> Self {
+        MatValue::Double {
+            rows: 1,
+            cols: data.len(),
+            data,
+        }
+    }
+
+    /// Matrix from row-major `rows`, each of length `cols`.
+    pub fn from_rows(rows: &[Vec<f64>], cols: usize) -> Self {
+        let mut data = Vec::with_capacity(rows.len() * cols);
+        for c in 0..cols {
+            data.extend(rows.iter().map(|r| r.get(c).copied().unwrap_or(0.0)));
+        }
+        MatValue::Double {
+            rows: rows.len(),
+            cols,
+            data,
+        }
+    }
+
+    /// Matrix whose columns are `columns`, all of length `rows`.
+    pub fn from_columns(columns: &[Vec<f64>], rows: usize) -> Self {
+        MatValue::Double {
+            rows,
+            cols: columns.len(),
+            data: columns.iter().flatten().copied().collect(),
+        }
+    }
+
+    /// Field `name` of a struct, matched exactly and then ignoring case
+    /// (`SD.Lambda` vs `SD.lambda` depends on the Homer version).
+    pub fn field(&self, name: &str) -> Option<&MatValue> {
+        let MatValue::Struct(fields) = self else {
+            return None;
+        };
+        fields
+            .iter()
+            .find(|(f, _)| f == name)
+            .or_else(|| fields.iter().find(|(f, _)| f.eq_ignore_ascii_case(name)))
+            .map(|(_, v)| v)
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            MatValue::Char(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// All values of a matrix, column-major.
+    pub fn values(&self) -> Option<&[f64]> {
+        match self {
+            MatValue::Double { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+
+    pub fn dims(&self) -> Option<(usize, usize)> {
+        match self {
+            MatValue::Double { rows, cols, .. } => Some((*rows, *cols)),
+            _ => None,
+        }
+    }
+
+    /// Column `c` of a matrix.
+    pub fn column_at(&self, c: usize) -> Option<&[f64]> {
+        match self {
+            MatValue::Double { rows, cols, data } if c < *cols => {
+                Some(&data[c * rows..(c + 1) * rows])
+            }
+            _ => None,
+        }
+    }
+
+    /// The rows of a matrix, each as a vector.
+    pub fn to_rows(&self) -> Vec<Vec<f64>> {
+        match self {
+            MatValue::Double { rows, cols, data } => (0..*rows)
+                .map(|r| (0..*cols).map(|c| data[c * rows + r]).collect())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// =============================================================================
+// Encoding
+// =============================================================================
+
+fn pad8(out: &mut Vec<u8>) {
+    while out.len() % 8 != 0 {
+        out.push(0);
+    }
+}
+
+/// A data element: tag, payload, zero padding to 8 bytes.
+fn element(out: &mut Vec<u8>, data_type: u32, payload: &[u8]) {
+    out.extend_from_slice(&data_type.to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    pad8(out);
+}
+
+fn array_header(out: &mut Vec<u8>, class: u32, dims: [usize; 2], name: &str) {
+    let mut flags = Vec::with_capacity(8);
+    flags.extend_from_slice(&class.to_le_bytes());
+    flags.extend_from_slice(&0u32.to_le_bytes());
+    element(out, MI_UINT32, &flags);
+
+    let dims: Vec<u8> = dims
+        .iter()
+        .flat_map(|&d| (d as i32).to_le_bytes())
+        .collect();
+    element(out, MI_INT32, &dims);
+    element(out, MI_INT8, name.as_bytes());
+}
+
+/// The body of a `miMATRIX` element (everything after its tag).
+fn matrix_body(name: &str, value: &MatValue) -> Result<Vec<u8>, NWError> {
+    let mut out = Vec::new();
+    match value {
+        MatValue::Double { rows, cols, data } => {
+            if data.len() != rows * cols {
+                return Err(NWError::Internal(format!(
+                    "{name}: {rows}×{cols} matrix holds {} values",
+                    data.len()
+                )));
+            }
+            array_header(&mut out, MX_DOUBLE_CLASS, [*rows, *cols], name);
+            let bytes: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+            element(&mut out, MI_DOUBLE, &bytes);
+        }
+        MatValue::Char(text) => {
+            let units: Vec<u16> = text.encode_utf16().collect();
+            array_header(&mut out, MX_CHAR_CLASS, [1, units.len()], name);
+            let bytes: Vec<u8> = units.iter().flat_map(|u| u.to_le_bytes()).collect();
+            element(&mut out, MI_UINT16, &bytes);
+        }
+        MatValue::Struct(fields) => {
+            array_header(&mut out, MX_STRUCT_CLASS, [1, 1], name);
+            element(&mut out, MI_INT32, &(FIELD_NAME_LEN as i32).to_le_bytes());
+            let mut names = Vec::with_capacity(fields.len() * FIELD_NAME_LEN);
+            for (field, _) in fields {
+                if field.len() >= FIELD_NAME_LEN {
+                    return Err(NWError::Internal(format!(
+                        "{name}.{field}: field name too long"
+                    )));
+                }
+                names.extend_from_slice(field.as_bytes());
+                names.resize(names.len() + FIELD_NAME_LEN - field.len(), 0);
+            }
+            element(&mut out, MI_INT8, &names);
+            for (field, value) in fields {
+                element(
+                    &mut out,
+                    MI_MATRIX,
+                    &matrix_body("", value)
+                        .map_err(|e| NWError::Internal(format!("{name}.{field}: {e}")))?,
+                );
+            }
+        }
+        MatValue::Cell(items) => {
+            array_header(&mut out, MX_CELL_CLASS, [1, items.len()], name);
+            for item in items {
+                element(&mut out, MI_MATRIX, &matrix_body("", item)?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Write `variables` to a Level 5 MAT-file (uncompressed), readable by
+/// MATLAB's `load` and by Homer.
+pub fn write_mat(path: &str, variables: &[(&str, MatValue)]) -> Result<(), NWError> {
+    let mut out = Vec::new();
+    let mut text = format!(
+        "MATLAB 5.0 MAT-file, Platform: NIRWizard {}",
+        env!("CARGO_PKG_VERSION")
+    )
+    .into_bytes();
+    text.resize(HEADER_TEXT_LEN, b' ');
+    out.extend_from_slice(&text);
+    out.extend_from_slice(&[0; 8]); // no subsystem data
+    out.extend_from_slice(&0x0100u16.to_le_bytes());
+    out.extend_from_slice(b"IM");
+
+    for (name, value) in variables {
+        element(&mut out, MI_MATRIX, &matrix_body(name, value)?);
+    }
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
+// =============================================================================
+// Decoding
+// =============================================================================
+
+fn mat_err(what: impl std::fmt::Display) -> NWError {
+    NWError::Parse(format!("MAT-file: {what}"))
+}
+
+/// Walks the data elements of one buffer in the file's byte order.
+struct Elements<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> Elements<'a> {
+    fn new(bytes: &'a [u8], little_endian: bool) -> Self {
+        Elements {
+            bytes,
+            pos: 0,
+            little_endian,
+        }
+    }
+
+    fn u32_at(&self, at: usize) -> Result<u32, NWError> {
+        let b: [u8; 4] = self
+            .bytes
+            .get(at..at + 4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| mat_err("truncated element tag"))?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    /// The next element's type and payload. Elements nested in a matrix are
+    /// padded to 8 bytes; compressed top-level elements are not.
+    fn next(&mut self) -> Result<Option<(u32, &'a [u8])>, NWError> {
+        if self.pos + 8 > self.bytes.len() {
+            return Ok(None);
+        }
+        let word = self.u32_at(self.pos)?;
+        // Small data element: type and size share the first word and up to
+        // four bytes of payload follow.
+        let (data_type, start, len, next) = if word >> 16 != 0 {
+            let len = (word >> 16) as usize;
+            (word & 0xffff, self.pos + 4, len, self.pos + 8)
+        } else {
+            let len = self.u32_at(self.pos + 4)? as usize;
+            let next = if word == MI_COMPRESSED {
+                self.pos + 8 + len
+            } else {
+                self.pos + 8 + len.div_ceil(8) * 8
+            };
+            (word, self.pos + 8, len, next)
+        };
+        let payload = self
+            .bytes
+            .get(start..start + len)
+            .ok_or_else(|| mat_err("truncated element"))?;
+        self.pos = next;
+        Ok(Some((data_type, payload)))
+    }
+
+    fn expect(&mut self) -> Result<(u32, &'a [u8]), NWError> {
+        self.next()?.ok_or_else(|| mat_err("missing array element"))
+    }
+}
+
+/// Numeric payload of any integer or floating type, as doubles.
+fn numbers(data_type: u32, bytes: &[u8], little_endian: bool) -> Result<Vec<f64>, NWError> {
+    macro_rules! decode {
+        ($t:ty) => {
+            bytes
+                .chunks_exact(std::mem::size_of::<$t>())
+                .map(|c| {
+                    let b = c.try_into().unwrap();
+                    (if little_endian {
+                        <$t>::from_le_bytes(b)
+                    } else {
+                        <$t>::from_be_bytes(b)
+                    }) as f64
+                })
+                .collect()
+        };
+    }
+    Ok(match data_type {
+        MI_INT8 => decode!(i8),
+        MI_UINT8 => decode!(u8),
+        MI_INT16 => decode!(i16),
+        MI_UINT16 => decode!(u16),
+        MI_INT32 => decode!(i32),
+        MI_UINT32 => decode!(u32),
+        MI_SINGLE => decode!(f32),
+        MI_DOUBLE => decode!(f64),
+        MI_INT64 => decode!(i64),
+        MI_UINT64 => decode!(u64),
+        other => return Err(mat_err(format!("unexpected numeric type {other}"))),
+    })
+}
+
+/// Character payload; a multi-row char matrix becomes one line per row
+/// with MATLAB's blank padding trimmed.
+fn chars(
+    data_type: u32,
+    bytes: &[u8],
+    rows: usize,
+    little_endian: bool,
+) -> Result<String, NWError> {
+    let units: Vec<char> = match data_type {
+        MI_UTF8 | MI_UINT8 | MI_INT8 => String::from_utf8_lossy(bytes).chars().collect(),
+        MI_UINT16 | MI_UTF16 => {
+            let units: Vec<u16> = numbers(MI_UINT16, bytes, little_endian)?
+                .into_iter()
+                .map(|u| u as u16)
+                .collect();
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        other => return Err(mat_err(format!("unexpected character type {other}"))),
+    };
+    if rows <= 1 {
+        return Ok(units.into_iter().collect());
+    }
+    let cols = units.len() / rows;
+    Ok((0..rows)
+        .map(|r| {
+            let row: String = (0..cols).map(|c| units[c * rows + r]).collect();
+            row.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Decode the body of a `miMATRIX` element into its name and value.
+fn decode_matrix(body: &[u8], little_endian: bool) -> Result<(String, MatValue), NWError> {
+    let mut el = Elements::new(body, little_endian);
+    // An empty miMATRIX stands for an empty array, e.g. an unset cell.
+    if body.is_empty() {
+        return Ok((String::new(), MatValue::from_rows(&[], 0)));
+    }
+    let (_, flags) = el.expect()?;
+    let class = numbers(MI_UINT32, flags, little_endian)?
+        .first()
+        .map_or(0, |&f| f as u32 & 0xff);
+    let (dims_type, dims) = el.expect()?;
+    let dims: Vec<usize> = numbers(dims_type, dims, little_endian)?
+        .into_iter()
+        .map(|d| d as usize)
+        .collect();
+    let rows = dims.first().copied().unwrap_or(0);
+    let count: usize = dims.iter().product();
+    let cols = count.checked_div(rows).unwrap_or(0);
+    let (_, name) = el.expect()?;
+    let name = String::from_utf8_lossy(name).into_owned();
+
+    let value = match class {
+        MX_DOUBLE_CLASS | MX_SINGLE_CLASS..=MX_UINT64_CLASS => {
+            // The imaginary part of a complex array, if any, is dropped.
+            let (data_type, real) = el.expect()?;
+            let data = numbers(data_type, real, little_endian)?;
+            if data.len() != count {
+                return Err(mat_err(format!(
+                    "{name}: {rows}×{cols} array holds {} values",
+                    data.len()
+                )));
+            }
+            MatValue::Double { rows, cols, data }
+        }
+        MX_CHAR_CLASS => match el.next()? {
+            Some((data_type, bytes)) => {
+                MatValue::Char(chars(data_type, bytes, rows, little_endian)?)
+            }
+            None => MatValue::Char(String::new()),
+        },
+        MX_STRUCT_CLASS => {
+            let (_, len) = el.expect()?;
+            let len = numbers(MI_INT32, len, little_endian)?
+                .first()
+                .map_or(0, |&n| n as usize);
+            let (_, names) = el.expect()?;
+            let fields: Vec<String> = if len == 0 {
+                Vec::new()
+            } else {
+                names
+                    .chunks(len)
+                    .map(|n| {
+                        let end = n.iter().position(|&b| b == 0).unwrap_or(n.len());
+                        String::from_utf8_lossy(&n[..end]).into_owned()
+                    })
+                    .collect()
+            };
+            let mut structs = Vec::with_capacity(count);
+            for _ in 0..count {
+                let mut values = Vec::with_capacity(fields.len());
+                for field in &fields {
+                    let (_, body) = el.expect()?;
+                    let (_, value) = decode_matrix(body, little_endian)
+                        .map_err(|e| mat_err(format!("{name}.{field}: {e}")))?;
+                    values.push((field.clone(), value));
+                }
+                structs.push(MatValue::Struct(values));
+            }
+            // A struct array becomes a cell of 1×1 structs.
+            if count == 1 {
+                structs.remove(0)
+            } else {
+                MatValue::Cell(structs)
+            }
+        }
+        MX_CELL_CLASS => {
+            let mut items = Vec::with_capacity(count);
+            for _ in 0..count {
+                let (_, body) = el.expect()?;
+                items.push(decode_matrix(body, little_endian)?.1);
+            }
+            MatValue::Cell(items)
+        }
+        other => return Err(mat_err(format!("{name}: unsupported array class {other}"))),
+    };
+    Ok((name, value))
+}
+
+/// Read the variables of a Level 5 MAT-file (`save -v6` or `-v7`,
+/// compressed or not). Variables of classes NIRS files have no use for —
+/// sparse arrays, objects, function handles — are skipped with a warning.
+pub fn read_mat(path: &str) -> Result<Vec<(String, MatValue)>, NWError> {
+    let bytes = std::fs::read(path)?;
+    if bytes.len() < HEADER_LEN {
+        return Err(mat_err(format!("{path}: too short for a MAT-file header")));
+    }
+    let little_endian = match &bytes[126..128] {
+        b"IM" => true,
+        b"MI" => false,
+        _ => {
+            return Err(mat_err(format!(
+                "{path}: not a Level 5 MAT-file (v7.3 files are HDF5)"
+            )))
+        }
+    };
+
+    let mut variables = Vec::new();
+    let mut el = Elements::new(&bytes[HEADER_LEN..], little_endian);
+    while let Some((data_type, payload)) = el.next()? {
+        let decoded = match data_type {
+            MI_MATRIX => decode_matrix(payload, little_endian),
+            MI_COMPRESSED => {
+                let mut inflated = Vec::new();
+                flate2::read::ZlibDecoder::new(payload).read_to_end(&mut inflated)?;
+                let mut inner = Elements::new(&inflated, little_endian);
+                match inner.next()? {
+                    Some((MI_MATRIX, body)) => decode_matrix(body, little_endian),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        match decoded {
+            Ok(variable) => variables.push(variable),
+            Err(e) => warn!("{path}: skipping variable: {e}"),
+        }
+    }
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::recording_importer::tests::{fixture, fixture_dir};
+    use std::io::Write;
+
+    fn variables() -> Vec<(&'static str, MatValue)> {
+        vec![
+            ("t", MatValue::column(vec![0.0, 0.1, 0.2])),
+            (
+                "d",
+                MatValue::from_rows(&[vec![1.0, 2.0], vec![3.0, 4.0]], 2),
+            ),
+            (
+                "SD",
+                MatValue::Struct(vec![
+                    ("Lambda".into(), MatValue::row(vec![690.0, 830.0])),
+                    ("SpatialUnit".into(), MatValue::Char("mm".into())),
+                    ("Nested".into(), MatValue::Struct(vec![])),
+                ]),
+            ),
+            (
+                "CondNames",
+                MatValue::Cell(vec![
+                    MatValue::Char("tap".into()),
+                    MatValue::Char("µ rest".into()),
+                ]),
+            ),
+            ("empty", MatValue::from_rows(&[], 0)),
+        ]
+    }
+
+    #[test]
+    fn written_variables_read_back() {
+        let path = fixture_dir("matlab-roundtrip").join("vars.mat");
+        let path = path.to_string_lossy();
+        write_mat(&path, &variables()).unwrap();
+        let read = read_mat(&path).unwrap();
+        let expected: Vec<(String, MatValue)> = variables()
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v))
+            .collect();
+        assert_eq!(read, expected);
+
+        let d = &read[1].1;
+        assert_eq!(d.values(), Some(&[1.0, 3.0, 2.0, 4.0][..]));
+        assert_eq!(d.column_at(1), Some(&[2.0, 4.0][..]));
+        assert_eq!(d.to_rows(), vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+        assert_eq!(read[2].1.field("lambda"), read[2].1.field("Lambda"));
+    }
+
+    #[test]
+    fn reads_compressed_variables() {
+        let dir = fixture_dir("matlab-compressed");
+        let plain = dir.join("plain.mat").to_string_lossy().into_owned();
+        write_mat(&plain, &[("t", MatValue::row(vec![1.5, -2.0]))]).unwrap();
+        let bytes = std::fs::read(&plain).unwrap();
+
+        // The same variable as one miCOMPRESSED element, like `save -v7`.
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        zlib.write_all(&bytes[HEADER_LEN..]).unwrap();
+        let deflated = zlib.finish().unwrap();
+        let mut compressed = bytes[..HEADER_LEN].to_vec();
+        compressed.extend_from_slice(&MI_COMPRESSED.to_le_bytes());
+        compressed.extend_from_slice(&(deflated.len() as u32).to_le_bytes());
+        compressed.extend_from_slice(&deflated);
+        let path = fixture(&dir, "compressed.mat", compressed);
+
+        assert_eq!(read_mat(&path).unwrap(), read_mat(&plain).unwrap());
+    }
+
+    #[test]
+    fn reads_big_endian_integers_in_small_elements() {
+        let tag = |data_type: u32, len: u32| [data_type.to_be_bytes(), len.to_be_bytes()].concat();
+        let small = |data_type: u32, payload: &[u8]| {
+            let mut out = ((payload.len() as u32) << 16 | data_type)
+                .to_be_bytes()
+                .to_vec();
+            out.extend_from_slice(payload);
+            out.resize(8, 0);
+            out
+        };
+        let mut body = tag(MI_UINT32, 8);
+        body.extend_from_slice(&[0, 0, 0, MX_DOUBLE_CLASS as u8, 0, 0, 0, 0]);
+        body.extend(tag(MI_INT32, 8));
+        body.extend([2i32, 1].iter().flat_map(|d| d.to_be_bytes()));
+        body.extend(small(MI_INT8, b"x"));
+        body.extend(small(MI_INT16, &[0, 3, 0xff, 0xfc]));
+
+        let mut file = vec![b' '; HEADER_LEN - 2];
+        file.extend_from_slice(b"MI");
+        file.extend(tag(MI_MATRIX, body.len() as u32));
+        file.extend(body);
+        let path = fixture(&fixture_dir("matlab-big-endian"), "x.mat", file);
+
+        let read = read_mat(&path).unwrap();
+        assert_eq!(
+            read,
+            vec![("x".to_string(), MatValue::column(vec![3.0, -4.0]))]
+        );
+    }
+
+    #[test]
+    fn rejects_hdf5_mat_files() {
+        let mut header = b"MATLAB 7.3 MAT-file".to_vec();
+        header.resize(HEADER_LEN, 0);
+        let path = fixture(&fixture_dir("matlab-v73"), "v73.mat", header);
+        assert!(matches!(read_mat(&path), Err(NWError::Parse(_))));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::domain::error::NWError;
+use crate::domain::optics::OpticalProperties;
+use crate::domain::voxel::VoxelVolume;
+use crate::photon::{PencilBeam, SimulationConfig};
+use nalgebra::Vector3;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Detector radius written to the sessions, in voxels.
+const DETECTOR_RADIUS: f64 = 1.0;
+
+/// One optode's MCX session: its beam from inside the first tissue voxel
+/// (see [`enter_tissue`](crate::photon::enter_tissue)), and where the
+/// optodes it forms channels with enter, as detectors. Both in continuous
+/// voxel coordinates (see [`PencilBeam`]).
+pub struct McxOptode {
+    pub name: String,
+    pub beam: PencilBeam,
+    pub detectors: Vec<[f64; 3]>,
+}
+
+/// MCX counts voxels from 1 in `Pos`.
+fn mcx_position(p: &[f64; 3]) -> [f64; 3] {
+    p.map(|c| c + 1.0)
+}
+
+fn session(
+    volume_file: &str,
+    dims: [usize; 3],
+    voxel: f64,
+    media: &[OpticalProperties],
+    optode: &McxOptode,
+    config: &SimulationConfig,
+) -> Value {
+    let d = Vector3::from(optode.beam.direction).normalize();
+    let dir = [d.x, d.y, d.z];
+    json!({
+        "Session": {
+            "ID": optode.name,
+            "Photons": config.photons,
+            "RNGSeed": config.seed,
+            "DoMismatch": u8::from(config.mismatch),
+            "DoNormalize": 1,
+            "DoAutoThread": 1,
+            "OutputType": "fluence",
+            "OutputFormat": "jnii"
+        },
+        "Forward": {
+            "T0": config.t0,
+            "T1": config.t1,
+            "Dt": (config.t1 - config.t0) / config.gates as f64
+        },
+        "Domain": {
+            "VolumeFile": volume_file,
+            "Dim": dims,
+            "LengthUnit": voxel,
+            "BackgroundFlag": 0,
+            "Media": media
+        },
+        "Optode": {
+            "Source": {
+                "Type": "pencil",
+                "Pos": mcx_position(&optode.beam.position),
+                "Dir": dir,
+                "Param1": [0, 0, 0, 0],
+                "Param2": [0, 0, 0, 0]
+            },
+            "Detector": optode
+                .detectors
+                .iter()
+                .map(|p| json!({ "Pos": mcx_position(p), "R": DETECTOR_RADIUS }))
+                .collect::<Vec<_>>()
+        }
+    })
+}
+
+/// Write `volume` and one MCX session per optode to `dir`, for running
+/// the simulations elsewhere with `mcx -f <optode>.json` from `dir`:
+///
+/// - `<volume name>.bin`: the labels as uint8, x fastest (MCX's order)
+/// - `<optode>.json`: the session, `media[label]` as `Domain.Media`; MCX
+///   writes its fluence to `<optode>.jnii`, which
+///   [`import_mcx_fluence`](crate::io::mcx_importer::import_mcx_fluence)
+///   reads back
+///
+/// MCX only takes cubic voxels. Returns the files written.
+pub fn export_mcx(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    optodes: &[McxOptode],
+    config: &SimulationConfig,
+    dir: &Path,
+) -> Result<Vec<String>, NWError> {
+    let size: [f64; 3] = std::array::from_fn(|c| volume.vox2ras.fixed_view::<3, 1>(0, c).norm());
+    if size.iter().any(|s| (s - size[0]).abs() > 1e-6 * size[0]) {
+        return Err(NWError::InvalidInput(format!(
+            "MCX needs cubic voxels, the volume's are {:.3}×{:.3}×{:.3} mm",
+            size[0], size[1], size[2]
+        )));
+    }
+    std::fs::create_dir_all(dir)
+        .map_err(|e| NWError::Internal(format!("failed to create '{}': {e}", dir.display())))?;
+
+    let mut written = Vec::with_capacity(optodes.len() + 1);
+    let mut write = |name: &str, bytes: &[u8]| {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes)
+            .map_err(|e| NWError::Internal(format!("failed to write '{}': {e}", path.display())))?;
+        written.push(path.to_string_lossy().into_owned());
+        Ok::<_, NWError>(())
+    };
+
+    let volume_file = format!("{}.bin", volume.name);
+    write(&volume_file, &volume.labels)?;
+    for optode in optodes {
+        let json = session(&volume_file, volume.dims, size[0], media, optode, config);
+        let text = serde_json::to_string_pretty(&json)
+            .map_err(|e| NWError::Internal(format!("failed to encode '{}': {e}", optode.name)))?;
+        write(&format!("{}.json", optode.name), text.as_bytes())?;
+    }
+    Ok(written)
+}
@@ -0,0 +1,174 @@
+use crate::domain::error::NWError;
+use base64::Engine as _;
+use serde_json::Value;
+use std::io::Read;
+use std::path::Path;
+
+/// An MCX fluence output as MCX lays it out: x fastest, then y, z and
+/// time gate. Fluence rate per unit source power, 1/(mm²·s), when the
+/// session normalised it (`DoNormalize`, the default).
+pub struct McxFluence {
+    pub dims: [usize; 3],
+    pub gates: usize,
+    /// Start of the first gate, s.
+    pub t0: f64,
+    /// Gate width, s.
+    pub gate_width: f64,
+    pub data: Vec<f32>,
+}
+
+/// What the session file says about the output: (dims, T0, Dt).
+fn read_session(path: &Path) -> Result<([usize; 3], f64, f64), String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+    let json: Value =
+        serde_json::from_str(&text).map_err(|e| format!("'{}': {e}", path.display()))?;
+    let number = |section: &str, key: &str| {
+        json[section][key]
+            .as_f64()
+            .ok_or_else(|| format!("'{}' has no {section}.{key}", path.display()))
+    };
+    let dims = json["Domain"]["Dim"]
+        .as_array()
+        .filter(|d| d.len() == 3)
+        .and_then(|d| d.iter().map(Value::as_u64).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| format!("'{}' has no Domain.Dim", path.display()))?;
+    Ok((
+        std::array::from_fn(|a| dims[a] as usize),
+        number("Forward", "T0")?,
+        number("Forward", "Dt")?,
+    ))
+}
+
+fn inflate(zip: &str, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match zip {
+        "zlib" => flate2::read::ZlibDecoder::new(bytes).read_to_end(&mut out),
+        "gzip" => flate2::read::GzDecoder::new(bytes).read_to_end(&mut out),
+        other => return Err(format!("unsupported _ArrayZipType_ '{other}'")),
+    }
+    .map_err(|e| format!("failed to inflate NIFTIData: {e}"))?;
+    Ok(out)
+}
+
+/// Little-endian `bytes` of JData type `kind` as f32.
+fn to_f32(kind: &str, bytes: &[u8]) -> Result<Vec<f32>, String> {
+    let values = match kind {
+        "single" | "float32" => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        "double" | "float64" => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(std::array::from_fn(|i| b[i])) as f32)
+            .collect(),
+        other => return Err(format!("unsupported _ArrayType_ '{other}'")),
+    };
+    Ok(values)
+}
+
+/// `NIFTIData` as annotated JData: compressed in `_ArrayZipData_` or
+/// plain in `_ArrayData_`.
+fn jdata_array(data: &Value) -> Result<Vec<f32>, String> {
+    if let Some(values) = data["_ArrayData_"].as_array() {
+        return values
+            .iter()
+            .map(|v| v.as_f64().map(|v| v as f32))
+            .collect::<Option<_>>()
+            .ok_or_else(|| "_ArrayData_ holds a non-number".into());
+    }
+    let kind = data["_ArrayType_"]
+        .as_str()
+        .ok_or("NIFTIData has no _ArrayType_")?;
+    let zipped = data["_ArrayZipData_"]
+        .as_str()
+        .ok_or("NIFTIData has neither _ArrayData_ nor _ArrayZipData_")?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(zipped.trim())
+        .map_err(|e| format!("failed to decode _ArrayZipData_: {e}"))?;
+    let zip = data["_ArrayZipType_"].as_str().unwrap_or("zlib");
+    to_f32(kind, &inflate(zip, &bytes)?)
+}
+
+fn read_jnii(path: &Path) -> Result<(Vec<usize>, f64, Vec<f32>), String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+    let json: Value =
+        serde_json::from_str(&text).map_err(|e| format!("'{}': {e}", path.display()))?;
+    let header = &json["NIFTIHeader"];
+    let dims = header["Dim"]
+        .as_array()
+        .and_then(|d| d.iter().map(|v| v.as_u64().map(|v| v as usize)).collect())
+        .ok_or("NIFTIHeader has no Dim")?;
+    let dt = header["VoxelSize"][3].as_f64().unwrap_or(0.0);
+    Ok((dims, dt, jdata_array(&json["NIFTIData"])?))
+}
+
+/// Read an MCX fluence output: `.jnii` (JNIfTI) or `.mc2` (raw f32).
+/// The session file it was run from, `<stem>.json` next to it as
+/// [`export_mcx`](crate::io::mcx_exporter::export_mcx) writes it, gives
+/// the grid and time gates; a `.jnii` carries them itself if it is
+/// missing. The data are taken as x fastest, as MCX writes them whatever
+/// `_ArrayOrder_` says.
+pub fn import_mcx_fluence(path: &str) -> Result<McxFluence, NWError> {
+    import_inner(Path::new(path)).map_err(NWError::Parse)
+}
+
+fn import_inner(path: &Path) -> Result<McxFluence, String> {
+    let session_file = path.with_extension("json");
+    let session = session_file
+        .exists()
+        .then(|| read_session(&session_file))
+        .transpose()?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let (dims, t0, gate_width, data) = match extension.as_deref() {
+        Some("jnii") => {
+            let (header_dims, dt, data) = read_jnii(path)?;
+            if header_dims.len() < 3 {
+                return Err("NIFTIHeader.Dim needs 3 spatial dimensions".into());
+            }
+            match session {
+                Some((dims, t0, dt)) => (dims, t0, dt, data),
+                None => (std::array::from_fn(|a| header_dims[a]), 0.0, dt, data),
+            }
+        }
+        Some("mc2") => {
+            let (dims, t0, dt) = session.ok_or_else(|| {
+                format!(
+                    "'{}' needs its session file '{}' for the grid and time gates",
+                    path.display(),
+                    session_file.display()
+                )
+            })?;
+            let bytes = std::fs::read(path)
+                .map_err(|e| format!("failed to read '{}': {e}", path.display()))?;
+            (dims, t0, dt, to_f32("single", &bytes)?)
+        }
+        _ => return Err("MCX fluence must be .jnii or .mc2".into()),
+    };
+
+    let voxels: usize = dims.iter().product();
+    if voxels == 0 || data.is_empty() || data.len() % voxels != 0 {
+        return Err(format!(
+            "{} values do not fill whole {}×{}×{} gates",
+            data.len(),
+            dims[0],
+            dims[1],
+            dims[2]
+        ));
+    }
+    if !gate_width.is_finite() || gate_width <= 0.0 {
+        return Err("the gate width (Forward.Dt) must be positive".into());
+    }
+    Ok(McxFluence {
+        dims,
+        gates: data.len() / voxels,
+        t0,
+        gate_width,
+        data,
+    })
+}
@@ -0,0 +1,23 @@
+use crate::domain::mesh::Mesh;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Write `mesh` to `path` as Wavefront OBJ with its vertex normals, as
+/// [`load_mesh`](crate::io::mesh_importer::load_mesh) reads it back.
+pub fn export_obj(mesh: &Mesh, path: &Path) -> Result<(), String> {
+    let geometry = &mesh.geometry;
+    let mut text = format!("# {}\no {}\n", path.display(), mesh.name);
+    for v in &geometry.verts {
+        let p = v.position;
+        let _ = writeln!(text, "v {:.4} {:.4} {:.4}", p.x, p.y, p.z);
+    }
+    for v in &geometry.verts {
+        let n = v.normal;
+        let _ = writeln!(text, "vn {:.5} {:.5} {:.5}", n.x, n.y, n.z);
+    }
+    for t in geometry.indices.chunks_exact(3) {
+        let [a, b, c] = [t[0] + 1, t[1] + 1, t[2] + 1];
+        let _ = writeln!(text, "f {a}//{a} {b}//{b} {c}//{c}");
+    }
+    std::fs::write(path, text).map_err(|e| format!("failed to write '{}': {e}", path.display()))
+}
@@ -3,7 +3,7 @@ use std::path::Path;
 use nalgebra::{Vector2, Vector3};
 use uuid::Uuid;
 
-use crate::domain::mesh::{build_kdtree, Mesh, MeshGeometry, MeshTopology, Vertex};
+use crate::domain::mesh::{Mesh, MeshGeometry, MeshTopology, Vertex};
 
 pub fn load_mesh(filepath: &str) -> Result<Mesh, String> {
     let load_options = tobj::LoadOptions {
@@ -66,35 +66,18 @@ pub fn load_mesh(filepath: &str) -> Result<Mesh, String> {
         })
         .collect();
 
-    // Build parry3d TriMesh (uses f32)
-    let trimesh = if !positions.is_empty() && indices.len() >= 3 {
-        let parry_verts: Vec<parry3d::math::Vector> = positions
-            .iter()
-            .map(|v| parry3d::math::Vector::new(v.x as f32, v.y as f32, v.z as f32))
-            .collect();
-        let tri_indices: Vec<[u32; 3]> = indices
-            .chunks(3)
-            .filter(|c| c.len() == 3)
-            .map(|c| [c[0], c[1], c[2]])
-            .collect();
-        parry3d::shape::TriMesh::new(parry_verts, tri_indices).ok()
-    } else {
-        None
-    };
-
-    let kdtree = build_kdtree(&positions);
-
     let name = Path::new(filepath)
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("unknown")
         .to_string();
 
+    let geometry = MeshGeometry { verts, indices };
     Ok(Mesh {
         id: Uuid::new_v4(),
         name,
         filepath: filepath.to_string(),
-        geometry: MeshGeometry { verts, indices },
-        topology: MeshTopology { trimesh, kdtree },
+        topology: MeshTopology::build(&geometry),
+        geometry,
     })
 }
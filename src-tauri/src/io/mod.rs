@@ -1,4 +1,20 @@
 pub mod anatomy_importer;
+pub mod atlas_importer;
+pub mod bids_exporter;
+pub mod event_table_importer;
+pub mod hitachi_importer;
+pub mod homer_importer;
+pub mod matlab;
+pub mod mcx_exporter;
+pub mod mcx_importer;
+pub mod mesh_exporter;
 pub mod mesh_importer;
+pub mod nirx_importer;
+pub mod position_exporter;
+pub mod recording_importer;
+pub mod sensitivity_exporter;
+pub mod sensitivity_importer;
+pub mod shimadzu_importer;
 pub mod snirf_exporter;
 pub mod snirf_parser;
+pub mod timeseries_exporter;
@@ -0,0 +1,426 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{DataBlock, EventMarker, NirsEntry, Optode, Snirf};
+use crate::io::matlab::{read_mat, MatValue};
+use crate::io::recording_importer::{
+    group_events, metadata, numbered_probe, optode, probe, raw_measurement, read_text,
+    single_entry, uniform_time,
+};
+use std::path::{Path, PathBuf};
+
+/// The key/value pairs of a NIRStar `.hdr` file, by section. Values are
+/// unquoted; `"# … #"` tables keep their lines.
+struct Header {
+    entries: Vec<(String, String, String)>,
+}
+
+impl Header {
+    fn parse(text: &str) -> Self {
+        let mut entries = Vec::new();
+        let mut section = String::new();
+        let mut table: Option<(String, String)> = None;
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if let Some((key, value)) = table.as_mut() {
+                if trimmed == "#\"" {
+                    entries.push((section.clone(), std::mem::take(key), std::mem::take(value)));
+                    table = None;
+                } else {
+                    value.push_str(trimmed);
+                    value.push('\n');
+                }
+                continue;
+            }
+            if let Some(name) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                section = name.to_string();
+            } else if let Some((key, value)) = trimmed.split_once('=') {
+                let value = value.trim();
+                if value == "\"#" {
+                    table = Some((key.trim().to_string(), String::new()));
+                } else {
+                    entries.push((
+                        section.clone(),
+                        key.trim().to_string(),
+                        value.trim_matches('"').to_string(),
+                    ));
+                }
+            }
+        }
+        Header { entries }
+    }
+
+    fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(s, k, _)| s == section && k == key)
+            .map(|(_, _, v)| v.as_str())
+    }
+
+    fn number(&self, section: &str, key: &str) -> Option<f64> {
+        self.get(section, key)?.trim().parse().ok()
+    }
+
+    /// A `"# … #"` table as rows of numbers.
+    fn table(&self, section: &str, key: &str) -> Vec<Vec<f64>> {
+        self.get(section, key).map(numeric_rows).unwrap_or_default()
+    }
+}
+
+fn numeric_rows(text: &str) -> Vec<Vec<f64>> {
+    text.lines()
+        .map(|l| {
+            l.split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect::<Vec<f64>>()
+        })
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+/// The `.hdr` file for a NIRx folder or any file in it.
+fn header_path(path: &str) -> Result<PathBuf, NWError> {
+    let p = Path::new(path);
+    if p.is_dir() {
+        let mut headers: Vec<PathBuf> = std::fs::read_dir(p)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|f| f.extension().is_some_and(|e| e.eq_ignore_ascii_case("hdr")))
+            .collect();
+        headers.sort();
+        return headers
+            .into_iter()
+            .next()
+            .ok_or_else(|| NWError::InvalidInput(format!("{path}: no NIRx .hdr file in folder")));
+    }
+    let hdr = p.with_extension("hdr");
+    if hdr.is_file() {
+        Ok(hdr)
+    } else {
+        Err(NWError::InvalidInput(format!(
+            "{path}: no {} beside it",
+            hdr.display()
+        )))
+    }
+}
+
+/// `"1-1:1,1-2:2,…"`: source and detector (1-based) of each data column,
+/// in column order.
+fn sd_key(key: &str) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(usize, (usize, usize))> = key
+        .split(',')
+        .filter_map(|item| {
+            let (sd, column) = item.trim().split_once(':')?;
+            let (s, d) = sd.split_once('-')?;
+            Some((column.parse().ok()?, (s.parse().ok()?, d.parse().ok()?)))
+        })
+        .collect();
+    pairs.sort_by_key(|&(column, _)| column);
+    pairs.into_iter().map(|(_, sd)| sd).collect()
+}
+
+/// "Mon, 14. Oct 2019" or "2019-10-14" as `YYYY-MM-DD`.
+fn iso_date(text: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let tokens: Vec<&str> = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let numbers: Vec<u32> = tokens.iter().filter_map(|t| t.parse().ok()).collect();
+    let year = *numbers.iter().find(|&&n| n >= 1000)?;
+    let month = tokens
+        .iter()
+        .find_map(|t| {
+            let t = t.to_ascii_lowercase();
+            MONTHS
+                .iter()
+                .position(|m| t.starts_with(m))
+                .map(|m| m as u32 + 1)
+        })
+        .or_else(|| {
+            numbers
+                .iter()
+                .copied()
+                .find(|&n| n != year && (1..=12).contains(&n))
+        })?;
+    let day = numbers
+        .iter()
+        .rev()
+        .copied()
+        .find(|&n| n != year && (1..=31).contains(&n))?;
+    Some(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+/// Trigger events from the `.evt` file (frame, then one column per input
+/// bit) or, failing that, the header's `[Markers]` table (time, code,
+/// frame). Each code becomes condition `{code}`.
+fn read_events(base: &Path, header: &Header, rate: f64) -> Vec<crate::domain::snirf::Event> {
+    let evt = base.with_extension("evt");
+    let rows: Vec<(f64, u32)> = match std::fs::read_to_string(&evt) {
+        Ok(text) => numeric_rows(&text)
+            .into_iter()
+            .map(|r| {
+                let code = r[1..]
+                    .iter()
+                    .enumerate()
+                    .map(|(bit, &v)| u32::from(v != 0.0) << bit)
+                    .sum();
+                (r[0] / rate, code)
+            })
+            .collect(),
+        Err(_) => header
+            .table("Markers", "Events")
+            .into_iter()
+            .filter(|r| r.len() >= 2)
+            .map(|r| (r[0], r[1] as u32))
+            .collect(),
+    };
+    group_events(
+        rows.into_iter()
+            .filter(|&(_, code)| code != 0)
+            .map(|(onset, code)| {
+                (
+                    code.to_string(),
+                    EventMarker {
+                        onset,
+                        duration: 0.0,
+                        value: code as f64,
+                    },
+                )
+            }),
+    )
+}
+
+/// Source and detector positions from NIRStar's `*probeInfo.mat`
+/// (`probeInfo.probes.coords_s3`, `coords_d3`, `coords_s2`, `coords_d2`,
+/// in cm).
+fn read_probe_info(dir: &Path) -> Option<(Vec<Optode>, Vec<Optode>)> {
+    let file = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .find(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with("probeInfo.mat"))
+        })?;
+    let variables = read_mat(file.to_str()?).ok()?;
+    let (_, info) = variables.iter().find(|(n, _)| n == "probeInfo")?;
+    let probes = info.field("probes")?;
+    let rows = |field: &str| {
+        probes
+            .field(field)
+            .map(MatValue::to_rows)
+            .unwrap_or_default()
+    };
+    let optodes = |prefix: &str, three: Vec<Vec<f64>>, two: Vec<Vec<f64>>| -> Vec<Optode> {
+        (0..three.len().max(two.len()))
+            .map(|i| {
+                let p3 = three
+                    .get(i)
+                    .filter(|r| r.len() >= 3)
+                    .map(|r| [r[0], r[1], r[2]]);
+                let p2 = two.get(i).filter(|r| r.len() >= 2).map(|r| [r[0], r[1]]);
+                optode(prefix, i, p2, p3)
+            })
+            .collect()
+    };
+    Some((
+        optodes("S", rows("coords_s3"), rows("coords_s2")),
+        optodes("D", rows("coords_d3"), rows("coords_d2")),
+    ))
+}
+
+/// Read a NIRx NIRScout/NIRSport recording folder written by NIRStar: the
+/// `.hdr` header, one `.wl1`, `.wl2`, … intensity matrix per wavelength
+/// (frames × every source–detector combination), the `.evt` triggers and,
+/// when present, the probe geometry. Only the channels set in the header's
+/// `S-D-Mask` are kept. `path` may be the folder or any file in it.
+pub fn parse_nirx(path: &str) -> Result<Snirf, NWError> {
+    let hdr = header_path(path)?;
+    let header = Header::parse(&read_text(&hdr.to_string_lossy())?);
+    let parse_err = |what: &str| NWError::Parse(format!("{}: {what}", hdr.display()));
+
+    let rate = header
+        .number("ImagingParameters", "SamplingRate")
+        .filter(|r| *r > 0.0)
+        .ok_or_else(|| parse_err("no SamplingRate"))?;
+    let wavelengths: Vec<f64> = header
+        .get("ImagingParameters", "Wavelengths")
+        .map(|w| {
+            w.split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    if wavelengths.is_empty() {
+        return Err(parse_err("no Wavelengths"));
+    }
+    let columns = sd_key(header.get("DataStructure", "S-D-Key").unwrap_or_default());
+    if columns.is_empty() {
+        return Err(parse_err("no S-D-Key"));
+    }
+    let mask = header.table("DataStructure", "S-D-Mask");
+    let measured = |&(s, d): &(usize, usize)| {
+        mask.is_empty()
+            || s.checked_sub(1)
+                .zip(d.checked_sub(1))
+                .and_then(|(s, d)| mask.get(s)?.get(d))
+                .is_some_and(|&m| m != 0.0)
+    };
+
+    let mut measurements = Vec::new();
+    let mut samples = None;
+    for wl in 1..=wavelengths.len() {
+        let file = hdr.with_extension(format!("wl{wl}"));
+        let rows = numeric_rows(&read_text(&file.to_string_lossy())?);
+        if rows.iter().any(|r| r.len() != columns.len()) {
+            return Err(NWError::Parse(format!(
+                "{}: expected {} columns per frame",
+                file.display(),
+                columns.len()
+            )));
+        }
+        if samples.is_some_and(|n| n != rows.len()) {
+            return Err(NWError::Parse(format!(
+                "{}: frame count differs from .wl1",
+                file.display()
+            )));
+        }
+        samples = Some(rows.len());
+        for (c, sd) in columns.iter().enumerate().filter(|(_, sd)| measured(sd)) {
+            let data = rows.iter().map(|r| r[c]).collect();
+            measurements.push(raw_measurement(sd.0, sd.1, wl, data));
+        }
+    }
+    // Channels by source and detector, wavelengths within a channel.
+    measurements.sort_by_key(|m| (m.source_index, m.detector_index, m.wavelength_index));
+
+    let n_sources = header.number("ImagingParameters", "Sources").unwrap_or(0.0) as usize;
+    let n_detectors = header
+        .number("ImagingParameters", "Detectors")
+        .unwrap_or(0.0) as usize;
+    let dir = hdr.parent().unwrap_or(Path::new("."));
+    let (probe, length_unit) = match read_probe_info(dir) {
+        Some((sources, detectors)) if !sources.is_empty() => {
+            (probe(wavelengths, sources, detectors), "cm")
+        }
+        _ => {
+            let n = n_sources.max(n_detectors);
+            let mut probe = numbered_probe(n, wavelengths, 30.0);
+            probe.sources.truncate(n_sources);
+            probe.detectors.truncate(n_detectors);
+            (probe, "mm")
+        }
+    };
+
+    let general = |key: &str| header.get("GeneralInfo", key).map(str::to_string);
+    let metadata = metadata(
+        general("Subject"),
+        general("Date").map(|d| iso_date(&d).unwrap_or(d)),
+        general("Time"),
+        length_unit,
+        vec![
+            ("ManufacturerName", "NIRx".to_string()),
+            ("Model", general("Device").unwrap_or_default()),
+        ],
+    );
+
+    let entry = NirsEntry {
+        metadata,
+        data_blocks: vec![DataBlock {
+            time: uniform_time(samples.unwrap_or(0), 1.0 / rate),
+            measurements,
+        }],
+        probe,
+        events: read_events(&hdr, &header, rate),
+        auxiliaries: Vec::new(),
+        provenance: Vec::new(),
+    };
+    Ok(single_entry(&hdr.to_string_lossy(), entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::recording_importer::tests::{fixture, fixture_dir};
+
+    const HEADER: &str = r##"[GeneralInfo]
+FileName="run"
+Date="Mon, 14. Oct 2019"
+Time="10:11:12"
+Device="NIRScout"
+Subject=7
+[ImagingParameters]
+Sources=2
+Detectors=2
+Wavelengths="760 850"
+SamplingRate=10
+[DataStructure]
+S-D-Key="1-1:1,1-2:2,2-1:3,2-2:4,"
+S-D-Mask="#
+1	1
+0	1
+#"
+"##;
+
+    #[test]
+    fn reads_a_nirstar_folder() {
+        let dir = fixture_dir("nirx");
+        fixture(&dir, "run.hdr", HEADER);
+        let wl1 = fixture(&dir, "run.wl1", "1 2 3 4\n5 6 7 8\n9 10 11 12\n");
+        fixture(&dir, "run.wl2", "21 22 23 24\n25 26 27 28\n29 30 31 32\n");
+        // Frame, then one column per trigger bit.
+        fixture(&dir, "run.evt", "2\t1\t0\n3\t0\t1\n");
+
+        let snirf = parse_nirx(&dir.to_string_lossy()).unwrap();
+        let entry = &snirf.nirs_entries[0];
+        let block = &entry.data_blocks[0];
+        assert_eq!(block.time, [0.0, 0.1, 0.2]);
+        // S2-D1 is masked out.
+        let channels: Vec<_> = block
+            .measurements
+            .iter()
+            .map(|m| (m.source_index, m.detector_index, m.wavelength_index))
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                (1, 1, Some(1)),
+                (1, 1, Some(2)),
+                (1, 2, Some(1)),
+                (1, 2, Some(2)),
+                (2, 2, Some(1)),
+                (2, 2, Some(2)),
+            ]
+        );
+        assert_eq!(block.measurements[1].data, [21.0, 25.0, 29.0]);
+        assert_eq!(block.measurements[4].data, [4.0, 8.0, 12.0]);
+
+        assert_eq!(entry.probe.wavelengths, [760.0, 850.0]);
+        assert_eq!(entry.probe.sources.len(), 2);
+        assert_eq!(entry.probe.detectors.len(), 2);
+        let tag = |name: &str| {
+            entry
+                .metadata
+                .iter()
+                .find(|t| t.name == name)
+                .map(|t| t.value.as_str())
+        };
+        assert_eq!(tag("SubjectID"), Some("7"));
+        assert_eq!(tag("MeasurementDate"), Some("2019-10-14"));
+        assert_eq!(tag("Model"), Some("NIRScout"));
+
+        let events: Vec<_> = entry
+            .events
+            .iter()
+            .map(|e| (e.name.as_str(), e.markers[0].onset))
+            .collect();
+        assert_eq!(events, [("1", 0.2), ("2", 0.3)]);
+
+        // Any file of the folder opens the same recording.
+        let from_file = parse_nirx(&wl1).unwrap();
+        assert_eq!(
+            from_file.nirs_entries[0].data_blocks[0].measurements.len(),
+            6
+        );
+    }
+}
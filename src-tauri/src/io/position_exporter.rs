@@ -0,0 +1,101 @@
+use crate::domain::error::NWError;
+use serde::Deserialize;
+use std::fmt::Write as _;
+
+/// File formats for sensor positions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionFormat {
+    /// ASA/EEProbe `.elc`.
+    Elc,
+    /// BESA/EEGLAB `.sfp`.
+    Sfp,
+    /// BIDS `*_optodes.tsv`.
+    Tsv,
+}
+
+/// One row of a positions file, in millimetres.
+pub struct PositionRow {
+    pub name: String,
+    /// BIDS optode type: `source` or `detector`.
+    pub kind: Option<&'static str>,
+    pub position: [f64; 3],
+    /// Written to the BIDS `description` column, e.g. the nearest 10-10
+    /// position.
+    pub description: Option<String>,
+}
+
+fn elc(rows: &[PositionRow]) -> String {
+    let mut text = String::from("# ASA electrode file\nReferenceLabel\tavg\nUnitPosition\tmm\n");
+    let _ = writeln!(text, "NumberPositions=\t{}", rows.len());
+    text.push_str("Positions\n");
+    for r in rows {
+        let [x, y, z] = r.position;
+        let _ = writeln!(text, "{x:.4}\t{y:.4}\t{z:.4}");
+    }
+    text.push_str("Labels\n");
+    for r in rows {
+        let _ = writeln!(text, "{}", r.name);
+    }
+    text
+}
+
+fn sfp(rows: &[PositionRow]) -> String {
+    rows.iter().fold(String::new(), |mut text, r| {
+        let [x, y, z] = r.position;
+        let _ = writeln!(text, "{}\t{x:.4}\t{y:.4}\t{z:.4}", r.name);
+        text
+    })
+}
+
+/// BIDS requires `name`, `type`, `x`, `y`, `z`; missing values are `n/a`.
+fn optodes_tsv(rows: &[PositionRow]) -> String {
+    let described = rows.iter().any(|r| r.description.is_some());
+    let mut text = String::from("name\ttype\tx\ty\tz");
+    if described {
+        text.push_str("\tdescription");
+    }
+    text.push('\n');
+    for r in rows {
+        let [x, y, z] = r.position;
+        let _ = write!(
+            text,
+            "{}\t{}\t{x}\t{y}\t{z}",
+            r.name,
+            r.kind.unwrap_or("n/a")
+        );
+        if described {
+            let _ = write!(text, "\t{}", r.description.as_deref().unwrap_or("n/a"));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Write `rows` to `path` as `format`. Names must not contain whitespace
+/// for `.elc`/`.sfp`; it is replaced by `_`.
+pub fn export_positions(
+    rows: &[PositionRow],
+    path: &str,
+    format: PositionFormat,
+) -> Result<(), NWError> {
+    if rows.is_empty() {
+        return Err(NWError::InvalidInput("no positions to export".into()));
+    }
+    let cleaned: Vec<PositionRow> = rows
+        .iter()
+        .map(|r| PositionRow {
+            name: r.name.split_whitespace().collect::<Vec<_>>().join("_"),
+            kind: r.kind,
+            position: r.position,
+            description: r.description.clone(),
+        })
+        .collect();
+    let text = match format {
+        PositionFormat::Elc => elc(&cleaned),
+        PositionFormat::Sfp => sfp(&cleaned),
+        PositionFormat::Tsv => optodes_tsv(&cleaned),
+    };
+    std::fs::write(path, text)?;
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{
+    Event, EventMarker, FileDescriptor, Measurement, MetadataTag, NirsEntry, Optode, Probe, Snirf,
+};
+use crate::io::{hitachi_importer, homer_importer, nirx_importer, shimadzu_importer, snirf_parser};
+use nalgebra::{Vector2, Vector3};
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+/// Recording formats that can be opened. Everything but SNIRF is mapped
+/// into the SNIRF domain model on import.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    Snirf,
+    /// NIRx NIRStar/NIRSport raw folder: `.hdr`, `.wl1`, `.wl2`, `.evt`.
+    Nirx,
+    /// Homer2/3 `.nirs` (a MATLAB Level 5 MAT-file).
+    Homer,
+    /// Hitachi ETG-4000/7000 CSV export.
+    Hitachi,
+    /// Shimadzu OMM/LABNIRS text export.
+    Shimadzu,
+}
+
+const HDF5_SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+
+fn head(path: &Path, len: usize) -> Result<Vec<u8>, NWError> {
+    let mut buf = Vec::with_capacity(len);
+    std::fs::File::open(path)?
+        .take(len as u64)
+        .read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Work out the format of `path`: a NIRx folder or any of its files, or a
+/// file recognised by its signature, its extension and — for the text
+/// exports — its first lines.
+pub fn detect_format(path: &str) -> Result<RecordingFormat, NWError> {
+    let p = Path::new(path);
+    if p.is_dir() {
+        return Ok(RecordingFormat::Nirx);
+    }
+    let head = head(p, 4096)?;
+    let ext = p
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    // MAT v7.3 `.nirs` files are HDF5 too.
+    if head.starts_with(HDF5_SIGNATURE) && ext != "nirs" {
+        return Ok(RecordingFormat::Snirf);
+    }
+    if head.starts_with(HDF5_SIGNATURE) || head.starts_with(b"MATLAB ") {
+        return Ok(RecordingFormat::Homer);
+    }
+    if matches!(ext.as_str(), "hdr" | "wl1" | "wl2" | "evt") {
+        return Ok(RecordingFormat::Nirx);
+    }
+
+    let text = String::from_utf8_lossy(&head);
+    let first = text.trim_start_matches('\u{feff}').trim_start();
+    if first.starts_with("Header") || text.contains("Probe1") && text.contains("Wave") {
+        return Ok(RecordingFormat::Hitachi);
+    }
+    if first.starts_with("[File Information]") || text.contains("Time(sec)") {
+        return Ok(RecordingFormat::Shimadzu);
+    }
+    match ext.as_str() {
+        "snirf" => Ok(RecordingFormat::Snirf),
+        "nirs" => Ok(RecordingFormat::Homer),
+        "csv" => Ok(RecordingFormat::Hitachi),
+        "txt" => Ok(RecordingFormat::Shimadzu),
+        _ => Err(NWError::InvalidInput(format!(
+            "{path}: unrecognised recording format"
+        ))),
+    }
+}
+
+/// Open a recording in any supported format.
+pub fn import_recording(path: &str) -> Result<Snirf, NWError> {
+    match detect_format(path)? {
+        RecordingFormat::Snirf => snirf_parser::parse_snirf(path),
+        RecordingFormat::Nirx => nirx_importer::parse_nirx(path),
+        RecordingFormat::Homer => homer_importer::parse_homer_nirs(path),
+        RecordingFormat::Hitachi => hitachi_importer::parse_hitachi_csv(path),
+        RecordingFormat::Shimadzu => shimadzu_importer::parse_shimadzu_txt(path),
+    }
+}
+
+// =============================================================================
+// Shared by the vendor importers
+// =============================================================================
+
+/// Wrap an imported entry as a single-entry SNIRF recording.
+pub(crate) fn single_entry(path: &str, entry: NirsEntry) -> Snirf {
+    Snirf {
+        format_version: "1.1".into(),
+        file_descriptor: FileDescriptor {
+            filepath: path.to_string(),
+            filename: Path::new(path)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown")
+                .to_string(),
+        },
+        nirs_entries: vec![entry],
+    }
+}
+
+/// The tags SNIRF requires, with `"unknown"` for what the file lacks,
+/// followed by `extra`.
+pub(crate) fn metadata(
+    subject: Option<String>,
+    date: Option<String>,
+    time: Option<String>,
+    length_unit: &str,
+    extra: Vec<(&str, String)>,
+) -> Vec<MetadataTag> {
+    let unknown = || "unknown".to_string();
+    [
+        ("SubjectID", subject.unwrap_or_else(unknown)),
+        ("MeasurementDate", date.unwrap_or_else(unknown)),
+        ("MeasurementTime", time.unwrap_or_else(unknown)),
+        ("LengthUnit", length_unit.to_string()),
+        ("TimeUnit", "s".to_string()),
+        ("FrequencyUnit", "Hz".to_string()),
+    ]
+    .into_iter()
+    .chain(extra)
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| MetadataTag {
+        name: name.to_string(),
+        value,
+    })
+    .collect()
+}
+
+/// Optode `id` (0-based) named `{prefix}{id + 1}`. Positions follow the
+/// SNIRF parser: a missing 2D position is the 3D one seen from above, a
+/// missing 3D one the 2D one at z = 0, and 2D y is flipped to screen
+/// orientation.
+pub(crate) fn optode(
+    prefix: &str,
+    id: usize,
+    pos_2d: Option<[f64; 2]>,
+    pos_3d: Option<[f64; 3]>,
+) -> Optode {
+    let [x, y] = pos_2d.or(pos_3d.map(|p| [p[0], p[1]])).unwrap_or([0.0; 2]);
+    let p3 = pos_3d.unwrap_or([x, y, 0.0]);
+    Optode {
+        name: format!("{prefix}{}", id + 1),
+        id,
+        pos_2d: Vector2::new(x, -y),
+        pos_3d: Vector3::new(p3[0], p3[1], p3[2]),
+    }
+}
+
+pub(crate) fn probe(wavelengths: Vec<f64>, sources: Vec<Optode>, detectors: Vec<Optode>) -> Probe {
+    Probe {
+        wavelengths,
+        wavelength_emission: None,
+        sources,
+        detectors,
+        landmarks: None,
+        coordinate_system: None,
+        coordinate_system_description: None,
+        use_local_index: None,
+    }
+}
+
+/// Probe for files that only number their channels: channel `k` gets
+/// source `k` and detector `k`, laid out `spacing` apart on a grid of
+/// rows of eight so the channels stay distinguishable.
+pub(crate) fn numbered_probe(channels: usize, wavelengths: Vec<f64>, spacing: f64) -> Probe {
+    let position = |k: usize, dx: f64| {
+        let (row, col) = ((k / 8) as f64, (k % 8) as f64);
+        Some([col * spacing + dx, -row * spacing])
+    };
+    let sources = (0..channels)
+        .map(|k| optode("S", k, position(k, 0.0), None))
+        .collect();
+    let detectors = (0..channels)
+        .map(|k| optode("D", k, position(k, spacing / 2.0), None))
+        .collect();
+    probe(wavelengths, sources, detectors)
+}
+
+/// A continuous-wave intensity measurement (`dataType` 1).
+pub(crate) fn raw_measurement(
+    source: usize,
+    detector: usize,
+    wavelength_index: usize,
+    data: Vec<f64>,
+) -> Measurement {
+    Measurement {
+        source_index: source,
+        detector_index: detector,
+        wavelength_index: Some(wavelength_index),
+        data_type: 1,
+        data_type_label: String::new(),
+        data_type_index: 1,
+        data_unit: None,
+        data,
+        wavelength_actual: None,
+        source_power: None,
+        detector_gain: None,
+        module_index: None,
+    }
+}
+
+/// A processed haemoglobin measurement (`dataType` 99999), labelled
+/// `HbO`, `HbR` or `HbT`.
+pub(crate) fn hemoglobin_measurement(
+    source: usize,
+    detector: usize,
+    label: &str,
+    unit: Option<&str>,
+    data: Vec<f64>,
+) -> Measurement {
+    Measurement {
+        source_index: source,
+        detector_index: detector,
+        wavelength_index: None,
+        data_type: 99999,
+        data_type_label: label.to_string(),
+        data_type_index: 1,
+        data_unit: unit.map(str::to_string),
+        data,
+        wavelength_actual: None,
+        source_power: None,
+        detector_gain: None,
+        module_index: None,
+    }
+}
+
+/// Time points `0, dt, 2·dt, …`.
+pub(crate) fn uniform_time(samples: usize, dt: f64) -> Vec<f64> {
+    (0..samples).map(|i| i as f64 * dt).collect()
+}
+
+/// Group `(condition, marker)` pairs into events, conditions in order of
+/// first appearance and markers by onset.
+pub(crate) fn group_events(markers: impl IntoIterator<Item = (String, EventMarker)>) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    for (name, marker) in markers {
+        match events.iter_mut().find(|e| e.name == name) {
+            Some(event) => event.markers.push(marker),
+            None => events.push(Event {
+                name,
+                markers: vec![marker],
+            }),
+        }
+    }
+    for event in &mut events {
+        event.markers.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+    }
+    events
+}
+
+/// Events from a per-sample mark column, as Hitachi and Shimadzu write
+/// them: a marker wherever the mark changes to a non-empty, non-zero
+/// value. Numeric marks become `{prefix}_{code}` with value = code.
+pub(crate) fn events_from_marks(time: &[f64], marks: &[&str], prefix: &str) -> Vec<Event> {
+    let idle = |m: &str| m.is_empty() || m.parse::<f64>().is_ok_and(|v| v == 0.0);
+    let mut previous = "";
+    let mut markers = Vec::new();
+    for (&t, &mark) in time.iter().zip(marks) {
+        let mark = mark.trim();
+        if !idle(mark) && mark != previous {
+            let (name, value) = match mark.parse::<f64>() {
+                Ok(code) => (format!("{prefix}_{mark}"), code),
+                Err(_) => (mark.to_string(), 1.0),
+            };
+            markers.push((
+                name,
+                EventMarker {
+                    onset: t,
+                    duration: 0.0,
+                    value,
+                },
+            ));
+        }
+        previous = mark;
+    }
+    group_events(markers)
+}
+
+/// What a vendor column header such as `CH3(830.0)`, `CH3(Oxy)` or
+/// `CH3 oxyHb` holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColumnKind {
+    Wavelength(f64),
+    /// `HbO`, `HbR` or `HbT`.
+    Hemoglobin(&'static str),
+}
+
+/// Classify a haemoglobin or wavelength label (`oxyHb`, `Deoxy`, `total`,
+/// `695.2`).
+pub(crate) fn column_kind(label: &str) -> Option<ColumnKind> {
+    let l = label.trim().to_ascii_lowercase();
+    if let Ok(nm) = l.trim_end_matches("nm").trim().parse::<f64>() {
+        return Some(ColumnKind::Wavelength(nm));
+    }
+    if l.starts_with("deoxy") || l == "hbr" || l == "hhb" {
+        Some(ColumnKind::Hemoglobin("HbR"))
+    } else if l.starts_with("oxy") || l == "hbo" || l == "o2hb" {
+        Some(ColumnKind::Hemoglobin("HbO"))
+    } else if l.starts_with("total") || l == "hbt" {
+        Some(ColumnKind::Hemoglobin("HbT"))
+    } else {
+        None
+    }
+}
+
+/// Split a header like `CH12(695.0)` or `CH12 oxyHb` into the 1-based
+/// channel number and what the column holds.
+pub(crate) fn channel_column(header: &str) -> Option<(usize, ColumnKind)> {
+    let h = header.trim();
+    let rest = h.get(..2)?.eq_ignore_ascii_case("ch").then(|| &h[2..])?;
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let channel = rest[..digits].parse().ok().filter(|&c| c > 0)?;
+    let label = rest[digits..]
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')');
+    Some((channel, column_kind(label)?))
+}
+
+/// Nominal wavelengths for measured ones that scatter per channel (695.2,
+/// 697.8, 829.6, …): values within 20 nm of each other are one band,
+/// reported as its rounded mean.
+pub(crate) fn wavelength_bands(measured: impl IntoIterator<Item = f64>) -> Vec<f64> {
+    let mut values: Vec<f64> = measured.into_iter().filter(|v| v.is_finite()).collect();
+    values.sort_by(f64::total_cmp);
+    let mut bands: Vec<Vec<f64>> = Vec::new();
+    for v in values {
+        match bands.last_mut() {
+            Some(band) if v - band[0] <= 20.0 => band.push(v),
+            _ => bands.push(vec![v]),
+        }
+    }
+    bands
+        .iter()
+        .map(|b| (b.iter().sum::<f64>() / b.len() as f64).round())
+        .collect()
+}
+
+/// Measurements for `(channel, kind, data)` columns, ordered by channel.
+/// `pair` maps a 1-based channel number to its source and detector.
+/// Wavelength columns are assigned the nearest of `wavelengths` and keep
+/// the measured value as `wavelength_actual`.
+pub(crate) fn channel_measurements(
+    mut columns: Vec<(usize, ColumnKind, Vec<f64>)>,
+    wavelengths: &[f64],
+    pair: impl Fn(usize) -> (usize, usize),
+    hemoglobin_unit: Option<&str>,
+) -> Vec<Measurement> {
+    columns.sort_by_key(|&(channel, _, _)| channel);
+    columns
+        .into_iter()
+        .map(|(channel, kind, data)| {
+            let (source, detector) = pair(channel);
+            match kind {
+                ColumnKind::Wavelength(nm) => {
+                    let index = (0..wavelengths.len())
+                        .min_by(|&a, &b| {
+                            (wavelengths[a] - nm)
+                                .abs()
+                                .total_cmp(&(wavelengths[b] - nm).abs())
+                        })
+                        .unwrap_or(0);
+                    let mut m = raw_measurement(source, detector, index + 1, data);
+                    m.wavelength_actual = Some(nm);
+                    m
+                }
+                ColumnKind::Hemoglobin(label) => {
+                    hemoglobin_measurement(source, detector, label, hemoglobin_unit, data)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Read a vendor text export. These are often written in a legacy code
+/// page; anything that is not UTF-8 is replaced rather than rejected.
+pub(crate) fn read_text(path: &str) -> Result<String, NWError> {
+    let bytes = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+/// Parse a numeric cell; blanks and non-numbers are NaN.
+pub(crate) fn number(cell: &str) -> f64 {
+    cell.trim().parse().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh, empty directory for one test's fixture files.
+    pub(crate) fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nirwizard-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write `contents` to `dir/name`, returning the path.
+    pub(crate) fn fixture(dir: &Path, name: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn detect_format_by_signature_extension_and_content() {
+        let dir = fixture_dir("detect");
+        let detect = |name: &str, contents: &[u8]| detect_format(&fixture(&dir, name, contents));
+
+        let mut hdf5 = HDF5_SIGNATURE.to_vec();
+        hdf5.extend_from_slice(&[0; 32]);
+        assert_eq!(detect("run.snirf", &hdf5).unwrap(), RecordingFormat::Snirf);
+        assert_eq!(detect("run.h5", &hdf5).unwrap(), RecordingFormat::Snirf);
+        assert_eq!(detect("run.nirs", &hdf5).unwrap(), RecordingFormat::Homer);
+        let mat = b"MATLAB 5.0 MAT-file, Platform: GLNXA64";
+        assert_eq!(detect("run.dat", mat).unwrap(), RecordingFormat::Homer);
+        let mat73 = b"MATLAB 7.3 MAT-file, Platform: GLNXA64";
+        assert_eq!(detect("run.dat", mat73).unwrap(), RecordingFormat::Homer);
+        assert_eq!(detect("run.wl1", b"1 2 3").unwrap(), RecordingFormat::Nirx);
+        assert_eq!(
+            detect("run.txt", b"\xef\xbb\xbfHeader,,,\nMode,3x3\n").unwrap(),
+            RecordingFormat::Hitachi
+        );
+        assert_eq!(
+            detect("run.csv", b"[File Information]\nID\ts01\n").unwrap(),
+            RecordingFormat::Shimadzu
+        );
+        assert_eq!(
+            detect("export", b"ID\ts01\nTime(sec)\tCH1\tCH2\n").unwrap(),
+            RecordingFormat::Shimadzu
+        );
+        // Nothing recognisable in the text: the extension decides.
+        assert_eq!(detect("run.snirf", b"?").unwrap(), RecordingFormat::Snirf);
+        assert_eq!(detect("run.nirs", b"?").unwrap(), RecordingFormat::Homer);
+        assert_eq!(detect("run.csv", b"?").unwrap(), RecordingFormat::Hitachi);
+        assert_eq!(detect("run.txt", b"?").unwrap(), RecordingFormat::Shimadzu);
+        assert!(matches!(
+            detect("run.xyz", b"?"),
+            Err(NWError::InvalidInput(_))
+        ));
+        assert_eq!(
+            detect_format(&dir.to_string_lossy()).unwrap(),
+            RecordingFormat::Nirx
+        );
+    }
+}
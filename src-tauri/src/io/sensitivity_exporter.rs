@@ -0,0 +1,80 @@
+use crate::domain::error::NWError;
+use crate::domain::optics::Sensitivity;
+use hdf5::types::VarLenUnicode;
+use hdf5::{File, Group, H5Type};
+use std::str::FromStr;
+
+fn write_1d<T: H5Type>(group: &Group, name: &str, data: &[T]) -> Result<(), String> {
+    group
+        .new_dataset::<T>()
+        .shape([data.len()])
+        .create(name)
+        .and_then(|ds| ds.write_raw(data))
+        .map_err(|e| format!("failed to write '{name}': {e}"))
+}
+
+fn write_strings(group: &Group, name: &str, values: &[&str]) -> Result<(), String> {
+    let data = values
+        .iter()
+        .map(|v| VarLenUnicode::from_str(v))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("'{name}': invalid value"))?;
+    write_1d(group, name, &data)
+}
+
+/// Write `sensitivity` to `path` as HDF5, the channels × voxels Jacobian
+/// in compressed sparse row form under `/sensitivity`:
+///
+/// - `data`, `indices`, `indptr`: CSR arrays (f32, u32, u64), as
+///   `scipy.sparse.csr_matrix((data, indices, indptr))` takes them; a
+///   column is the voxel x + y*nx + z*nx*ny
+/// - `dims` (nx, ny, nz) and `vox2ras` (4×4, row-major)
+/// - `channel`, `source`, `detector`: one per row
+pub fn export_sensitivity(sensitivity: &Sensitivity, path: &str) -> Result<(), NWError> {
+    export_inner(sensitivity, path).map_err(NWError::Internal)
+}
+
+fn export_inner(sensitivity: &Sensitivity, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("failed to create '{path}': {e}"))?;
+    let group = file
+        .create_group("sensitivity")
+        .map_err(|e| format!("failed to create group 'sensitivity': {e}"))?;
+
+    let channels = &sensitivity.channels;
+    let mut indptr = Vec::with_capacity(channels.len() + 1);
+    indptr.push(0u64);
+    for ch in channels {
+        indptr.push(indptr[indptr.len() - 1] + ch.voxels.len() as u64);
+    }
+    let indices: Vec<u32> = channels
+        .iter()
+        .flat_map(|ch| ch.voxels.iter().copied())
+        .collect();
+    let data: Vec<f32> = channels
+        .iter()
+        .flat_map(|ch| ch.values.iter().copied())
+        .collect();
+    write_1d(&group, "data", &data)?;
+    write_1d(&group, "indices", &indices)?;
+    write_1d(&group, "indptr", &indptr)?;
+
+    let dims: Vec<u64> = sensitivity.dims.iter().map(|d| *d as u64).collect();
+    write_1d(&group, "dims", &dims)?;
+    let m = &sensitivity.vox2ras;
+    let rows: Vec<f64> = (0..4)
+        .flat_map(|r| (0..4).map(move |c| m[(r, c)]))
+        .collect();
+    group
+        .new_dataset::<f64>()
+        .shape([4, 4])
+        .create("vox2ras")
+        .and_then(|ds| ds.write_raw(&rows))
+        .map_err(|e| format!("failed to write 'vox2ras': {e}"))?;
+
+    let ids: Vec<u64> = channels.iter().map(|ch| ch.channel as u64).collect();
+    write_1d(&group, "channel", &ids)?;
+    let sources: Vec<&str> = channels.iter().map(|ch| ch.source.as_str()).collect();
+    write_strings(&group, "source", &sources)?;
+    let detectors: Vec<&str> = channels.iter().map(|ch| ch.detector.as_str()).collect();
+    write_strings(&group, "detector", &detectors)
+}
@@ -0,0 +1,99 @@
+use crate::domain::error::NWError;
+use crate::domain::optics::{ChannelSensitivity, Sensitivity};
+use hdf5::types::{VarLenAscii, VarLenUnicode};
+use hdf5::{File, Group, H5Type};
+use nalgebra::Matrix4;
+
+fn read_1d<T: H5Type>(group: &Group, name: &str) -> Result<Vec<T>, String> {
+    group
+        .dataset(name)
+        .and_then(|ds| ds.read_raw::<T>())
+        .map_err(|e| format!("failed to read '{name}': {e}"))
+}
+
+fn read_strings(group: &Group, name: &str) -> Result<Vec<String>, String> {
+    let ds = group
+        .dataset(name)
+        .map_err(|e| format!("failed to read '{name}': {e}"))?;
+    if let Ok(values) = ds.read_raw::<VarLenUnicode>() {
+        return Ok(values.iter().map(|v| v.as_str().to_string()).collect());
+    }
+    ds.read_raw::<VarLenAscii>()
+        .map(|values| values.iter().map(|v| v.as_str().to_string()).collect())
+        .map_err(|e| format!("failed to read '{name}': {e}"))
+}
+
+/// Read a sensitivity written by
+/// [`export_sensitivity`](crate::io::sensitivity_exporter::export_sensitivity),
+/// or by any tool writing the same CSR layout under `/sensitivity`.
+/// `channel` may be left out, in which case rows are numbered from 0.
+pub fn import_sensitivity(path: &str) -> Result<Sensitivity, NWError> {
+    import_inner(path).map_err(NWError::Parse)
+}
+
+fn import_inner(path: &str) -> Result<Sensitivity, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open '{path}': {e}"))?;
+    let group = file
+        .group("sensitivity")
+        .map_err(|_| format!("'{path}' has no 'sensitivity' group"))?;
+
+    let data: Vec<f32> = read_1d(&group, "data")?;
+    let indices: Vec<u64> = read_1d(&group, "indices")?;
+    let indptr: Vec<u64> = read_1d(&group, "indptr")?;
+    let dims: Vec<u64> = read_1d(&group, "dims")?;
+    let vox2ras: Vec<f64> = read_1d(&group, "vox2ras")?;
+    let sources = read_strings(&group, "source")?;
+    let detectors = read_strings(&group, "detector")?;
+    let rows = indptr.len().saturating_sub(1);
+    let ids: Vec<u64> = match group.dataset("channel") {
+        Ok(_) => read_1d(&group, "channel")?,
+        Err(_) => (0..rows as u64).collect(),
+    };
+
+    if dims.len() != 3 || vox2ras.len() != 16 {
+        return Err("'dims' must hold 3 values and 'vox2ras' 16".into());
+    }
+    if data.len() != indices.len()
+        || indptr.first() != Some(&0)
+        || indptr.last().copied() != Some(data.len() as u64)
+        || indptr.windows(2).any(|w| w[1] < w[0])
+    {
+        return Err("'data', 'indices' and 'indptr' do not form a CSR matrix".into());
+    }
+    if [sources.len(), detectors.len(), ids.len()]
+        .iter()
+        .any(|n| *n != rows)
+    {
+        return Err("'channel', 'source' and 'detector' need one entry per row".into());
+    }
+    let dims: [usize; 3] = std::array::from_fn(|a| dims[a] as usize);
+    let voxels = dims.iter().product::<usize>() as u64;
+    if indices.iter().any(|i| *i >= voxels) {
+        return Err("a voxel index lies outside 'dims'".into());
+    }
+
+    let channels = (0..rows)
+        .map(|r| {
+            let (a, b) = (indptr[r] as usize, indptr[r + 1] as usize);
+            let mut pairs: Vec<(u32, f32)> = indices[a..b]
+                .iter()
+                .zip(&data[a..b])
+                .map(|(i, v)| (*i as u32, *v))
+                .collect();
+            pairs.sort_unstable_by_key(|(i, _)| *i);
+            let (voxels, values) = pairs.into_iter().unzip();
+            ChannelSensitivity {
+                channel: ids[r] as usize,
+                source: sources[r].clone(),
+                detector: detectors[r].clone(),
+                voxels,
+                values,
+            }
+        })
+        .collect();
+    Ok(Sensitivity {
+        dims,
+        vox2ras: Matrix4::from_row_slice(&vox2ras),
+        channels,
+    })
+}
@@ -0,0 +1,217 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{DataBlock, NirsEntry, Snirf};
+use crate::io::recording_importer::{
+    channel_column, channel_measurements, column_kind, events_from_marks, metadata, number,
+    numbered_probe, read_text, single_entry, wavelength_bands, ColumnKind,
+};
+
+/// Wavelengths of the OMM-3000 and LABNIRS light sources, nm.
+const SHIMADZU_WAVELENGTHS: [f64; 3] = [780.0, 805.0, 830.0];
+
+fn split(line: &str) -> Vec<&str> {
+    let delim = if line.contains('\t') { '\t' } else { ',' };
+    line.split(delim).map(str::trim).collect()
+}
+
+/// Column labels, where a channel's label may span the header line
+/// (`CH1`, blank, blank) and a sub-header below it (`oxyHb`, `deoxyHb`,
+/// `totalHb`).
+fn column_labels(header: &[&str], sub: Option<&[&str]>) -> Vec<String> {
+    let mut current = "";
+    header
+        .iter()
+        .enumerate()
+        .map(|(c, &h)| {
+            if !h.is_empty() {
+                current = h;
+            }
+            match sub.and_then(|s| s.get(c)).filter(|s| !s.is_empty()) {
+                Some(s) => format!("{current} {s}"),
+                None => current.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Read a Shimadzu OMM-3000/LABNIRS text export: `key<TAB>value` header
+/// lines, then a column header starting with `Time`, optionally a
+/// sub-header naming each channel's oxy/deoxy/total columns, and one row
+/// per sample. Channel columns are haemoglobin (mM·cm); columns named only
+/// by haemoglobin type are assigned to channels in order. `Mark` becomes
+/// events. The export carries no optode geometry, so each channel gets a
+/// numbered source–detector pair.
+pub fn parse_shimadzu_txt(path: &str) -> Result<Snirf, NWError> {
+    let text = read_text(path)?;
+    let lines: Vec<Vec<&str>> = text.lines().map(split).collect();
+    let value = |key: &str| {
+        lines
+            .iter()
+            .find(|r| r[0].trim_end_matches(':').eq_ignore_ascii_case(key))
+            .and_then(|r| r.get(1).copied())
+            .filter(|v| !v.is_empty())
+    };
+
+    let header_at = lines
+        .iter()
+        .position(|r| r[0].to_ascii_lowercase().starts_with("time") && r.len() > 2)
+        .ok_or_else(|| NWError::Parse(format!("{path}: no Time(sec) column header")))?;
+    let header = &lines[header_at];
+    let sub = lines
+        .get(header_at + 1)
+        .filter(|r| r[0].parse::<f64>().is_err())
+        .map(Vec::as_slice);
+    let labels = column_labels(header, sub);
+    let data: Vec<&Vec<&str>> = lines[header_at + 1..]
+        .iter()
+        .filter(|r| r[0].parse::<f64>().is_ok())
+        .collect();
+
+    // Channel of each column: from `CHn` in its label, else the next
+    // channel whenever a haemoglobin type repeats.
+    let mut columns: Vec<(usize, ColumnKind, Vec<f64>)> = Vec::new();
+    let mut running = (0, Vec::new());
+    for (c, label) in labels.iter().enumerate().skip(1) {
+        let (channel, kind) = match channel_column(label) {
+            Some(found) => found,
+            None => match column_kind(label) {
+                Some(kind) => {
+                    let (channel, seen) = &mut running;
+                    if *channel == 0 || seen.contains(&kind) {
+                        *channel += 1;
+                        seen.clear();
+                    }
+                    seen.push(kind);
+                    (*channel, kind)
+                }
+                None => continue,
+            },
+        };
+        let data = data
+            .iter()
+            .map(|r| r.get(c).map_or(f64::NAN, |v| number(v)))
+            .collect();
+        columns.push((channel, kind, data));
+    }
+    if columns.is_empty() {
+        return Err(NWError::Parse(format!("{path}: no channel columns")));
+    }
+    let n_channels = columns.iter().map(|(ch, _, _)| *ch).max().unwrap_or(0);
+
+    let measured = wavelength_bands(columns.iter().filter_map(|(_, kind, _)| match kind {
+        ColumnKind::Wavelength(nm) => Some(*nm),
+        ColumnKind::Hemoglobin(_) => None,
+    }));
+    let wavelengths = if measured.is_empty() {
+        SHIMADZU_WAVELENGTHS.to_vec()
+    } else {
+        measured
+    };
+    let measurements = channel_measurements(columns, &wavelengths, |ch| (ch, ch), Some("mM*cm"));
+
+    let time: Vec<f64> = data.iter().map(|r| number(r[0])).collect();
+    let events = match labels.iter().position(|l| l.eq_ignore_ascii_case("Mark")) {
+        Some(c) => {
+            let marks: Vec<&str> = data
+                .iter()
+                .map(|r| r.get(c).copied().unwrap_or(""))
+                .collect();
+            events_from_marks(&time, &marks, "Mark")
+        }
+        None => Vec::new(),
+    };
+
+    let metadata = metadata(
+        value("ID").map(str::to_string),
+        value("Measure Date")
+            .or(value("Date"))
+            .map(|d| d.replace('/', "-")),
+        value("Measure Time").map(str::to_string),
+        "mm",
+        vec![
+            ("ManufacturerName", "Shimadzu".to_string()),
+            ("SubjectName", value("Name").unwrap_or_default().to_string()),
+        ],
+    );
+
+    let entry = NirsEntry {
+        metadata,
+        data_blocks: vec![DataBlock { time, measurements }],
+        probe: numbered_probe(n_channels, wavelengths, 30.0),
+        events,
+        auxiliaries: Vec::new(),
+        provenance: Vec::new(),
+    };
+    Ok(single_entry(path, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::recording_importer::tests::{fixture, fixture_dir};
+
+    const EXPORT: &str = "\
+[File Information]
+ID\ts02
+Name\tBob
+Measure Date\t2021/03/04
+Measure Time\t09:08:07
+[Data]
+Time(sec)\tTask\tMark\tCH1\t\t\tCH2\t\t
+\t\t\toxyHb\tdeoxyHb\ttotalHb\toxyHb\tdeoxyHb\ttotalHb
+0.0\t0\t0\t0.10\t-0.10\t0.00\t0.20\t-0.20\t0.00
+0.5\t1\t1\t0.11\t-0.11\t0.00\t0.21\t-0.21\t0.00
+1.0\t1\t0\t0.12\t-0.12\t0.00\t0.22\t-0.22\t0.00
+";
+
+    #[test]
+    fn reads_a_labnirs_export() {
+        let path = fixture(&fixture_dir("shimadzu"), "run.txt", EXPORT);
+        let snirf = parse_shimadzu_txt(&path).unwrap();
+        let entry = &snirf.nirs_entries[0];
+        let block = &entry.data_blocks[0];
+        assert_eq!(block.time, [0.0, 0.5, 1.0]);
+
+        let channels: Vec<_> = block
+            .measurements
+            .iter()
+            .map(|m| {
+                (
+                    m.source_index,
+                    m.detector_index,
+                    m.data_type_label.as_str(),
+                    m.data_unit.as_deref(),
+                )
+            })
+            .collect();
+        let mm_cm = Some("mM*cm");
+        assert_eq!(
+            channels,
+            [
+                (1, 1, "HbO", mm_cm),
+                (1, 1, "HbR", mm_cm),
+                (1, 1, "HbT", mm_cm),
+                (2, 2, "HbO", mm_cm),
+                (2, 2, "HbR", mm_cm),
+                (2, 2, "HbT", mm_cm),
+            ]
+        );
+        assert_eq!(block.measurements[4].data, [-0.20, -0.21, -0.22]);
+        assert_eq!(entry.probe.wavelengths, SHIMADZU_WAVELENGTHS);
+        assert_eq!(entry.probe.sources.len(), 2);
+
+        let tag = |name: &str| {
+            entry
+                .metadata
+                .iter()
+                .find(|t| t.name == name)
+                .map(|t| t.value.as_str())
+        };
+        assert_eq!(tag("SubjectID"), Some("s02"));
+        assert_eq!(tag("MeasurementDate"), Some("2021-03-04"));
+        assert_eq!(tag("MeasurementTime"), Some("09:08:07"));
+
+        assert_eq!(entry.events.len(), 1);
+        assert_eq!(entry.events[0].name, "Mark_1");
+        assert_eq!(entry.events[0].markers[0].onset, 0.5);
+    }
+}
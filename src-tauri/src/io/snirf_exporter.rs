@@ -1,5 +1,6 @@
+use crate::domain::provenance::PROVENANCE_TAG;
 use crate::domain::*;
-use hdf5::File;
+use hdf5::{File, Group};
 use ndarray16::Array2;
 
 // =============================================================================
@@ -32,6 +33,47 @@ fn write_i32(group: &hdf5::Group, name: &str, value: i32) -> Result<(), String>
         .map_err(|e| format!("write_i32: failed to write '{}': {}", name, e))
 }
 
+/// Write an f64 scalar dataset.
+fn write_f64(group: &hdf5::Group, name: &str, value: f64) -> Result<(), String> {
+    let ds = group
+        .new_dataset::<f64>()
+        .create(name)
+        .map_err(|e| format!("write_f64: failed to create '{}': {}", name, e))?;
+    ds.write_scalar(&value)
+        .map_err(|e| format!("write_f64: failed to write '{}': {}", name, e))
+}
+
+/// Write a 1-D VarLenUnicode array dataset (e.g. `landmarkLabels`).
+fn write_string_array(group: &hdf5::Group, name: &str, values: &[String]) -> Result<(), String> {
+    use hdf5::types::VarLenUnicode;
+    use std::str::FromStr;
+    let data = values
+        .iter()
+        .map(|v| VarLenUnicode::from_str(v))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("write_string_array '{}': invalid value", name))?;
+    let ds = group
+        .new_dataset::<VarLenUnicode>()
+        .shape([data.len()])
+        .create(name)
+        .map_err(|e| format!("write_string_array: failed to create '{}': {}", name, e))?;
+    ds.write_raw(&data)
+        .map_err(|e| format!("write_string_array: failed to write '{}': {}", name, e))
+}
+
+fn create_group(parent: &hdf5::Group, name: &str) -> Result<Group, String> {
+    parent
+        .create_group(name)
+        .map_err(|e| format!("failed to create group '{}': {}", name, e))
+}
+
+/// Stack equally long rows into an `n × cols` array.
+fn rows_to_array<const N: usize>(rows: impl ExactSizeIterator<Item = [f64; N]>) -> Array2<f64> {
+    let n = rows.len();
+    let flat: Vec<f64> = rows.flatten().collect();
+    Array2::from_shape_vec((n, N), flat).expect("row length matches shape")
+}
+
 /// Write a contiguous 1-D f64 dataset from a slice.
 fn write_f64_1d(group: &hdf5::Group, name: &str, data: &[f64]) -> Result<(), String> {
     let ds = group
@@ -59,13 +101,224 @@ fn write_f64_2d(group: &hdf5::Group, name: &str, array: &Array2<f64>) -> Result<
 // Public exporter entry
 // =============================================================================
 
-pub fn export_snirf(snirf: &SNIRF, path: &str) -> Result<(), String> {
-    // TODO : Rewrite exporter based on sNIRF specification
-    //
-    // For each nirs entry
-    //  write metadata, probe, events, auxiliaries,
-    //  for each data block
-    //      write measurementList, time, dataTimeSeries, etc
-    //
+/// Write `snirf` to `path` following the SNIRF specification, as the mirror
+/// image of [`parse_snirf`](crate::io::snirf_parser::parse_snirf): a file written
+/// here parses back into the same structure.
+///
+/// The processing history of each entry is stored as a JSON metadata tag so
+/// derived blocks can be traced and re-computed.
+pub fn export_snirf(snirf: &Snirf, path: &str) -> Result<(), NWError> {
+    export_snirf_inner(snirf, path).map_err(NWError::Internal)
+}
+
+fn export_snirf_inner(snirf: &Snirf, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("failed to create '{}': {}", path, e))?;
+    write_string(&file, "formatVersion", &snirf.format_version)?;
+
+    // A single entry is written as `/nirs`, several as `/nirs1`, `/nirs2`, …
+    let single = snirf.nirs_entries.len() == 1;
+    for (i, entry) in snirf.nirs_entries.iter().enumerate() {
+        let name = if single {
+            "nirs".to_string()
+        } else {
+            format!("nirs{}", i + 1)
+        };
+        let group = create_group(&file, &name)?;
+        write_nirs_entry(&group, entry).map_err(|e| format!("/{name}: {e}"))?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// NIRS entry
+// =============================================================================
+
+fn write_nirs_entry(nirs: &Group, entry: &NirsEntry) -> Result<(), String> {
+    write_metadata(nirs, entry).map_err(|e| format!("metaDataTags: {e}"))?;
+    for (j, block) in entry.data_blocks.iter().enumerate() {
+        let group = create_group(nirs, &format!("data{}", j + 1))?;
+        write_data_block(&group, block).map_err(|e| format!("data{}: {e}", j + 1))?;
+    }
+    write_probe(nirs, &entry.probe).map_err(|e| format!("probe: {e}"))?;
+    for (i, event) in entry.events.iter().enumerate() {
+        let group = create_group(nirs, &format!("stim{}", i + 1))?;
+        write_event(&group, event).map_err(|e| format!("stim{}: {e}", i + 1))?;
+    }
+    for (i, aux) in entry.auxiliaries.iter().enumerate() {
+        let group = create_group(nirs, &format!("aux{}", i + 1))?;
+        write_auxiliary(&group, aux).map_err(|e| format!("aux{}: {e}", i + 1))?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Metadata  —  nirs/metaDataTags/*
+// =============================================================================
+
+/// Tags the specification requires, with the value used when the source file
+/// did not provide one.
+const REQUIRED_TAGS: [(&str, &str); 6] = [
+    ("SubjectID", "unknown"),
+    ("MeasurementDate", "unknown"),
+    ("MeasurementTime", "unknown"),
+    ("LengthUnit", "mm"),
+    ("TimeUnit", "s"),
+    ("FrequencyUnit", "Hz"),
+];
+
+fn write_metadata(nirs: &Group, entry: &NirsEntry) -> Result<(), String> {
+    let group = create_group(nirs, "metaDataTags")?;
+    for tag in &entry.metadata {
+        write_string(&group, &tag.name, &tag.value)?;
+    }
+    for (name, default) in REQUIRED_TAGS {
+        if !entry.metadata.iter().any(|t| t.name == name) {
+            write_string(&group, name, default)?;
+        }
+    }
+    if !entry.provenance.is_empty() {
+        let json = serde_json::to_string(&entry.provenance)
+            .map_err(|e| format!("{PROVENANCE_TAG}: {e}"))?;
+        write_string(&group, PROVENANCE_TAG, &json)?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Probe  —  nirs/probe/*
+// =============================================================================
+
+fn write_probe(nirs: &Group, probe: &Probe) -> Result<(), String> {
+    let group = create_group(nirs, "probe")?;
+    write_f64_1d(&group, "wavelengths", &probe.wavelengths)?;
+    if let Some(emission) = &probe.wavelength_emission {
+        write_f64_1d(&group, "wavelengthEmission", emission)?;
+    }
+
+    // The parser flips 2D y to screen orientation; flip it back.
+    let pos2d =
+        |optodes: &[Optode]| rows_to_array(optodes.iter().map(|o| [o.pos_2d.x, -o.pos_2d.y]));
+    let pos3d = |optodes: &[Optode]| {
+        rows_to_array(optodes.iter().map(|o| [o.pos_3d.x, o.pos_3d.y, o.pos_3d.z]))
+    };
+    write_f64_2d(&group, "sourcePos2D", &pos2d(&probe.sources))?;
+    write_f64_2d(&group, "sourcePos3D", &pos3d(&probe.sources))?;
+    write_f64_2d(&group, "detectorPos2D", &pos2d(&probe.detectors))?;
+    write_f64_2d(&group, "detectorPos3D", &pos3d(&probe.detectors))?;
+
+    if let Some(cs) = &probe.coordinate_system {
+        write_string(&group, "coordinateSystem", cs)?;
+    }
+    if let Some(desc) = &probe.coordinate_system_description {
+        write_string(&group, "coordinateSystemDescription", desc)?;
+    }
+    if let Some(local) = probe.use_local_index {
+        write_i32(&group, "useLocalIndex", local)?;
+    }
+    if let Some(landmarks) = &probe.landmarks {
+        write_landmarks(&group, landmarks)?;
+    }
+    Ok(())
+}
+
+fn write_landmarks(probe: &Group, landmarks: &[Landmark]) -> Result<(), String> {
+    let labels: Vec<String> = landmarks.iter().map(|l| l.label.clone()).collect();
+    write_string_array(probe, "landmarkLabels", &labels)?;
+
+    // Positions are written only when every landmark has them, since the
+    // arrays are indexed in step with the labels.
+    if let Some(rows) = landmarks
+        .iter()
+        .map(|l| l.pos_2d)
+        .collect::<Option<Vec<_>>>()
+    {
+        write_f64_2d(probe, "landmarkPos2D", &rows_to_array(rows.into_iter()))?;
+    }
+    if let Some(rows) = landmarks
+        .iter()
+        .map(|l| l.pos_3d)
+        .collect::<Option<Vec<_>>>()
+    {
+        write_f64_2d(probe, "landmarkPos3D", &rows_to_array(rows.into_iter()))?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Data blocks  —  nirs/data{j}/*
+// =============================================================================
+
+fn write_data_block(data: &Group, block: &DataBlock) -> Result<(), String> {
+    let n_time = block.time.len();
+    let n_cols = block.measurements.len();
+    let ts = Array2::from_shape_fn((n_time, n_cols), |(t, c)| {
+        block.measurements[c]
+            .data
+            .get(t)
+            .copied()
+            .unwrap_or(f64::NAN)
+    });
+    write_f64_2d(data, "dataTimeSeries", &ts)?;
+    write_f64_1d(data, "time", &block.time)?;
+
+    for (k, m) in block.measurements.iter().enumerate() {
+        let name = format!("measurementList{}", k + 1);
+        let ml = create_group(data, &name)?;
+        write_measurement(&ml, m).map_err(|e| format!("{name}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn write_measurement(ml: &Group, m: &Measurement) -> Result<(), String> {
+    write_i32(ml, "sourceIndex", m.source_index as i32)?;
+    write_i32(ml, "detectorIndex", m.detector_index as i32)?;
+    if let Some(w) = m.wavelength_index {
+        write_i32(ml, "wavelengthIndex", w as i32)?;
+    }
+    write_i32(ml, "dataType", m.data_type)?;
+    if !m.data_type_label.is_empty() {
+        write_string(ml, "dataTypeLabel", &m.data_type_label)?;
+    }
+    write_i32(ml, "dataTypeIndex", m.data_type_index)?;
+    if let Some(unit) = &m.data_unit {
+        write_string(ml, "dataUnit", unit)?;
+    }
+
+    let optional = [
+        ("wavelengthActual", m.wavelength_actual),
+        ("sourcePower", m.source_power),
+        ("detectorGain", m.detector_gain),
+        ("moduleIndex", m.module_index),
+    ];
+    for (name, value) in optional {
+        if let Some(v) = value {
+            write_f64(ml, name, v)?;
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Events  —  nirs/stim{i}/*
+// =============================================================================
+
+fn write_event(stim: &Group, event: &Event) -> Result<(), String> {
+    write_string(stim, "name", &event.name)?;
+    let rows = event.markers.iter().map(|m| [m.onset, m.duration, m.value]);
+    write_f64_2d(stim, "data", &rows_to_array(rows))
+}
+
+// =============================================================================
+// Auxiliaries  —  nirs/aux{i}/*
+// =============================================================================
+
+fn write_auxiliary(aux: &Group, data: &AuxiliaryData) -> Result<(), String> {
+    write_string(aux, "name", &data.name)?;
+    write_string(aux, "dataUnit", &data.unit)?;
+    write_f64_1d(aux, "dataTimeSeries", &data.data)?;
+    write_f64_1d(aux, "time", &data.time)?;
+    if let Some(offset) = data.time_offset {
+        write_f64(aux, "timeOffset", offset)?;
+    }
     Ok(())
 }
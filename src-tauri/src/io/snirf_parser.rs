@@ -1,3 +1,4 @@
+use crate::domain::provenance::{ProvenanceRecord, PROVENANCE_TAG};
 use crate::domain::snirf::*;
 use crate::domain::NWError;
 use anyhow::{bail, Context, Result};
@@ -128,10 +129,24 @@ fn read_i32(ds: &hdf5::Dataset) -> Result<i32> {
 /// group from a lower-level parse failure.  Use `{:#}` formatting on the error
 /// to print the full context chain.
 pub fn parse_snirf(path: &str) -> Result<Snirf, NWError> {
-    parse_snirf_inner(path).map_err(NWError::from)
+    parse_snirf_inner(path, true).map_err(typed_error)
 }
 
-fn parse_snirf_inner(path: &str) -> Result<Snirf, NWError> {
+/// Parse everything but the time series: measurements keep their metadata
+/// with empty `data`, so a file can be summarised without reading its
+/// samples.
+pub fn parse_snirf_header(path: &str) -> Result<Snirf, NWError> {
+    parse_snirf_inner(path, false).map_err(typed_error)
+}
+
+fn typed_error(e: anyhow::Error) -> NWError {
+    match e.downcast::<NWError>() {
+        Ok(typed) => typed,
+        Err(e) => NWError::Parse(format!("{e:#}")),
+    }
+}
+
+fn parse_snirf_inner(path: &str, with_data: bool) -> Result<Snirf> {
     let file = File::open(path).with_context(|| format!("Failed to open '{path}'"))?;
 
     #[cfg(debug_assertions)]
@@ -155,13 +170,15 @@ fn parse_snirf_inner(path: &str) -> Result<Snirf, NWError> {
     // SNIRF allows a single `/nirs` group or indexed `/nirs1`, `/nirs2`, …
     let mut nirs_entries = Vec::new();
     if let Ok(g) = file.group("nirs") {
-        nirs_entries.push(parse_nirs_entry(&g).context("/nirs")?);
+        nirs_entries.push(parse_nirs_entry(&g, with_data).context("/nirs")?);
     } else {
         let mut i = 1usize;
         loop {
             match file.group(&format!("nirs{i}")) {
                 Ok(g) => {
-                    nirs_entries.push(parse_nirs_entry(&g).with_context(|| format!("/nirs{i}"))?);
+                    nirs_entries.push(
+                        parse_nirs_entry(&g, with_data).with_context(|| format!("/nirs{i}"))?,
+                    );
                     i += 1;
                 }
                 Err(_) => break,
@@ -170,7 +187,7 @@ fn parse_snirf_inner(path: &str) -> Result<Snirf, NWError> {
     }
 
     if nirs_entries.is_empty() {
-        // Propagate as the dedicated NoNirsGroup variant via the From impl.
+        // Propagate as the dedicated NoEntries variant; parse_snirf downcasts it.
         return Err(NWError::NoEntries.into());
     }
 
@@ -189,9 +206,10 @@ fn parse_snirf_inner(path: &str) -> Result<Snirf, NWError> {
 // NIRS entry
 // =============================================================================
 
-fn parse_nirs_entry(nirs: &Group) -> Result<NirsEntry> {
-    let metadata = parse_metadata(nirs).context("metaDataTags")?;
-    let data_blocks = parse_data_blocks(nirs).context("data blocks")?;
+fn parse_nirs_entry(nirs: &Group, with_data: bool) -> Result<NirsEntry> {
+    let mut metadata = parse_metadata(nirs).context("metaDataTags")?;
+    let provenance = take_provenance(&mut metadata).context("provenance")?;
+    let data_blocks = parse_data_blocks(nirs, with_data).context("data blocks")?;
     let probe = parse_probe(nirs).context("probe")?;
     let events = parse_events(nirs).context("stim events")?;
     let auxiliaries = parse_auxiliaries(nirs).context("auxiliaries")?;
@@ -202,6 +220,7 @@ fn parse_nirs_entry(nirs: &Group) -> Result<NirsEntry> {
         probe,
         events,
         auxiliaries,
+        provenance,
     })
 }
 
@@ -228,6 +247,16 @@ fn parse_metadata(nirs: &Group) -> Result<Vec<MetadataTag>> {
     Ok(tags)
 }
 
+/// Remove the processing history written by the exporter from the metadata
+/// tags and decode it.  Files without it yield an empty history.
+fn take_provenance(metadata: &mut Vec<MetadataTag>) -> Result<Vec<ProvenanceRecord>> {
+    let Some(pos) = metadata.iter().position(|t| t.name == PROVENANCE_TAG) else {
+        return Ok(Vec::new());
+    };
+    let tag = metadata.remove(pos);
+    serde_json::from_str(&tag.value).with_context(|| format!("{PROVENANCE_TAG}: invalid JSON"))
+}
+
 // =============================================================================
 // Probe  —  nirs/probe/*
 // =============================================================================
@@ -399,10 +428,10 @@ fn parse_landmarks(probe: &Group) -> Result<Option<Vec<Landmark>>> {
 // Data blocks  —  nirs/data{j}/*
 // =============================================================================
 
-fn parse_data_blocks(nirs: &Group) -> Result<Vec<DataBlock>> {
+fn parse_data_blocks(nirs: &Group, with_data: bool) -> Result<Vec<DataBlock>> {
     let mut blocks: Vec<DataBlock> = (1..)
         .map_while(|j| nirs.group(&format!("data{j}")).ok().map(|g| (j, g)))
-        .map(|(j, g)| parse_data_block(&g, j).with_context(|| format!("data{j}")))
+        .map(|(j, g)| parse_data_block(&g, j, with_data).with_context(|| format!("data{j}")))
         .collect::<Result<_>>()?;
 
     // Second pass: fill in missing wavelength_index values by matching column
@@ -438,23 +467,30 @@ fn parse_data_blocks(nirs: &Group) -> Result<Vec<DataBlock>> {
     Ok(blocks)
 }
 
-fn parse_data_block(data: &Group, block_idx: usize) -> Result<DataBlock> {
+fn parse_data_block(data: &Group, block_idx: usize, with_data: bool) -> Result<DataBlock> {
     let time: Vec<f64> = data
         .dataset("time")
         .context("time dataset missing")?
         .read_raw()
         .context("time: read failed")?;
 
-    let ts: Array2<f64> = data
+    let ts_ds = data
         .dataset("dataTimeSeries")
-        .context("dataTimeSeries dataset missing")?
-        .read_2d()
-        .context("dataTimeSeries: read failed")?;
+        .context("dataTimeSeries dataset missing")?;
+    let ts: Option<Array2<f64>> = if with_data {
+        Some(ts_ds.read_2d().context("dataTimeSeries: read failed")?)
+    } else {
+        None
+    };
 
-    let n_cols = ts.ncols();
+    // [time × channels]; a single channel may be stored as a 1-D dataset.
+    let n_cols = match &ts {
+        Some(ts) => ts.ncols(),
+        None => ts_ds.shape().get(1).copied().unwrap_or(1),
+    };
     let measurements = (0..n_cols)
         .map(|col| {
-            parse_measurement(data, block_idx, col, &ts)
+            parse_measurement(data, block_idx, col, ts.as_ref())
                 .with_context(|| format!("measurementList{}", col + 1))
         })
         .collect::<Result<Vec<Measurement>>>()?;
@@ -466,7 +502,7 @@ fn parse_measurement(
     data: &Group,
     block_idx: usize,
     col: usize,
-    ts: &Array2<f64>,
+    ts: Option<&Array2<f64>>,
 ) -> Result<Measurement> {
     let ml_name = format!("measurementList{}", col + 1);
     let ml = data
@@ -537,7 +573,7 @@ fn parse_measurement(
         data_type_label,
         data_type_index,
         data_unit,
-        data: ts.column(col).to_vec(),
+        data: ts.map(|ts| ts.column(col).to_vec()).unwrap_or_default(),
         wavelength_actual,
         source_power,
         detector_gain,
@@ -625,5 +661,6 @@ fn parse_auxiliary(aux: &Group) -> Result<AuxiliaryData> {
         data,
         time,
         time_offset,
+        resampled_from: None,
     })
 }
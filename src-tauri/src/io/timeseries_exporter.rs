@@ -0,0 +1,375 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{Event, NirsEntry};
+use crate::io::matlab::{write_mat, MatValue};
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use std::sync::Arc;
+
+/// Timeseries laid out for export: one time column and one named column
+/// per exported series, all of the same length.
+pub struct TimeseriesTable {
+    pub time: Vec<f64>,
+    pub columns: Vec<(String, Vec<f64>)>,
+}
+
+/// A channel's overlap with one atlas region, for export beside the data.
+pub struct RegionRow {
+    pub channel: String,
+    /// The channel on the cortex, mm.
+    pub position: [f64; 3],
+    pub label: u32,
+    pub region: String,
+    pub percent: f64,
+}
+
+fn events_by_onset(events: &[Event]) -> Vec<(&str, f64, f64, f64)> {
+    let mut rows: Vec<(&str, f64, f64, f64)> = events
+        .iter()
+        .flat_map(|e| {
+            e.markers
+                .iter()
+                .map(move |m| (e.name.as_str(), m.onset, m.duration, m.value))
+        })
+        .collect();
+    rows.sort_by(|a, b| a.1.total_cmp(&b.1));
+    rows
+}
+
+// =============================================================================
+// CSV / TSV
+// =============================================================================
+
+/// Quote a cell if it contains the delimiter, a quote or a line break.
+fn text_cell(s: &str, delim: char) -> String {
+    if s.contains([delim, '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_lines(path: &str, lines: impl Iterator<Item = String>) -> Result<(), NWError> {
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// `time` followed by the table's columns, separated by `delim`.
+pub fn write_delimited(path: &str, table: &TimeseriesTable, delim: char) -> Result<(), NWError> {
+    let sep = delim.to_string();
+    let header = std::iter::once("time".to_string())
+        .chain(table.columns.iter().map(|(name, _)| text_cell(name, delim)))
+        .collect::<Vec<_>>()
+        .join(&sep);
+    let rows = table.time.iter().enumerate().map(|(i, t)| {
+        std::iter::once(t.to_string())
+            .chain(table.columns.iter().map(|(_, data)| data[i].to_string()))
+            .collect::<Vec<_>>()
+            .join(&sep)
+    });
+    write_lines(path, std::iter::once(header).chain(rows))
+}
+
+/// One row per marker: `onset, duration, trial_type, value`, by onset.
+pub fn write_events_delimited(path: &str, events: &[Event], delim: char) -> Result<(), NWError> {
+    let sep = delim.to_string();
+    let header = ["onset", "duration", "trial_type", "value"].join(&sep);
+    let rows = events_by_onset(events)
+        .into_iter()
+        .map(|(name, onset, duration, value)| {
+            [
+                onset.to_string(),
+                duration.to_string(),
+                text_cell(name, delim),
+                value.to_string(),
+            ]
+            .join(&sep)
+        });
+    write_lines(path, std::iter::once(header).chain(rows))
+}
+
+/// One row per channel and atlas region:
+/// `channel, x, y, z, label, region, percent`.
+pub fn write_regions_delimited(path: &str, rows: &[RegionRow], delim: char) -> Result<(), NWError> {
+    let sep = delim.to_string();
+    let header = ["channel", "x", "y", "z", "label", "region", "percent"].join(&sep);
+    let lines = rows.iter().map(|r| {
+        let [x, y, z] = r.position;
+        [
+            text_cell(&r.channel, delim),
+            x.to_string(),
+            y.to_string(),
+            z.to_string(),
+            r.label.to_string(),
+            text_cell(&r.region, delim),
+            r.percent.to_string(),
+        ]
+        .join(&sep)
+    });
+    write_lines(path, std::iter::once(header).chain(lines))
+}
+
+// =============================================================================
+// Parquet
+// =============================================================================
+
+fn parquet_err(e: parquet::errors::ParquetError) -> NWError {
+    NWError::Internal(format!("parquet: {e}"))
+}
+
+enum ParquetColumn<'a> {
+    Double(&'a [f64]),
+    Text(Vec<ByteArray>),
+}
+
+/// Write one row group of required columns.
+fn write_parquet(path: &str, columns: &[(&str, ParquetColumn)]) -> Result<(), NWError> {
+    let fields = columns
+        .iter()
+        .map(|(name, column)| {
+            let builder = match column {
+                ParquetColumn::Double(_) => {
+                    Type::primitive_type_builder(name, PhysicalType::DOUBLE)
+                }
+                ParquetColumn::Text(_) => {
+                    Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                        .with_converted_type(ConvertedType::UTF8)
+                }
+            };
+            builder
+                .with_repetition(Repetition::REQUIRED)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(parquet_err)?;
+    let schema = Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()
+        .map_err(parquet_err)?;
+
+    let file = std::fs::File::create(path)?;
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut writer =
+        SerializedFileWriter::new(file, Arc::new(schema), props).map_err(parquet_err)?;
+    let mut row_group = writer.next_row_group().map_err(parquet_err)?;
+    let mut values = columns.iter().map(|(_, c)| c);
+    while let Some(mut column) = row_group.next_column().map_err(parquet_err)? {
+        match values.next() {
+            Some(ParquetColumn::Double(data)) => column
+                .typed::<DoubleType>()
+                .write_batch(data, None, None)
+                .map_err(parquet_err)?,
+            Some(ParquetColumn::Text(data)) => column
+                .typed::<ByteArrayType>()
+                .write_batch(data, None, None)
+                .map_err(parquet_err)?,
+            None => break,
+        };
+        column.close().map_err(parquet_err)?;
+    }
+    row_group.close().map_err(parquet_err)?;
+    writer.close().map_err(parquet_err)?;
+    Ok(())
+}
+
+/// `time` and the table's columns as DOUBLE columns of one row group.
+pub fn write_timeseries_parquet(path: &str, table: &TimeseriesTable) -> Result<(), NWError> {
+    let columns: Vec<(&str, ParquetColumn)> = std::iter::once(("time", &table.time))
+        .chain(table.columns.iter().map(|(n, d)| (n.as_str(), d)))
+        .map(|(name, data)| (name, ParquetColumn::Double(data)))
+        .collect();
+    write_parquet(path, &columns)
+}
+
+pub fn write_events_parquet(path: &str, events: &[Event]) -> Result<(), NWError> {
+    let rows = events_by_onset(events);
+    let onset: Vec<f64> = rows.iter().map(|r| r.1).collect();
+    let duration: Vec<f64> = rows.iter().map(|r| r.2).collect();
+    let value: Vec<f64> = rows.iter().map(|r| r.3).collect();
+    let trial_type = rows.iter().map(|r| ByteArray::from(r.0)).collect();
+    write_parquet(
+        path,
+        &[
+            ("onset", ParquetColumn::Double(&onset)),
+            ("duration", ParquetColumn::Double(&duration)),
+            ("trial_type", ParquetColumn::Text(trial_type)),
+            ("value", ParquetColumn::Double(&value)),
+        ],
+    )
+}
+
+/// [`write_regions_delimited`] as Parquet; the label is stored as a double.
+pub fn write_regions_parquet(path: &str, rows: &[RegionRow]) -> Result<(), NWError> {
+    let coord = |axis: usize| rows.iter().map(|r| r.position[axis]).collect::<Vec<f64>>();
+    let (x, y, z) = (coord(0), coord(1), coord(2));
+    let label: Vec<f64> = rows.iter().map(|r| r.label as f64).collect();
+    let percent: Vec<f64> = rows.iter().map(|r| r.percent).collect();
+    let text = |f: fn(&RegionRow) -> &str| rows.iter().map(|r| ByteArray::from(f(r))).collect();
+    write_parquet(
+        path,
+        &[
+            ("channel", ParquetColumn::Text(text(|r| &r.channel))),
+            ("x", ParquetColumn::Double(&x)),
+            ("y", ParquetColumn::Double(&y)),
+            ("z", ParquetColumn::Double(&z)),
+            ("label", ParquetColumn::Double(&label)),
+            ("region", ParquetColumn::Text(text(|r| &r.region))),
+            ("percent", ParquetColumn::Double(&percent)),
+        ],
+    )
+}
+
+// =============================================================================
+// Homer .nirs (MATLAB v5)
+// =============================================================================
+
+/// Linear interpolation of `(x, y)` at `at`, holding the end values.
+fn interpolate(x: &[f64], y: &[f64], at: f64) -> f64 {
+    let n = x.len().min(y.len());
+    if n == 0 {
+        return f64::NAN;
+    }
+    let i = x[..n].partition_point(|&v| v < at);
+    if i == 0 {
+        y[0]
+    } else if i >= n {
+        y[n - 1]
+    } else {
+        let (x0, x1) = (x[i - 1], x[i]);
+        let w = if x1 > x0 { (at - x0) / (x1 - x0) } else { 0.0 };
+        y[i - 1] + w * (y[i] - y[i - 1])
+    }
+}
+
+/// Write block `block_idx` of `entry` as a Homer2/3 `.nirs` file with the
+/// variables `t`, `d`, `s`, `SD`, `aux` and `CondNames`. `channels` lists
+/// the measurement indices of each exported channel, in order; a
+/// measurement's position within its channel stands in for a missing
+/// wavelength index.
+pub fn write_homer_nirs(
+    path: &str,
+    entry: &NirsEntry,
+    block_idx: usize,
+    channels: &[Vec<usize>],
+) -> Result<(), NWError> {
+    let block = entry
+        .data_blocks
+        .get(block_idx)
+        .ok_or(NWError::BlockOutOfRange(block_idx))?;
+    let t = &block.time;
+    let probe = &entry.probe;
+
+    let mut d = Vec::new();
+    let mut meas_list = Vec::new();
+    for indices in channels {
+        for (position, &i) in indices.iter().enumerate() {
+            let m = &block.measurements[i];
+            d.push(m.data.clone());
+            meas_list.push(vec![
+                m.source_index as f64,
+                m.detector_index as f64,
+                1.0,
+                m.wavelength_index.unwrap_or(position + 1) as f64,
+            ]);
+        }
+    }
+
+    // Stimulus matrix: a 1 at the first sample at or after each onset.
+    let s: Vec<Vec<f64>> = entry
+        .events
+        .iter()
+        .map(|e| {
+            let mut column = vec![0.0; t.len()];
+            for m in &e.markers {
+                let i = t.partition_point(|&v| v < m.onset);
+                if let Some(c) = column.get_mut(i) {
+                    *c = 1.0;
+                }
+            }
+            column
+        })
+        .collect();
+
+    let aux: Vec<Vec<f64>> = entry
+        .auxiliaries
+        .iter()
+        .map(|a| {
+            let offset = a.time_offset.unwrap_or(0.0);
+            let time: Vec<f64> = a.time.iter().map(|x| x + offset).collect();
+            t.iter()
+                .map(|&at| interpolate(&time, &a.data, at))
+                .collect()
+        })
+        .collect();
+
+    // Homer expects 3D positions; fall back to the 2D layout when the
+    // file has none.
+    let has_3d = probe
+        .sources
+        .iter()
+        .chain(&probe.detectors)
+        .any(|o| o.pos_3d.norm() > 0.0);
+    let positions = |optodes: &[crate::domain::snirf::Optode]| -> Vec<Vec<f64>> {
+        optodes
+            .iter()
+            .map(|o| {
+                if has_3d {
+                    vec![o.pos_3d.x, o.pos_3d.y, o.pos_3d.z]
+                } else {
+                    vec![o.pos_2d.x, o.pos_2d.y, 0.0]
+                }
+            })
+            .collect()
+    };
+    let unit = entry
+        .metadata
+        .iter()
+        .find(|tag| tag.name == "LengthUnit")
+        .map_or("mm", |tag| tag.value.as_str());
+
+    let sd = MatValue::Struct(vec![
+        ("Lambda".into(), MatValue::row(probe.wavelengths.clone())),
+        (
+            "SrcPos".into(),
+            MatValue::from_rows(&positions(&probe.sources), 3),
+        ),
+        (
+            "DetPos".into(),
+            MatValue::from_rows(&positions(&probe.detectors), 3),
+        ),
+        ("nSrcs".into(), MatValue::scalar(probe.sources.len() as f64)),
+        (
+            "nDets".into(),
+            MatValue::scalar(probe.detectors.len() as f64),
+        ),
+        ("MeasList".into(), MatValue::from_rows(&meas_list, 4)),
+        ("SpatialUnit".into(), MatValue::Char(unit.to_string())),
+    ]);
+    let cond_names = MatValue::Cell(
+        entry
+            .events
+            .iter()
+            .map(|e| MatValue::Char(e.name.clone()))
+            .collect(),
+    );
+
+    write_mat(
+        path,
+        &[
+            ("t", MatValue::column(t.clone())),
+            ("d", MatValue::from_columns(&d, t.len())),
+            ("s", MatValue::from_columns(&s, t.len())),
+            ("SD", sd),
+            ("aux", MatValue::from_columns(&aux, t.len())),
+            ("CondNames", cond_names),
+        ],
+    )
+}
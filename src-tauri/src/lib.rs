@@ -1,12 +1,17 @@
 pub mod commands;
 pub mod domain;
+pub mod dot;
 pub mod dsp;
 pub mod io;
+pub mod meshing;
+pub mod photon;
 pub mod services;
 pub mod state;
 
+use state::jobs::JobState;
 use state::selection::SelectionState;
 use state::session::SessionState;
+use state::workspace::WorkspaceState;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -26,10 +31,29 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .manage(SessionState::default())
         .manage(SelectionState::default())
+        .manage(WorkspaceState::default())
+        .manage(JobState::default())
         .invoke_handler(tauri::generate_handler![
             // File I/O
             commands::file_commands::import_snirf,
-            //commands::file_commands::export_snirf,
+            commands::file_commands::export_snirf,
+            commands::file_commands::export_bids_sidecars,
+            commands::file_commands::export_timeseries_data,
+            // Datasets
+            commands::dataset_commands::open_dataset,
+            commands::dataset_commands::list_recordings,
+            commands::dataset_commands::load_dataset_recording,
+            // Events
+            commands::event_commands::get_events,
+            commands::event_commands::preview_event_import,
+            commands::event_commands::import_events,
+            commands::event_commands::edit_events,
+            commands::event_commands::get_auxiliaries,
+            commands::event_commands::preview_triggers,
+            commands::event_commands::extract_triggers,
+            // Projects
+            commands::project_commands::save_project,
+            commands::project_commands::open_project,
             // Info
             commands::info_commands::get_snirf_summary,
             // Timeseries
@@ -39,9 +63,69 @@ pub fn run() {
             commands::probe_commands::get_probe_layout,
             commands::selection_commands::set_selected_channels,
             commands::selection_commands::set_active_block,
+            // Workspace
+            commands::workspace_commands::set_optode_layout_transform,
+            commands::workspace_commands::set_optode_layout_settings,
+            commands::workspace_commands::set_anatomy_path,
+            commands::workspace_commands::get_optode_layout_3d,
+            // Anatomy pipeline
+            commands::anatomy_commands::start_anatomy_job,
+            commands::anatomy_commands::cancel_anatomy_job,
+            commands::anatomy_commands::get_anatomy_job,
+            commands::anatomy_commands::load_subject_meshes,
+            // Registration
+            commands::registration_commands::get_probe_landmarks,
+            commands::registration_commands::detect_scalp_landmarks,
+            commands::registration_commands::preview_registration,
+            commands::registration_commands::register_probe,
+            commands::projection_commands::project_probe,
+            commands::head_position_commands::compute_head_positions,
+            commands::head_position_commands::get_head_positions,
+            commands::head_position_commands::label_optodes,
+            commands::head_position_commands::place_optodes,
+            commands::head_position_commands::export_head_positions,
+            commands::atlas_commands::set_atlas,
+            commands::atlas_commands::label_channels_with_atlas,
+            commands::atlas_commands::get_channel_regions,
+            commands::atlas_commands::get_atlas_rois,
+            commands::atlas_commands::export_channel_regions,
+            commands::photon_commands::simulate_photons,
+            commands::photon_commands::export_mcx,
+            commands::photon_commands::import_mcx_fluence,
+            commands::sensitivity_commands::compute_sensitivity,
+            commands::sensitivity_commands::get_cortex_sensitivity,
+            commands::sensitivity_commands::export_sensitivity,
+            commands::sensitivity_commands::import_sensitivity,
+            commands::reconstruction_commands::reconstruct_image,
             // Spectral
             commands::spectral_commands::get_spectrum,
             commands::spectral_commands::get_spectrogram,
+            // Resampling
+            commands::resample_commands::regularize_time_base,
+            commands::resample_commands::resample_data_block,
+            // Processing
+            commands::processing_commands::detrend_data_block,
+            commands::processing_commands::baseline_correct_block,
+            commands::spatial_filter_commands::pca_filter_data_block,
+            commands::spatial_filter_commands::get_ica_components,
+            commands::spatial_filter_commands::reject_ica_components,
+            // Pipelines
+            commands::pipeline_commands::run_processing_step,
+            commands::pipeline_commands::run_processing_pipeline,
+            commands::pipeline_commands::load_pipeline,
+            commands::pipeline_commands::save_pipeline,
+            commands::pipeline_commands::get_block_pipeline,
+            commands::pipeline_commands::get_provenance,
+            commands::pipeline_commands::run_pipeline_batch,
+            // History
+            commands::history_commands::get_history,
+            commands::history_commands::undo,
+            commands::history_commands::redo,
+            // Annotations
+            commands::annotation_commands::get_annotations,
+            commands::annotation_commands::add_bad_segment,
+            commands::annotation_commands::remove_bad_segment,
+            commands::annotation_commands::set_bad_channel,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,267 @@
+use super::TriangleMesh;
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// An edge collapse waiting in the queue, cheapest first.
+struct Candidate {
+    cost: f64,
+    keep: u32,
+    drop: u32,
+    /// The two vertices' versions when queued; a collapse since makes the
+    /// candidate stale.
+    versions: (u32, u32),
+    target: Vector3<f64>,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost.total_cmp(&other.cost) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn homogeneous(p: &Vector3<f64>) -> Vector4<f64> {
+    Vector4::new(p.x, p.y, p.z, 1.0)
+}
+
+/// Sum of squared distances to a set of planes, as a 4×4 quadric.
+fn error(q: &Matrix4<f64>, p: &Vector3<f64>) -> f64 {
+    let v = homogeneous(p);
+    v.dot(&(q * v))
+}
+
+struct Decimator {
+    positions: Vec<Vector3<f64>>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    /// Triangles around each vertex; dead ones are pruned lazily.
+    around: Vec<Vec<u32>>,
+    quadrics: Vec<Matrix4<f64>>,
+    versions: Vec<u32>,
+    removed: Vec<bool>,
+    queue: BinaryHeap<Candidate>,
+}
+
+impl Decimator {
+    fn new(mesh: &TriangleMesh) -> Self {
+        let n = mesh.positions.len();
+        let mut around = vec![Vec::new(); n];
+        let mut quadrics = vec![Matrix4::zeros(); n];
+        for (f, t) in mesh.triangles.iter().enumerate() {
+            let [a, b, c] = t.map(|v| mesh.positions[v as usize]);
+            if let Some(normal) = (b - a).cross(&(c - a)).try_normalize(0.0) {
+                let plane = Vector4::new(normal.x, normal.y, normal.z, -normal.dot(&a));
+                let q = plane * plane.transpose();
+                for v in t {
+                    quadrics[*v as usize] += q;
+                }
+            }
+            for v in t {
+                around[*v as usize].push(f as u32);
+            }
+        }
+        let mut decimator = Decimator {
+            positions: mesh.positions.clone(),
+            triangles: mesh.triangles.clone(),
+            alive: vec![true; mesh.triangles.len()],
+            around,
+            quadrics,
+            versions: vec![0; n],
+            removed: vec![false; n],
+            queue: BinaryHeap::new(),
+        };
+        for t in &mesh.triangles {
+            for (u, v) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                // Each interior edge is met from both sides; queue it once.
+                if u < v {
+                    decimator.enqueue(u, v);
+                }
+            }
+        }
+        decimator
+    }
+
+    /// Queue the collapse of edge (u, v) onto the point of least error:
+    /// the quadric's minimum if it lies near the edge, else the better of
+    /// the ends and the midpoint.
+    fn enqueue(&mut self, u: u32, v: u32) {
+        let q = self.quadrics[u as usize] + self.quadrics[v as usize];
+        let (pu, pv) = (self.positions[u as usize], self.positions[v as usize]);
+        let mid = (pu + pv) / 2.0;
+        let length = (pu - pv).norm();
+        let a: Matrix3<f64> = q.fixed_view::<3, 3>(0, 0).into();
+        let optimum = a
+            .try_inverse()
+            .map(|inv| -(inv * q.fixed_view::<3, 1>(0, 3)))
+            .filter(|p| (p - mid).norm() <= length);
+        let (cost, target) = optimum
+            .into_iter()
+            .chain([mid, pu, pv])
+            .map(|p| (error(&q, &p), p))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or((0.0, mid));
+        self.queue.push(Candidate {
+            cost,
+            keep: u,
+            drop: v,
+            versions: (self.versions[u as usize], self.versions[v as usize]),
+            target,
+        });
+    }
+
+    fn live_around(&mut self, v: u32) -> Vec<u32> {
+        let alive = &self.alive;
+        self.around[v as usize].retain(|f| alive[*f as usize]);
+        self.around[v as usize].clone()
+    }
+
+    fn neighbours(&self, faces: &[u32], v: u32) -> Vec<u32> {
+        let mut out: Vec<u32> = faces
+            .iter()
+            .flat_map(|f| self.triangles[*f as usize])
+            .filter(|u| *u != v)
+            .collect();
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+
+    /// Collapse `drop` into `keep` at `target`, unless that would tear the
+    /// surface (the edge is not between exactly two triangles, or the two
+    /// ends share more neighbours than those triangles' far corners) or
+    /// turn a triangle over. Returns the triangles removed.
+    fn collapse(&mut self, keep: u32, drop: u32, target: Vector3<f64>) -> usize {
+        let (faces_keep, faces_drop) = (self.live_around(keep), self.live_around(drop));
+        let shared: Vec<u32> = faces_keep
+            .iter()
+            .copied()
+            .filter(|f| self.triangles[*f as usize].contains(&drop))
+            .collect();
+        if shared.len() != 2 {
+            return 0;
+        }
+        let (around_keep, around_drop) = (
+            self.neighbours(&faces_keep, keep),
+            self.neighbours(&faces_drop, drop),
+        );
+        let common = around_keep
+            .iter()
+            .filter(|v| **v != drop && around_drop.binary_search(v).is_ok())
+            .count();
+        if common != 2 {
+            return 0;
+        }
+
+        for f in faces_keep.iter().chain(&faces_drop) {
+            if shared.contains(f) {
+                continue;
+            }
+            let t = self.triangles[*f as usize];
+            let corner = |v: u32| self.positions[v as usize];
+            let moved = |v: u32| {
+                if v == keep || v == drop {
+                    target
+                } else {
+                    corner(v)
+                }
+            };
+            let before = (corner(t[1]) - corner(t[0])).cross(&(corner(t[2]) - corner(t[0])));
+            let after = (moved(t[1]) - moved(t[0])).cross(&(moved(t[2]) - moved(t[0])));
+            if after.norm_squared() <= 1e-12 * before.norm_squared() || before.dot(&after) <= 0.0 {
+                return 0;
+            }
+        }
+
+        for f in &shared {
+            self.alive[*f as usize] = false;
+        }
+        for f in faces_drop {
+            if !self.alive[f as usize] {
+                continue;
+            }
+            for v in self.triangles[f as usize].iter_mut() {
+                if *v == drop {
+                    *v = keep;
+                }
+            }
+            self.around[keep as usize].push(f);
+        }
+        self.around[drop as usize].clear();
+        self.positions[keep as usize] = target;
+        let merged = self.quadrics[drop as usize];
+        self.quadrics[keep as usize] += merged;
+        self.removed[drop as usize] = true;
+        self.versions[keep as usize] += 1;
+        self.versions[drop as usize] += 1;
+
+        let faces = self.live_around(keep);
+        for v in self.neighbours(&faces, keep) {
+            self.enqueue(keep, v);
+        }
+        shared.len()
+    }
+
+    fn run(&mut self, target: usize) {
+        let mut left = self.alive.iter().filter(|a| **a).count();
+        while left > target {
+            let Some(c) = self.queue.pop() else { break };
+            let (k, d) = (c.keep as usize, c.drop as usize);
+            if self.removed[k]
+                || self.removed[d]
+                || (self.versions[k], self.versions[d]) != c.versions
+            {
+                continue;
+            }
+            left -= self.collapse(c.keep, c.drop, c.target);
+        }
+    }
+
+    /// The triangles left, over the vertices they use.
+    fn finish(self) -> TriangleMesh {
+        let mut index = vec![u32::MAX; self.positions.len()];
+        let mut mesh = TriangleMesh::default();
+        for (f, t) in self.triangles.iter().enumerate() {
+            if !self.alive[f] {
+                continue;
+            }
+            let t = t.map(|v| {
+                if index[v as usize] == u32::MAX {
+                    index[v as usize] = mesh.positions.len() as u32;
+                    mesh.positions.push(self.positions[v as usize]);
+                }
+                index[v as usize]
+            });
+            mesh.triangles.push(t);
+        }
+        mesh
+    }
+}
+
+/// Reduce `mesh` to about `target` triangles by quadric error edge
+/// collapse (Garland & Heckbert): edges are collapsed cheapest first, the
+/// cost of each the squared distance of the merged vertex to the planes of
+/// the original triangles around it. Collapses that would make the
+/// surface non-manifold or flip a triangle are skipped, so a mesh that
+/// cannot be reduced further keeps more triangles than asked for.
+pub fn decimate(mesh: &TriangleMesh, target: usize) -> TriangleMesh {
+    if mesh.triangles.len() <= target {
+        return mesh.clone();
+    }
+    let mut decimator = Decimator::new(mesh);
+    decimator.run(target);
+    decimator.finish()
+}
@@ -0,0 +1,91 @@
+/// Samples on part of a voxel grid, one per voxel centre.
+pub struct ScalarField {
+    /// [nx, ny, nz] of the samples.
+    pub dims: [usize; 3],
+    /// Voxel index of sample 0 in the full grid, negative where the
+    /// samples reach past it.
+    pub origin: [i64; 3],
+    /// values[x + y*nx + z*nx*ny]
+    pub values: Vec<f32>,
+}
+
+/// A normalised Gaussian kernel, cut at 3σ.
+fn kernel(sigma: f64) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|w| (w / sum) as f32).collect()
+}
+
+/// Convolve `values` with `kernel` along `axis`, zero outside.
+fn blur_axis(values: &[f32], dims: [usize; 3], axis: usize, kernel: &[f32]) -> Vec<f32> {
+    let stride = [1, dims[0], dims[0] * dims[1]][axis];
+    let n = dims[axis] as i64;
+    let radius = (kernel.len() / 2) as i64;
+    let mut out = vec![0.0f32; values.len()];
+    for (i, o) in out.iter_mut().enumerate() {
+        let at = ((i / stride) % dims[axis]) as i64;
+        let mut sum = 0.0;
+        for (k, w) in kernel.iter().enumerate() {
+            let j = at + k as i64 - radius;
+            if (0..n).contains(&j) {
+                sum += w * values[(i as i64 + (j - at) * stride as i64) as usize];
+            }
+        }
+        *o = sum;
+    }
+    out
+}
+
+/// The voxels of `labels` (x fastest, over `dims`) that `inside` accepts
+/// as 1 and the rest as 0, Gaussian-blurred by `sigma` voxels, cropped to
+/// them with enough background around that the 0.5 level closes. `None`
+/// if no voxel is inside.
+pub fn tissue_field(
+    labels: &[u8],
+    dims: [usize; 3],
+    inside: impl Fn(u8) -> bool,
+    sigma: f64,
+) -> Option<ScalarField> {
+    let [nx, ny, _] = dims;
+    let (mut lo, mut hi) = ([usize::MAX; 3], [0usize; 3]);
+    for (i, _) in labels.iter().enumerate().filter(|(_, l)| inside(**l)) {
+        let at = [i % nx, (i / nx) % ny, i / (nx * ny)];
+        for a in 0..3 {
+            lo[a] = lo[a].min(at[a]);
+            hi[a] = hi[a].max(at[a]);
+        }
+    }
+    if lo[0] == usize::MAX {
+        return None;
+    }
+
+    // The blur spreads 3σ; one more sample of background closes the
+    // surface, which may lie outside the original grid.
+    let margin = (3.0 * sigma.max(0.0)).ceil() as usize + 2;
+    let sub: [usize; 3] = std::array::from_fn(|a| hi[a] - lo[a] + 1 + 2 * margin);
+    let mut values = vec![0.0f32; sub.iter().product()];
+    for z in lo[2]..=hi[2] {
+        for y in lo[1]..=hi[1] {
+            for x in lo[0]..=hi[0] {
+                if inside(labels[x + y * nx + z * nx * ny]) {
+                    let (i, j, k) = (x - lo[0] + margin, y - lo[1] + margin, z - lo[2] + margin);
+                    values[i + j * sub[0] + k * sub[0] * sub[1]] = 1.0;
+                }
+            }
+        }
+    }
+    if sigma > 0.0 {
+        let kernel = kernel(sigma);
+        for axis in 0..3 {
+            values = blur_axis(&values, sub, axis, &kernel);
+        }
+    }
+    Some(ScalarField {
+        dims: sub,
+        origin: std::array::from_fn(|a| lo[a] as i64 - margin as i64),
+        values,
+    })
+}
@@ -0,0 +1,138 @@
+mod decimation;
+mod field;
+mod smoothing;
+mod surface_nets;
+
+pub use decimation::decimate;
+pub use field::{tissue_field, ScalarField};
+pub use smoothing::taubin;
+pub use surface_nets::surface_nets;
+
+use nalgebra::Vector3;
+
+/// A triangle mesh as it passes through the meshing steps. Triangles wind
+/// counter-clockwise seen from outside the surface.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vector3<f64>>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    /// Outward vertex normals, each the area-weighted mean of the normals
+    /// of the triangles around the vertex.
+    pub fn normals(&self) -> Vec<Vector3<f64>> {
+        let mut normals = vec![Vector3::zeros(); self.positions.len()];
+        for t in &self.triangles {
+            let [a, b, c] = t.map(|v| self.positions[v as usize]);
+            // Twice the area, along the face normal.
+            let n = (b - a).cross(&(c - a));
+            for v in t {
+                normals[*v as usize] += n;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|n| n.try_normalize(0.0).unwrap_or_else(Vector3::z))
+            .collect()
+    }
+
+    /// Each vertex's neighbours along the triangle edges, ascending.
+    fn neighbours(&self) -> Vec<Vec<u32>> {
+        let mut neighbours = vec![Vec::new(); self.positions.len()];
+        for [a, b, c] in &self.triangles {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                neighbours[*u as usize].push(*v);
+                neighbours[*v as usize].push(*u);
+            }
+        }
+        for list in &mut neighbours {
+            list.sort_unstable();
+            list.dedup();
+        }
+        neighbours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A ball of `radius` voxels in the middle of a cube of `n` voxels,
+    /// x fastest, and how many voxels it has.
+    fn ball(n: usize, radius: f64) -> (Vec<u8>, usize) {
+        let centre = (n as f64 - 1.0) / 2.0;
+        let labels: Vec<u8> = (0..n * n * n)
+            .map(|i| {
+                let at = Vector3::new(i % n, (i / n) % n, i / (n * n)).map(|c| c as f64);
+                u8::from((at - Vector3::repeat(centre)).norm() <= radius)
+            })
+            .collect();
+        let count = labels.iter().filter(|l| **l == 1).count();
+        (labels, count)
+    }
+
+    /// Closed and manifold: every directed edge appears once and is met by
+    /// the same edge the other way round in a neighbouring triangle.
+    fn assert_closed(mesh: &TriangleMesh) {
+        let mut edges = HashMap::new();
+        for [a, b, c] in &mesh.triangles {
+            assert!(a != b && b != c && c != a, "degenerate triangle");
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for ((a, b), count) in &edges {
+            assert_eq!(*count, 1, "edge {a}-{b} is used {count} times");
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {a}-{b} is open");
+        }
+    }
+
+    /// Enclosed volume, positive if the triangles wind outward.
+    fn signed_volume(mesh: &TriangleMesh) -> f64 {
+        mesh.triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|v| mesh.positions[v as usize]);
+                a.dot(&b.cross(&c)) / 6.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn sphere_mesh_is_closed_and_outward() {
+        let n = 32;
+        let (labels, voxels) = ball(n, 10.0);
+        let field = tissue_field(&labels, [n; 3], |l| l == 1, 1.0).unwrap();
+        let mut mesh = surface_nets(&field, 0.5);
+        assert_closed(&mesh);
+        let extracted = signed_volume(&mesh);
+        assert!(
+            (extracted / voxels as f64 - 1.0).abs() < 0.05,
+            "volume {extracted} for {voxels} voxels"
+        );
+
+        taubin(&mut mesh, 10, 0.5, -0.53);
+        let target = mesh.triangles.len() / 4;
+        let mesh = decimate(&mesh, target);
+        assert_closed(&mesh);
+        assert!(mesh.triangles.len() <= target + target / 10);
+        let kept = signed_volume(&mesh);
+        assert!(
+            (kept / extracted - 1.0).abs() < 0.03,
+            "volume {kept} after smoothing and decimation, {extracted} before"
+        );
+        // Every vertex normal points away from the centre.
+        let centre = Vector3::repeat((n as f64 - 1.0) / 2.0);
+        for (p, normal) in mesh.positions.iter().zip(mesh.normals()) {
+            assert!((p - centre).dot(&normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn empty_mask_has_no_field() {
+        let labels = vec![0u8; 8];
+        assert!(tissue_field(&labels, [2; 3], |l| l == 1, 1.0).is_none());
+    }
+}
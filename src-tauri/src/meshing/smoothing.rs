@@ -0,0 +1,29 @@
+use super::TriangleMesh;
+use nalgebra::Vector3;
+
+/// Taubin's λ|μ smoothing: each pass moves every vertex a share `lambda`
+/// towards the mean of its neighbours, then `mu` (negative, a little
+/// larger) back, which takes out the voxel staircase without shrinking
+/// the surface the way plain Laplacian smoothing does.
+pub fn taubin(mesh: &mut TriangleMesh, passes: usize, lambda: f64, mu: f64) {
+    let neighbours = mesh.neighbours();
+    let mut moved = vec![Vector3::zeros(); mesh.positions.len()];
+    for _ in 0..passes {
+        for factor in [lambda, mu] {
+            for (i, around) in neighbours.iter().enumerate() {
+                let p = mesh.positions[i];
+                moved[i] = if around.is_empty() {
+                    p
+                } else {
+                    let mean = around
+                        .iter()
+                        .map(|v| mesh.positions[*v as usize])
+                        .sum::<Vector3<f64>>()
+                        / around.len() as f64;
+                    p + (mean - p) * factor
+                };
+            }
+            std::mem::swap(&mut mesh.positions, &mut moved);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::{ScalarField, TriangleMesh};
+use nalgebra::Vector3;
+
+/// Corner offsets of a cell, bit `a` of the index set along axis `a`.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+];
+
+/// The cell's 12 edges, as pairs of corners.
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// The surface where `field` crosses `level`, by (naive) surface nets: one
+/// vertex per cell of eight samples the surface passes through, at the
+/// mean of its crossings on the cell's edges, and a quad between the four
+/// cells around every sample edge it crosses. Samples above `level` are
+/// inside. Positions are voxel indices of the full grid (`field.origin`
+/// added), and the mesh is closed if the field is below `level` all
+/// around its border.
+pub fn surface_nets(field: &ScalarField, level: f32) -> TriangleMesh {
+    let [nx, ny, nz] = field.dims;
+    let value = |x: usize, y: usize, z: usize| field.values[x + y * nx + z * nx * ny];
+    let cells = [
+        nx.saturating_sub(1),
+        ny.saturating_sub(1),
+        nz.saturating_sub(1),
+    ];
+    let cell_index = |x: usize, y: usize, z: usize| x + y * cells[0] + z * cells[0] * cells[1];
+    let origin = Vector3::from(field.origin.map(|o| o as f64));
+
+    let mut mesh = TriangleMesh::default();
+    let mut cell_vertex = vec![u32::MAX; cells.iter().product()];
+    for z in 0..cells[2] {
+        for y in 0..cells[1] {
+            for x in 0..cells[0] {
+                let v: [f32; 8] = CORNERS.map(|[i, j, k]| value(x + i, y + j, z + k));
+                let inside = v.iter().filter(|s| **s > level).count();
+                if inside == 0 || inside == 8 {
+                    continue;
+                }
+                let (mut sum, mut count) = (Vector3::zeros(), 0.0);
+                for (a, b) in EDGES {
+                    if (v[a] > level) != (v[b] > level) {
+                        let t = ((level - v[a]) / (v[b] - v[a])) as f64;
+                        let (pa, pb) = (
+                            Vector3::from(CORNERS[a].map(|c| c as f64)),
+                            Vector3::from(CORNERS[b].map(|c| c as f64)),
+                        );
+                        sum += pa + (pb - pa) * t;
+                        count += 1.0;
+                    }
+                }
+                cell_vertex[cell_index(x, y, z)] = mesh.positions.len() as u32;
+                let corner = Vector3::new(x as f64, y as f64, z as f64);
+                mesh.positions.push(origin + corner + sum / count);
+            }
+        }
+    }
+
+    // A sample edge along `a` is shared by the cells one step back along
+    // the other two axes, b and c; going round them b, then c, faces +a.
+    for z in 0..nz {
+        for y in 0..ny {
+            for x in 0..nx {
+                let p = [x, y, z];
+                let inside = value(x, y, z) > level;
+                for a in 0..3 {
+                    let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+                    if p[a] + 1 >= field.dims[a] || p[b] == 0 || p[c] == 0 {
+                        continue;
+                    }
+                    if p[b] >= cells[b] || p[c] >= cells[c] {
+                        continue;
+                    }
+                    let mut q = p;
+                    q[a] += 1;
+                    if (value(q[0], q[1], q[2]) > level) == inside {
+                        continue;
+                    }
+                    let cell = |db: usize, dc: usize| {
+                        let mut at = p;
+                        at[b] -= db;
+                        at[c] -= dc;
+                        cell_vertex[cell_index(at[0], at[1], at[2])]
+                    };
+                    let mut quad = [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)];
+                    // Outward is towards the outside sample.
+                    if !inside {
+                        quad.reverse();
+                    }
+                    push_quad(&mut mesh, quad);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// Two triangles, split along the shorter diagonal.
+fn push_quad(mesh: &mut TriangleMesh, [a, b, c, d]: [u32; 4]) {
+    let at = |v: u32| mesh.positions[v as usize];
+    if (at(a) - at(c)).norm_squared() <= (at(b) - at(d)).norm_squared() {
+        mesh.triangles.extend([[a, b, c], [a, c, d]]);
+    } else {
+        mesh.triangles.extend([[a, b, d], [b, c, d]]);
+    }
+}
@@ -0,0 +1,638 @@
+use super::rng::Rng;
+use crate::domain::error::NWError;
+use crate::domain::optics::{Fluence, OpticalProperties};
+use crate::domain::voxel::VoxelVolume;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Speed of light in vacuum, mm/s.
+const C_MM_PER_S: f64 = 2.997_924_58e11;
+/// Photons lighter than this play Russian roulette.
+const ROULETTE_WEIGHT: f64 = 1e-4;
+/// Chance of surviving the roulette; survivors carry the lost weight.
+const ROULETTE_SURVIVAL: f64 = 0.1;
+/// Photons traced from one random stream. Streams follow the batch, not
+/// the worker thread, so the thread count does not change the result.
+const BATCH_PHOTONS: u64 = 10_000;
+/// Path lengths are summed as integers of 2⁻²⁸ mm, so the total does not
+/// depend on the order the threads add them in.
+const FIXED_POINT_SCALE: f64 = (1u64 << 28) as f64;
+
+/// A pencil beam, MCX `Pos`/`Dir` conventions.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PencilBeam {
+    /// Launch point in continuous voxel coordinates, origin at the outer
+    /// corner of voxel 0 (the centre of voxel i is at i + 0.5).
+    pub position: [f64; 3],
+    /// Along the voxel axes; need not be normalised.
+    pub direction: [f64; 3],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SimulationConfig {
+    pub photons: u64,
+    /// Start of the time window, s.
+    pub t0: f64,
+    /// End of the time window, s. Photons are followed until then.
+    pub t1: f64,
+    /// Time gates the window is split into; 1 for CW.
+    pub gates: usize,
+    pub seed: u64,
+    /// Worker threads; 0 uses every core. The result is the same for any
+    /// count.
+    pub threads: usize,
+    /// Reflect and refract where the refractive index changes (MCX
+    /// `DoMismatch`), including on entering and leaving the head.
+    pub mismatch: bool,
+    /// Take the specular reflection at the skin off every photon's launch
+    /// weight. Off by default, as in MCX.
+    pub specular: bool,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            photons: 1_000_000,
+            t0: 0.0,
+            t1: 5e-9,
+            gates: 1,
+            seed: 1,
+            threads: 0,
+            mismatch: true,
+            specular: false,
+        }
+    }
+}
+
+/// The labelled volume as the photons see it, distances in mm along the
+/// voxel axes.
+struct Grid<'a> {
+    dims: [usize; 3],
+    /// Voxel edge lengths, mm.
+    voxel: [f64; 3],
+    labels: &'a [u8],
+    media: &'a [OpticalProperties],
+}
+
+impl<'a> Grid<'a> {
+    fn new(volume: &'a VoxelVolume, media: &'a [OpticalProperties]) -> Self {
+        Grid {
+            dims: volume.dims,
+            voxel: std::array::from_fn(|c| volume.vox2ras.fixed_view::<3, 1>(0, c).norm()),
+            labels: &volume.labels,
+            media,
+        }
+    }
+
+    fn contains(&self, idx: [i64; 3]) -> bool {
+        (0..3).all(|a| idx[a] >= 0 && (idx[a] as usize) < self.dims[a])
+    }
+
+    fn offset(&self, idx: [i64; 3]) -> usize {
+        let [nx, ny, _] = self.dims;
+        idx[0] as usize + idx[1] as usize * nx + idx[2] as usize * nx * ny
+    }
+
+    /// The label at `idx`; 0 (background) outside the grid.
+    fn label(&self, idx: [i64; 3]) -> u8 {
+        if self.contains(idx) {
+            self.labels[self.offset(idx)]
+        } else {
+            0
+        }
+    }
+
+    fn medium(&self, idx: [i64; 3]) -> &OpticalProperties {
+        &self.media[self.label(idx) as usize]
+    }
+
+    /// Distance from `p` along `d` to the nearest face of voxel `idx`, and
+    /// the axis that face is normal to.
+    fn to_boundary(&self, p: &[f64; 3], d: &[f64; 3], idx: [i64; 3]) -> (f64, usize) {
+        let mut nearest = (f64::INFINITY, 0);
+        for a in 0..3 {
+            let face = if d[a] > 0.0 {
+                (idx[a] + 1) as f64 * self.voxel[a]
+            } else if d[a] < 0.0 {
+                idx[a] as f64 * self.voxel[a]
+            } else {
+                continue;
+            };
+            let dist = ((face - p[a]) / d[a]).max(0.0);
+            if dist < nearest.0 {
+                nearest = (dist, a);
+            }
+        }
+        nearest
+    }
+
+    /// The coordinate of the face of voxel `idx` crossed along `step`.
+    fn face(&self, idx: [i64; 3], axis: usize, step: i64) -> f64 {
+        let i = if step > 0 { idx[axis] + 1 } else { idx[axis] };
+        i as f64 * self.voxel[axis]
+    }
+}
+
+/// Where and how the beam enters the tissue; the same for every photon.
+#[derive(Clone, Copy)]
+struct Launch {
+    p: [f64; 3],
+    d: [f64; 3],
+    idx: [i64; 3],
+    /// Share transmitted through the skin surface.
+    weight: f64,
+}
+
+/// Fresnel reflectance of unpolarised light going from index `n1` into
+/// `n2` at incidence cosine `cos_i`, and the transmitted cosine (None on
+/// total internal reflection).
+fn fresnel(n1: f64, n2: f64, cos_i: f64) -> (f64, Option<f64>) {
+    let sin_t = n1 / n2 * (1.0 - cos_i * cos_i).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        return (1.0, None);
+    }
+    let cos_t = (1.0 - sin_t * sin_t).sqrt();
+    let rs = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+    let rp = (n1 * cos_t - n2 * cos_i) / (n1 * cos_t + n2 * cos_i);
+    ((rs * rs + rp * rp) / 2.0, Some(cos_t))
+}
+
+/// Bend `d` through a face normal to `axis`, leaving at cosine `cos_t`.
+fn refract(d: &mut [f64; 3], axis: usize, ratio: f64, cos_t: f64) {
+    for (a, c) in d.iter_mut().enumerate() {
+        if a == axis {
+            *c = c.signum() * cos_t;
+        } else {
+            *c *= ratio;
+        }
+    }
+    normalise(d);
+}
+
+fn normalise(d: &mut [f64; 3]) {
+    let norm = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+    for c in d.iter_mut() {
+        *c /= norm;
+    }
+}
+
+/// Turn `d` by an angle drawn from the Henyey-Greenstein phase function.
+fn scatter(d: &mut [f64; 3], g: f64, rng: &mut Rng) {
+    let cos_t = if g.abs() > 1e-6 {
+        let tmp = (1.0 - g * g) / (1.0 - g + 2.0 * g * rng.uniform());
+        ((1.0 + g * g - tmp * tmp) / (2.0 * g)).clamp(-1.0, 1.0)
+    } else {
+        2.0 * rng.uniform() - 1.0
+    };
+    let sin_t = (1.0 - cos_t * cos_t).sqrt();
+    let (sin_p, cos_p) = (TAU * rng.uniform()).sin_cos();
+    let [ux, uy, uz] = *d;
+    *d = if uz.abs() > 1.0 - 1e-10 {
+        [sin_t * cos_p, sin_t * sin_p, uz.signum() * cos_t]
+    } else {
+        let tmp = (1.0 - uz * uz).sqrt();
+        [
+            sin_t * (ux * uz * cos_p - uy * sin_p) / tmp + ux * cos_t,
+            sin_t * (uy * uz * cos_p + ux * sin_p) / tmp + uy * cos_t,
+            -sin_t * cos_p * tmp + uz * cos_t,
+        ]
+    };
+    normalise(d);
+}
+
+/// Distance along the ray `p + t·d` to where it enters the grid box.
+fn enter_box(grid: &Grid, p: &[f64; 3], d: &[f64; 3]) -> Option<f64> {
+    let (mut near, mut far) = (0.0f64, f64::INFINITY);
+    for a in 0..3 {
+        let size = grid.dims[a] as f64 * grid.voxel[a];
+        if d[a] == 0.0 {
+            if p[a] < 0.0 || p[a] > size {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((0.0 - p[a]) / d[a], (size - p[a]) / d[a]);
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    (near <= far).then_some(near)
+}
+
+/// Carry the beam through the background to the first tissue voxel.
+fn launch(grid: &Grid, source: &PencilBeam, config: &SimulationConfig) -> Result<Launch, NWError> {
+    let mut d = source.direction;
+    if d.iter().all(|c| *c == 0.0) || d.iter().any(|c| !c.is_finite()) {
+        return Err(NWError::InvalidInput(
+            "the source direction must be a non-zero vector".into(),
+        ));
+    }
+    normalise(&mut d);
+    let misses = || NWError::InvalidInput("the source beam does not reach any tissue".into());
+
+    let mut p: [f64; 3] = std::array::from_fn(|a| source.position[a] * grid.voxel[a]);
+    let along = enter_box(grid, &p, &d).ok_or_else(misses)?;
+    for a in 0..3 {
+        p[a] += d[a] * along;
+    }
+    let mut idx: [i64; 3] = std::array::from_fn(|a| {
+        ((p[a] / grid.voxel[a]).floor() as i64).clamp(0, grid.dims[a] as i64 - 1)
+    });
+
+    // The face crossed into the first tissue voxel, if the beam started
+    // outside it.
+    let mut entry_axis = (along > 0.0).then(|| {
+        (0..3)
+            .find(|&a| p[a] <= 0.0 || p[a] >= grid.dims[a] as f64 * grid.voxel[a])
+            .unwrap_or(0)
+    });
+    while grid.label(idx) == 0 {
+        let (dist, axis) = grid.to_boundary(&p, &d, idx);
+        let step = if d[axis] > 0.0 { 1 } else { -1 };
+        for a in 0..3 {
+            p[a] += d[a] * dist;
+        }
+        p[axis] = grid.face(idx, axis, step);
+        idx[axis] += step;
+        if !grid.contains(idx) {
+            return Err(misses());
+        }
+        entry_axis = Some(axis);
+    }
+
+    let weight = match entry_axis {
+        Some(axis) if config.mismatch => {
+            let (n_out, n_in) = (grid.media[0].n, grid.medium(idx).n);
+            let (r, cos_t) = fresnel(n_out, n_in, d[axis].abs());
+            if let Some(cos_t) = cos_t {
+                refract(&mut d, axis, n_out / n_in, cos_t);
+            }
+            if config.specular {
+                1.0 - r
+            } else {
+                1.0
+            }
+        }
+        _ => 1.0,
+    };
+    Ok(Launch { p, d, idx, weight })
+}
+
+/// Add `value` (≥ 0) to a fixed-point sum.
+fn accumulate(cell: &AtomicU64, value: f64) {
+    cell.fetch_add(
+        (value * FIXED_POINT_SCALE).round() as u64,
+        Ordering::Relaxed,
+    );
+}
+
+/// The value of a fixed-point sum.
+fn total(cell: &AtomicU64) -> f64 {
+    cell.load(Ordering::Relaxed) as f64 / FIXED_POINT_SCALE
+}
+
+/// Follow one photon, adding its weighted path lengths to `acc`. Returns
+/// the weight it carries out of the head; 0 if it outlives the time window
+/// or loses the roulette.
+fn trace(
+    grid: &Grid,
+    start: &Launch,
+    config: &SimulationConfig,
+    rng: &mut Rng,
+    acc: &[AtomicU64],
+) -> f64 {
+    let voxels = grid.labels.len();
+    let gate_width = (config.t1 - config.t0) / config.gates as f64;
+    let (mut p, mut d, mut idx, mut w) = (start.p, start.d, start.idx, start.weight);
+    let mut t = 0.0;
+
+    loop {
+        // Scattering lengths left to the next scattering event.
+        let mut s = -rng.uniform().ln();
+        while s > 0.0 {
+            let m = *grid.medium(idx);
+            let (to_face, axis) = grid.to_boundary(&p, &d, idx);
+            let to_scatter = if m.mus > 0.0 {
+                s / m.mus
+            } else {
+                f64::INFINITY
+            };
+            let hits_face = to_face < to_scatter;
+            let len = to_face.min(to_scatter);
+
+            if t >= config.t1 {
+                return 0.0;
+            }
+            let attenuation = (-m.mua * len).exp();
+            if t >= config.t0 {
+                let gate = (((t - config.t0) / gate_width) as usize).min(config.gates - 1);
+                let track = if m.mua > 0.0 {
+                    w * (1.0 - attenuation) / m.mua
+                } else {
+                    w * len
+                };
+                accumulate(&acc[gate * voxels + grid.offset(idx)], track);
+            }
+            w *= attenuation;
+            t += len * m.n / C_MM_PER_S;
+            for a in 0..3 {
+                p[a] += d[a] * len;
+            }
+            if !hits_face {
+                break;
+            }
+            s -= len * m.mus;
+
+            let step = if d[axis] > 0.0 { 1 } else { -1 };
+            p[axis] = grid.face(idx, axis, step);
+            let mut next = idx;
+            next[axis] += step;
+            let n_next = grid.medium(next).n;
+            if config.mismatch && n_next != m.n {
+                let (r, cos_t) = fresnel(m.n, n_next, d[axis].abs());
+                match cos_t {
+                    Some(cos_t) if rng.uniform() > r => refract(&mut d, axis, m.n / n_next, cos_t),
+                    _ => {
+                        d[axis] = -d[axis];
+                        continue;
+                    }
+                }
+            }
+            if grid.label(next) == 0 {
+                // Escaped the head.
+                return w;
+            }
+            idx = next;
+        }
+
+        scatter(&mut d, grid.medium(idx).g, rng);
+        if w < ROULETTE_WEIGHT {
+            if rng.uniform() > ROULETTE_SURVIVAL {
+                return 0.0;
+            }
+            w /= ROULETTE_SURVIVAL;
+        }
+    }
+}
+
+fn validate(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    config: &SimulationConfig,
+) -> Result<(), NWError> {
+    if config.photons == 0 {
+        return Err(NWError::InvalidInput(
+            "at least one photon is needed".into(),
+        ));
+    }
+    let finite = config.t0.is_finite() && config.t1.is_finite();
+    if config.gates == 0 || !finite || config.t1 <= config.t0 || config.t0 < 0.0 {
+        return Err(NWError::InvalidInput(
+            "the time window needs 0 ≤ t0 < t1 and at least one gate".into(),
+        ));
+    }
+    if volume.labels.len() != volume.dims.iter().product::<usize>() {
+        return Err(NWError::InvalidInput(
+            "the volume's labels do not match its dimensions".into(),
+        ));
+    }
+    if let Some(bad) = media
+        .iter()
+        .position(|m| !(m.mua >= 0.0 && m.mus >= 0.0 && m.n > 0.0 && (-1.0..=1.0).contains(&m.g)))
+    {
+        return Err(NWError::InvalidInput(format!(
+            "medium {bad} needs μa ≥ 0, μs ≥ 0, n > 0 and -1 ≤ g ≤ 1"
+        )));
+    }
+    match volume.labels.iter().max() {
+        Some(&max) if (max as usize) < media.len() => Ok(()),
+        Some(&max) => Err(NWError::InvalidInput(format!(
+            "label {max} has no optical properties ({} media given)",
+            media.len()
+        ))),
+        None => Err(NWError::NoData),
+    }
+}
+
+/// Where `source`'s beam enters the tissue: the first tissue voxel, and the
+/// beam from there on, refracted at the skin if `config.mismatch` is set.
+/// The beam starts just inside that voxel, so it also lands there where a
+/// face is shared; launching it is how [`simulate`] starts every photon.
+pub fn enter_tissue(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    source: &PencilBeam,
+    config: &SimulationConfig,
+) -> Result<(PencilBeam, [usize; 3]), NWError> {
+    validate(volume, media, config)?;
+    let grid = Grid::new(volume, media);
+    let start = launch(&grid, source, config)?;
+    let beam = PencilBeam {
+        position: std::array::from_fn(|a| {
+            let i = start.idx[a] as f64;
+            (start.p[a] / grid.voxel[a]).clamp(i + 1e-6, i + 1.0 - 1e-6)
+        }),
+        direction: start.d,
+    };
+    Ok((beam, start.idx.map(|i| i as usize)))
+}
+
+/// Monte Carlo photon migration through the labelled `volume`, medium
+/// `media[label]` in each voxel and `media[0]` around the head (MCX's
+/// model). Photons are traced in batches of [`BATCH_PHOTONS`], each with its
+/// own random stream, spread over the worker threads; their weighted path
+/// lengths are collected per voxel and
+/// time gate; the result is normalised to fluence rate per unit source
+/// power, as MCX's `DoNormalize`.
+pub fn simulate(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    source: &PencilBeam,
+    config: &SimulationConfig,
+) -> Result<Fluence, NWError> {
+    validate(volume, media, config)?;
+    let grid = Grid::new(volume, media);
+    let start = launch(&grid, source, config)?;
+
+    let voxels = volume.labels.len();
+    let acc: Vec<AtomicU64> = (0..voxels * config.gates)
+        .map(|_| AtomicU64::new(0))
+        .collect();
+    let batches = config.photons.div_ceil(BATCH_PHOTONS);
+    let threads = match config.threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .clamp(1, batches.min(usize::MAX as u64) as usize);
+    let next_batch = AtomicU64::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let (grid, start, acc, next_batch) = (&grid, &start, &acc, &next_batch);
+            scope.spawn(move || loop {
+                let batch = next_batch.fetch_add(1, Ordering::Relaxed);
+                if batch >= batches {
+                    break;
+                }
+                let count = BATCH_PHOTONS.min(config.photons - batch * BATCH_PHOTONS);
+                let mut rng = Rng::stream(config.seed, batch);
+                for _ in 0..count {
+                    trace(grid, start, config, &mut rng, acc);
+                }
+            });
+        }
+    });
+
+    let gate_width = (config.t1 - config.t0) / config.gates as f64;
+    let voxel_volume: f64 = grid.voxel.iter().product();
+    let scale = 1.0 / (config.photons as f64 * voxel_volume * gate_width);
+    Ok(Fluence {
+        dims: volume.dims,
+        vox2ras: volume.vox2ras,
+        t0: config.t0,
+        gate_width,
+        gates: config.gates,
+        entry: start.idx.map(|i| i as usize),
+        data: acc.iter().map(|c| (total(c) * scale) as f32).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Matrix4;
+    use std::collections::HashMap;
+
+    /// An `n`³ cube of 1 mm voxels, all labelled 1.
+    fn cube(n: usize) -> VoxelVolume {
+        VoxelVolume {
+            name: "cube".into(),
+            dims: [n; 3],
+            vox2ras: Matrix4::identity(),
+            labels: vec![1; n * n * n],
+            label_names: HashMap::new(),
+        }
+    }
+
+    fn media(mua: f64, mus: f64, g: f64) -> Vec<OpticalProperties> {
+        vec![
+            OpticalProperties {
+                mua: 0.0,
+                mus: 0.0,
+                g: 1.0,
+                n: 1.0,
+            },
+            OpticalProperties {
+                mua,
+                mus,
+                g,
+                n: 1.0,
+            },
+        ]
+    }
+
+    /// Straight down the middle of the cube from its z = 0 face.
+    fn beam(n: usize) -> PencilBeam {
+        PencilBeam {
+            position: [n as f64 / 2.0, n as f64 / 2.0, 0.0],
+            direction: [0.0, 0.0, 1.0],
+        }
+    }
+
+    fn config(photons: u64) -> SimulationConfig {
+        SimulationConfig {
+            photons,
+            t1: 1e-6,
+            threads: 2,
+            mismatch: false,
+            ..SimulationConfig::default()
+        }
+    }
+
+    #[test]
+    fn fixed_seed_repeats_on_any_thread_count() {
+        let (volume, media) = (cube(10), media(0.02, 1.0, 0.9));
+        // Several batches, the last one partial.
+        let config = config(2 * BATCH_PHOTONS + 500);
+        let a = simulate(&volume, &media, &beam(10), &config).unwrap();
+        for threads in [1, 3] {
+            let config = SimulationConfig {
+                threads,
+                ..config.clone()
+            };
+            let b = simulate(&volume, &media, &beam(10), &config).unwrap();
+            assert_eq!(a.data, b.data, "{threads} threads");
+        }
+        let other = SimulationConfig { seed: 2, ..config };
+        let c = simulate(&volume, &media, &beam(10), &other).unwrap();
+        assert_ne!(a.data, c.data);
+    }
+
+    #[test]
+    fn cw_fluence_falls_off_along_the_beam() {
+        let n = 20;
+        let (volume, media) = (cube(n), media(0.1, 2.0, 0.0));
+        let fluence = simulate(&volume, &media, &beam(n), &config(50_000)).unwrap();
+        let on_axis: Vec<f32> = (0..10)
+            .map(|z| fluence.data[n / 2 + n / 2 * n + z * n * n])
+            .collect();
+        assert!(on_axis[9] > 0.0);
+        for pair in on_axis.windows(2) {
+            assert!(pair[0] > pair[1], "{on_axis:?}");
+        }
+    }
+
+    #[test]
+    fn absorbed_and_escaped_weight_add_up() {
+        let n = 10;
+        let (volume, media) = (cube(n), media(0.05, 1.0, 0.8));
+        let config = config(20_000);
+        let grid = Grid::new(&volume, &media);
+        let start = launch(&grid, &beam(n), &config).unwrap();
+        let acc: Vec<AtomicU64> = (0..volume.labels.len())
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        let mut rng = Rng::new(config.seed);
+        let escaped: f64 = (0..config.photons)
+            .map(|_| trace(&grid, &start, &config, &mut rng, &acc))
+            .sum();
+        // Weighted path length times μa is the weight absorbed.
+        let absorbed: f64 = acc.iter().map(|c| total(c) * media[1].mua).sum();
+        let total = (absorbed + escaped) / config.photons as f64;
+        assert!((total - 1.0).abs() < 0.02, "{total}");
+    }
+
+    #[test]
+    fn validate_rejects_bad_input() {
+        let (volume, media) = (cube(4), media(0.02, 1.0, 0.9));
+        let ok = config(10);
+        assert!(validate(&volume, &media, &ok).is_ok());
+
+        let no_photons = SimulationConfig {
+            photons: 0,
+            ..ok.clone()
+        };
+        assert!(validate(&volume, &media, &no_photons).is_err());
+        let backwards = SimulationConfig {
+            t0: 2e-9,
+            t1: 1e-9,
+            ..ok.clone()
+        };
+        assert!(validate(&volume, &media, &backwards).is_err());
+        let no_gates = SimulationConfig {
+            gates: 0,
+            ..ok.clone()
+        };
+        assert!(validate(&volume, &media, &no_gates).is_err());
+
+        let mut unlabelled = cube(4);
+        unlabelled.labels[5] = 2;
+        assert!(validate(&unlabelled, &media, &ok).is_err());
+
+        let nowhere = PencilBeam {
+            direction: [0.0; 3],
+            ..beam(4)
+        };
+        assert!(simulate(&volume, &media, &nowhere, &ok).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+mod engine;
+mod rng;
+
+pub use engine::{enter_tissue, simulate, PencilBeam, SimulationConfig};
@@ -0,0 +1,536 @@
+use crate::domain::anatomy::{AnatomyStep, SegmentationBackend, StepStatus, SubjectAnatomy};
+use crate::domain::error::NWError;
+use crate::io::anatomy_importer::{
+    build_head_labels, load_head_labels_volume, load_subject_anatomy,
+};
+use crate::io::mesh_exporter::export_obj;
+use crate::services::meshing_service::{tissue_mesh, MeshingOptions, TISSUES};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const CUDA_IMAGE: &str = "nvidia/cuda:12.0.0-base-ubuntu22.04";
+
+/// What segmentation leaves in the subject's mri/ folder for voxelisation.
+const SEGMENTATION_FILES: [&str; 3] = ["aseg.mgz", "T1.mgz", "brainmask.mgz"];
+
+/// How often a running subprocess is checked for exit or cancellation.
+const POLL: Duration = Duration::from_millis(200);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AnatomyJobOptions {
+    /// The T1 image, by default at data_root/input/<sub>_….nii.gz. Not
+    /// needed with a precomputed subject.
+    pub mri: Option<String>,
+    pub backend: SegmentationBackend,
+    /// Folder the subject folder is written to; data_root/output if unset.
+    pub output: Option<String>,
+    /// Folder holding the FreeSurfer license.txt; data_root/license if
+    /// unset.
+    pub license: Option<String>,
+    pub meshing: MeshingOptions,
+    /// Run this step and the ones after it even where their outputs are
+    /// already there; by default a job resumes after the last step done.
+    pub rerun_from: Option<AnatomyStep>,
+}
+
+/// Where a job reads and writes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubjectPaths {
+    pub mri: Option<PathBuf>,
+    pub subject_id: String,
+    /// The subjects folder segmentation writes the subject folder into.
+    pub output: PathBuf,
+    pub license: PathBuf,
+    pub subject_dir: PathBuf,
+}
+
+/// Where the meshing step writes `tissue`'s mesh in a subject folder.
+pub fn subject_mesh(subject_dir: &Path, tissue: &str) -> PathBuf {
+    subject_dir.join("surf").join(format!("{tissue}.obj"))
+}
+
+impl SubjectPaths {
+    pub fn mesh(&self, tissue: &str) -> PathBuf {
+        subject_mesh(&self.subject_dir, tissue)
+    }
+
+    fn head_labels(&self) -> PathBuf {
+        self.subject_dir.join("mri").join("head_labels.mgz")
+    }
+}
+
+/// Where a running job reports to, and how it learns it was cancelled.
+/// `log` is called from the threads reading a subprocess's output.
+pub trait JobMonitor: Sync {
+    fn progress(&self, step: AnatomyStep, status: StepStatus, detail: &str);
+    fn log(&self, step: AnatomyStep, line: &str);
+    fn cancelled(&self) -> bool;
+}
+
+impl AnatomyJobOptions {
+    pub fn paths(&self) -> Result<SubjectPaths, NWError> {
+        if let SegmentationBackend::Precomputed { subject_dir } = &self.backend {
+            let subject_dir = PathBuf::from(subject_dir);
+            let subject_id = subject_dir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .ok_or_else(|| NWError::InvalidInput("the subject folder has no name".into()))?;
+            let output = subject_dir.parent().unwrap_or(Path::new("")).to_path_buf();
+            return Ok(SubjectPaths {
+                mri: self.mri.as_ref().map(PathBuf::from),
+                subject_id,
+                license: self.license.as_ref().map(PathBuf::from).unwrap_or_default(),
+                output,
+                subject_dir,
+            });
+        }
+
+        let mri = PathBuf::from(
+            self.mri
+                .as_ref()
+                .ok_or_else(|| NWError::InvalidInput("segmentation needs the T1 MRI".into()))?,
+        );
+        // mri is at data_root/input/<file>.nii.gz, so data_root is two
+        // levels up (the parent of the input folder).
+        let data_root = || {
+            mri.parent().and_then(|p| p.parent()).ok_or_else(|| {
+                NWError::InvalidInput(
+                    "the MRI must be inside an 'input' subfolder (e.g. data_root/input/file.nii.gz) \
+                     unless the output and license folders are given"
+                        .into(),
+                )
+            })
+        };
+        let output = match &self.output {
+            Some(output) => PathBuf::from(output),
+            None => data_root()?.join("output"),
+        };
+        let license = match &self.license {
+            Some(license) => PathBuf::from(license),
+            None => data_root()?.join("license"),
+        };
+        let subject_id = bids_subject(&mri);
+        Ok(SubjectPaths {
+            subject_dir: output.join(&subject_id),
+            mri: Some(mri),
+            subject_id,
+            output,
+            license,
+        })
+    }
+}
+
+/// The subject of a BIDS file name, its first entity:
+/// sub-116_ses-BL_T1w.nii.gz → sub-116.
+fn bids_subject(mri: &Path) -> String {
+    let fname = mri
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("subject");
+    let stem = fname
+        .strip_suffix(".nii.gz")
+        .or_else(|| fname.strip_suffix(".nii"))
+        .unwrap_or(fname);
+    stem.split('_').next().unwrap_or(stem).to_string()
+}
+
+/// Whether a step's outputs are all there.
+fn step_done(step: AnatomyStep, paths: &SubjectPaths) -> bool {
+    match step {
+        AnatomyStep::Verify | AnatomyStep::Load => false,
+        AnatomyStep::Segment => SEGMENTATION_FILES
+            .iter()
+            .all(|f| paths.subject_dir.join("mri").join(f).is_file()),
+        AnatomyStep::Voxelise => paths.head_labels().is_file(),
+        AnatomyStep::Meshify => TISSUES.iter().all(|(name, _)| paths.mesh(name).is_file()),
+    }
+}
+
+/// Which steps a job runs: verify and load always; the others when their
+/// outputs are missing, when a step they build on runs, or from
+/// `rerun_from` on. A precomputed subject is never segmented.
+pub fn plan(options: &AnatomyJobOptions, paths: &SubjectPaths) -> Vec<(AnatomyStep, bool)> {
+    let precomputed = matches!(options.backend, SegmentationBackend::Precomputed { .. });
+    let mut stale = false;
+    AnatomyStep::ALL
+        .into_iter()
+        .map(|step| {
+            stale |= options.rerun_from.is_some_and(|from| step >= from);
+            let run = match step {
+                AnatomyStep::Verify | AnatomyStep::Load => true,
+                AnatomyStep::Segment if precomputed => false,
+                _ => stale || !step_done(step, paths),
+            };
+            if run && step != AnatomyStep::Verify {
+                stale = true;
+            }
+            (step, run)
+        })
+        .collect()
+}
+
+/// Run the anatomy pipeline for `options`, resuming after the steps
+/// already done, and load the meshes it leaves. Blocks until done, failed
+/// or cancelled; each step's progress and output goes to `monitor`.
+pub fn run_anatomy_job(
+    options: &AnatomyJobOptions,
+    monitor: &dyn JobMonitor,
+) -> Result<(SubjectPaths, SubjectAnatomy), NWError> {
+    let paths = options.paths()?;
+    let plan = plan(options, &paths);
+    for (step, _) in &plan {
+        monitor.progress(*step, StepStatus::Pending, "");
+    }
+
+    let mut anatomy = None;
+    for (step, run) in &plan {
+        let step = *step;
+        if monitor.cancelled() {
+            monitor.progress(step, StepStatus::Cancelled, "");
+            return Err(NWError::Cancelled);
+        }
+        if !run {
+            let why = match options.backend {
+                SegmentationBackend::Precomputed { .. } if step == AnatomyStep::Segment => {
+                    "precomputed subject"
+                }
+                _ => "outputs already there",
+            };
+            monitor.progress(step, StepStatus::Skipped, why);
+            continue;
+        }
+        monitor.progress(step, StepStatus::Running, "");
+        let result = match step {
+            AnatomyStep::Verify => verify(options, &paths, &plan, monitor),
+            AnatomyStep::Segment => segment(&options.backend, &paths, monitor),
+            AnatomyStep::Voxelise => {
+                build_head_labels(&paths.subject_dir, &|line| monitor.log(step, line))
+                    .map_err(NWError::Internal)
+            }
+            AnatomyStep::Meshify => meshify(&options.meshing, &paths, monitor),
+            AnatomyStep::Load => load(&paths, monitor).map(|a| anatomy = Some(a)),
+        };
+        match result {
+            Ok(()) => monitor.progress(step, StepStatus::Done, ""),
+            Err(e) => {
+                let status = match e {
+                    NWError::Cancelled => StepStatus::Cancelled,
+                    _ => StepStatus::Failed,
+                };
+                monitor.progress(step, status, &e.to_string());
+                return Err(e);
+            }
+        }
+    }
+    let anatomy = anatomy.ok_or_else(|| NWError::Internal("the load step did not run".into()))?;
+    Ok((paths, anatomy))
+}
+
+/// `e` with a hint on what to do about it, unless the job was cancelled.
+fn hint(e: NWError, hint: &str) -> NWError {
+    match e {
+        NWError::Cancelled => e,
+        e => NWError::Internal(format!("{e} — {hint}")),
+    }
+}
+
+// ------------------------------------------------------------------
+// Verify
+// ------------------------------------------------------------------
+
+/// Check what the steps in `plan` need before any of them starts: the
+/// segmentation tools, license and MRI if the MRI is to be segmented, the
+/// segmentation if only voxelisation is.
+fn verify(
+    options: &AnatomyJobOptions,
+    paths: &SubjectPaths,
+    plan: &[(AnatomyStep, bool)],
+    monitor: &dyn JobMonitor,
+) -> Result<(), NWError> {
+    let step = AnatomyStep::Verify;
+    let runs = |s: AnatomyStep| plan.iter().any(|(p, run)| *p == s && *run);
+
+    if runs(AnatomyStep::Segment) {
+        match &options.backend {
+            SegmentationBackend::Container { image, gpu } => {
+                // `docker info` connects to the daemon; `docker --version`
+                // only reads the local binary and always succeeds.
+                run_process(step, "docker info", docker(&["info"]), monitor, || {})
+                    .map_err(|e| hint(e, "is Docker running? Start it and retry."))?;
+                if *gpu {
+                    run_process(
+                        step,
+                        "nvidia-smi in Docker",
+                        docker(&["run", "--gpus", "all", "--rm", CUDA_IMAGE, "nvidia-smi"]),
+                        monitor,
+                        || {},
+                    )
+                    .map_err(|e| hint(e, "enable GPU support in Docker, or run on the CPU."))?;
+                }
+                run_process(
+                    step,
+                    &format!("inspect {image}"),
+                    docker(&["image", "inspect", image]),
+                    monitor,
+                    || {},
+                )
+                .map_err(|e| hint(e, &format!("pull it with `docker pull {image}`.")))?;
+            }
+            SegmentationBackend::Native { command } => {
+                if !Path::new(command).is_file() {
+                    return Err(NWError::InvalidInput(format!(
+                        "FastSurfer not found at: {command}"
+                    )));
+                }
+                monitor.log(step, "[OK] FastSurfer found");
+            }
+            SegmentationBackend::Precomputed { .. } => {}
+        }
+
+        let license = paths.license.join("license.txt");
+        if !license.is_file() {
+            return Err(NWError::InvalidInput(format!(
+                "FreeSurfer license not found at: {}",
+                license.display()
+            )));
+        }
+        monitor.log(step, "[OK] license.txt found");
+
+        if !paths.mri.as_deref().is_some_and(Path::is_file) {
+            return Err(NWError::InvalidInput(format!(
+                "Input MRI not found: {}",
+                paths.mri.as_deref().unwrap_or(Path::new("")).display()
+            )));
+        }
+        monitor.log(step, "[OK] Input MRI found");
+    } else if runs(AnatomyStep::Voxelise) {
+        for file in SEGMENTATION_FILES {
+            let path = paths.subject_dir.join("mri").join(file);
+            if !path.is_file() {
+                return Err(NWError::InvalidInput(format!(
+                    "segmentation output not found: {}",
+                    path.display()
+                )));
+            }
+        }
+        monitor.log(step, "[OK] Segmentation found");
+    }
+
+    monitor.log(step, "All checks passed.");
+    Ok(())
+}
+
+// ------------------------------------------------------------------
+// Segment
+// ------------------------------------------------------------------
+
+fn segment(
+    backend: &SegmentationBackend,
+    paths: &SubjectPaths,
+    monitor: &dyn JobMonitor,
+) -> Result<(), NWError> {
+    let step = AnatomyStep::Segment;
+    let mri = paths
+        .mri
+        .as_deref()
+        .ok_or_else(|| NWError::InvalidInput("segmentation needs the T1 MRI".into()))?;
+    std::fs::create_dir_all(&paths.output)?;
+    let subject_id = paths.subject_id.as_str();
+
+    match backend {
+        SegmentationBackend::Container { image, gpu } => {
+            let input_folder = mri.parent().unwrap_or(Path::new("."));
+            let filename = mri
+                .file_name()
+                .ok_or_else(|| NWError::InvalidInput("the MRI path has no file name".into()))?
+                .to_string_lossy()
+                .to_string();
+            // Named so a cancel can stop it: killing the client leaves the
+            // container running.
+            let name: String = format!("nirwizard-fastsurfer-{subject_id}-{}", std::process::id())
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+                .collect();
+            let mounts = [
+                format!("{}:/data", docker_path(input_folder)),
+                format!("{}:/output", docker_path(&paths.output)),
+                format!("{}:/fs_license", docker_path(&paths.license)),
+            ];
+            let mut cmd = docker(&["run", "--name", &name]);
+            if *gpu {
+                cmd.args(["--gpus", "all"]);
+            }
+            for mount in &mounts {
+                cmd.args(["-v", mount]);
+            }
+            cmd.args(["--rm", "--user", "root", image])
+                .args(["--fs_license", "/fs_license/license.txt"])
+                .args(["--t1", &format!("/data/{filename}")])
+                .args(["--sid", subject_id, "--sd", "/output"])
+                .args(["--3T", "--threads", "max", "--allow_root"]);
+            if !*gpu {
+                cmd.args(["--device", "cpu"]);
+            }
+            run_process(step, &format!("docker run {image}"), cmd, monitor, || {
+                let _ = docker(&["kill", &name]).output();
+            })
+        }
+        SegmentationBackend::Native { command } => {
+            let mut cmd = Command::new(command);
+            cmd.arg("--fs_license")
+                .arg(paths.license.join("license.txt"))
+                .arg("--t1")
+                .arg(mri)
+                .args(["--sid", subject_id, "--sd"])
+                .arg(&paths.output)
+                .args(["--3T", "--threads", "max"]);
+            run_process(step, command, cmd, monitor, || {})
+        }
+        SegmentationBackend::Precomputed { .. } => Ok(()),
+    }
+}
+
+fn docker(args: &[&str]) -> Command {
+    let mut cmd = Command::new("docker");
+    cmd.args(args);
+    cmd
+}
+
+/// Convert a Windows path to forward-slash form for Docker volume mounts.
+fn docker_path(p: &Path) -> String {
+    p.to_string_lossy().replace('\\', "/")
+}
+
+/// Run `cmd`, passing each line it prints to `monitor`, until it exits or
+/// the job is cancelled. On cancel the process (on Unix its whole process
+/// group, so a script's children go too) is killed and `on_cancel` run
+/// for whatever outlives it.
+fn run_process(
+    step: AnatomyStep,
+    label: &str,
+    mut cmd: Command,
+    monitor: &dyn JobMonitor,
+    on_cancel: impl FnOnce(),
+) -> Result<(), NWError> {
+    monitor.log(step, &format!("[RUN] {label}"));
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| NWError::Internal(format!("[{label}] failed to launch process: {e}")))?;
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+    std::thread::scope(|scope| {
+        if let Some(stdout) = stdout {
+            scope.spawn(move || forward(stdout, step, monitor));
+        }
+        if let Some(stderr) = stderr {
+            scope.spawn(move || forward(stderr, step, monitor));
+        }
+        loop {
+            if monitor.cancelled() {
+                #[cfg(unix)]
+                let _ = Command::new("kill")
+                    .args(["-s", "KILL", "--", &format!("-{}", child.id())])
+                    .output();
+                let _ = child.kill();
+                let _ = child.wait();
+                on_cancel();
+                return Err(NWError::Cancelled);
+            }
+            match child.try_wait()? {
+                Some(status) if status.success() => return Ok(()),
+                Some(status) => {
+                    return Err(NWError::Internal(format!(
+                        "[{label}] exited with code {}",
+                        status.code().unwrap_or(-1)
+                    )))
+                }
+                None => std::thread::sleep(POLL),
+            }
+        }
+    })
+}
+
+fn forward(pipe: impl Read, step: AnatomyStep, monitor: &dyn JobMonitor) {
+    for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+        monitor.log(step, &line);
+    }
+}
+
+// ------------------------------------------------------------------
+// Meshify
+// ------------------------------------------------------------------
+
+/// Mesh each tissue of head_labels.mgz (meshing_service) into
+/// surf/<tissue>.obj, in RAS mm like the FreeSurfer surfaces.
+fn meshify(
+    options: &MeshingOptions,
+    paths: &SubjectPaths,
+    monitor: &dyn JobMonitor,
+) -> Result<(), NWError> {
+    let step = AnatomyStep::Meshify;
+    std::fs::create_dir_all(paths.subject_dir.join("surf"))?;
+    let volume = load_head_labels_volume(&paths.head_labels()).map_err(NWError::Parse)?;
+    for (name, label) in TISSUES {
+        if monitor.cancelled() {
+            return Err(NWError::Cancelled);
+        }
+        monitor.log(step, &format!("Meshing {name} ..."));
+        let mesh = tissue_mesh(&volume, name, label, options)?;
+        monitor.log(
+            step,
+            &format!(
+                "{:15}: {:>8} vertices, {:>8} triangles",
+                name,
+                mesh.geometry.verts.len(),
+                mesh.geometry.indices.len() / 3
+            ),
+        );
+        export_obj(&mesh, &paths.mesh(name)).map_err(NWError::Internal)?;
+    }
+    monitor.log(
+        step,
+        &format!(
+            "[OK] Meshes written to {}",
+            paths.subject_dir.join("surf").display()
+        ),
+    );
+    Ok(())
+}
+
+// ------------------------------------------------------------------
+// Load
+// ------------------------------------------------------------------
+
+fn load(paths: &SubjectPaths, monitor: &dyn JobMonitor) -> Result<SubjectAnatomy, NWError> {
+    load_subject(&paths.subject_dir, &|line| {
+        monitor.log(AnatomyStep::Load, line)
+    })
+}
+
+/// Read the tissue meshes of a subject folder the pipeline has already
+/// been run on, as the load step does; `log` gets the meshes left out.
+pub fn load_subject(subject_dir: &Path, log: &dyn Fn(&str)) -> Result<SubjectAnatomy, NWError> {
+    let anatomy = load_subject_anatomy(subject_dir, log);
+    let loaded = [
+        &anatomy.skull,
+        &anatomy.csf,
+        &anatomy.grey_matter,
+        &anatomy.white_matter,
+    ];
+    if loaded.iter().all(|mesh| mesh.is_none()) {
+        return Err(NWError::Parse(format!(
+            "no tissue mesh could be read from {}",
+            subject_dir.join("surf").display()
+        )));
+    }
+    Ok(anatomy)
+}
@@ -0,0 +1,128 @@
+use crate::domain::atlas::{Atlas, ChannelRegions, RegionOverlap};
+use crate::domain::error::NWError;
+use crate::services::projection_service::ProjectedChannel;
+use nalgebra::{Point3, Vector3};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// An atlas region and the channels over it: a region of interest for
+/// averaging.
+#[derive(Clone, Debug, Serialize)]
+pub struct AtlasRoi {
+    pub label: u32,
+    pub name: String,
+    pub channels: Vec<usize>,
+}
+
+/// The atlas labels of the voxels within `radius` mm of `p`, counted.
+fn labels_within(
+    atlas: &Atlas,
+    p: &Point3<f64>,
+    radius: f64,
+) -> Result<BTreeMap<u32, usize>, NWError> {
+    let ras2vox = atlas
+        .vox2ras
+        .try_inverse()
+        .ok_or_else(|| NWError::InvalidInput(format!("{}: singular vox2ras", atlas.name)))?;
+    // Voxel-space bounding box of the sphere, from its bounding cube.
+    let mut lo = [f64::INFINITY; 3];
+    let mut hi = [f64::NEG_INFINITY; 3];
+    for corner in 0..8 {
+        let offset = Vector3::from_fn(|axis, _| {
+            if corner >> axis & 1 == 1 {
+                radius
+            } else {
+                -radius
+            }
+        });
+        let v = ras2vox.transform_point(&(p + offset));
+        for axis in 0..3 {
+            lo[axis] = lo[axis].min(v[axis]);
+            hi[axis] = hi[axis].max(v[axis]);
+        }
+    }
+    let range = |axis: usize| {
+        let from = lo[axis].floor().max(0.0);
+        let to = (hi[axis].ceil() + 1.0).min(atlas.dims[axis] as f64);
+        from as usize..to.max(from) as usize
+    };
+
+    let mut counts = BTreeMap::new();
+    for k in range(2) {
+        for j in range(1) {
+            for i in range(0) {
+                let world = atlas
+                    .vox2ras
+                    .transform_point(&Point3::new(i as f64, j as f64, k as f64));
+                if (world - p).norm() > radius {
+                    continue;
+                }
+                let label = atlas.label_at([i, j, k]);
+                if label != 0 {
+                    *counts.entry(label).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// For each channel projected on the cortex, the atlas regions within
+/// `radius` mm, with the share of the labelled voxels each one covers.
+pub fn label_channels(
+    atlas: &Atlas,
+    channels: &[ProjectedChannel],
+    radius: f64,
+) -> Result<Vec<ChannelRegions>, NWError> {
+    if radius.is_nan() || radius <= 0.0 {
+        return Err(NWError::InvalidInput(format!(
+            "sensitivity radius must be positive, got {radius}"
+        )));
+    }
+    if channels.is_empty() {
+        return Err(NWError::InvalidInput(
+            "no channels are projected on the cortex".into(),
+        ));
+    }
+    channels
+        .iter()
+        .map(|ch| {
+            let position = Point3::from(ch.cortex.position);
+            let counts = labels_within(atlas, &position, radius)?;
+            let total: usize = counts.values().sum();
+            let mut regions: Vec<RegionOverlap> = counts
+                .into_iter()
+                .map(|(label, n)| RegionOverlap {
+                    label,
+                    name: atlas.label_name(label),
+                    percent: 100.0 * n as f64 / total as f64,
+                })
+                .collect();
+            regions.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+            Ok(ChannelRegions {
+                channel: ch.id,
+                position: ch.cortex.position,
+                regions,
+            })
+        })
+        .collect()
+}
+
+/// Group channels by atlas region: each region with the channels it
+/// covers at least `min_percent` of, by label.
+pub fn atlas_rois(table: &[ChannelRegions], min_percent: f64) -> Vec<AtlasRoi> {
+    let mut rois: BTreeMap<u32, AtlasRoi> = BTreeMap::new();
+    for row in table {
+        for region in row.regions.iter().filter(|r| r.percent >= min_percent) {
+            rois.entry(region.label)
+                .or_insert_with(|| AtlasRoi {
+                    label: region.label,
+                    name: region.name.clone(),
+                    channels: Vec::new(),
+                })
+                .channels
+                .push(row.channel);
+        }
+    }
+    rois.into_values().collect()
+}
@@ -0,0 +1,178 @@
+use crate::domain::dataset::{BidsEntities, Dataset, DatasetRecording, Validation};
+use crate::domain::error::NWError;
+use crate::domain::snirf::Snirf;
+use crate::domain::summary::SnirfSummary;
+use crate::io::snirf_parser;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+/// Folders that hold outputs or raw vendor files rather than recordings.
+const SKIPPED_DIRS: [&str; 2] = ["derivatives", "sourcedata"];
+
+/// Index every `.snirf` file below `root`. Only headers are read, so large
+/// datasets index quickly; a file that fails to parse is listed with an
+/// error instead of aborting the scan.
+pub fn index_dataset(root: &str) -> Result<Dataset, NWError> {
+    let root_path = Path::new(root);
+    if !root_path.is_dir() {
+        return Err(NWError::InvalidInput(format!("'{root}' is not a folder")));
+    }
+
+    let description = root_path.join("dataset_description.json");
+    let is_bids = description.is_file();
+    let name = std::fs::read_to_string(&description)
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        .and_then(|json| json.get("Name")?.as_str().map(str::to_string));
+
+    let mut files = Vec::new();
+    find_snirf_files(root_path, &mut files)?;
+    files.sort();
+
+    let recordings: Vec<DatasetRecording> = files
+        .iter()
+        .enumerate()
+        .map(|(id, path)| index_recording(id, root_path, path, is_bids))
+        .collect();
+
+    info!(
+        "Indexed {} recordings in '{root}'{}",
+        recordings.len(),
+        if is_bids { " (BIDS)" } else { "" }
+    );
+    Ok(Dataset {
+        root: root.to_string(),
+        name,
+        is_bids,
+        recordings,
+    })
+}
+
+fn find_snirf_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), NWError> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name) {
+                find_snirf_files(&path, out)?;
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("snirf"))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn index_recording(id: usize, root: &Path, path: &Path, is_bids: bool) -> DatasetRecording {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let entities = BidsEntities::from_filename(file_name);
+    let path_str = path.to_string_lossy().into_owned();
+
+    let header = snirf_parser::parse_snirf_header(&path_str);
+    if let Err(e) = &header {
+        warn!("Dataset: cannot read '{path_str}': {e}");
+    }
+    let validation = validate(root, path, &entities, header.as_ref(), is_bids);
+
+    DatasetRecording {
+        id,
+        relative_path: path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned(),
+        path: path_str,
+        entities,
+        summary: header
+            .ok()
+            .filter(|s| !s.nirs_entries.is_empty())
+            .map(|s| SnirfSummary::from_snirf(&s)),
+        validation,
+    }
+}
+
+fn validate(
+    root: &Path,
+    path: &Path,
+    entities: &BidsEntities,
+    header: Result<&Snirf, &NWError>,
+    is_bids: bool,
+) -> Validation {
+    let mut v = Validation::default();
+    match header {
+        Err(e) => v.fail(e.to_string()),
+        Ok(snirf) => {
+            for (i, entry) in snirf.nirs_entries.iter().enumerate() {
+                if entry.data_blocks.is_empty() {
+                    v.fail(format!("entry {} has no data blocks", i + 1));
+                }
+                if entry.data_blocks.iter().any(|b| b.time.len() < 2) {
+                    v.warn(format!(
+                        "entry {} has a block with fewer than 2 samples",
+                        i + 1
+                    ));
+                }
+                if entry
+                    .data_blocks
+                    .iter()
+                    .any(|b| b.time.windows(2).any(|w| w[1] <= w[0]))
+                {
+                    v.warn(format!("entry {} has non-increasing time stamps", i + 1));
+                }
+            }
+        }
+    }
+    if is_bids {
+        check_bids_layout(root, path, entities, &mut v);
+    }
+    v
+}
+
+/// `sub-<label>/[ses-<label>/]nirs/sub-<label>[_ses-<label>]…_nirs.snirf`,
+/// with a `_nirs.json` sidecar next to it.
+fn check_bids_layout(root: &Path, path: &Path, entities: &BidsEntities, v: &mut Validation) {
+    let Some(subject) = &entities.subject else {
+        v.warn("file name has no sub-<label> entity");
+        return;
+    };
+    if entities.suffix.as_deref() != Some("nirs") {
+        v.warn("file name should end in _nirs.snirf");
+    }
+
+    let dirs: Vec<String> = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .parent()
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    let expected: Vec<String> = [
+        Some(format!("sub-{subject}")),
+        entities.session.as_ref().map(|s| format!("ses-{s}")),
+        Some("nirs".to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if dirs != expected {
+        v.warn(format!(
+            "expected to be in '{}', found in '{}'",
+            expected.join("/"),
+            dirs.join("/")
+        ));
+    }
+
+    if !path.with_extension("json").is_file() {
+        v.warn("missing _nirs.json sidecar");
+    }
+}
@@ -0,0 +1,170 @@
+use crate::domain::annotation::{good_sample_mask, TimeSegment};
+use crate::domain::error::NWError;
+use crate::domain::snirf::DataBlock;
+use crate::domain::timebase::TimeBase;
+use crate::dsp::{polynomial_trend, savitzky_golay_trend, spline_trend};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DetrendMethod {
+    /// Subtract the mean.
+    Mean,
+    /// Subtract the least-squares line.
+    Linear,
+    /// Subtract a least-squares polynomial in time.
+    Polynomial { order: usize },
+    /// Subtract a least-squares cubic B-spline with knots every `knot_spacing` s.
+    Spline { knot_spacing: f64 },
+    /// Subtract a Savitzky–Golay smooth over `window` seconds.
+    #[serde(rename = "savitzkygolay")]
+    SavitzkyGolay { window: f64, order: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BaselineMode {
+    /// x − mean(baseline)
+    #[default]
+    Subtract,
+    /// x / mean(baseline)
+    Divide,
+}
+
+/// Samples that may inform a fit: finite and outside every bad segment.
+fn usable_mask(time: &[f64], data: &[f64], bad_segments: &[TimeSegment]) -> Vec<bool> {
+    good_sample_mask(time, bad_segments)
+        .into_iter()
+        .zip(data)
+        .map(|(good, x)| good && x.is_finite())
+        .collect()
+}
+
+/// Remove a slow trend from every measurement in `block`, returning a new
+/// block. Trends are fitted only on samples outside `bad_segments` and then
+/// subtracted everywhere, so bad stretches are corrected but never steer the fit.
+pub fn detrend_block(
+    block: &DataBlock,
+    method: DetrendMethod,
+    bad_segments: &[TimeSegment],
+) -> Result<DataBlock, NWError> {
+    let window_len = match method {
+        DetrendMethod::Polynomial { order } if order > 20 => {
+            return Err(NWError::InvalidInput(format!(
+                "polynomial order {order} is too high (max 20)"
+            )));
+        }
+        DetrendMethod::Spline { knot_spacing } if knot_spacing.is_nan() || knot_spacing <= 0.0 => {
+            return Err(NWError::InvalidInput(format!(
+                "invalid knot spacing {knot_spacing} s"
+            )));
+        }
+        DetrendMethod::SavitzkyGolay { window, order } => {
+            let rate = TimeBase::analyze(&block.time).sampling_rate;
+            let window_len = (window * rate).round() as usize | 1;
+            if window_len <= order + 1 {
+                return Err(NWError::InvalidInput(format!(
+                    "Savitzky–Golay window of {window} s is too short for order {order}"
+                )));
+            }
+            window_len
+        }
+        _ => 0,
+    };
+
+    let mut measurements = Vec::with_capacity(block.measurements.len());
+    for m in &block.measurements {
+        let mask = usable_mask(&block.time, &m.data, bad_segments);
+        let trend = match method {
+            DetrendMethod::Mean => masked_mean(&m.data, &mask).map(|mu| vec![mu; m.data.len()]),
+            DetrendMethod::Linear => polynomial_trend(&block.time, &m.data, &mask, 1),
+            DetrendMethod::Polynomial { order } => {
+                polynomial_trend(&block.time, &m.data, &mask, order)
+            }
+            DetrendMethod::Spline { knot_spacing } => {
+                spline_trend(&block.time, &m.data, &mask, knot_spacing)
+            }
+            DetrendMethod::SavitzkyGolay { order, .. } => {
+                Some(savitzky_golay_trend(&m.data, &mask, window_len, order))
+            }
+        };
+        let trend = trend.ok_or_else(|| {
+            NWError::InvalidInput(format!(
+                "not enough usable samples to fit a {method:?} trend (S{}-D{})",
+                m.source_index, m.detector_index
+            ))
+        })?;
+        let data = m.data.iter().zip(&trend).map(|(x, t)| x - t).collect();
+        measurements.push(m.with_data(data));
+    }
+
+    info!(
+        "Detrended block ({:?}), {} measurements, {} bad segments excluded",
+        method,
+        measurements.len(),
+        bad_segments.len()
+    );
+
+    Ok(DataBlock {
+        time: block.time.clone(),
+        measurements,
+    })
+}
+
+/// Normalise every measurement in `block` to the mean of its samples within
+/// `baseline` (e.g. a pre-stimulus or resting interval), returning a new block.
+/// Samples inside `bad_segments` do not contribute to the baseline mean.
+pub fn baseline_block(
+    block: &DataBlock,
+    baseline: TimeSegment,
+    mode: BaselineMode,
+    bad_segments: &[TimeSegment],
+) -> Result<DataBlock, NWError> {
+    let mut measurements = Vec::with_capacity(block.measurements.len());
+    for m in &block.measurements {
+        let mask: Vec<bool> = usable_mask(&block.time, &m.data, bad_segments)
+            .into_iter()
+            .zip(&block.time)
+            .map(|(usable, &t)| usable && baseline.contains(t))
+            .collect();
+        let mean = masked_mean(&m.data, &mask).ok_or_else(|| {
+            NWError::InvalidInput(format!(
+                "no usable samples between {:.2} s and {:.2} s",
+                baseline.start, baseline.end
+            ))
+        })?;
+        let data = match mode {
+            BaselineMode::Subtract => m.data.iter().map(|x| x - mean).collect(),
+            BaselineMode::Divide => {
+                if mean.abs() < f64::EPSILON {
+                    return Err(NWError::InvalidInput(format!(
+                        "baseline mean of S{}-D{} is zero; cannot divide",
+                        m.source_index, m.detector_index
+                    )));
+                }
+                m.data.iter().map(|x| x / mean).collect()
+            }
+        };
+        measurements.push(m.with_data(data));
+    }
+
+    info!(
+        "Baseline-corrected block ({:?}) over {:.2}–{:.2} s",
+        mode, baseline.start, baseline.end
+    );
+
+    Ok(DataBlock {
+        time: block.time.clone(),
+        measurements,
+    })
+}
+
+fn masked_mean(data: &[f64], mask: &[bool]) -> Option<f64> {
+    let (sum, count) = data
+        .iter()
+        .zip(mask)
+        .filter(|(_, &keep)| keep)
+        .fold((0.0, 0usize), |(s, c), (x, _)| (s + x, c + 1));
+    (count > 0).then(|| sum / count as f64)
+}
@@ -0,0 +1,543 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{Event, EventMarker, NirsEntry};
+use crate::io::event_table_importer::EventTable;
+use serde::{Deserialize, Serialize};
+
+/// Unit of the onset and duration columns of an event log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    #[default]
+    S,
+    Ms,
+}
+
+impl TimeUnit {
+    fn seconds(self, value: f64) -> f64 {
+        match self {
+            TimeUnit::S => value,
+            TimeUnit::Ms => value / 1000.0,
+        }
+    }
+}
+
+/// How log times are brought onto the recording's clock.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TimeSync {
+    /// Log times are already recording times.
+    #[default]
+    None,
+    /// Add a fixed number of seconds.
+    Offset { seconds: f64 },
+    /// Shift the log so its first marker (of `condition`, if given) falls on
+    /// the first marker of the recording's stim `stim`, e.g. a sync trigger.
+    Align {
+        stim: String,
+        #[serde(default)]
+        condition: Option<String>,
+    },
+}
+
+/// What happens to the recording's existing events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Markers join existing conditions of the same name; others are added.
+    #[default]
+    Merge,
+    /// Existing events are discarded.
+    Replace,
+}
+
+/// Which columns of an [`EventTable`] hold what. Columns left unset fall
+/// back to the BIDS names (`duration`, `trial_type`, `value`, else
+/// `amplitude`) when present.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EventImportOptions {
+    /// Defaults to `onset`.
+    pub onset_column: Option<String>,
+    pub duration_column: Option<String>,
+    /// Columns whose values, joined by `_`, name a marker's condition.
+    #[serde(default)]
+    pub condition_columns: Vec<String>,
+    pub value_column: Option<String>,
+    /// Seconds, used where the log has no duration.
+    #[serde(default)]
+    pub default_duration: f64,
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+    #[serde(default)]
+    pub sync: TimeSync,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+/// Events read from a log, for preview before they are merged in.
+#[derive(Serialize, Clone, Debug)]
+pub struct EventImport {
+    pub columns: Vec<String>,
+    /// Seconds added to every log time by the chosen [`TimeSync`].
+    pub offset: f64,
+    pub events: Vec<Event>,
+    /// Rows without a usable onset.
+    pub skipped_rows: usize,
+    pub warnings: Vec<String>,
+}
+
+/// `None` for empty cells and BIDS `n/a`.
+fn cell(row: &[String], column: Option<usize>) -> Option<&str> {
+    column
+        .and_then(|c| row.get(c))
+        .map(|s| s.as_str())
+        .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("n/a"))
+}
+
+fn require_column(table: &EventTable, name: &str) -> Result<usize, NWError> {
+    table
+        .column(name)
+        .ok_or_else(|| NWError::InvalidInput(format!("the log has no '{name}' column")))
+}
+
+/// The explicitly chosen column, or the first of `fallbacks` the table has.
+fn optional_column(
+    table: &EventTable,
+    chosen: Option<&str>,
+    fallbacks: &[&str],
+) -> Result<Option<usize>, NWError> {
+    match chosen {
+        Some(name) => require_column(table, name).map(Some),
+        None => Ok(fallbacks.iter().find_map(|name| table.column(name))),
+    }
+}
+
+/// Turn the rows of `table` into one [`Event`] per condition, in the order
+/// conditions first appear, aligned to `entry`'s clock. Rows of a log
+/// without condition columns go to `fallback_name`. Columns that are not
+/// imported are listed in the warnings.
+pub fn build_events(
+    table: &EventTable,
+    options: &EventImportOptions,
+    entry: &NirsEntry,
+    fallback_name: &str,
+) -> Result<EventImport, NWError> {
+    let onset_col = require_column(table, options.onset_column.as_deref().unwrap_or("onset"))?;
+    let duration_col = optional_column(table, options.duration_column.as_deref(), &["duration"])?;
+    let value_col = optional_column(
+        table,
+        options.value_column.as_deref(),
+        &["value", "amplitude"],
+    )?;
+    let condition_cols: Vec<usize> = if options.condition_columns.is_empty() {
+        table.column("trial_type").into_iter().collect()
+    } else {
+        options
+            .condition_columns
+            .iter()
+            .map(|c| require_column(table, c))
+            .collect::<Result<_, _>>()?
+    };
+    let unit = options.time_unit;
+
+    let mut events: Vec<Event> = Vec::new();
+    let mut skipped_rows = 0;
+    let mut non_numeric_values = 0;
+    for row in &table.rows {
+        let Some(onset) = cell(row, Some(onset_col)).and_then(|s| s.parse::<f64>().ok()) else {
+            skipped_rows += 1;
+            continue;
+        };
+        let duration = cell(row, duration_col)
+            .and_then(|s| s.parse::<f64>().ok())
+            .map_or(options.default_duration, |d| unit.seconds(d));
+        let value = match cell(row, value_col) {
+            Some(s) => s.parse::<f64>().unwrap_or_else(|_| {
+                non_numeric_values += 1;
+                1.0
+            }),
+            None => 1.0,
+        };
+        let parts: Vec<&str> = condition_cols
+            .iter()
+            .filter_map(|&c| cell(row, Some(c)))
+            .collect();
+        let name = if parts.is_empty() {
+            fallback_name.to_string()
+        } else {
+            parts.join("_")
+        };
+
+        let marker = EventMarker {
+            onset: unit.seconds(onset),
+            duration,
+            value,
+        };
+        match events.iter_mut().find(|e| e.name == name) {
+            Some(event) => event.markers.push(marker),
+            None => events.push(Event {
+                name,
+                markers: vec![marker],
+            }),
+        }
+    }
+
+    let offset = sync_offset(&options.sync, &events, entry)?;
+    for event in &mut events {
+        for m in &mut event.markers {
+            m.onset += offset;
+        }
+        event.markers.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+    }
+
+    let mut warnings = Vec::new();
+    let used = [Some(onset_col), duration_col, value_col];
+    let ignored: Vec<&str> = (0..table.columns.len())
+        .filter(|c| !used.contains(&Some(*c)) && !condition_cols.contains(c))
+        .map(|c| table.columns[c].as_str())
+        .collect();
+    if !ignored.is_empty() {
+        warnings.push(format!("columns not imported: {}", ignored.join(", ")));
+    }
+    if skipped_rows > 0 {
+        warnings.push(format!("{skipped_rows} rows have no numeric onset"));
+    }
+    if non_numeric_values > 0 {
+        warnings.push(format!(
+            "{non_numeric_values} non-numeric values were set to 1"
+        ));
+    }
+    if let Some((start, end)) = recording_span(entry) {
+        let outside = events
+            .iter()
+            .flat_map(|e| &e.markers)
+            .filter(|m| m.onset < start || m.onset > end)
+            .count();
+        if outside > 0 {
+            warnings.push(format!(
+                "{outside} markers lie outside the recording ({start:.2}–{end:.2} s)"
+            ));
+        }
+    }
+
+    Ok(EventImport {
+        columns: table.columns.clone(),
+        offset,
+        events,
+        skipped_rows,
+        warnings,
+    })
+}
+
+/// Time range of the entry's first data block.
+fn recording_span(entry: &NirsEntry) -> Option<(f64, f64)> {
+    let time = &entry.data_blocks.first()?.time;
+    Some((*time.first()?, *time.last()?))
+}
+
+fn sync_offset(sync: &TimeSync, events: &[Event], entry: &NirsEntry) -> Result<f64, NWError> {
+    match sync {
+        TimeSync::None => Ok(0.0),
+        TimeSync::Offset { seconds } => Ok(*seconds),
+        TimeSync::Align { stim, condition } => {
+            let target = entry
+                .events
+                .iter()
+                .find(|e| &e.name == stim)
+                .and_then(|e| e.markers.iter().map(|m| m.onset).reduce(f64::min))
+                .ok_or_else(|| {
+                    NWError::InvalidInput(format!("the recording has no markers in '{stim}'"))
+                })?;
+            let first = events
+                .iter()
+                .filter(|e| condition.as_ref().map_or(true, |c| &e.name == c))
+                .flat_map(|e| &e.markers)
+                .map(|m| m.onset)
+                .reduce(f64::min)
+                .ok_or_else(|| {
+                    NWError::InvalidInput(match condition {
+                        Some(c) => format!("the log has no markers in '{c}'"),
+                        None => "the log has no markers".into(),
+                    })
+                })?;
+            Ok(target - first)
+        }
+    }
+}
+
+/// Add `imported` to `existing` as `mode` says, keeping markers in onset order.
+pub fn merge_events(existing: &mut Vec<Event>, imported: Vec<Event>, mode: ImportMode) {
+    if mode == ImportMode::Replace {
+        existing.clear();
+    }
+    for event in imported {
+        match existing.iter_mut().find(|e| e.name == event.name) {
+            Some(target) => {
+                target.markers.extend(event.markers);
+                target.markers.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+            }
+            None => existing.push(event),
+        }
+    }
+}
+
+/// One edit of the recording's conditions (stims) and their markers.
+/// Markers are addressed by their position in the condition, which is kept
+/// in onset order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EventEdit {
+    /// Add a marker, creating the condition if it does not exist.
+    AddMarker {
+        condition: String,
+        onset: f64,
+        #[serde(default)]
+        duration: f64,
+        #[serde(default = "default_value")]
+        value: f64,
+    },
+    MoveMarker {
+        condition: String,
+        index: usize,
+        onset: f64,
+    },
+    DeleteMarker {
+        condition: String,
+        index: usize,
+    },
+    Rename {
+        condition: String,
+        name: String,
+    },
+    /// Combine `conditions` into one condition called `name`.
+    Merge {
+        conditions: Vec<String>,
+        name: String,
+    },
+    /// Split a condition into `<condition>_<value>`, one per marker value.
+    Split {
+        condition: String,
+    },
+    /// Set the duration and/or value of every marker of a condition.
+    Recode {
+        condition: String,
+        duration: Option<f64>,
+        value: Option<f64>,
+    },
+    DeleteCondition {
+        condition: String,
+    },
+}
+
+fn default_value() -> f64 {
+    1.0
+}
+
+impl EventEdit {
+    /// Short description for the undo history.
+    pub fn label(&self) -> String {
+        match self {
+            EventEdit::AddMarker { condition, .. } => format!("Add marker to {condition}"),
+            EventEdit::MoveMarker { condition, .. } => format!("Move marker of {condition}"),
+            EventEdit::DeleteMarker { condition, .. } => format!("Delete marker of {condition}"),
+            EventEdit::Rename { condition, name } => format!("Rename {condition} to {name}"),
+            EventEdit::Merge { name, .. } => format!("Merge conditions into {name}"),
+            EventEdit::Split { condition } => format!("Split {condition} by value"),
+            EventEdit::Recode { condition, .. } => format!("Recode {condition}"),
+            EventEdit::DeleteCondition { condition } => format!("Delete {condition}"),
+        }
+    }
+}
+
+fn find<'a>(events: &'a mut [Event], condition: &str) -> Result<&'a mut Event, NWError> {
+    events
+        .iter_mut()
+        .find(|e| e.name == condition)
+        .ok_or_else(|| NWError::InvalidInput(format!("no condition '{condition}'")))
+}
+
+fn marker_mut<'a>(
+    events: &'a mut [Event],
+    condition: &str,
+    index: usize,
+) -> Result<&'a mut EventMarker, NWError> {
+    find(events, condition)?
+        .markers
+        .get_mut(index)
+        .ok_or_else(|| NWError::InvalidInput(format!("'{condition}' has no marker {index}")))
+}
+
+fn check_name(events: &[Event], name: &str) -> Result<(), NWError> {
+    if name.trim().is_empty() {
+        return Err(NWError::InvalidInput(
+            "condition names cannot be empty".into(),
+        ));
+    }
+    if events.iter().any(|e| e.name == name) {
+        return Err(NWError::InvalidInput(format!(
+            "a condition named '{name}' already exists"
+        )));
+    }
+    Ok(())
+}
+
+/// `onset` must be finite and, when the entry has data, inside its first
+/// block's time range.
+fn check_onset(onset: f64, span: Option<(f64, f64)>) -> Result<(), NWError> {
+    if !onset.is_finite() {
+        return Err(NWError::InvalidInput("marker onset must be finite".into()));
+    }
+    match span {
+        Some((start, end)) if onset < start || onset > end => Err(NWError::InvalidInput(format!(
+            "onset {onset} s lies outside the recording ({start}–{end} s)"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn check_duration(duration: f64) -> Result<(), NWError> {
+    if duration.is_finite() && duration >= 0.0 {
+        Ok(())
+    } else {
+        Err(NWError::InvalidInput(
+            "marker duration must be zero or positive".into(),
+        ))
+    }
+}
+
+fn sort_markers(event: &mut Event) {
+    event.markers.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+}
+
+/// Apply `edit` to the entry's events. Nothing is changed when the edit is
+/// rejected.
+pub fn apply_event_edit(entry: &mut NirsEntry, edit: &EventEdit) -> Result<(), NWError> {
+    let span = recording_span(entry);
+    let events = &mut entry.events;
+    match edit {
+        EventEdit::AddMarker {
+            condition,
+            onset,
+            duration,
+            value,
+        } => {
+            check_onset(*onset, span)?;
+            check_duration(*duration)?;
+            let marker = EventMarker {
+                onset: *onset,
+                duration: *duration,
+                value: *value,
+            };
+            match events.iter_mut().find(|e| &e.name == condition) {
+                Some(event) => {
+                    event.markers.push(marker);
+                    sort_markers(event);
+                }
+                None => {
+                    check_name(events, condition)?;
+                    events.push(Event {
+                        name: condition.clone(),
+                        markers: vec![marker],
+                    });
+                }
+            }
+        }
+        EventEdit::MoveMarker {
+            condition,
+            index,
+            onset,
+        } => {
+            check_onset(*onset, span)?;
+            marker_mut(events, condition, *index)?.onset = *onset;
+            sort_markers(find(events, condition)?);
+        }
+        EventEdit::DeleteMarker { condition, index } => {
+            marker_mut(events, condition, *index)?;
+            find(events, condition)?.markers.remove(*index);
+        }
+        EventEdit::Rename { condition, name } => {
+            find(events, condition)?;
+            if name != condition {
+                check_name(events, name)?;
+            }
+            find(events, condition)?.name = name.clone();
+        }
+        EventEdit::Merge { conditions, name } => {
+            if conditions.is_empty() {
+                return Err(NWError::InvalidInput("no conditions to merge".into()));
+            }
+            for c in conditions {
+                find(events, c)?;
+            }
+            if !conditions.contains(name) {
+                check_name(events, name)?;
+            }
+            // The merged condition takes the place of the first one listed.
+            let at = events
+                .iter()
+                .position(|e| conditions.contains(&e.name))
+                .unwrap_or(events.len());
+            let mut merged = Event {
+                name: name.clone(),
+                markers: Vec::new(),
+            };
+            events.retain_mut(|e| {
+                let take = conditions.contains(&e.name);
+                if take {
+                    merged.markers.append(&mut e.markers);
+                }
+                !take
+            });
+            sort_markers(&mut merged);
+            events.insert(at.min(events.len()), merged);
+        }
+        EventEdit::Split { condition } => {
+            let at = events
+                .iter()
+                .position(|e| &e.name == condition)
+                .ok_or_else(|| NWError::InvalidInput(format!("no condition '{condition}'")))?;
+            let mut parts: Vec<Event> = Vec::new();
+            for m in &events[at].markers {
+                let name = format!("{condition}_{}", m.value);
+                match parts.iter_mut().find(|p| p.name == name) {
+                    Some(part) => part.markers.push(m.clone()),
+                    None => parts.push(Event {
+                        name,
+                        markers: vec![m.clone()],
+                    }),
+                }
+            }
+            if let Some(taken) = parts
+                .iter()
+                .find(|p| events.iter().any(|e| e.name == p.name))
+            {
+                return Err(NWError::InvalidInput(format!(
+                    "a condition named '{}' already exists",
+                    taken.name
+                )));
+            }
+            events.splice(at..=at, parts);
+        }
+        EventEdit::Recode {
+            condition,
+            duration,
+            value,
+        } => {
+            if let Some(d) = duration {
+                check_duration(*d)?;
+            }
+            for m in &mut find(events, condition)?.markers {
+                if let Some(d) = duration {
+                    m.duration = *d;
+                }
+                if let Some(v) = value {
+                    m.value = *v;
+                }
+            }
+        }
+        EventEdit::DeleteCondition { condition } => {
+            find(events, condition)?;
+            events.retain(|e| &e.name != condition);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::DataBlock;
+use crate::domain::timebase::TimeBase;
+use crate::dsp::{butterworth_highpass, butterworth_lowpass, filtfilt, tddr, Biquad};
+use log::{info, warn};
+
+/// Sampling rate of `block`, which must be uniformly sampled: these filters
+/// assume a fixed sample spacing.
+fn uniform_rate(block: &DataBlock, step: &str) -> Result<f64, NWError> {
+    let time_base = TimeBase::analyze(&block.time);
+    if !time_base.is_uniform || time_base.sampling_rate <= 0.0 {
+        return Err(NWError::InvalidInput(format!(
+            "{step} needs a uniformly sampled block; regularize the time base first"
+        )));
+    }
+    Ok(time_base.sampling_rate)
+}
+
+/// Apply `f` to every measurement. Measurements with non-finite samples are
+/// copied unchanged, since the filters cannot run through gaps.
+fn map_measurements(block: &DataBlock, step: &str, f: impl Fn(&[f64]) -> Vec<f64>) -> DataBlock {
+    let mut skipped = 0;
+    let measurements = block
+        .measurements
+        .iter()
+        .map(|m| {
+            if m.data.iter().all(|v| v.is_finite()) {
+                m.with_data(f(&m.data))
+            } else {
+                skipped += 1;
+                m.with_data(m.data.clone())
+            }
+        })
+        .collect();
+    if skipped > 0 {
+        warn!("{step}: {skipped} measurements contain NaN/inf and were left unfiltered");
+    }
+    DataBlock {
+        time: block.time.clone(),
+        measurements,
+    }
+}
+
+/// Zero-phase Butterworth band-pass of every measurement: a high-pass at
+/// `low` Hz followed by a low-pass at `high` Hz, each of `order`. Either edge
+/// may be omitted for a pure low- or high-pass.
+pub fn bandpass_block(
+    block: &DataBlock,
+    low: Option<f64>,
+    high: Option<f64>,
+    order: usize,
+) -> Result<DataBlock, NWError> {
+    let fs = uniform_rate(block, "band-pass")?;
+    let nyquist = fs / 2.0;
+    let check = |f: f64| -> Result<f64, NWError> {
+        if f > 0.0 && f < nyquist {
+            Ok(f)
+        } else {
+            Err(NWError::InvalidInput(format!(
+                "cutoff {f} Hz must lie between 0 and Nyquist ({nyquist:.3} Hz)"
+            )))
+        }
+    };
+    if order == 0 || order > 10 {
+        return Err(NWError::InvalidInput(format!(
+            "filter order {order} must be between 1 and 10"
+        )));
+    }
+    if let (Some(l), Some(h)) = (low, high) {
+        if l >= h {
+            return Err(NWError::InvalidInput(format!(
+                "high-pass cutoff {l} Hz must be below the low-pass cutoff {h} Hz"
+            )));
+        }
+    }
+
+    let mut stages: Vec<Vec<Biquad>> = Vec::new();
+    if let Some(l) = low {
+        stages.push(butterworth_highpass(order, check(l)?, fs));
+    }
+    if let Some(h) = high {
+        stages.push(butterworth_lowpass(order, check(h)?, fs));
+    }
+    if stages.is_empty() {
+        return Err(NWError::InvalidInput("no cutoff frequency given".into()));
+    }
+
+    info!("Band-pass {low:?}–{high:?} Hz, order {order}, fs {fs:.3} Hz");
+    Ok(map_measurements(block, "band-pass", |x| {
+        stages.iter().fold(x.to_vec(), |y, s| filtfilt(s, &y))
+    }))
+}
+
+/// Temporal Derivative Distribution Repair motion correction of every
+/// measurement. Intended for optical density data.
+pub fn tddr_block(block: &DataBlock) -> Result<DataBlock, NWError> {
+    let fs = uniform_rate(block, "TDDR")?;
+    info!("TDDR motion correction, fs {fs:.3} Hz");
+    Ok(map_measurements(block, "TDDR", |x| tddr(x, fs)))
+}
@@ -0,0 +1,502 @@
+use crate::domain::anatomy::NamedPoint;
+use crate::domain::error::NWError;
+use crate::domain::mesh::Mesh;
+use crate::domain::scene::Transform;
+use crate::domain::snirf::NirsEntry;
+use crate::io::position_exporter::{export_positions, PositionFormat, PositionRow};
+use crate::services::registration_service::{canonical_label, mm_per_unit};
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+/// Rays cast per surface curve.
+const CURVE_SAMPLES: usize = 720;
+/// Iterations placing Cz at the middle of both the sagittal and the
+/// coronal curve.
+const CZ_ITERATIONS: usize = 10;
+
+/// Rows of the 10-5 system from nasion to inion, one per 5 % of the
+/// sagittal curve.
+const ROWS: [&str; 21] = [
+    "N", "NFp", "Fp", "AFp", "AF", "AFF", "F", "FFC", "FC", "FCC", "C", "CCP", "CP", "CPP", "P",
+    "PPO", "PO", "POO", "O", "OI", "I",
+];
+
+/// The International 10-20 positions, in 10-10 names.
+const TEN_TWENTY: [&str; 23] = [
+    "Nz", "Fpz", "Fp1", "Fp2", "F7", "F3", "Fz", "F4", "F8", "T7", "C3", "Cz", "C4", "T8", "P7",
+    "P3", "Pz", "P4", "P8", "O1", "Oz", "O2", "Iz",
+];
+
+/// Which member of the 10-20 family to generate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PositionSystem {
+    #[serde(rename = "10-20")]
+    TenTwenty,
+    #[default]
+    #[serde(rename = "10-10")]
+    TenTen,
+    #[serde(rename = "10-5")]
+    TenFive,
+}
+
+/// What a positions file lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionSet {
+    /// The probe's sources and detectors.
+    Optodes,
+    /// The computed 10-20/10-10/10-5 positions.
+    Head,
+}
+
+/// An optode and the head position nearest to it.
+#[derive(Clone, Debug, Serialize)]
+pub struct OptodeLabel {
+    pub optode: String,
+    pub label: String,
+    /// Millimetres.
+    pub distance: f64,
+}
+
+/// Move the optode named `optode` (`S1`, `D3`…) onto the position `label`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OptodePlacement {
+    pub optode: String,
+    pub label: String,
+}
+
+/// The column name of a lateral position in `row`: temporal rows take a
+/// `T` where the 10-10 names have one (FT7, T7, TP7…).
+fn lateral_row(row: &str) -> &str {
+    match row {
+        "FC" => "FT",
+        "FCC" => "FTT",
+        "C" => "T",
+        "CCP" => "TTP",
+        "CP" => "TP",
+        other => other,
+    }
+}
+
+/// A curve on the scalp, sampled densely, with its cumulative length.
+struct Curve {
+    points: Vec<Point3<f64>>,
+    lengths: Vec<f64>,
+}
+
+impl Curve {
+    fn new(points: Vec<Point3<f64>>) -> Self {
+        let mut lengths = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, p) in points.iter().enumerate() {
+            if i > 0 {
+                total += (p - points[i - 1]).norm();
+            }
+            lengths.push(total);
+        }
+        Curve { points, lengths }
+    }
+
+    fn length(&self) -> f64 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    /// The point `distance` along the curve.
+    fn at_length(&self, distance: f64) -> Point3<f64> {
+        let i = self
+            .lengths
+            .partition_point(|&l| l < distance)
+            .clamp(1, self.points.len() - 1);
+        let (l0, l1) = (self.lengths[i - 1], self.lengths[i]);
+        let t = if l1 > l0 {
+            ((distance - l0) / (l1 - l0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.points[i - 1] + (self.points[i] - self.points[i - 1]) * t
+    }
+
+    fn at(&self, fraction: f64) -> Point3<f64> {
+        self.at_length(fraction * self.length())
+    }
+
+    /// Length along the curve to the sample nearest to `p`.
+    fn length_to(&self, p: &Point3<f64>) -> f64 {
+        let nearest = (0..self.points.len())
+            .min_by(|&a, &b| {
+                (self.points[a] - p)
+                    .norm()
+                    .total_cmp(&(self.points[b] - p).norm())
+            })
+            .unwrap_or(0);
+        self.lengths[nearest]
+    }
+}
+
+/// The scalp seen from a point inside the head.
+struct Scalp<'a> {
+    mesh: &'a Mesh,
+    centre: Point3<f64>,
+    reach: f64,
+}
+
+impl<'a> Scalp<'a> {
+    fn new(mesh: &'a Mesh, fiducials: &[Point3<f64>]) -> Self {
+        let centre = Point3::from(
+            fiducials.iter().map(|p| p.coords).sum::<Vector3<f64>>() / fiducials.len() as f64,
+        );
+        let reach = mesh
+            .geometry
+            .verts
+            .iter()
+            .map(|v| (v.position - centre.coords).norm())
+            .fold(0.0, f64::max)
+            * 2.0;
+        Scalp {
+            mesh,
+            centre,
+            reach,
+        }
+    }
+
+    /// The outermost scalp point seen from `origin` along `dir` (unit). The
+    /// ray stops at `origin`, so a miss never picks up the far side.
+    fn along(&self, origin: &Point3<f64>, dir: &Vector3<f64>) -> Option<Point3<f64>> {
+        let outside = origin + dir * self.reach;
+        self.mesh
+            .cast_ray(&outside, &-dir, self.reach)
+            .map(|(hit, _)| hit.point)
+    }
+
+    fn snap(&self, p: &Point3<f64>) -> Point3<f64> {
+        self.mesh.closest_point(p).map_or(*p, |hit| hit.point)
+    }
+
+    /// The section of the scalp by the plane through `a`, `b` and `c`,
+    /// from `a` through `b` to `c`.
+    fn curve(&self, a: &Point3<f64>, b: &Point3<f64>, c: &Point3<f64>) -> Result<Curve, NWError> {
+        let degenerate = || NWError::InvalidInput("fiducials too close to span the head".into());
+        let normal = (b - a)
+            .cross(&(c - a))
+            .try_normalize(f64::EPSILON)
+            .ok_or_else(degenerate)?;
+        // The head centre, moved into the plane.
+        let origin = self.centre - normal * normal.dot(&(self.centre - a));
+        let u = (a - origin)
+            .try_normalize(f64::EPSILON)
+            .ok_or_else(degenerate)?;
+        let v = normal.cross(&u);
+        let angle = |p: &Point3<f64>| {
+            let d = p - origin;
+            d.dot(&v).atan2(d.dot(&u)).rem_euclid(TAU)
+        };
+        let (to_b, to_c) = (angle(b), angle(c));
+        // Sweep from `a` to `c` the way that passes `b`.
+        let sweep = if to_b < to_c { to_c } else { to_c - TAU };
+
+        let mut points = vec![*a];
+        points.extend((1..CURVE_SAMPLES).filter_map(|i| {
+            let theta = sweep * i as f64 / CURVE_SAMPLES as f64;
+            self.along(&origin, &(u * theta.cos() + v * theta.sin()))
+        }));
+        points.push(*c);
+        if points.len() < CURVE_SAMPLES / 2 {
+            return Err(NWError::InvalidInput(
+                "the scalp mesh is not closed around the fiducials".into(),
+            ));
+        }
+        Ok(Curve::new(points))
+    }
+}
+
+/// Find the four fiducials among `points` (by label, with the usual
+/// synonyms).
+fn fiducials(points: &[NamedPoint]) -> Result<[Point3<f64>; 4], NWError> {
+    let find = |key: &str| {
+        points
+            .iter()
+            .find(|p| canonical_label(&p.label) == key)
+            .map(|p| Point3::from(p.position))
+            .ok_or_else(|| {
+                NWError::InvalidInput(format!("fiducial {} is missing", key.to_uppercase()))
+            })
+    };
+    Ok([find("nz")?, find("iz")?, find("lpa")?, find("rpa")?])
+}
+
+/// The 10-20, 10-10 or 10-5 positions on `scalp` (RAS, mm) from the nasion,
+/// inion and preauricular points, after Oostenveld & Praamstra (2001):
+/// - Cz halves both the Nz–Iz and the LPA–RPA curve over the top;
+/// - the sagittal curve is cut in 5 % steps into the rows Fpz … Oz;
+/// - the 10 % ring (Fpz–T7–Oz) and the 0 % ring (Nz–LPA–Iz) are cut the
+///   same way into the lateral positions (F7, FT7, … and F9, FT9, …);
+/// - each row is the curve from its left ring position through its
+///   midline position to the right one, each half cut in quarters (10-10)
+///   or eighths (10-5, the `h` positions).
+///
+/// Curves are plane sections of the surface, measured by arc length. The
+/// 10-5 set stops at the 10 % ring; below it, the 0 % ring is given at
+/// 10-10 spacing.
+pub fn head_positions(
+    scalp: &Mesh,
+    fiducial_points: &[NamedPoint],
+    system: PositionSystem,
+) -> Result<Vec<NamedPoint>, NWError> {
+    if scalp.geometry.verts.is_empty() {
+        return Err(NWError::InvalidInput(
+            "the scalp mesh has no vertices".into(),
+        ));
+    }
+    let [nz, iz, lpa, rpa] = fiducials(fiducial_points)?;
+    let head = Scalp::new(scalp, &[nz, iz, lpa, rpa]);
+
+    // Start from the top of the head and let Cz settle.
+    let up = (rpa - lpa)
+        .cross(&(nz - iz))
+        .try_normalize(f64::EPSILON)
+        .ok_or_else(|| NWError::InvalidInput("fiducials too close to span the head".into()))?;
+    let mut cz = head
+        .along(&head.centre, &up)
+        .ok_or_else(|| NWError::InvalidInput("no scalp above the fiducials".into()))?;
+    for _ in 0..CZ_ITERATIONS {
+        let top = head.curve(&nz, &cz, &iz)?.at(0.5);
+        let next = head.curve(&lpa, &top, &rpa)?.at(0.5);
+        let moved = (next - cz).norm();
+        cz = next;
+        if moved < 0.1 {
+            break;
+        }
+    }
+
+    let step = match system {
+        PositionSystem::TenFive => 1,
+        _ => 2,
+    };
+    let mut out: Vec<(String, Point3<f64>)> = Vec::new();
+
+    let sagittal = head.curve(&nz, &cz, &iz)?;
+    let midline: Vec<Point3<f64>> = (0..=20).map(|k| sagittal.at(k as f64 / 20.0)).collect();
+    out.extend(
+        (0..=20)
+            .step_by(step)
+            .map(|k| (format!("{}z", ROWS[k]), midline[k])),
+    );
+
+    let coronal = head.curve(&lpa, &cz, &rpa)?;
+    let (t7, t8) = (coronal.at(0.1), coronal.at(0.9));
+    let (fpz, oz) = (midline[2], midline[18]);
+
+    // Per side: the 10 % ring (Fpz to Oz over T7/T8) and the 0 % ring
+    // (Nz to Iz over the preauricular point), each cut in 5 % steps.
+    let mut rings = Vec::new();
+    for (side, tragus, ring_no, low_no, parity) in
+        [(&t7, &lpa, "7", "9", 1), (&t8, &rpa, "8", "10", 2)]
+    {
+        let ring = head.curve(&fpz, side, &oz)?;
+        let ring_points: Vec<Point3<f64>> = (0..=20).map(|k| ring.at(k as f64 / 20.0)).collect();
+        for k in (step..20).step_by(step) {
+            let name = match k {
+                1 => format!("Fp{parity}h"),
+                2 => format!("Fp{parity}"),
+                18 => format!("O{parity}"),
+                19 => format!("O{parity}h"),
+                _ => format!("{}{ring_no}", lateral_row(ROWS[k])),
+            };
+            out.push((name, ring_points[k]));
+        }
+
+        let low = head.curve(&nz, tragus, &iz)?;
+        for k in (2..=18).step_by(2) {
+            let name = match k {
+                2 => format!("N{parity}"),
+                18 => format!("I{parity}"),
+                _ => format!("{}{low_no}", lateral_row(ROWS[k])),
+            };
+            out.push((name, low.at(k as f64 / 20.0)));
+        }
+        rings.push(ring_points);
+    }
+
+    // Rows: left ring, midline, right ring; each half cut in eighths,
+    // numbered from the midline outwards (1h, 1, 3h, 3, 5h, 5, 7h on the
+    // left; 2h, 2, 4h … on the right).
+    let rows = match system {
+        PositionSystem::TenFive => 3..=17,
+        _ => 4..=16,
+    };
+    for k in rows.step_by(step) {
+        let (left, mid, right) = (&rings[0][k], &midline[k], &rings[1][k]);
+        let row = head.curve(left, mid, right)?;
+        let at_mid = row.length_to(mid);
+        let total = row.length();
+        for j in (step..8).step_by(step) {
+            let column = j.div_ceil(2) * 2;
+            let half = if j % 2 == 1 { "h" } else { "" };
+            let prefix = if j == 7 {
+                lateral_row(ROWS[k])
+            } else {
+                ROWS[k]
+            };
+            let from_mid = j as f64 / 8.0;
+            out.push((
+                format!("{prefix}{}{half}", column - 1),
+                row.at_length(at_mid * (1.0 - from_mid)),
+            ));
+            out.push((
+                format!("{prefix}{column}{half}"),
+                row.at_length(at_mid + (total - at_mid) * from_mid),
+            ));
+        }
+    }
+
+    let mut points: Vec<NamedPoint> = out
+        .into_iter()
+        .map(|(label, p)| NamedPoint {
+            label,
+            position: head.snap(&p).into(),
+        })
+        .collect();
+    if system == PositionSystem::TenTwenty {
+        points.retain(|p| TEN_TWENTY.contains(&p.label.as_str()));
+    }
+    Ok(points)
+}
+
+/// An optode in head space (mm).
+pub(crate) struct PlacedOptode {
+    pub name: String,
+    /// BIDS optode type.
+    pub kind: &'static str,
+    pub position: Point3<f64>,
+}
+
+/// Each optode, placed by `transform`.
+pub(crate) fn optode_positions(
+    entry: &NirsEntry,
+    transform: &Transform,
+) -> Result<Vec<PlacedOptode>, NWError> {
+    let mm = mm_per_unit(&entry.metadata)?;
+    let probe = &entry.probe;
+    let sources = probe.sources.iter().map(|o| ("source", o));
+    let detectors = probe.detectors.iter().map(|o| ("detector", o));
+    Ok(sources
+        .chain(detectors)
+        .map(|(kind, o)| PlacedOptode {
+            name: o.name.clone(),
+            kind,
+            position: transform.transform_point(&Point3::from(o.pos_3d * mm)),
+        })
+        .collect())
+}
+
+fn nearest<'a>(positions: &'a [NamedPoint], p: &Point3<f64>) -> Option<(&'a NamedPoint, f64)> {
+    positions
+        .iter()
+        .map(|q| (q, (Point3::from(q.position) - p).norm()))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Name each optode after the nearest of `positions`.
+pub fn label_optodes(
+    entry: &NirsEntry,
+    transform: &Transform,
+    positions: &[NamedPoint],
+) -> Result<Vec<OptodeLabel>, NWError> {
+    if positions.is_empty() {
+        return Err(NWError::InvalidInput(
+            "no head positions have been computed".into(),
+        ));
+    }
+    Ok(optode_positions(entry, transform)?
+        .into_iter()
+        .filter_map(|o| {
+            nearest(positions, &o.position).map(|(q, distance)| OptodeLabel {
+                optode: o.name,
+                label: q.label.clone(),
+                distance,
+            })
+        })
+        .collect())
+}
+
+/// Move optodes onto named head positions. The new positions are written
+/// in the entry's `LengthUnit`, before `transform`, so that the optodes
+/// land on the positions once the probe is placed.
+pub fn place_optodes(
+    entry: &mut NirsEntry,
+    transform: &Transform,
+    positions: &[NamedPoint],
+    placements: &[OptodePlacement],
+) -> Result<(), NWError> {
+    let mm = mm_per_unit(&entry.metadata)?;
+    let inverse = transform
+        .matrix()
+        .try_inverse()
+        .ok_or_else(|| NWError::InvalidInput("the probe transform has a zero scale".into()))?;
+    let probe = &mut entry.probe;
+    for placement in placements {
+        let target = positions
+            .iter()
+            .find(|p| p.label.eq_ignore_ascii_case(&placement.label))
+            .ok_or_else(|| {
+                NWError::InvalidInput(format!("no head position '{}'", placement.label))
+            })?;
+        let optode = probe
+            .sources
+            .iter_mut()
+            .chain(probe.detectors.iter_mut())
+            .find(|o| o.name == placement.optode)
+            .ok_or_else(|| NWError::InvalidInput(format!("no optode '{}'", placement.optode)))?;
+        optode.pos_3d = inverse
+            .transform_point(&Point3::from(target.position))
+            .coords
+            / mm;
+    }
+    Ok(())
+}
+
+/// Write the optodes (described by their nearest head position, if any
+/// are computed) or the head positions to `path`. BIDS `optodes.tsv` only
+/// lists optodes.
+pub fn export_head_positions(
+    entry: Option<&NirsEntry>,
+    transform: &Transform,
+    positions: &[NamedPoint],
+    set: PositionSet,
+    format: PositionFormat,
+    path: &str,
+) -> Result<(), NWError> {
+    let rows: Vec<PositionRow> = match set {
+        PositionSet::Head => {
+            if format == PositionFormat::Tsv {
+                return Err(NWError::InvalidInput(
+                    "optodes.tsv lists sources and detectors; export head positions as .elc or .sfp"
+                        .into(),
+                ));
+            }
+            positions
+                .iter()
+                .map(|p| PositionRow {
+                    name: p.label.clone(),
+                    kind: None,
+                    position: p.position,
+                    description: None,
+                })
+                .collect()
+        }
+        PositionSet::Optodes => {
+            let entry = entry.ok_or(NWError::NoData)?;
+            optode_positions(entry, transform)?
+                .into_iter()
+                .map(|o| PositionRow {
+                    description: nearest(positions, &o.position).map(|(q, _)| q.label.clone()),
+                    name: o.name,
+                    kind: Some(o.kind),
+                    position: o.position.into(),
+                })
+                .collect()
+        }
+    };
+    export_positions(&rows, path, format)
+}
@@ -0,0 +1,211 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{DataBlock, Measurement, MetadataTag, Optode, Probe};
+use log::{info, warn};
+use nalgebra::{DMatrix, DVector};
+use std::collections::BTreeMap;
+
+/// SNIRF `dataType` for processed data.
+const PROCESSED: i32 = 99999;
+
+/// Molar extinction coefficients of oxy- and deoxy-haemoglobin in
+/// cm⁻¹/M (base-10), every 10 nm from 650 to 950 nm (Prahl, compiled from
+/// Gratzer and Kollias).
+const EXTINCTION_START_NM: f64 = 650.0;
+const EXTINCTION_STEP_NM: f64 = 10.0;
+#[rustfmt::skip]
+const EXTINCTION: [(f64, f64); 31] = [
+    (368.0, 3750.12), (319.6, 3226.56), (294.0, 2795.12), (277.6, 2407.92),
+    (276.0, 2051.96), (290.0, 1794.28), (314.0, 1540.48), (348.0, 1325.88),
+    (390.0, 1102.20), (446.0, 1115.88), (518.0, 1405.24), (586.0, 1548.52),
+    (650.0, 1311.88), (710.0, 1075.44), (756.0, 890.80), (816.0, 761.72),
+    (864.0, 717.08), (916.0, 693.76), (974.0, 693.04), (1022.0, 692.36),
+    (1058.0, 691.32), (1092.0, 694.32), (1128.0, 705.84), (1154.0, 726.44),
+    (1178.0, 743.60), (1198.0, 761.84), (1214.0, 774.56), (1224.0, 777.36),
+    (1222.0, 763.84), (1214.0, 693.44), (1204.0, 602.24),
+];
+
+/// `(ε_HbO, ε_HbR)` at `wavelength` nm, linearly interpolated, converted to
+/// natural-log units to match optical density computed with `ln`.
+pub(crate) fn extinction(wavelength: f64) -> Option<(f64, f64)> {
+    let pos = (wavelength - EXTINCTION_START_NM) / EXTINCTION_STEP_NM;
+    let last = (EXTINCTION.len() - 1) as f64;
+    if !(0.0..=last).contains(&pos) {
+        return None;
+    }
+    let i = (pos.floor() as usize).min(EXTINCTION.len() - 2);
+    let f = pos - i as f64;
+    let (o0, r0) = EXTINCTION[i];
+    let (o1, r1) = EXTINCTION[i + 1];
+    let ln10 = std::f64::consts::LN_10;
+    Some((ln10 * (o0 + f * (o1 - o0)), ln10 * (r0 + f * (r1 - r0))))
+}
+
+/// Change in optical density, `−ln(I / mean(I))`, of every measurement.
+/// Non-positive intensities become NaN. Wavelength indices are kept so the
+/// block can be converted to concentrations.
+pub fn optical_density_block(block: &DataBlock) -> Result<DataBlock, NWError> {
+    if block.measurements.iter().any(|m| m.data_type == PROCESSED) {
+        return Err(NWError::InvalidInput(
+            "optical density needs raw intensity data".into(),
+        ));
+    }
+    let measurements = block
+        .measurements
+        .iter()
+        .map(|m| {
+            let valid: Vec<f64> = m
+                .data
+                .iter()
+                .copied()
+                .filter(|v| v.is_finite() && *v > 0.0)
+                .collect();
+            let mean = valid.iter().sum::<f64>() / valid.len().max(1) as f64;
+            let data = m
+                .data
+                .iter()
+                .map(|&v| {
+                    if v.is_finite() && v > 0.0 && mean > 0.0 {
+                        -(v / mean).ln()
+                    } else {
+                        f64::NAN
+                    }
+                })
+                .collect();
+            let mut od = m.with_data(data);
+            od.data_type = PROCESSED;
+            od.data_type_label = "dOD".to_string();
+            od.data_unit = None;
+            od
+        })
+        .collect();
+    Ok(DataBlock {
+        time: block.time.clone(),
+        measurements,
+    })
+}
+
+/// Centimetres per unit of the entry's `LengthUnit` tag (mm when absent).
+pub(crate) fn length_unit_to_cm(metadata: &[MetadataTag]) -> Result<f64, NWError> {
+    let unit = metadata
+        .iter()
+        .find(|t| t.name == "LengthUnit")
+        .map(|t| t.value.trim())
+        .unwrap_or("mm");
+    match unit {
+        "mm" => Ok(0.1),
+        "cm" => Ok(1.0),
+        "m" => Ok(100.0),
+        other => Err(NWError::InvalidInput(format!(
+            "unknown LengthUnit '{other}'"
+        ))),
+    }
+}
+
+/// Modified Beer–Lambert law: convert an optical density block to changes in
+/// HbO and HbR concentration (µM). Each channel is solved by least squares
+/// over its wavelengths, with path length `distance × dpf`.
+pub fn mbll_block(
+    block: &DataBlock,
+    probe: &Probe,
+    metadata: &[MetadataTag],
+    dpf: f64,
+) -> Result<DataBlock, NWError> {
+    if !(dpf > 0.0 && dpf.is_finite()) {
+        return Err(NWError::InvalidInput(format!("invalid DPF {dpf}")));
+    }
+    if block
+        .measurements
+        .iter()
+        .any(|m| m.data_type != PROCESSED || m.wavelength_index.is_none())
+    {
+        return Err(NWError::InvalidInput(
+            "MBLL needs an optical density block; compute optical density first".into(),
+        ));
+    }
+    let to_cm = length_unit_to_cm(metadata)?;
+
+    let mut channels: BTreeMap<(usize, usize), Vec<&Measurement>> = BTreeMap::new();
+    for m in &block.measurements {
+        channels
+            .entry((m.source_index, m.detector_index))
+            .or_default()
+            .push(m);
+    }
+
+    let n = block.time.len();
+    let mut measurements = Vec::with_capacity(2 * channels.len());
+    // SNIRF optode indices are 1-based.
+    let optode =
+        |list: &[Optode], i: usize| i.checked_sub(1).and_then(|i| list.get(i)).map(|o| o.pos_3d);
+    for ((src, det), group) in channels {
+        let (Some(s), Some(d)) = (optode(&probe.sources, src), optode(&probe.detectors, det))
+        else {
+            return Err(NWError::InvalidInput(format!(
+                "S{src}-D{det}: optode missing from the probe"
+            )));
+        };
+        let path_cm = (s - d).norm() * to_cm * dpf;
+        if path_cm <= 0.0 {
+            warn!("MBLL: S{src}-D{det} has zero source–detector distance; skipped");
+            continue;
+        }
+
+        // ΔOD(λ) = [ε_HbO(λ)  ε_HbR(λ)] · [ΔHbO ΔHbR]ᵀ · path
+        let mut rows = Vec::with_capacity(2 * group.len());
+        for m in &group {
+            let wavelength = m
+                .wavelength_index
+                .and_then(|i| i.checked_sub(1))
+                .and_then(|i| probe.wavelengths.get(i))
+                .copied()
+                .ok_or_else(|| {
+                    NWError::InvalidInput(format!("S{src}-D{det}: wavelength index out of range"))
+                })?;
+            let wavelength = m.wavelength_actual.unwrap_or(wavelength);
+            let (eo, er) = extinction(wavelength).ok_or_else(|| {
+                NWError::InvalidInput(format!(
+                    "no extinction coefficients for {wavelength} nm (650–950 nm supported)"
+                ))
+            })?;
+            rows.extend([eo * path_cm, er * path_cm]);
+        }
+        let e = DMatrix::from_row_slice(group.len(), 2, &rows);
+        let pinv = e
+            .clone()
+            .pseudo_inverse(1e-12)
+            .map_err(|e| NWError::Internal(format!("S{src}-D{det}: extinction matrix: {e}")))?;
+        if group.len() < 2 {
+            warn!("MBLL: S{src}-D{det} has a single wavelength; result is underdetermined");
+        }
+
+        let mut hbo = Vec::with_capacity(n);
+        let mut hbr = Vec::with_capacity(n);
+        for t in 0..n {
+            let od = DVector::from_iterator(
+                group.len(),
+                group
+                    .iter()
+                    .map(|m| m.data.get(t).copied().unwrap_or(f64::NAN)),
+            );
+            let c = &pinv * od;
+            // Molar → micromolar
+            hbo.push(c[0] * 1e6);
+            hbr.push(c[1] * 1e6);
+        }
+
+        for (label, data) in [("HbO", hbo), ("HbR", hbr)] {
+            let mut m = group[0].with_data(data);
+            m.wavelength_index = None;
+            m.wavelength_actual = None;
+            m.data_type_label = label.to_string();
+            m.data_unit = Some("uM".to_string());
+            measurements.push(m);
+        }
+    }
+
+    info!("MBLL: {} channels, DPF {dpf}", measurements.len() / 2);
+    Ok(DataBlock {
+        time: block.time.clone(),
+        measurements,
+    })
+}
@@ -0,0 +1,91 @@
+use crate::domain::error::NWError;
+use crate::domain::mesh::{Mesh, MeshGeometry, Vertex};
+use crate::domain::voxel::VoxelVolume;
+use crate::meshing::{decimate, surface_nets, taubin, tissue_field};
+use nalgebra::{Point3, Vector2};
+use serde::{Deserialize, Serialize};
+
+/// The surfaces meshed from head_labels.mgz, by the file name the anatomy
+/// loader expects and the label of the tissue. The labels nest from the
+/// outside in, so each surface is the outside of its tissue and of every
+/// tissue within it: "skull" is the head (the skull label takes in the
+/// scalp), "grey_matter" the pial surface.
+pub const TISSUES: [(&str, u8); 4] = [
+    ("skull", 1),
+    ("csf", 2),
+    ("grey_matter", 3),
+    ("white_matter", 4),
+];
+
+/// Taubin pass factors; μ a little larger than λ keeps the volume.
+const TAUBIN_LAMBDA: f64 = 0.5;
+const TAUBIN_MU: f64 = -0.53;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MeshingOptions {
+    /// Gaussian blur of the tissue mask before the surface is extracted,
+    /// in voxels; 0 meshes the voxel staircase.
+    pub sigma: f64,
+    /// Taubin smoothing passes over the extracted surface.
+    pub smoothing: usize,
+    /// Share of the extracted triangles decimation keeps, (0, 1].
+    pub keep: f64,
+}
+
+impl Default for MeshingOptions {
+    fn default() -> Self {
+        MeshingOptions {
+            sigma: 1.0,
+            smoothing: 10,
+            keep: 0.25,
+        }
+    }
+}
+
+/// The surface of the voxels of `volume` labelled `label` or higher (see
+/// [`TISSUES`]), smoothed and decimated, in RAS millimetres with outward
+/// normals.
+pub fn tissue_mesh(
+    volume: &VoxelVolume,
+    name: &str,
+    label: u8,
+    options: &MeshingOptions,
+) -> Result<Mesh, NWError> {
+    let valid = options.keep > 0.0 && options.keep <= 1.0 && options.sigma >= 0.0;
+    if !valid {
+        return Err(NWError::InvalidInput(
+            "meshing needs 0 < keep ≤ 1 and sigma ≥ 0".into(),
+        ));
+    }
+    let field = tissue_field(&volume.labels, volume.dims, |l| l >= label, options.sigma)
+        .ok_or_else(|| NWError::InvalidInput(format!("no voxel is labelled {label} or higher")))?;
+    let mut mesh = surface_nets(&field, 0.5);
+
+    let vox2ras = volume.vox2ras;
+    for p in &mut mesh.positions {
+        *p = vox2ras.transform_point(&Point3::from(*p)).coords;
+    }
+    // A mirroring vox2ras turns the triangles inside out.
+    if vox2ras.fixed_view::<3, 3>(0, 0).determinant() < 0.0 {
+        for t in &mut mesh.triangles {
+            t.swap(1, 2);
+        }
+    }
+    taubin(&mut mesh, options.smoothing, TAUBIN_LAMBDA, TAUBIN_MU);
+    let target = (mesh.triangles.len() as f64 * options.keep).ceil() as usize;
+    let mesh = decimate(&mesh, target);
+
+    let verts = mesh
+        .positions
+        .iter()
+        .zip(mesh.normals())
+        .map(|(position, normal)| Vertex {
+            position: *position,
+            normal,
+            uv: Vector2::zeros(),
+        })
+        .collect();
+    let indices = mesh.triangles.iter().flatten().copied().collect();
+    Ok(Mesh::from_geometry(name, MeshGeometry { verts, indices }))
+}
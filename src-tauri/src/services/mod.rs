@@ -1 +1,21 @@
+pub mod anatomy_pipeline_service;
+pub mod atlas_service;
+pub mod dataset_service;
+pub mod detrend_service;
+pub mod event_service;
+pub mod filter_service;
+pub mod head_position_service;
+pub mod hemoglobin_service;
+pub mod meshing_service;
+pub mod photon_service;
+pub mod pipeline_service;
+pub mod project_service;
+pub mod projection_service;
+pub mod reconstruction_service;
+pub mod registration_service;
+pub mod resample_service;
+pub mod sensitivity_service;
 pub mod session_service;
+pub mod spatial_filter_service;
+pub mod timeseries_service;
+pub mod trigger_service;
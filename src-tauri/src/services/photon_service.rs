@@ -0,0 +1,220 @@
+use crate::domain::error::NWError;
+use crate::domain::optics::{Fluence, OpticalProperties};
+use crate::domain::probe::OptodeLayout;
+use crate::domain::scene::Transform;
+use crate::domain::snirf::NirsEntry;
+use crate::domain::voxel::VoxelVolume;
+use crate::io::mcx_exporter::McxOptode;
+use crate::io::mcx_importer::McxFluence;
+use crate::photon::{enter_tissue, simulate, PencilBeam, SimulationConfig};
+use crate::services::head_position_service::{optode_positions, PlacedOptode};
+use nalgebra::{Matrix4, Point3, Vector3};
+use serde::Serialize;
+
+/// What the fluence viewer shows next to the slices, as in MCX's
+/// visualisation script.
+#[derive(Serialize, Clone, Debug)]
+pub struct FluenceSummary {
+    pub optode: String,
+    pub dims: [usize; 3],
+    /// Voxel edge lengths, mm.
+    pub voxel_size: [f64; 3],
+    pub gates: usize,
+    /// Of the CW fluence, 1/mm².
+    pub max: f64,
+    pub total: f64,
+    pub nonzero: usize,
+    /// log10 of the largest over the smallest non-zero value.
+    pub dynamic_range: f64,
+    /// Where the beam enters the tissue, for centring the slices.
+    pub entry: [usize; 3],
+    /// (depth along the beam in mm, CW fluence) per voxel the beam crosses.
+    pub depth_profile: Vec<[f64; 2]>,
+}
+
+fn voxel_size(vox2ras: &Matrix4<f64>) -> [f64; 3] {
+    std::array::from_fn(|c| vox2ras.fixed_view::<3, 1>(0, c).norm())
+}
+
+/// Centre of the tissue voxels, in continuous voxel coordinates.
+fn tissue_centroid(volume: &VoxelVolume) -> Option<Vector3<f64>> {
+    let [nx, ny, _] = volume.dims;
+    let (sum, count) = volume
+        .labels
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| **l != 0)
+        .fold((Vector3::zeros(), 0usize), |(sum, count), (i, _)| {
+            let v = Vector3::new(
+                (i % nx) as f64,
+                ((i / nx) % ny) as f64,
+                (i / (nx * ny)) as f64,
+            );
+            (sum + v, count + 1)
+        });
+    (count > 0).then(|| sum / count as f64 + Vector3::repeat(0.5))
+}
+
+/// A pencil beam from `position` (head space, mm) into `volume`, aimed at
+/// the centre of the tissue: optodes sit on the scalp, facing in.
+pub fn optode_beam(volume: &VoxelVolume, position: &Point3<f64>) -> Result<PencilBeam, NWError> {
+    let ras2vox = volume
+        .vox2ras
+        .try_inverse()
+        .ok_or_else(|| NWError::InvalidInput("the volume's vox2ras is singular".into()))?;
+    // Voxel centres are at integer indices in vox2ras, at i + 0.5 here.
+    let at = ras2vox.transform_point(position).coords + Vector3::repeat(0.5);
+    let centre = tissue_centroid(volume).ok_or(NWError::NoData)?;
+    let size = voxel_size(&volume.vox2ras);
+    let towards = centre - at;
+    Ok(PencilBeam {
+        position: at.into(),
+        direction: std::array::from_fn(|a| towards[a] * size[a]),
+    })
+}
+
+fn placed_beam(
+    volume: &VoxelVolume,
+    placed: &[PlacedOptode],
+    name: &str,
+) -> Result<PencilBeam, NWError> {
+    let optode = placed
+        .iter()
+        .find(|o| o.name == name)
+        .ok_or_else(|| NWError::InvalidInput(format!("no optode named {name}")))?;
+    optode_beam(volume, &optode.position)
+}
+
+/// Simulate the fluence from each of `optodes` (`S1`, `D2`…) at its
+/// `placed` position. Takes the positions rather than the recording so
+/// callers need not hold the session through the simulation.
+pub(crate) fn simulate_optodes(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    placed: &[PlacedOptode],
+    optodes: &[String],
+    config: &SimulationConfig,
+) -> Result<Vec<(String, PencilBeam, Fluence)>, NWError> {
+    let mut out = Vec::with_capacity(optodes.len());
+    for name in optodes {
+        let beam = placed_beam(volume, placed, name)?;
+        let fluence = simulate(volume, media, &beam, config)?;
+        out.push((name.clone(), beam, fluence));
+    }
+    Ok(out)
+}
+
+/// The MCX session of each of `optodes`: its beam from where it enters
+/// the tissue, so MCX launches the same photons [`simulate`] would, and
+/// the optodes it forms channels with in `layout` as detectors.
+pub fn mcx_optodes(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    entry: &NirsEntry,
+    transform: &Transform,
+    layout: &OptodeLayout,
+    optodes: &[String],
+    config: &SimulationConfig,
+) -> Result<Vec<McxOptode>, NWError> {
+    let placed = optode_positions(entry, transform)?;
+    let inside = |name: &str| {
+        let beam = placed_beam(volume, &placed, name)?;
+        enter_tissue(volume, media, &beam, config).map(|(beam, _)| beam)
+    };
+    optodes
+        .iter()
+        .map(|name| {
+            let partners = layout.channels.iter().filter_map(|ch| {
+                let source = &layout.sources[ch.source_idx].name;
+                let detector = &layout.detectors[ch.detector_idx].name;
+                match (source == name, detector == name) {
+                    (true, _) => Some(detector),
+                    (_, true) => Some(source),
+                    _ => None,
+                }
+            });
+            let detectors = partners
+                .map(|p| inside(p).map(|beam| beam.position))
+                .collect::<Result<_, _>>()?;
+            Ok(McxOptode {
+                name: name.clone(),
+                beam: inside(name)?,
+                detectors,
+            })
+        })
+        .collect()
+}
+
+/// `mcx`, simulated by MCX from `optode`'s session, as if simulated here:
+/// on `volume`'s grid, entering where the optode's beam does.
+pub fn mcx_fluence(
+    volume: &VoxelVolume,
+    media: &[OpticalProperties],
+    entry: &NirsEntry,
+    transform: &Transform,
+    optode: &str,
+    mcx: McxFluence,
+) -> Result<(PencilBeam, Fluence), NWError> {
+    if mcx.dims != volume.dims {
+        return Err(NWError::InvalidInput(format!(
+            "{optode}'s fluence is {:?} voxels, the volume {:?}",
+            mcx.dims, volume.dims
+        )));
+    }
+    let placed = optode_positions(entry, transform)?;
+    let beam = placed_beam(volume, &placed, optode)?;
+    let (_, entry) = enter_tissue(volume, media, &beam, &SimulationConfig::default())?;
+    let fluence = Fluence {
+        dims: mcx.dims,
+        vox2ras: volume.vox2ras,
+        t0: mcx.t0,
+        gate_width: mcx.gate_width,
+        gates: mcx.gates,
+        entry,
+        data: mcx.data,
+    };
+    Ok((beam, fluence))
+}
+
+/// The CW fluence's range and its profile along the beam.
+pub fn summarize(optode: &str, fluence: &Fluence, beam: &PencilBeam) -> FluenceSummary {
+    let cw = fluence.cw();
+    let size = voxel_size(&fluence.vox2ras);
+    let nonzero: Vec<f64> = cw.iter().filter(|v| **v > 0.0).map(|v| *v as f64).collect();
+    let max = nonzero.iter().copied().fold(0.0, f64::max);
+    let min = nonzero.iter().copied().fold(f64::INFINITY, f64::min);
+
+    let [nx, ny, nz] = fluence.dims;
+    let inside = |p: &[f64; 3]| (0..3).all(|a| p[a] >= 0.0 && p[a] < fluence.dims[a] as f64);
+    let voxel_of = |p: &[f64; 3]| -> [usize; 3] { std::array::from_fn(|a| p[a] as usize) };
+
+    // Walk the beam in steps of the smallest voxel edge.
+    let step_mm = size.iter().copied().fold(f64::INFINITY, f64::min);
+    let step = Vector3::from(beam.direction).normalize() * step_mm;
+    let step = step.component_div(&Vector3::from(size));
+    let mut depth_profile = Vec::new();
+    let mut p = Vector3::from(beam.position);
+    for k in 0..(nx + ny + nz) * 2 {
+        let at: [f64; 3] = p.into();
+        if inside(&at) {
+            let [i, j, l] = voxel_of(&at);
+            depth_profile.push([k as f64 * step_mm, cw[i + j * nx + l * nx * ny] as f64]);
+        } else if !depth_profile.is_empty() {
+            break;
+        }
+        p += step;
+    }
+
+    FluenceSummary {
+        optode: optode.to_string(),
+        dims: fluence.dims,
+        voxel_size: size,
+        gates: fluence.gates,
+        max,
+        total: nonzero.iter().sum(),
+        nonzero: nonzero.len(),
+        dynamic_range: if max > 0.0 { (max / min).log10() } else { 0.0 },
+        entry: fluence.entry,
+        depth_profile,
+    }
+}
@@ -0,0 +1,398 @@
+use crate::domain::annotation::TimeSegment;
+use crate::domain::error::NWError;
+use crate::domain::provenance::ProvenanceRecord;
+use crate::domain::snirf::{DataBlock, NirsEntry};
+use crate::io::{recording_importer, snirf_exporter};
+use crate::services::detrend_service::{
+    baseline_block, detrend_block, BaselineMode, DetrendMethod,
+};
+use crate::services::filter_service::{bandpass_block, tddr_block};
+use crate::services::hemoglobin_service::{mbll_block, optical_density_block};
+use crate::services::resample_service::{
+    regularize_block, resample_auxiliary, resample_block, ResampleMethod,
+};
+use crate::services::spatial_filter_service::{ica_reject_block, pca_filter_block};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+fn default_filter_order() -> usize {
+    3
+}
+
+fn default_dpf() -> f64 {
+    6.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// One typed processing step. Every step reads one data block and appends
+/// the block it derives, so the original data is never modified.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProcessingStep {
+    /// Raw intensity → change in optical density.
+    OpticalDensity,
+    /// Zero-phase Butterworth band-pass; either edge may be omitted.
+    Bandpass {
+        low: Option<f64>,
+        high: Option<f64>,
+        #[serde(default = "default_filter_order")]
+        order: usize,
+    },
+    /// Temporal Derivative Distribution Repair motion correction.
+    Tddr,
+    /// Modified Beer–Lambert law: optical density → HbO/HbR in µM.
+    Mbll {
+        #[serde(default = "default_dpf")]
+        dpf: f64,
+    },
+    Detrend {
+        method: DetrendMethod,
+    },
+    Baseline {
+        start: f64,
+        end: f64,
+        #[serde(default)]
+        mode: BaselineMode,
+    },
+    Pca {
+        n_components: usize,
+        fit_start: Option<f64>,
+        fit_end: Option<f64>,
+    },
+    IcaReject {
+        reject: Vec<usize>,
+        n_components: Option<usize>,
+        fit_start: Option<f64>,
+        fit_end: Option<f64>,
+    },
+    Regularize {
+        target_rate: Option<f64>,
+        #[serde(default)]
+        method: ResampleMethod,
+    },
+    /// Polyphase resampling; with `include_aux` the auxiliary signals are
+    /// resampled too and added alongside the originals, replacing copies
+    /// at the same rate.
+    Resample {
+        target_rate: f64,
+        #[serde(default = "default_true")]
+        include_aux: bool,
+    },
+}
+
+impl ProcessingStep {
+    /// The serialised `kind` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProcessingStep::OpticalDensity => "opticaldensity",
+            ProcessingStep::Bandpass { .. } => "bandpass",
+            ProcessingStep::Tddr => "tddr",
+            ProcessingStep::Mbll { .. } => "mbll",
+            ProcessingStep::Detrend { .. } => "detrend",
+            ProcessingStep::Baseline { .. } => "baseline",
+            ProcessingStep::Pca { .. } => "pca",
+            ProcessingStep::IcaReject { .. } => "icareject",
+            ProcessingStep::Regularize { .. } => "regularize",
+            ProcessingStep::Resample { .. } => "resample",
+        }
+    }
+
+    /// Human-readable name, e.g. for the undo history.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProcessingStep::OpticalDensity => "Optical density",
+            ProcessingStep::Bandpass { .. } => "Band-pass filter",
+            ProcessingStep::Tddr => "TDDR motion correction",
+            ProcessingStep::Mbll { .. } => "Beer–Lambert conversion",
+            ProcessingStep::Detrend { .. } => "Detrend",
+            ProcessingStep::Baseline { .. } => "Baseline correction",
+            ProcessingStep::Pca { .. } => "PCA filter",
+            ProcessingStep::IcaReject { .. } => "ICA rejection",
+            ProcessingStep::Regularize { .. } => "Regularize time base",
+            ProcessingStep::Resample { .. } => "Resample",
+        }
+    }
+}
+
+/// The interval a PCA/ICA decomposition is fitted on; `None` (the whole
+/// block) unless both ends are given.
+pub(crate) fn fit_segment(start: Option<f64>, end: Option<f64>) -> Option<TimeSegment> {
+    match (start, end) {
+        (Some(a), Some(b)) => Some(TimeSegment::new(a, b)),
+        _ => None,
+    }
+}
+
+/// Run `step` on data block `input` of `entry`, append the derived block and
+/// record how it was made, `bad_segments` included. `pipeline` names the pipeline the step belongs to,
+/// if any. Returns the index of the new block.
+pub fn run_step(
+    entry: &mut NirsEntry,
+    input: usize,
+    step: &ProcessingStep,
+    bad_segments: &[TimeSegment],
+    pipeline: Option<&str>,
+) -> Result<usize, NWError> {
+    let block = entry
+        .data_blocks
+        .get(input)
+        .ok_or(NWError::BlockOutOfRange(input))?;
+
+    let mut aux = Vec::new();
+    let derived: DataBlock = match step {
+        ProcessingStep::OpticalDensity => optical_density_block(block)?,
+        ProcessingStep::Bandpass { low, high, order } => {
+            bandpass_block(block, *low, *high, *order)?
+        }
+        ProcessingStep::Tddr => tddr_block(block)?,
+        ProcessingStep::Mbll { dpf } => mbll_block(block, &entry.probe, &entry.metadata, *dpf)?,
+        ProcessingStep::Detrend { method } => detrend_block(block, *method, bad_segments)?,
+        ProcessingStep::Baseline { start, end, mode } => {
+            baseline_block(block, TimeSegment::new(*start, *end), *mode, bad_segments)?
+        }
+        ProcessingStep::Pca {
+            n_components,
+            fit_start,
+            fit_end,
+        } => pca_filter_block(
+            block,
+            *n_components,
+            fit_segment(*fit_start, *fit_end),
+            bad_segments,
+        )?,
+        ProcessingStep::IcaReject {
+            reject,
+            n_components,
+            fit_start,
+            fit_end,
+        } => ica_reject_block(
+            block,
+            *n_components,
+            fit_segment(*fit_start, *fit_end),
+            bad_segments,
+            reject,
+        )?,
+        ProcessingStep::Regularize {
+            target_rate,
+            method,
+        } => regularize_block(block, *target_rate, *method)?,
+        ProcessingStep::Resample {
+            target_rate,
+            include_aux,
+        } => {
+            if *include_aux {
+                aux = entry
+                    .auxiliaries
+                    .iter()
+                    .filter(|a| a.resampled_from.is_none())
+                    .map(|a| {
+                        resample_auxiliary(a, *target_rate).map(|mut r| {
+                            r.name = format!("{} ({:.3} Hz)", a.name, target_rate);
+                            r.resampled_from = Some(a.name.clone());
+                            r
+                        })
+                    })
+                    .collect::<Result<Vec<_>, NWError>>()?;
+            }
+            resample_block(block, *target_rate)?
+        }
+    };
+
+    entry.data_blocks.push(derived);
+    // Resampling again at the same rate replaces the earlier copies.
+    for resampled in aux {
+        let earlier = entry
+            .auxiliaries
+            .iter_mut()
+            .find(|a| a.resampled_from == resampled.resampled_from && a.name == resampled.name);
+        match earlier {
+            Some(earlier) => *earlier = resampled,
+            None => entry.auxiliaries.push(resampled),
+        }
+    }
+    let output = entry.data_blocks.len() - 1;
+    let parameters =
+        serde_json::to_value(step).map_err(|e| NWError::Internal(format!("step: {e}")))?;
+    entry.provenance.push(ProvenanceRecord::new(
+        output,
+        input,
+        step.kind(),
+        parameters,
+        pipeline,
+        bad_segments,
+    ));
+    Ok(output)
+}
+
+// =============================================================================
+// Pipelines
+// =============================================================================
+
+/// An ordered list of steps, each applied to the output of the previous one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<ProcessingStep>,
+}
+
+/// File format chosen by extension: `.toml`, anything else is JSON.
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+}
+
+impl Pipeline {
+    pub fn load(path: &str) -> Result<Pipeline, NWError> {
+        let text = std::fs::read_to_string(path)?;
+        if is_toml(Path::new(path)) {
+            toml::from_str(&text).map_err(|e| NWError::Parse(format!("{path}: {e}")))
+        } else {
+            serde_json::from_str(&text).map_err(|e| NWError::Parse(format!("{path}: {e}")))
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), NWError> {
+        let text = if is_toml(Path::new(path)) {
+            toml::to_string_pretty(self).map_err(|e| NWError::Internal(e.to_string()))?
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| NWError::Internal(e.to_string()))?
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Reconstruct the pipeline that produced `block` from the entry's
+    /// provenance, so it can be re-run on other recordings.
+    pub fn from_provenance(
+        records: &[ProvenanceRecord],
+        block: usize,
+    ) -> Result<Pipeline, NWError> {
+        let chain = ProvenanceRecord::chain(records, block);
+        let steps = chain
+            .iter()
+            .map(|r| {
+                serde_json::from_value(r.parameters.clone()).map_err(|e| {
+                    NWError::Parse(format!("block {}: step '{}': {e}", r.output_block, r.step))
+                })
+            })
+            .collect::<Result<Vec<ProcessingStep>, NWError>>()?;
+        let name = chain
+            .last()
+            .and_then(|r| r.pipeline.clone())
+            .unwrap_or_else(|| format!("block {block}"));
+        Ok(Pipeline {
+            name,
+            description: String::new(),
+            steps,
+        })
+    }
+}
+
+/// Run every step of `pipeline` starting from data block `input`. Returns the
+/// indices of the derived blocks, the last one being the final result.
+pub fn run_pipeline(
+    entry: &mut NirsEntry,
+    input: usize,
+    pipeline: &Pipeline,
+    bad_segments: &[TimeSegment],
+) -> Result<Vec<usize>, NWError> {
+    let mut outputs = Vec::with_capacity(pipeline.steps.len());
+    let mut current = input;
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        current = run_step(entry, current, step, bad_segments, Some(&pipeline.name))
+            .map_err(|e| NWError::Internal(format!("step {} ({}): {e}", i + 1, step.kind())))?;
+        outputs.push(current);
+    }
+    info!(
+        "Pipeline '{}': {} steps from block {input}",
+        pipeline.name,
+        pipeline.steps.len()
+    );
+    Ok(outputs)
+}
+
+// =============================================================================
+// Batch
+// =============================================================================
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BatchResult {
+    pub input: String,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Run `pipeline` from the first data block of every file in `inputs` and
+/// write each result, provenance included, as a SNIRF file of the same name
+/// in `output_dir`. A failing file is reported and does not stop the batch.
+/// Each file is processed with the bad segments of its latest provenance
+/// record, so a recording exported with masks keeps them; raw recordings
+/// have none.
+pub fn run_batch(pipeline: &Pipeline, inputs: &[String], output_dir: &str) -> Vec<BatchResult> {
+    inputs
+        .iter()
+        .map(|input| {
+            let result = run_batch_file(pipeline, input, output_dir);
+            match result {
+                Ok(output) => BatchResult {
+                    input: input.clone(),
+                    output: Some(output),
+                    error: None,
+                },
+                Err(e) => BatchResult {
+                    input: input.clone(),
+                    output: None,
+                    error: Some(e.to_string()),
+                },
+            }
+        })
+        .collect()
+}
+
+/// `path` with links and `.`/`..` resolved, so that two spellings of the
+/// same file compare equal. A file that does not exist yet is resolved
+/// through its folder.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        let folder = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        match (folder.canonicalize(), path.file_name()) {
+            (Ok(folder), Some(name)) => folder.join(name),
+            _ => path.to_path_buf(),
+        }
+    })
+}
+
+fn run_batch_file(pipeline: &Pipeline, input: &str, output_dir: &str) -> Result<String, NWError> {
+    let mut snirf = recording_importer::import_recording(input)?;
+    for entry in &mut snirf.nirs_entries {
+        let bad_segments = entry
+            .provenance
+            .last()
+            .map(|r| r.bad_segments.clone())
+            .unwrap_or_default();
+        run_pipeline(entry, 0, pipeline, &bad_segments)?;
+    }
+    // Recordings imported from other formats are written as SNIRF.
+    let output = Path::new(output_dir)
+        .join(&snirf.file_descriptor.filename)
+        .with_extension("snirf");
+    if canonical(&output) == canonical(Path::new(input)) {
+        return Err(NWError::InvalidInput(format!(
+            "output would overwrite the input file '{input}'"
+        )));
+    }
+    let output = output.to_string_lossy().into_owned();
+    snirf_exporter::export_snirf(&snirf, &output)?;
+    info!("Batch: {input} → {output}");
+    Ok(output)
+}
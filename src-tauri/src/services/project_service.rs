@@ -0,0 +1,225 @@
+use crate::domain::anatomy::{AnatomyPaths, SegmentationBackend};
+use crate::domain::annotation::Annotations;
+use crate::domain::error::NWError;
+use crate::domain::probe::ProbeDisplaySettings;
+use crate::domain::provenance::ProvenanceRecord;
+use crate::domain::scene::Transform;
+use crate::domain::snirf::Event;
+use crate::io::recording_importer;
+use crate::services::pipeline_service::{run_step, Pipeline, ProcessingStep};
+use crate::services::session_service::LoadResult;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File extension of project files.
+pub const PROJECT_EXTENSION: &str = "nirwproj";
+const PROJECT_FORMAT: &str = "nirwizard-project";
+const PROJECT_VERSION: u32 = 1;
+
+/// The SNIRF file a project was built on and the steps that derived the
+/// session's other blocks from it. Data is never copied into the project;
+/// derived blocks are recomputed on open.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordingRef {
+    /// Relative to the project file when the recording lies beside or below it.
+    pub path: String,
+    #[serde(default)]
+    pub provenance: Vec<ProvenanceRecord>,
+}
+
+/// A saved session: everything needed to rebuild the same state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Project {
+    pub format: String,
+    pub version: u32,
+    pub recording: Option<RecordingRef>,
+    /// Always 0 until entries other than the first can be viewed.
+    #[serde(default)]
+    pub active_entry: usize,
+    #[serde(default)]
+    pub active_block: usize,
+    #[serde(default)]
+    pub selected_channels: Vec<usize>,
+    #[serde(default)]
+    pub annotations: Annotations,
+    /// The first entry's events as edited in the session (imports, edits);
+    /// `None` keeps the events stored in the recording.
+    #[serde(default)]
+    pub events: Option<Vec<Event>>,
+    /// The pipeline behind each final derived block, for re-use on other data.
+    #[serde(default)]
+    pub pipelines: Vec<Pipeline>,
+    #[serde(default)]
+    pub anatomy: AnatomyPaths,
+    #[serde(default)]
+    pub anatomy_backend: Option<SegmentationBackend>,
+    #[serde(default)]
+    pub probe_transform: Transform,
+    #[serde(default)]
+    pub probe_settings: ProbeDisplaySettings,
+    /// Frontend view settings (panel sizes, layer visibility…), stored as-is.
+    #[serde(default)]
+    pub view: serde_json::Value,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Project {
+            format: PROJECT_FORMAT.to_string(),
+            version: PROJECT_VERSION,
+            recording: None,
+            active_entry: 0,
+            active_block: 0,
+            selected_channels: Vec::new(),
+            annotations: Annotations::default(),
+            events: None,
+            pipelines: Vec::new(),
+            anatomy: AnatomyPaths::default(),
+            anatomy_backend: None,
+            probe_transform: Transform::default(),
+            probe_settings: ProbeDisplaySettings::default(),
+            view: serde_json::Value::Null,
+        }
+    }
+}
+
+/// `path` relative to `base` if it lies inside it, unchanged otherwise.
+fn relative_to(path: &str, base: &Path) -> String {
+    Path::new(path)
+        .strip_prefix(base)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Relative `path` resolved against `base`; absolute paths are kept.
+fn resolve(path: &str, base: &Path) -> String {
+    let p = Path::new(path);
+    if p.is_absolute() {
+        path.to_string()
+    } else {
+        base.join(p).to_string_lossy().into_owned()
+    }
+}
+
+/// Apply `f` to every file path the project refers to.
+fn map_paths(project: &mut Project, f: impl Fn(&str) -> String) {
+    if let Some(recording) = &mut project.recording {
+        recording.path = f(&recording.path);
+    }
+    let anatomy = &mut project.anatomy;
+    for path in [
+        &mut anatomy.mri,
+        &mut anatomy.cortex,
+        &mut anatomy.scalp,
+        &mut anatomy.atlas,
+        &mut anatomy.subject_dir,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = f(path);
+    }
+    if let Some(SegmentationBackend::Precomputed { subject_dir }) = &mut project.anatomy_backend {
+        *subject_dir = f(subject_dir);
+    }
+}
+
+fn project_dir(path: &str) -> PathBuf {
+    Path::new(path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+impl Project {
+    /// Read a project file; the paths it refers to are made absolute.
+    pub fn load(path: &str) -> Result<Project, NWError> {
+        let text = std::fs::read_to_string(path)?;
+        let mut project: Project =
+            serde_json::from_str(&text).map_err(|e| NWError::Parse(format!("{path}: {e}")))?;
+        if project.format != PROJECT_FORMAT {
+            return Err(NWError::Parse(format!("{path}: not a NIRWizard project")));
+        }
+        if project.version > PROJECT_VERSION {
+            return Err(NWError::Parse(format!(
+                "{path}: project version {} is newer than this build supports ({PROJECT_VERSION})",
+                project.version
+            )));
+        }
+        let base = project_dir(path);
+        map_paths(&mut project, |p| resolve(p, &base));
+        Ok(project)
+    }
+
+    /// Write the project as JSON; paths inside the project's directory are
+    /// stored relative to it so the folder can be moved as a whole.
+    pub fn save(&self, path: &str) -> Result<(), NWError> {
+        let base = project_dir(path);
+        let mut project = self.clone();
+        map_paths(&mut project, |p| relative_to(p, &base));
+        let text =
+            serde_json::to_string_pretty(&project).map_err(|e| NWError::Internal(e.to_string()))?;
+        std::fs::write(path, text)?;
+        info!("Saved project to {path}");
+        Ok(())
+    }
+}
+
+/// One pipeline per derived block that no other step used as input.
+pub fn leaf_pipelines(records: &[ProvenanceRecord]) -> Result<Vec<Pipeline>, NWError> {
+    records
+        .iter()
+        .filter(|r| !records.iter().any(|o| o.input_block == r.output_block))
+        .map(|r| Pipeline::from_provenance(records, r.output_block))
+        .collect()
+}
+
+/// Load the project's recording and recompute its derived blocks by
+/// replaying the recorded steps in order. Blocks already present in the
+/// file (e.g. an exported session) are not recomputed. Each step runs with
+/// the bad segments it was recorded with, not the project's current ones.
+pub fn open_recording(recording: &RecordingRef) -> Result<LoadResult, NWError> {
+    let mut snirf = recording_importer::import_recording(&recording.path)?;
+    let entry = snirf.nirs_entries.first_mut().ok_or(NWError::NoEntries)?;
+    let stored = entry.data_blocks.len();
+
+    let mut replayed = 0;
+    for record in recording
+        .provenance
+        .iter()
+        .filter(|r| r.output_block >= stored)
+    {
+        let step: ProcessingStep =
+            serde_json::from_value(record.parameters.clone()).map_err(|e| {
+                NWError::Parse(format!(
+                    "block {}: step '{}': {e}",
+                    record.output_block, record.step
+                ))
+            })?;
+        let output = run_step(
+            entry,
+            record.input_block,
+            &step,
+            &record.bad_segments,
+            record.pipeline.as_deref(),
+        )?;
+        if output != record.output_block {
+            return Err(NWError::InvalidInput(format!(
+                "replaying '{}' produced block {output}, the project expects block {}",
+                record.step, record.output_block
+            )));
+        }
+        // Keep the original record (and its timestamp) rather than the new one.
+        if let Some(last) = entry.provenance.last_mut() {
+            *last = record.clone();
+        }
+        replayed += 1;
+    }
+
+    info!(
+        "Opened '{}' for project: {stored} stored blocks, {replayed} recomputed",
+        recording.path
+    );
+    Ok(LoadResult::from_snirf(snirf))
+}
@@ -0,0 +1,215 @@
+use crate::domain::error::NWError;
+use crate::domain::mesh::{Mesh, SurfaceHit};
+use crate::domain::probe::{Optode3D, OptodeLayout};
+use crate::domain::scene::Transform;
+use nalgebra::{Matrix3, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Radius of the patch whose best-fit plane gives the local surface
+/// normal, mm.
+const NORMAL_PATCH_RADIUS: f64 = 10.0;
+
+/// How a point is carried onto a surface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionMethod {
+    /// The closest point of the surface.
+    #[default]
+    Nearest,
+    /// Along the local surface normal (a plane fitted to the vertices
+    /// around the nearest one), whichever way the surface is closer.
+    Normal,
+    /// Along the line through the head centre; the outermost crossing.
+    Centre,
+}
+
+/// A point carried onto a mesh.
+#[derive(Clone, Debug, Serialize)]
+pub struct SurfacePoint {
+    /// Millimetres, head space.
+    pub position: [f64; 3],
+    /// The mesh vertex nearest to `position`.
+    pub vertex: usize,
+    /// The triangle `position` lies on, if known.
+    pub face: Option<u32>,
+    /// How far the point moved, mm.
+    pub distance: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProjectedOptode {
+    pub id: usize,
+    pub name: String,
+    /// The optode's position before projection, mm.
+    pub original: [f64; 3],
+    pub scalp: SurfacePoint,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProjectedChannel {
+    pub id: usize,
+    /// The midpoint of the projected source and detector, back on the scalp.
+    pub scalp: SurfacePoint,
+    /// `scalp` carried onto the grey matter.
+    pub cortex: SurfacePoint,
+    /// Scalp-to-cortex distance, mm.
+    pub depth: f64,
+}
+
+/// Optodes on the scalp and, when a grey-matter mesh is given, channels on
+/// the cortex.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProbeProjection {
+    pub sources: Vec<ProjectedOptode>,
+    pub detectors: Vec<ProjectedOptode>,
+    pub channels: Vec<ProjectedChannel>,
+}
+
+fn centroid(mesh: &Mesh) -> Point3<f64> {
+    let verts = &mesh.geometry.verts;
+    let sum: Vector3<f64> = verts.iter().map(|v| v.position).sum();
+    Point3::from(sum / verts.len().max(1) as f64)
+}
+
+/// Unit normal of the plane fitted to the vertices near `p`, pointing away
+/// from `centre`.
+fn local_normal(mesh: &Mesh, p: &Point3<f64>, centre: &Point3<f64>) -> Option<Vector3<f64>> {
+    let anchor = Point3::from(mesh.geometry.verts[mesh.nearest_vertex(p)?].position);
+    let patch: Vec<Vector3<f64>> = mesh
+        .vertices_within(&anchor, NORMAL_PATCH_RADIUS)
+        .into_iter()
+        .map(|i| mesh.geometry.verts[i].position)
+        .collect();
+    if patch.len() < 3 {
+        return None;
+    }
+    let mean = patch.iter().sum::<Vector3<f64>>() / patch.len() as f64;
+    let scatter = patch
+        .iter()
+        .map(|v| (v - mean) * (v - mean).transpose())
+        .sum::<Matrix3<f64>>();
+    let eigen = scatter.symmetric_eigen();
+    let normal = eigen
+        .eigenvectors
+        .column(eigen.eigenvalues.imin())
+        .into_owned();
+    Some(if normal.dot(&(anchor - centre)) < 0.0 {
+        -normal
+    } else {
+        normal
+    })
+}
+
+fn surface_point(hit: SurfaceHit, from: &Point3<f64>) -> SurfacePoint {
+    SurfacePoint {
+        position: hit.point.into(),
+        vertex: hit.vertex,
+        face: hit.face,
+        distance: (hit.point - from).norm(),
+    }
+}
+
+/// Carry `p` onto `mesh`. Ray-based methods fall back to the nearest point
+/// when the ray misses (or the mesh has no `TriMesh`).
+pub fn project_point(
+    mesh: &Mesh,
+    p: &Point3<f64>,
+    method: ProjectionMethod,
+    centre: &Point3<f64>,
+) -> Option<SurfacePoint> {
+    let reach = mesh
+        .geometry
+        .verts
+        .iter()
+        .map(|v| (v.position - centre.coords).norm())
+        .fold((p - centre).norm(), f64::max)
+        * 2.0;
+    let hit = match method {
+        ProjectionMethod::Nearest => None,
+        ProjectionMethod::Normal => local_normal(mesh, p, centre).and_then(|n| {
+            let inward = mesh.cast_ray(p, &-n, reach);
+            let outward = mesh.cast_ray(p, &n, reach);
+            match (inward, outward) {
+                (Some(a), Some(b)) => Some(if a.1 <= b.1 { a.0 } else { b.0 }),
+                (a, b) => a.or(b).map(|(hit, _)| hit),
+            }
+        }),
+        ProjectionMethod::Centre => {
+            // Come in from outside the head so that the first crossing is
+            // the outermost one.
+            let dir = (p - centre).try_normalize(f64::EPSILON)?;
+            let outside = centre + dir * reach;
+            mesh.cast_ray(&outside, &-dir, reach).map(|(hit, _)| hit)
+        }
+    };
+    hit.or_else(|| mesh.closest_point(p))
+        .map(|hit| surface_point(hit, p))
+}
+
+/// Project the probe onto the head: each optode onto the scalp, then each
+/// channel's midpoint back onto the scalp and from there onto the cortex.
+/// Positions are the layout's (mm) placed by `transform`. The head centre
+/// for the normal and centre methods is `centre`, or the scalp's centroid.
+pub fn project_probe(
+    layout: &OptodeLayout,
+    mm_per_unit: f64,
+    transform: &Transform,
+    scalp: &Mesh,
+    cortex: Option<&Mesh>,
+    method: ProjectionMethod,
+    centre: Option<Point3<f64>>,
+) -> Result<ProbeProjection, NWError> {
+    if scalp.geometry.verts.is_empty() {
+        return Err(NWError::InvalidInput(
+            "the scalp mesh has no vertices".into(),
+        ));
+    }
+    let centre = centre.unwrap_or_else(|| centroid(scalp));
+    let project = |mesh: &Mesh, p: &Point3<f64>| {
+        project_point(mesh, p, method, &centre)
+            .ok_or_else(|| NWError::Internal(format!("could not project {p} onto {}", mesh.name)))
+    };
+    let optodes = |list: &[Optode3D]| {
+        list.iter()
+            .map(|o| {
+                let p = transform.transform_point(&Point3::from(o.position * mm_per_unit));
+                Ok(ProjectedOptode {
+                    id: o.id,
+                    name: o.name.clone(),
+                    original: p.into(),
+                    scalp: project(scalp, &p)?,
+                })
+            })
+            .collect::<Result<Vec<_>, NWError>>()
+    };
+    let sources = optodes(&layout.sources)?;
+    let detectors = optodes(&layout.detectors)?;
+
+    let channels = match cortex {
+        Some(cortex) => layout
+            .channels
+            .iter()
+            .map(|ch| {
+                let a = Point3::from(sources[ch.source_idx].scalp.position);
+                let b = Point3::from(detectors[ch.detector_idx].scalp.position);
+                let mid = nalgebra::center(&a, &b);
+                let scalp_point = project(scalp, &mid)?;
+                let on_scalp = Point3::from(scalp_point.position);
+                let cortex_point = project(cortex, &on_scalp)?;
+                Ok(ProjectedChannel {
+                    id: ch.id,
+                    depth: cortex_point.distance,
+                    scalp: scalp_point,
+                    cortex: cortex_point,
+                })
+            })
+            .collect::<Result<Vec<_>, NWError>>()?,
+        None => Vec::new(),
+    };
+
+    Ok(ProbeProjection {
+        sources,
+        detectors,
+        channels,
+    })
+}
@@ -0,0 +1,452 @@
+use crate::domain::error::NWError;
+use crate::domain::mesh::Mesh;
+use crate::domain::nirs_view::{HemoType, NirsView, SignalKind};
+use crate::domain::optics::{ChannelSensitivity, Sensitivity};
+use crate::domain::snirf::{DataBlock, NirsEntry, Optode};
+use crate::dot::{ModelRow, Regularization, SparseRow, Tikhonov};
+use crate::services::hemoglobin_service::extinction;
+use crate::services::sensitivity_service::{vertex_neighbourhoods, vertex_positions};
+use nalgebra::DVector;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// ε is per cm and per M; the sensitivity is in mm and the image in µM.
+const EXTINCTION_TO_MM_UM: f64 = 0.1 * 1e-6;
+
+/// Where the image is solved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReconstructionSpace {
+    /// Every voxel some channel is sensitive to.
+    #[default]
+    Voxel,
+    /// The cortex vertices; each voxel near the cortex takes the mean of
+    /// the vertices around it and the rest of the head is held at zero.
+    Vertex,
+}
+
+/// Which values of the active block are reconstructed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ReconstructionInput {
+    /// The sample nearest `time`, s.
+    Timepoint { time: f64 },
+    /// The mean of the samples from `start` to `end`, s.
+    Range { start: f64, end: f64 },
+    /// One (HbO, HbR) value per channel id, such as GLM betas.
+    Betas { values: BTreeMap<usize, [f64; 2]> },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ReconstructionOptions {
+    pub space: ReconstructionSpace,
+    pub regularization: Regularization,
+    /// β of spatially variant regularisation, relative to the largest
+    /// diagonal element of AᵀA; `None` for plain Tikhonov.
+    pub spatial: Option<f64>,
+    /// Radius of the voxels around each cortex vertex, mm.
+    pub radius: f64,
+}
+
+impl Default for ReconstructionOptions {
+    fn default() -> Self {
+        Self {
+            space: ReconstructionSpace::Voxel,
+            regularization: Regularization::LCurve,
+            spatial: None,
+            radius: 3.0,
+        }
+    }
+}
+
+/// ΔHbO and ΔHbR per vertex of the grey-matter mesh, with the mesh to
+/// draw them on.
+#[derive(Serialize, Clone, Debug)]
+pub struct CortexImage {
+    /// xyz per vertex, mm.
+    pub positions: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub hbo: Vec<f32>,
+    pub hbr: Vec<f32>,
+    /// Largest magnitude over both, for a symmetric colour scale.
+    pub max: f32,
+    /// Of the values; µM from optical density, else the block's unit.
+    pub unit: Option<String>,
+    /// The regularisation used for HbO and HbR, relative to the largest
+    /// eigenvalue.
+    pub alpha: [f64; 2],
+    /// Channels that entered the reconstruction.
+    pub channels: usize,
+}
+
+/// One value per measurement of a channel, from the recording or given.
+enum ChannelValues {
+    /// ΔOD at a wavelength in nm, per (source, detector).
+    OpticalDensity(Vec<(String, String, f64, f64)>),
+    /// (ΔHbO, ΔHbR) per (source, detector).
+    Hemoglobin(Vec<(String, String, [f64; 2])>, Option<String>),
+}
+
+/// The value of `data` at `input`: the nearest sample or the mean of the
+/// finite samples in the range.
+fn sample(time: &[f64], data: &[f64], input: &ReconstructionInput) -> f64 {
+    match input {
+        ReconstructionInput::Timepoint { time: t } => {
+            let nearest = time
+                .iter()
+                .enumerate()
+                .min_by(|a, b| (a.1 - t).abs().total_cmp(&(b.1 - t).abs()))
+                .map(|(i, _)| i);
+            nearest
+                .and_then(|i| data.get(i))
+                .copied()
+                .unwrap_or(f64::NAN)
+        }
+        ReconstructionInput::Range { start, end } => {
+            let (sum, n) = time
+                .iter()
+                .zip(data)
+                .filter(|(t, v)| **t >= *start && **t <= *end && v.is_finite())
+                .fold((0.0, 0usize), |(s, n), (_, v)| (s + v, n + 1));
+            if n > 0 {
+                sum / n as f64
+            } else {
+                f64::NAN
+            }
+        }
+        ReconstructionInput::Betas { .. } => f64::NAN,
+    }
+}
+
+fn block_values(
+    entry: &NirsEntry,
+    block: &DataBlock,
+    input: &ReconstructionInput,
+) -> Result<ChannelValues, NWError> {
+    if let ReconstructionInput::Range { start, end } = input {
+        if !block.time.iter().any(|t| t >= start && t <= end) {
+            return Err(NWError::InvalidInput(format!(
+                "no samples between {start} and {end} s"
+            )));
+        }
+    }
+    let view = NirsView::new(entry);
+    let name = |list: &[Optode], i: usize| {
+        i.checked_sub(1)
+            .and_then(|i| list.get(i))
+            .map(|o| o.name.clone())
+    };
+    let mut od = Vec::new();
+    let mut hb: BTreeMap<(String, String), [f64; 2]> = BTreeMap::new();
+    let mut unit = None;
+    for m in &block.measurements {
+        let (Some(source), Some(detector)) = (
+            name(&entry.probe.sources, m.source_index),
+            name(&entry.probe.detectors, m.detector_index),
+        ) else {
+            continue;
+        };
+        if m.data_type != 99999 {
+            return Err(NWError::InvalidInput(
+                "reconstruction needs optical density or haemoglobin data".into(),
+            ));
+        }
+        let value = sample(&block.time, &m.data, input);
+        if let Some(index) = m.wavelength_index {
+            let wavelength = m
+                .wavelength_actual
+                .or_else(|| view.wavelength_nm(index))
+                .ok_or_else(|| {
+                    NWError::InvalidInput(format!(
+                        "{source}-{detector}: wavelength index out of range"
+                    ))
+                })?;
+            od.push((source, detector, wavelength, value));
+            continue;
+        }
+        let slot = match view.signal_kind(m) {
+            SignalKind::Hemoglobin(HemoType::HbO) => 0,
+            SignalKind::Hemoglobin(HemoType::HbR) => 1,
+            _ => continue,
+        };
+        unit = unit.or_else(|| m.data_unit.clone());
+        hb.entry((source, detector)).or_insert([f64::NAN; 2])[slot] = value;
+    }
+    match (od.is_empty(), hb.is_empty()) {
+        (false, true) => Ok(ChannelValues::OpticalDensity(od)),
+        (true, false) => Ok(ChannelValues::Hemoglobin(
+            hb.into_iter().map(|((s, d), v)| (s, d, v)).collect(),
+            unit,
+        )),
+        _ => Err(NWError::InvalidInput(
+            "the block has no optical density or haemoglobin channels".into(),
+        )),
+    }
+}
+
+/// The unknowns of the chosen space and each channel's sensitivity over
+/// them, with the neighbourhoods that carry voxels onto the cortex.
+struct Space {
+    unknowns: usize,
+    rows: Vec<SparseRow>,
+    /// Per cortex vertex: its unknown (vertex space) or the unknowns
+    /// averaged onto it and their count (voxel space).
+    vertices: Vec<(Vec<usize>, usize)>,
+}
+
+fn build_space(
+    sensitivity: &Sensitivity,
+    channels: &[&ChannelSensitivity],
+    cortex: &Mesh,
+    options: &ReconstructionOptions,
+) -> Result<Space, NWError> {
+    let mut voxels: Vec<u32> = channels
+        .iter()
+        .flat_map(|ch| ch.voxels.iter().copied())
+        .collect();
+    voxels.sort_unstable();
+    voxels.dedup();
+    let columns: HashMap<u32, usize> = voxels.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+    let neighbourhoods = vertex_neighbourhoods(
+        sensitivity.dims,
+        &sensitivity.vox2ras,
+        cortex,
+        options.radius,
+        &columns,
+    )?;
+    let voxel_rows = channels.iter().map(|ch| SparseRow {
+        columns: ch.voxels.iter().map(|v| columns[v] as u32).collect(),
+        values: ch.values.iter().map(|v| *v as f64).collect(),
+    });
+
+    match options.space {
+        ReconstructionSpace::Voxel => Ok(Space {
+            unknowns: voxels.len(),
+            rows: voxel_rows.collect(),
+            vertices: neighbourhoods
+                .into_iter()
+                .map(|n| (n.columns, n.count))
+                .collect(),
+        }),
+        ReconstructionSpace::Vertex => {
+            // Unknowns are the vertices near any sensitive voxel; each voxel
+            // is shared equally among the vertices that reach it.
+            let mut unknown_of = vec![usize::MAX; neighbourhoods.len()];
+            let mut claims: Vec<Vec<u32>> = vec![Vec::new(); voxels.len()];
+            let mut unknowns = 0;
+            for (vertex, n) in neighbourhoods.iter().enumerate() {
+                if n.columns.is_empty() {
+                    continue;
+                }
+                unknown_of[vertex] = unknowns;
+                for c in &n.columns {
+                    claims[*c].push(unknowns as u32);
+                }
+                unknowns += 1;
+            }
+            let mut dense = vec![0.0; unknowns];
+            let rows = voxel_rows
+                .map(|row| {
+                    let mut touched: Vec<u32> = Vec::new();
+                    for (c, v) in row.columns.iter().zip(&row.values) {
+                        let owners = &claims[*c as usize];
+                        for u in owners {
+                            if dense[*u as usize] == 0.0 {
+                                touched.push(*u);
+                            }
+                            dense[*u as usize] += v / owners.len() as f64;
+                        }
+                    }
+                    touched.sort_unstable();
+                    let values = touched
+                        .iter()
+                        .map(|u| std::mem::take(&mut dense[*u as usize]))
+                        .collect();
+                    SparseRow {
+                        columns: touched,
+                        values,
+                    }
+                })
+                .collect();
+            Ok(Space {
+                unknowns,
+                rows,
+                vertices: unknown_of
+                    .into_iter()
+                    .map(|u| {
+                        if u == usize::MAX {
+                            (Vec::new(), 0)
+                        } else {
+                            (vec![u], 1)
+                        }
+                    })
+                    .collect(),
+            })
+        }
+    }
+}
+
+/// The row of `ch` in `channels`, appending it if new.
+fn row_of<'a>(channels: &mut Vec<&'a ChannelSensitivity>, ch: &'a ChannelSensitivity) -> usize {
+    match channels.iter().position(|c| c.channel == ch.channel) {
+        Some(i) => i,
+        None => {
+            channels.push(ch);
+            channels.len() - 1
+        }
+    }
+}
+
+/// Each vertex's value of the image `x`, from its entry in
+/// [`Space::vertices`].
+fn to_vertices(vertices: &[(Vec<usize>, usize)], x: &[f64]) -> Vec<f32> {
+    vertices
+        .iter()
+        .map(|(columns, count)| {
+            if *count > 0 {
+                (columns.iter().map(|c| x[*c]).sum::<f64>() / *count as f64) as f32
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Reconstruct ΔHbO and ΔHbR from `entry`'s block `block` (or the given
+/// betas) and show them on `cortex`.
+///
+/// Optical density is solved for both chromophores at once, each
+/// wavelength's rows carrying the extinction coefficients. Concentrations
+/// are already per channel, so each is modelled as the mean over the
+/// channel's sensitivity (its row divided by the mean path length) and the
+/// two are solved separately. The same sensitivity serves every
+/// wavelength.
+pub fn reconstruct(
+    sensitivity: &Sensitivity,
+    entry: &NirsEntry,
+    block: &DataBlock,
+    input: &ReconstructionInput,
+    cortex: &Mesh,
+    options: &ReconstructionOptions,
+) -> Result<CortexImage, NWError> {
+    let values = match input {
+        ReconstructionInput::Betas { values } => ChannelValues::Hemoglobin(
+            sensitivity
+                .channels
+                .iter()
+                .filter_map(|ch| {
+                    let v = values.get(&ch.channel)?;
+                    Some((ch.source.clone(), ch.detector.clone(), *v))
+                })
+                .collect(),
+            None,
+        ),
+        _ => block_values(entry, block, input)?,
+    };
+    let by_pair: HashMap<(&str, &str), &ChannelSensitivity> = sensitivity
+        .channels
+        .iter()
+        .map(|ch| ((ch.source.as_str(), ch.detector.as_str()), ch))
+        .collect();
+
+    // The channels with finite data and a sensitivity, in data order.
+    let mut channels: Vec<&ChannelSensitivity> = Vec::new();
+    let mut model = Vec::new();
+    let mut data: Vec<[f64; 2]> = Vec::new();
+    let components = match &values {
+        ChannelValues::OpticalDensity(od) => {
+            for (source, detector, wavelength, value) in od {
+                let Some(ch) = by_pair.get(&(source.as_str(), detector.as_str())) else {
+                    continue;
+                };
+                if !value.is_finite() || ch.voxels.is_empty() {
+                    continue;
+                }
+                let (eo, er) = extinction(*wavelength).ok_or_else(|| {
+                    NWError::InvalidInput(format!(
+                        "no extinction coefficients for {wavelength} nm (650–950 nm supported)"
+                    ))
+                })?;
+                model.push(ModelRow {
+                    row: row_of(&mut channels, ch),
+                    coefficients: vec![eo * EXTINCTION_TO_MM_UM, er * EXTINCTION_TO_MM_UM],
+                });
+                data.push([*value, f64::NAN]);
+            }
+            2
+        }
+        ChannelValues::Hemoglobin(hb, _) => {
+            for (source, detector, value) in hb {
+                let Some(ch) = by_pair.get(&(source.as_str(), detector.as_str())) else {
+                    continue;
+                };
+                if !value.iter().all(|v| v.is_finite()) || ch.voxels.is_empty() {
+                    continue;
+                }
+                let path: f64 = ch.values.iter().map(|v| *v as f64).sum();
+                if path <= 0.0 {
+                    continue;
+                }
+                model.push(ModelRow {
+                    row: row_of(&mut channels, ch),
+                    coefficients: vec![1.0 / path],
+                });
+                data.push(*value);
+            }
+            1
+        }
+    };
+    if model.is_empty() {
+        return Err(NWError::InvalidInput(
+            "no channel has both data and a sensitivity".into(),
+        ));
+    }
+
+    let Space {
+        unknowns,
+        rows,
+        vertices,
+    } = build_space(sensitivity, &channels, cortex, options)?;
+    let used = channels.len();
+    let solver = Tikhonov::new(rows, model, unknowns, options.spatial)?;
+    let column = |i: usize| DVector::from_iterator(data.len(), data.iter().map(|d| d[i]));
+    let (hbo, hbr, alpha) = if components == 2 {
+        let y = column(0);
+        let alpha = solver.select(&y, options.regularization)?;
+        let mut x = solver.solve(&y, alpha)?;
+        let hbr = x.pop().unwrap_or_default();
+        let hbo = x.pop().unwrap_or_default();
+        (hbo, hbr, [alpha; 2])
+    } else {
+        let (yo, yr) = (column(0), column(1));
+        let (ao, ar) = (
+            solver.select(&yo, options.regularization)?,
+            solver.select(&yr, options.regularization)?,
+        );
+        let hbo = solver.solve(&yo, ao)?.swap_remove(0);
+        let hbr = solver.solve(&yr, ar)?.swap_remove(0);
+        (hbo, hbr, [ao, ar])
+    };
+
+    let hbo = to_vertices(&vertices, &hbo);
+    let hbr = to_vertices(&vertices, &hbr);
+    let max = hbo
+        .iter()
+        .chain(&hbr)
+        .map(|v| v.abs())
+        .fold(0.0f32, f32::max);
+    Ok(CortexImage {
+        positions: vertex_positions(cortex),
+        indices: cortex.geometry.indices.clone(),
+        hbo,
+        hbr,
+        max,
+        unit: match values {
+            ChannelValues::OpticalDensity(_) => Some("uM".to_string()),
+            ChannelValues::Hemoglobin(_, unit) => unit,
+        },
+        alpha,
+        channels: used,
+    })
+}
@@ -0,0 +1,365 @@
+use crate::domain::anatomy::NamedPoint;
+use crate::domain::error::NWError;
+use crate::domain::scene::SceneObject;
+use crate::domain::snirf::{MetadataTag, NirsEntry};
+use crate::io::mesh_importer::load_mesh;
+use crate::services::hemoglobin_service::length_unit_to_cm;
+use nalgebra::{DMatrix, Matrix3, Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Degrees of freedom of the probe-to-head transform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransformModel {
+    /// Rotation and translation.
+    Rigid,
+    /// Rotation, translation and one uniform scale.
+    #[default]
+    Similarity,
+    /// Any linear map plus translation (needs 4 non-coplanar landmarks).
+    Affine,
+}
+
+/// Distance between a probe landmark, once transformed, and its target.
+#[derive(Clone, Debug, Serialize)]
+pub struct LandmarkResidual {
+    pub label: String,
+    /// Millimetres.
+    pub error: f64,
+}
+
+/// A solved probe-to-head transform.
+#[derive(Clone, Debug, Serialize)]
+pub struct Registration {
+    pub model: TransformModel,
+    /// Row-major 4×4 matrix from probe millimetres to head millimetres.
+    pub matrix: [[f64; 4]; 4],
+    pub residuals: Vec<LandmarkResidual>,
+    /// Root mean square of the residuals, mm.
+    pub rms: f64,
+    /// Probe landmarks without a target, and targets without a probe
+    /// landmark; neither takes part in the fit.
+    pub unmatched: Vec<String>,
+}
+
+/// Landmark names compared without case or punctuation, with the usual
+/// synonyms of the fiducials folded together ("Nasion" = "Nz", "A1" =
+/// "LPA", …).
+pub fn canonical_label(label: &str) -> String {
+    let key: String = label
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match key.as_str() {
+        "nz" | "na" | "nas" | "nasion" => "nz".into(),
+        "iz" | "in" | "ini" | "inion" => "iz".into(),
+        "lpa" | "al" | "a1" | "leftear" | "leftpreauricular" => "lpa".into(),
+        "rpa" | "ar" | "a2" | "rightear" | "rightpreauricular" => "rpa".into(),
+        _ => key,
+    }
+}
+
+/// Millimetres per unit of the entry's `LengthUnit` tag.
+pub(crate) fn mm_per_unit(metadata: &[MetadataTag]) -> Result<f64, NWError> {
+    Ok(length_unit_to_cm(metadata)? * 10.0)
+}
+
+/// The probe's landmarks that have a 3D position, in millimetres.
+pub fn probe_landmarks(entry: &NirsEntry) -> Result<Vec<NamedPoint>, NWError> {
+    let mm = mm_per_unit(&entry.metadata)?;
+    Ok(entry
+        .probe
+        .landmarks
+        .iter()
+        .flatten()
+        .filter_map(|l| {
+            let [x, y, z] = l.pos_3d?;
+            Some(NamedPoint {
+                label: l.label.clone(),
+                position: [x * mm, y * mm, z * mm],
+            })
+        })
+        .collect())
+}
+
+fn centroid(points: &[Point3<f64>]) -> Vector3<f64> {
+    points.iter().map(|p| p.coords).sum::<Vector3<f64>>() / points.len() as f64
+}
+
+/// Least-squares rigid or similarity transform taking `from` onto `to`
+/// (Umeyama, 1991).
+fn solve_orthogonal(
+    from: &[Point3<f64>],
+    to: &[Point3<f64>],
+    with_scale: bool,
+) -> Result<Matrix4<f64>, NWError> {
+    let (mu_from, mu_to) = (centroid(from), centroid(to));
+    let mut cov = Matrix3::zeros();
+    let mut spread = Matrix3::zeros();
+    let mut variance = 0.0;
+    for (p, q) in from.iter().zip(to) {
+        let (a, b) = (p.coords - mu_from, q.coords - mu_to);
+        cov += b * a.transpose();
+        spread += a * a.transpose();
+        variance += a.norm_squared();
+    }
+    let n = from.len() as f64;
+    cov /= n;
+    variance /= n;
+
+    // Three points on a line leave the rotation about that line free.
+    let extent = spread.symmetric_eigenvalues();
+    let largest = extent.max();
+    if largest <= 0.0 || extent.iter().filter(|&&e| e > largest * 1e-9).count() < 2 {
+        return Err(NWError::InvalidInput(format!(
+            "a rigid or similarity fit needs 3 landmarks not on one line ({} given)",
+            from.len()
+        )));
+    }
+
+    let svd = cov.svd(true, true);
+    let (u, v_t) = (svd.u.unwrap(), svd.v_t.unwrap());
+    // Flip the weakest axis rather than return a reflection.
+    let mut s = Matrix3::identity();
+    if (u * v_t).determinant() < 0.0 {
+        s[(2, 2)] = -1.0;
+    }
+    let rotation = u * s * v_t;
+    let scale = if with_scale {
+        (Matrix3::from_diagonal(&svd.singular_values) * s).trace() / variance
+    } else {
+        1.0
+    };
+    let translation = mu_to - rotation * mu_from * scale;
+
+    let mut m = Matrix4::identity();
+    m.fixed_view_mut::<3, 3>(0, 0)
+        .copy_from(&(rotation * scale));
+    m.fixed_view_mut::<3, 1>(0, 3).copy_from(&translation);
+    Ok(m)
+}
+
+/// Least-squares affine transform taking `from` onto `to`.
+fn solve_affine(from: &[Point3<f64>], to: &[Point3<f64>]) -> Result<Matrix4<f64>, NWError> {
+    let n = from.len();
+    let degenerate = || {
+        NWError::InvalidInput(format!(
+            "an affine fit needs 4 landmarks not in one plane ({n} given)"
+        ))
+    };
+    if n < 4 {
+        return Err(degenerate());
+    }
+    let design = DMatrix::from_fn(n, 4, |r, c| if c < 3 { from[r][c] } else { 1.0 });
+    let target = DMatrix::from_fn(n, 3, |r, c| to[r][c]);
+    let svd = design.svd(true, true);
+    let largest = svd.singular_values.max();
+    if svd.singular_values.iter().any(|&s| s <= largest * 1e-9) {
+        return Err(degenerate());
+    }
+    let solution = svd
+        .solve(&target, 1e-12)
+        .map_err(|e| NWError::Internal(e.to_string()))?;
+    let mut m = Matrix4::identity();
+    for r in 0..3 {
+        for c in 0..4 {
+            m[(r, c)] = solution[(c, r)];
+        }
+    }
+    Ok(m)
+}
+
+/// Fit `model` to the probe landmarks that have a target of the same
+/// (canonical) label. Targets are head coordinates in millimetres, e.g.
+/// picked on or detected from the scalp mesh.
+pub fn register(
+    entry: &NirsEntry,
+    targets: &[NamedPoint],
+    model: TransformModel,
+) -> Result<Registration, NWError> {
+    let landmarks = probe_landmarks(entry)?;
+    if landmarks.is_empty() {
+        return Err(NWError::InvalidInput(
+            "the probe has no landmarks with 3D positions".into(),
+        ));
+    }
+    let mut labels = Vec::new();
+    let (mut from, mut to) = (Vec::new(), Vec::new());
+    let mut unmatched = Vec::new();
+    for l in &landmarks {
+        let key = canonical_label(&l.label);
+        match targets.iter().find(|t| canonical_label(&t.label) == key) {
+            Some(t) => {
+                labels.push(l.label.clone());
+                from.push(Point3::from(l.position));
+                to.push(Point3::from(t.position));
+            }
+            None => unmatched.push(l.label.clone()),
+        }
+    }
+    unmatched.extend(
+        targets
+            .iter()
+            .filter(|t| {
+                let key = canonical_label(&t.label);
+                !landmarks.iter().any(|l| canonical_label(&l.label) == key)
+            })
+            .map(|t| t.label.clone()),
+    );
+
+    let m = match model {
+        TransformModel::Rigid => solve_orthogonal(&from, &to, false)?,
+        TransformModel::Similarity => solve_orthogonal(&from, &to, true)?,
+        TransformModel::Affine => solve_affine(&from, &to)?,
+    };
+    let residuals: Vec<LandmarkResidual> = labels
+        .into_iter()
+        .zip(from.iter().zip(&to))
+        .map(|(label, (p, q))| LandmarkResidual {
+            label,
+            error: (m.transform_point(p) - q).norm(),
+        })
+        .collect();
+    let rms =
+        (residuals.iter().map(|r| r.error * r.error).sum::<f64>() / residuals.len() as f64).sqrt();
+    Ok(Registration {
+        model,
+        matrix: std::array::from_fn(|r| std::array::from_fn(|c| m[(r, c)])),
+        residuals,
+        rms,
+        unmatched,
+    })
+}
+
+/// Move the probe's sources, detectors and landmarks into head space. The
+/// positions end up in millimetres, whatever the `LengthUnit` was; the 2D
+/// layout is only rescaled.
+pub fn apply_registration(
+    entry: &mut NirsEntry,
+    registration: &Registration,
+) -> Result<(), NWError> {
+    let mm = mm_per_unit(&entry.metadata)?;
+    let m = Matrix4::from_fn(|r, c| registration.matrix[r][c]);
+    let to_head = |p: Vector3<f64>| m.transform_point(&Point3::from(p * mm)).coords;
+
+    let probe = &mut entry.probe;
+    for optode in probe.sources.iter_mut().chain(probe.detectors.iter_mut()) {
+        optode.pos_3d = to_head(optode.pos_3d);
+        optode.pos_2d *= mm;
+    }
+    for landmark in probe.landmarks.iter_mut().flatten() {
+        if let Some(p) = landmark.pos_3d {
+            landmark.pos_3d = Some(to_head(Vector3::from(p)).into());
+        }
+        if let Some(p) = &mut landmark.pos_2d {
+            p.iter_mut().for_each(|v| *v *= mm);
+        }
+    }
+    probe.coordinate_system = Some("Other".into());
+    probe.coordinate_system_description = Some(format!(
+        "Subject head space, registered from landmarks ({:?}, RMS {:.1} mm)",
+        registration.model, registration.rms
+    ));
+    match entry.metadata.iter_mut().find(|t| t.name == "LengthUnit") {
+        Some(tag) => tag.value = "mm".into(),
+        None => entry.metadata.push(MetadataTag {
+            name: "LengthUnit".into(),
+            value: "mm".into(),
+        }),
+    }
+    Ok(())
+}
+
+/// The point with the smallest `key`.
+fn lowest<'a>(
+    points: impl Iterator<Item = &'a Point3<f64>>,
+    key: impl Fn(&Point3<f64>) -> f64,
+) -> Option<Point3<f64>> {
+    points.min_by(|a, b| key(a).total_cmp(&key(b))).copied()
+}
+
+/// Rough fiducials on a scalp surface in RAS millimetres (x right,
+/// y anterior, z superior), as FreeSurfer writes them:
+/// - Nz: the deepest point of the midline profile above the nose tip;
+/// - Iz: the most posterior midline point at about the nasion's height;
+/// - LPA/RPA: the outermost points at ear level, between Nz and Iz;
+/// - Cz: the top of the head midway between the other four.
+///
+/// Ears and noses vary, so these are a starting guess to correct by hand.
+pub fn detect_scalp_landmarks(vertices: &[Point3<f64>]) -> Result<Vec<NamedPoint>, NWError> {
+    if vertices.is_empty() {
+        return Err(NWError::InvalidInput(
+            "the scalp mesh has no vertices".into(),
+        ));
+    }
+    let centre = centroid(vertices);
+    let midline: Vec<&Point3<f64>> = vertices
+        .iter()
+        .filter(|p| (p.x - centre.x).abs() < 5.0)
+        .collect();
+    let not_found = |what: &str| NWError::InvalidInput(format!("no {what} found on the scalp"));
+
+    // Front profile: the most anterior vertex per 2 mm of height, from the
+    // nose tip up 40 mm; the nasion is where it dips furthest back.
+    let tip = lowest(midline.iter().copied().filter(|p| p.y > centre.y), |p| -p.y)
+        .ok_or_else(|| not_found("nose tip"))?;
+    let mut profile: Vec<Point3<f64>> = Vec::new();
+    for p in midline.iter().filter(|p| p.z > tip.z && p.z < tip.z + 40.0) {
+        let bin = ((p.z - tip.z) / 2.0) as usize;
+        if profile.len() <= bin {
+            profile.resize(bin + 1, Point3::new(0.0, f64::NEG_INFINITY, 0.0));
+        }
+        if p.y > profile[bin].y {
+            profile[bin] = **p;
+        }
+    }
+    let nz = lowest(profile.iter().filter(|p| p.y.is_finite()), |p| p.y)
+        .ok_or_else(|| not_found("nasion"))?;
+
+    let iz = lowest(
+        midline
+            .iter()
+            .copied()
+            .filter(|p| p.y < centre.y && (p.z - nz.z).abs() < 20.0),
+        |p| p.y,
+    )
+    .ok_or_else(|| not_found("inion"))?;
+
+    let mid_y = (nz.y + iz.y) / 2.0;
+    let ear_level = vertices
+        .iter()
+        .filter(|p| p.z > nz.z - 40.0 && p.z < nz.z && (p.y - mid_y).abs() < 30.0);
+    let lpa = lowest(ear_level.clone(), |p| p.x).ok_or_else(|| not_found("left ear"))?;
+    let rpa = lowest(ear_level, |p| -p.x).ok_or_else(|| not_found("right ear"))?;
+
+    let mid_x = (lpa.x + rpa.x) / 2.0;
+    let cz = lowest(
+        vertices
+            .iter()
+            .filter(|p| (p.x - mid_x).abs() < 5.0 && (p.y - mid_y).abs() < 5.0),
+        |p| -p.z,
+    )
+    .or_else(|| lowest(vertices.iter(), |p| -p.z))
+    .ok_or_else(|| not_found("vertex"))?;
+
+    Ok([
+        ("Nz", nz),
+        ("Iz", iz),
+        ("LPA", lpa),
+        ("RPA", rpa),
+        ("Cz", cz),
+    ]
+    .into_iter()
+    .map(|(label, p)| NamedPoint {
+        label: label.into(),
+        position: [p.x, p.y, p.z],
+    })
+    .collect())
+}
+
+/// Detect the fiducials on the scalp mesh stored at `path`.
+pub fn detect_landmarks_on_mesh(path: &str) -> Result<Vec<NamedPoint>, NWError> {
+    let mesh = load_mesh(path).map_err(NWError::Parse)?;
+    detect_scalp_landmarks(&SceneObject::new("scalp", mesh).world_positions())
+}
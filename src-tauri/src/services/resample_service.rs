@@ -0,0 +1,218 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{AuxiliaryData, DataBlock};
+use crate::domain::timebase::TimeBase;
+use crate::dsp::{interpolate, rational_approximation, resample_poly, uniform_grid, Interpolation};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Largest denominator considered when turning a rate ratio into `up / down`.
+const MAX_RATIO_DENOMINATOR: usize = 100;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleMethod {
+    #[default]
+    Linear,
+    Cubic,
+    /// Linear regridding at the native median rate, then a polyphase
+    /// anti-aliasing FIR for the rate change.
+    Polyphase,
+}
+
+/// Indices of the samples to keep so that time is strictly increasing:
+/// samples are ordered by time and later duplicates of a timestamp dropped.
+fn monotonic_order(time: &[f64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..time.len()).filter(|&i| time[i].is_finite()).collect();
+    order.sort_by(|&a, &b| time[a].total_cmp(&time[b]));
+    order.dedup_by(|b, a| time[*b] <= time[*a]);
+    order
+}
+
+/// Produce a uniformly sampled copy of `block` at `target_rate` (default: the
+/// block's median rate). Gaps are bridged by the chosen interpolation and
+/// out-of-order or duplicate timestamps are dropped. Measurement metadata is
+/// preserved; a measurement whose length differs from the time base is an
+/// error.
+pub fn regularize_block(
+    block: &DataBlock,
+    target_rate: Option<f64>,
+    method: ResampleMethod,
+) -> Result<DataBlock, NWError> {
+    let order = monotonic_order(&block.time);
+    if order.len() < 2 {
+        return Err(NWError::InvalidInput(
+            "time base needs at least two distinct samples".into(),
+        ));
+    }
+    let time: Vec<f64> = order.iter().map(|&i| block.time[i]).collect();
+    let native_rate = TimeBase::analyze(&time).sampling_rate;
+    let target_rate = target_rate.unwrap_or(native_rate);
+    if !(target_rate > 0.0 && target_rate.is_finite()) {
+        return Err(NWError::InvalidInput(format!(
+            "invalid target sampling rate {target_rate}"
+        )));
+    }
+    let (start, end) = (time[0], time[time.len() - 1]);
+
+    let native_grid = uniform_grid(start, end, native_rate);
+    let (up, down) = rational_approximation(target_rate / native_rate, MAX_RATIO_DENOMINATOR);
+    let new_time: Vec<f64> = match method {
+        ResampleMethod::Linear | ResampleMethod::Cubic => uniform_grid(start, end, target_rate),
+        ResampleMethod::Polyphase => {
+            let effective_rate = native_rate * up as f64 / down as f64;
+            (0..(native_grid.len() * up).div_ceil(down))
+                .map(|i| start + i as f64 / effective_rate)
+                .collect()
+        }
+    };
+    let resample = |x: &[f64]| -> Vec<f64> {
+        match method {
+            ResampleMethod::Linear => interpolate(&time, x, &new_time, Interpolation::Linear),
+            ResampleMethod::Cubic => interpolate(&time, x, &new_time, Interpolation::Cubic),
+            ResampleMethod::Polyphase => {
+                let regular = interpolate(&time, x, &native_grid, Interpolation::Linear);
+                resample_poly(&regular, up, down)
+            }
+        }
+    };
+
+    let measurements = block
+        .measurements
+        .iter()
+        .map(|m| {
+            if m.data.len() != block.time.len() {
+                return Err(NWError::InvalidInput(format!(
+                    "S{}-D{} has {} samples for {} time points",
+                    m.source_index,
+                    m.detector_index,
+                    m.data.len(),
+                    block.time.len()
+                )));
+            }
+            let data: Vec<f64> = order.iter().map(|&i| m.data[i]).collect();
+            Ok(m.with_data(resample(&data)))
+        })
+        .collect::<Result<Vec<_>, NWError>>()?;
+
+    info!(
+        "Regularized block: {} → {} samples, {:.3} Hz → {:.3} Hz ({:?})",
+        block.time.len(),
+        new_time.len(),
+        native_rate,
+        target_rate,
+        method
+    );
+
+    Ok(DataBlock {
+        time: new_time,
+        measurements,
+    })
+}
+
+/// A uniformly sampled version of one signal for DSP, together with its rate.
+/// Uniform inputs are returned unchanged; irregular ones are linearly
+/// interpolated onto a grid at the median rate. Time points past the end of
+/// `data` are skipped.
+pub fn uniform_signal(time: &[f64], data: &[f64]) -> (Vec<f64>, f64) {
+    let time_base = TimeBase::analyze(time);
+    if time_base.is_uniform {
+        return (data.to_vec(), time_base.sampling_rate);
+    }
+    let (t, x): (Vec<f64>, Vec<f64>) = monotonic_order(time)
+        .into_iter()
+        .filter_map(|i| Some((time[i], *data.get(i)?)))
+        .unzip();
+    if t.len() < 2 {
+        return (data.to_vec(), time_base.sampling_rate);
+    }
+    let grid = uniform_grid(t[0], t[t.len() - 1], time_base.sampling_rate);
+    (
+        interpolate(&t, &x, &grid, Interpolation::Linear),
+        time_base.sampling_rate,
+    )
+}
+
+/// Change the sampling rate of a whole block to approximately `target_rate`
+/// using the polyphase anti-aliasing filter. The rate ratio is approximated as
+/// `up / down` with `down <= 100`; the block's time vector reflects the
+/// effective rate. Irregular blocks are regularised at their median rate first.
+pub fn resample_block(block: &DataBlock, target_rate: f64) -> Result<DataBlock, NWError> {
+    let time_base = TimeBase::analyze(&block.time);
+    if !time_base.is_uniform {
+        let regular = regularize_block(block, None, ResampleMethod::Linear)?;
+        return resample_block(&regular, target_rate);
+    }
+    let (up, down) = rate_factors(time_base.sampling_rate, target_rate)?;
+    let effective_rate = time_base.sampling_rate * up as f64 / down as f64;
+    let start = block.time.first().copied().unwrap_or(0.0);
+
+    let measurements: Vec<_> = block
+        .measurements
+        .iter()
+        .map(|m| m.with_data(resample_poly(&m.data, up, down)))
+        .collect();
+    let n_out = measurements
+        .first()
+        .map(|m| m.data.len())
+        .unwrap_or_else(|| (block.time.len() * up).div_ceil(down));
+    let time = (0..n_out)
+        .map(|i| start + i as f64 / effective_rate)
+        .collect();
+
+    info!(
+        "Resampled block: {:.3} Hz → {:.3} Hz (×{up}/{down}), {} → {} samples",
+        time_base.sampling_rate,
+        effective_rate,
+        block.time.len(),
+        n_out
+    );
+
+    Ok(DataBlock { time, measurements })
+}
+
+/// Resample an auxiliary signal to approximately `target_rate`, keeping its
+/// name, unit and time offset.
+pub fn resample_auxiliary(aux: &AuxiliaryData, target_rate: f64) -> Result<AuxiliaryData, NWError> {
+    let (time, data) = if TimeBase::analyze(&aux.time).is_uniform {
+        (aux.time.clone(), aux.data.clone())
+    } else {
+        let (data, rate) = uniform_signal(&aux.time, &aux.data);
+        let start = aux.time.iter().copied().fold(f64::INFINITY, f64::min);
+        let time = (0..data.len()).map(|i| start + i as f64 / rate).collect();
+        (time, data)
+    };
+    let rate = TimeBase::analyze(&time).sampling_rate;
+    let (up, down) = rate_factors(rate, target_rate)?;
+    let effective_rate = rate * up as f64 / down as f64;
+    let start = time.first().copied().unwrap_or(0.0);
+    let data = resample_poly(&data, up, down);
+
+    Ok(AuxiliaryData {
+        name: aux.name.clone(),
+        unit: aux.unit.clone(),
+        time: (0..data.len())
+            .map(|i| start + i as f64 / effective_rate)
+            .collect(),
+        data,
+        time_offset: aux.time_offset,
+        resampled_from: aux.resampled_from.clone(),
+    })
+}
+
+/// `(up, down)` such that `from_rate * up / down ≈ to_rate`.
+pub fn rate_factors(from_rate: f64, to_rate: f64) -> Result<(usize, usize), NWError> {
+    if !(from_rate > 0.0 && from_rate.is_finite()) {
+        return Err(NWError::InvalidInput(format!(
+            "cannot resample from sampling rate {from_rate}"
+        )));
+    }
+    if !(to_rate > 0.0 && to_rate.is_finite()) {
+        return Err(NWError::InvalidInput(format!(
+            "invalid target sampling rate {to_rate}"
+        )));
+    }
+    Ok(rational_approximation(
+        to_rate / from_rate,
+        MAX_RATIO_DENOMINATOR,
+    ))
+}
@@ -0,0 +1,305 @@
+use crate::domain::error::NWError;
+use crate::domain::mesh::Mesh;
+use crate::domain::optics::{ChannelSensitivity, Fluence, Sensitivity, Simulation};
+use crate::domain::probe::OptodeLayout;
+use nalgebra::{Matrix4, Point3};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// Voxels below this share of a channel's peak sensitivity are dropped.
+pub const DEFAULT_THRESHOLD: f64 = 1e-4;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChannelSensitivitySummary {
+    pub channel: usize,
+    pub source: String,
+    pub detector: String,
+    /// Voxels kept.
+    pub voxels: usize,
+    /// Sum over the voxels: the mean path length, mm.
+    pub path_length: f64,
+    /// Largest voxel value, mm.
+    pub peak: f64,
+}
+
+/// Per-vertex sensitivity of the selected channels on the grey-matter
+/// mesh, with the mesh to draw it on.
+#[derive(Serialize, Clone, Debug)]
+pub struct CortexSensitivity {
+    /// xyz per vertex, mm.
+    pub positions: Vec<f32>,
+    pub indices: Vec<u32>,
+    /// Summed over the channels, mm per voxel.
+    pub values: Vec<f32>,
+    pub max: f32,
+}
+
+fn voxel_volume(vox2ras: &Matrix4<f64>) -> f64 {
+    (0..3)
+        .map(|c| vox2ras.fixed_view::<3, 1>(0, c).norm())
+        .product()
+}
+
+/// The sources and detectors the layout's channels use, by name.
+pub fn channel_optodes(layout: &OptodeLayout) -> Vec<String> {
+    let names: BTreeSet<String> = layout
+        .channels
+        .iter()
+        .flat_map(|ch| {
+            [
+                layout.sources[ch.source_idx].name.clone(),
+                layout.detectors[ch.detector_idx].name.clone(),
+            ]
+        })
+        .collect();
+    names.into_iter().collect()
+}
+
+fn offset(fluence: &Fluence, [i, j, k]: [usize; 3]) -> usize {
+    let [nx, ny, _] = fluence.dims;
+    i + j * nx + k * nx * ny
+}
+
+/// The Jacobian of one source-detector pair by the adjoint method: the
+/// detector's fluence stands for the adjoint field, so the sensitivity to
+/// absorption in voxel v is Φs(v)·Φd(v)·V / Φs(rd) (Rytov approximation).
+/// The measurement Φs(rd) is averaged with its reciprocal Φd(rs) to halve
+/// the noise. `phi_s` and `phi_d` are the optodes' [`Fluence::cw`] fields.
+/// Voxels under `threshold` × the peak are dropped.
+pub fn channel_sensitivity(
+    source: &Fluence,
+    phi_s: &[f32],
+    detector: &Fluence,
+    phi_d: &[f32],
+    threshold: f64,
+) -> Result<(Vec<u32>, Vec<f32>), NWError> {
+    if source.dims != detector.dims || source.gate_width <= 0.0 {
+        return Err(NWError::InvalidInput(
+            "the fluences were simulated on different volumes".into(),
+        ));
+    }
+    let measured = (phi_s[offset(source, detector.entry)] as f64
+        + phi_d[offset(detector, source.entry)] as f64)
+        / 2.0;
+    if measured <= 0.0 {
+        return Err(NWError::InvalidInput(
+            "no photons reached the detector; simulate more photons".into(),
+        ));
+    }
+    let scale = voxel_volume(&source.vox2ras) / measured;
+    let dense: Vec<f64> = phi_s
+        .iter()
+        .zip(phi_d)
+        .map(|(s, d)| *s as f64 * *d as f64 * scale)
+        .collect();
+    let cutoff = dense.iter().copied().fold(0.0, f64::max) * threshold;
+    Ok(dense
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| **v > cutoff)
+        .map(|(i, v)| (i as u32, *v as f32))
+        .unzip())
+}
+
+/// The sensitivity of every channel in `layout` from the optodes' fluence
+/// in `simulation`.
+pub fn compute_sensitivity(
+    layout: &OptodeLayout,
+    simulation: &Simulation,
+    threshold: f64,
+) -> Result<Sensitivity, NWError> {
+    let fluence = |name: &str| {
+        simulation
+            .fluence
+            .get(name)
+            .ok_or_else(|| NWError::InvalidInput(format!("{name} has not been simulated")))
+    };
+    let first = simulation.fluence.values().next().ok_or(NWError::NoData)?;
+    // Each optode's CW field, computed once for all the channels it is in.
+    let cw: HashMap<String, Vec<f32>> = channel_optodes(layout)
+        .into_iter()
+        .filter_map(|name| {
+            let field = simulation.fluence.get(&name)?.cw();
+            Some((name, field))
+        })
+        .collect();
+    let channels = layout
+        .channels
+        .iter()
+        .map(|ch| {
+            let source = &layout.sources[ch.source_idx].name;
+            let detector = &layout.detectors[ch.detector_idx].name;
+            let (voxels, values) = channel_sensitivity(
+                fluence(source)?,
+                &cw[source],
+                fluence(detector)?,
+                &cw[detector],
+                threshold,
+            )
+            .map_err(|e| match e {
+                NWError::InvalidInput(msg) => {
+                    NWError::InvalidInput(format!("{source}-{detector}: {msg}"))
+                }
+                other => other,
+            })?;
+            Ok(ChannelSensitivity {
+                channel: ch.id,
+                source: source.clone(),
+                detector: detector.clone(),
+                voxels,
+                values,
+            })
+        })
+        .collect::<Result<Vec<_>, NWError>>()?;
+    Ok(Sensitivity {
+        dims: first.dims,
+        vox2ras: first.vox2ras,
+        channels,
+    })
+}
+
+pub fn summarize(sensitivity: &Sensitivity) -> Vec<ChannelSensitivitySummary> {
+    sensitivity
+        .channels
+        .iter()
+        .map(|ch| ChannelSensitivitySummary {
+            channel: ch.channel,
+            source: ch.source.clone(),
+            detector: ch.detector.clone(),
+            voxels: ch.voxels.len(),
+            path_length: ch.values.iter().map(|v| *v as f64).sum(),
+            peak: ch.values.iter().copied().fold(0.0f32, f32::max) as f64,
+        })
+        .collect()
+}
+
+/// The voxels around one cortex vertex.
+pub(crate) struct Neighbourhood {
+    /// Of the voxels, those in the caller's column map, by column.
+    pub columns: Vec<usize>,
+    /// All of the voxels inside the volume.
+    pub count: usize,
+}
+
+/// For each vertex of `cortex`, its own voxel and the voxels whose centres
+/// lie within `radius` mm of it, as columns of `columns` (keyed by voxel
+/// offset x + y*nx + z*nx*ny).
+pub(crate) fn vertex_neighbourhoods(
+    dims: [usize; 3],
+    vox2ras: &Matrix4<f64>,
+    cortex: &Mesh,
+    radius: f64,
+    columns: &HashMap<u32, usize>,
+) -> Result<Vec<Neighbourhood>, NWError> {
+    let ras2vox = vox2ras
+        .try_inverse()
+        .ok_or_else(|| NWError::InvalidInput("the volume's vox2ras is singular".into()))?;
+    let [nx, ny, nz] = dims;
+    let size: [f64; 3] = std::array::from_fn(|c| vox2ras.fixed_view::<3, 1>(0, c).norm());
+    let reach: [i64; 3] = std::array::from_fn(|a| (radius / size[a]).ceil().max(0.0) as i64);
+    Ok(cortex
+        .geometry
+        .verts
+        .iter()
+        .map(|v| {
+            let p = Point3::from(v.position);
+            let at = ras2vox.transform_point(&p);
+            let centre: [i64; 3] = std::array::from_fn(|a| at[a].round() as i64);
+            let mut out = Neighbourhood {
+                columns: Vec::new(),
+                count: 0,
+            };
+            for k in centre[2] - reach[2]..=centre[2] + reach[2] {
+                for j in centre[1] - reach[1]..=centre[1] + reach[1] {
+                    for i in centre[0] - reach[0]..=centre[0] + reach[0] {
+                        if i < 0 || j < 0 || k < 0 {
+                            continue;
+                        }
+                        let (i, j, k) = (i as usize, j as usize, k as usize);
+                        if i >= nx || j >= ny || k >= nz {
+                            continue;
+                        }
+                        let centre_ras =
+                            vox2ras.transform_point(&Point3::new(i as f64, j as f64, k as f64));
+                        if [i as i64, j as i64, k as i64] != centre
+                            && (centre_ras - p).norm() > radius
+                        {
+                            continue;
+                        }
+                        if let Some(c) = columns.get(&((i + j * nx + k * nx * ny) as u32)) {
+                            out.columns.push(*c);
+                        }
+                        out.count += 1;
+                    }
+                }
+            }
+            out
+        })
+        .collect())
+}
+
+/// xyz per vertex of `mesh`, as the viewport takes them.
+pub(crate) fn vertex_positions(mesh: &Mesh) -> Vec<f32> {
+    mesh.geometry
+        .verts
+        .iter()
+        .flat_map(|v| {
+            [
+                v.position.x as f32,
+                v.position.y as f32,
+                v.position.z as f32,
+            ]
+        })
+        .collect()
+}
+
+/// Carry the summed sensitivity of `channels` onto the vertices of the
+/// grey-matter mesh: each vertex takes the mean of its own voxel and
+/// those within `radius` mm of it.
+pub fn cortex_sensitivity(
+    sensitivity: &Sensitivity,
+    cortex: &Mesh,
+    channels: &[usize],
+    radius: f64,
+) -> Result<CortexSensitivity, NWError> {
+    let mut columns: HashMap<u32, usize> = HashMap::new();
+    let mut summed: Vec<f32> = Vec::new();
+    for ch in sensitivity
+        .channels
+        .iter()
+        .filter(|ch| channels.contains(&ch.channel))
+    {
+        for (v, value) in ch.voxels.iter().zip(&ch.values) {
+            let next = summed.len();
+            let c = *columns.entry(*v).or_insert(next);
+            if c == next {
+                summed.push(0.0);
+            }
+            summed[c] += value;
+        }
+    }
+
+    let values: Vec<f32> = vertex_neighbourhoods(
+        sensitivity.dims,
+        &sensitivity.vox2ras,
+        cortex,
+        radius,
+        &columns,
+    )?
+    .iter()
+    .map(|n| {
+        if n.count > 0 {
+            n.columns.iter().map(|c| summed[*c]).sum::<f32>() / n.count as f32
+        } else {
+            0.0
+        }
+    })
+    .collect();
+
+    Ok(CortexSensitivity {
+        positions: vertex_positions(cortex),
+        indices: cortex.geometry.indices.clone(),
+        max: values.iter().copied().fold(0.0, f32::max),
+        values,
+    })
+}
@@ -1,8 +1,9 @@
+use crate::domain::annotation::Annotations;
 use crate::domain::channel::ChannelIndex;
 use crate::domain::error::NWError;
 use crate::domain::snirf::Snirf;
 use crate::domain::summary::SnirfSummary;
-use crate::io::snirf_parser;
+use crate::io::{bids_exporter, recording_importer, snirf_exporter};
 use log::info;
 
 pub struct LoadResult {
@@ -11,16 +12,26 @@ pub struct LoadResult {
     pub summary: SnirfSummary,
 }
 
-pub fn load_snirf(path: &str) -> Result<LoadResult, NWError> {
-    let snirf = snirf_parser::parse_snirf(path)?;
-
-    let channel_indices = snirf
-        .nirs_entries
-        .first()
-        .map(|e| e.data_blocks.iter().map(ChannelIndex::build).collect())
-        .unwrap_or_default();
+impl LoadResult {
+    /// Index the channels of every block of the first entry and summarise.
+    pub fn from_snirf(snirf: Snirf) -> Self {
+        let channel_indices = snirf
+            .nirs_entries
+            .first()
+            .map(|e| e.data_blocks.iter().map(ChannelIndex::build).collect())
+            .unwrap_or_default();
+        let summary = SnirfSummary::from_snirf(&snirf);
+        LoadResult {
+            snirf,
+            channel_indices,
+            summary,
+        }
+    }
+}
 
-    let summary = SnirfSummary::from_snirf(&snirf);
+pub fn load_snirf(path: &str) -> Result<LoadResult, NWError> {
+    let result = LoadResult::from_snirf(recording_importer::import_recording(path)?);
+    let summary = &result.summary;
 
     info!(
         "Loaded '{}': {} channels, {:.1}s, {} blocks",
@@ -34,9 +45,33 @@ pub fn load_snirf(path: &str) -> Result<LoadResult, NWError> {
         summary.data_blocks.len()
     );
 
-    Ok(LoadResult {
-        snirf,
-        channel_indices,
-        summary,
-    })
+    Ok(result)
+}
+
+/// Write the session's recording, derived blocks and their provenance
+/// included, to `path`.
+pub fn save_snirf(snirf: &Snirf, path: &str) -> Result<(), NWError> {
+    snirf_exporter::export_snirf(snirf, path)?;
+    info!("Exported '{}' to {path}", snirf.file_descriptor.filename);
+    Ok(())
+}
+
+/// Write the BIDS-NIRS sidecars of the recording to `dir`. Without a
+/// `prefix`, the file name is used with its `_nirs.snirf` ending removed.
+pub fn save_bids_sidecars(
+    snirf: &Snirf,
+    annotations: &Annotations,
+    dir: &str,
+    prefix: Option<&str>,
+) -> Result<Vec<String>, NWError> {
+    let filename = &snirf.file_descriptor.filename;
+    let stem = filename.split('.').next().unwrap_or(filename);
+    let prefix = prefix.unwrap_or_else(|| stem.strip_suffix("_nirs").unwrap_or(stem));
+    let entry = snirf.nirs_entries.first().ok_or(NWError::NoEntries)?;
+    let written = bids_exporter::export_bids_sidecars(entry, annotations, dir, prefix)?;
+    info!(
+        "Wrote {} BIDS sidecars for '{filename}' to {dir}",
+        written.len()
+    );
+    Ok(written)
 }
@@ -0,0 +1,291 @@
+use crate::domain::annotation::{good_sample_mask, TimeSegment};
+use crate::domain::error::NWError;
+use crate::domain::snirf::DataBlock;
+use crate::dsp::{fast_ica, Ica, Pca};
+use log::{info, warn};
+use nalgebra::DMatrix;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const ICA_MAX_ITERATIONS: usize = 500;
+const ICA_TOLERANCE: f64 = 1e-6;
+
+/// Measurements of one signal type (one wavelength, or one chromophore).
+/// Spatial filters are fitted per group: mixing HbO with HbR, or 760 nm with
+/// 850 nm, would let the scale difference between them dominate the components.
+#[derive(Serialize, Clone, Debug)]
+pub struct SignalGroup {
+    pub label: String,
+    /// Indices into `block.measurements`.
+    pub measurement_indices: Vec<usize>,
+    /// "S{src}-D{det}" for each measurement, same order.
+    pub channel_names: Vec<String>,
+}
+
+pub fn signal_groups(block: &DataBlock) -> Vec<SignalGroup> {
+    let mut groups: BTreeMap<(Option<usize>, String), Vec<usize>> = BTreeMap::new();
+    for (i, m) in block.measurements.iter().enumerate() {
+        groups
+            .entry((m.wavelength_index, m.data_type_label.trim().to_string()))
+            .or_default()
+            .push(i);
+    }
+    groups
+        .into_iter()
+        .map(|((wavelength, label), measurement_indices)| SignalGroup {
+            label: match (label.is_empty(), wavelength) {
+                (false, _) => label,
+                (true, Some(w)) => format!("wavelength {w}"),
+                (true, None) => "signal".to_string(),
+            },
+            channel_names: measurement_indices
+                .iter()
+                .map(|&i| {
+                    let m = &block.measurements[i];
+                    format!("S{}-D{}", m.source_index, m.detector_index)
+                })
+                .collect(),
+            measurement_indices,
+        })
+        .collect()
+}
+
+/// Samples used to fit the decomposition: inside `fit_segment` (whole block
+/// if `None`) and outside every bad segment.
+fn fit_mask(
+    time: &[f64],
+    fit_segment: Option<TimeSegment>,
+    bad_segments: &[TimeSegment],
+) -> Vec<bool> {
+    good_sample_mask(time, bad_segments)
+        .into_iter()
+        .zip(time)
+        .map(|(good, &t)| good && fit_segment.map_or(true, |s| s.contains(t)))
+        .collect()
+}
+
+fn group_matrix(block: &DataBlock, group: &SignalGroup) -> DMatrix<f64> {
+    let n = block.time.len();
+    DMatrix::from_fn(group.measurement_indices.len(), n, |r, c| {
+        block.measurements[group.measurement_indices[r]]
+            .data
+            .get(c)
+            .copied()
+            .unwrap_or(f64::NAN)
+    })
+}
+
+/// Copy of `block` with the rows of `filtered` written back into the
+/// measurements of each group.
+fn rebuild_block(block: &DataBlock, filtered: Vec<(&SignalGroup, DMatrix<f64>)>) -> DataBlock {
+    let mut data: Vec<Option<Vec<f64>>> = vec![None; block.measurements.len()];
+    for (group, matrix) in filtered {
+        for (r, &mi) in group.measurement_indices.iter().enumerate() {
+            data[mi] = Some(matrix.row(r).iter().copied().collect());
+        }
+    }
+    DataBlock {
+        time: block.time.clone(),
+        measurements: block
+            .measurements
+            .iter()
+            .zip(data)
+            .map(|(m, d)| m.with_data(d.unwrap_or_else(|| m.data.clone())))
+            .collect(),
+    }
+}
+
+fn check_fit_samples(mask: &[bool], channels: usize) -> Result<(), NWError> {
+    let count = mask.iter().filter(|&&k| k).count();
+    if count <= channels {
+        return Err(NWError::InvalidInput(format!(
+            "{count} usable samples in the fit interval; need more than the {channels} channels"
+        )));
+    }
+    Ok(())
+}
+
+// =============================================================================
+// PCA
+// =============================================================================
+
+/// Remove the `n_components` strongest principal components of each signal
+/// group — typically global, systemic physiology. The components are fitted on
+/// `fit_segment` (e.g. a resting period) or the whole block, skipping bad
+/// segments, and removed from the entire block.
+pub fn pca_filter_block(
+    block: &DataBlock,
+    n_components: usize,
+    fit_segment: Option<TimeSegment>,
+    bad_segments: &[TimeSegment],
+) -> Result<DataBlock, NWError> {
+    let mask = fit_mask(&block.time, fit_segment, bad_segments);
+    let groups = signal_groups(block);
+    let mut filtered = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let data = group_matrix(block, group);
+        check_fit_samples(&mask, data.nrows())?;
+        let pca = Pca::fit(&data, &mask)
+            .ok_or_else(|| NWError::InvalidInput(format!("PCA fit failed for {}", group.label)))?;
+        let k = n_components.min(data.nrows());
+        let removed: f64 = pca.explained_variance_ratio().iter().take(k).sum();
+        info!(
+            "PCA filter {}: removed {k} of {} components ({:.1}% of variance)",
+            group.label,
+            data.nrows(),
+            removed * 100.0
+        );
+        filtered.push((group, pca.remove_components(&data, k)));
+    }
+    Ok(rebuild_block(block, filtered))
+}
+
+// =============================================================================
+// ICA
+// =============================================================================
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IcaComponent {
+    /// Unique across all groups; pass back to [`ica_reject_block`].
+    pub id: usize,
+    /// Index into [`IcaDecomposition::groups`].
+    pub group: usize,
+    pub time_course: Vec<f64>,
+    /// Weight of the component in each measurement of its group
+    /// (column of the mixing matrix), same order as `channel_names`.
+    pub spatial_map: Vec<f64>,
+    /// Share of the variance captured by its group's components.
+    pub variance_fraction: f64,
+    /// Excess kurtosis of the time course; spiky artefacts score high.
+    pub kurtosis: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IcaDecomposition {
+    pub time: Vec<f64>,
+    pub groups: Vec<SignalGroup>,
+    pub components: Vec<IcaComponent>,
+}
+
+/// FastICA of each signal group with up to `n_components` components
+/// (default: one per measurement). Deterministic, so calling
+/// [`ica_reject_block`] with the same arguments reproduces the same component ids.
+pub fn ica_decompose(
+    block: &DataBlock,
+    n_components: Option<usize>,
+    fit_segment: Option<TimeSegment>,
+    bad_segments: &[TimeSegment],
+) -> Result<IcaDecomposition, NWError> {
+    let mask = fit_mask(&block.time, fit_segment, bad_segments);
+    let groups = signal_groups(block);
+    let mut components = Vec::new();
+
+    for (g, group) in groups.iter().enumerate() {
+        let data = group_matrix(block, group);
+        check_fit_samples(&mask, data.nrows())?;
+        let ica = run_ica(&data, &mask, n_components, &group.label)?;
+        let sources = ica.sources(&data);
+        let total: f64 = (0..ica.n_components())
+            .map(|i| ica.mixing.column(i).norm_squared())
+            .sum();
+
+        for i in 0..ica.n_components() {
+            let time_course: Vec<f64> = sources.row(i).iter().copied().collect();
+            let power = ica.mixing.column(i).norm_squared();
+            components.push(IcaComponent {
+                id: components.len(),
+                group: g,
+                kurtosis: excess_kurtosis(&time_course, &mask),
+                time_course,
+                spatial_map: ica.mixing.column(i).iter().copied().collect(),
+                variance_fraction: if total > 0.0 { power / total } else { 0.0 },
+            });
+        }
+    }
+
+    Ok(IcaDecomposition {
+        time: block.time.clone(),
+        groups,
+        components,
+    })
+}
+
+/// Recompute the decomposition of [`ica_decompose`] and remove the components
+/// whose ids are in `reject`, returning a new block.
+pub fn ica_reject_block(
+    block: &DataBlock,
+    n_components: Option<usize>,
+    fit_segment: Option<TimeSegment>,
+    bad_segments: &[TimeSegment],
+    reject: &[usize],
+) -> Result<DataBlock, NWError> {
+    let mask = fit_mask(&block.time, fit_segment, bad_segments);
+    let groups = signal_groups(block);
+    let mut filtered = Vec::with_capacity(groups.len());
+    let mut first_id = 0;
+
+    for group in &groups {
+        let data = group_matrix(block, group);
+        check_fit_samples(&mask, data.nrows())?;
+        let ica = run_ica(&data, &mask, n_components, &group.label)?;
+        let local: Vec<usize> = reject
+            .iter()
+            .filter_map(|&id| id.checked_sub(first_id))
+            .filter(|&i| i < ica.n_components())
+            .collect();
+        first_id += ica.n_components();
+        if local.is_empty() {
+            filtered.push((group, data));
+            continue;
+        }
+        info!(
+            "ICA reject {}: removing components {:?} of {}",
+            group.label,
+            local,
+            ica.n_components()
+        );
+        filtered.push((group, ica.remove_components(&data, &local)));
+    }
+    if let Some(&bad) = reject.iter().find(|&&id| id >= first_id) {
+        return Err(NWError::InvalidInput(format!(
+            "no ICA component with id {bad} ({first_id} components)"
+        )));
+    }
+    Ok(rebuild_block(block, filtered))
+}
+
+fn run_ica(
+    data: &DMatrix<f64>,
+    mask: &[bool],
+    n_components: Option<usize>,
+    label: &str,
+) -> Result<Ica, NWError> {
+    let n = n_components.unwrap_or(data.nrows()).clamp(1, data.nrows());
+    let ica = fast_ica(data, mask, n, ICA_MAX_ITERATIONS, ICA_TOLERANCE)
+        .ok_or_else(|| NWError::InvalidInput(format!("ICA fit failed for {label}")))?;
+    if !ica.converged {
+        warn!("ICA for {label} did not converge in {ICA_MAX_ITERATIONS} iterations");
+    }
+    Ok(ica)
+}
+
+fn excess_kurtosis(x: &[f64], mask: &[bool]) -> f64 {
+    let values: Vec<f64> = x
+        .iter()
+        .zip(mask)
+        .filter(|(v, &keep)| keep && v.is_finite())
+        .map(|(v, _)| *v)
+        .collect();
+    let n = values.len() as f64;
+    if n < 4.0 {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f64>() / n;
+    let m2 = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    let m4 = values.iter().map(|v| (v - mean).powi(4)).sum::<f64>() / n;
+    if m2 <= 0.0 {
+        0.0
+    } else {
+        m4 / (m2 * m2) - 3.0
+    }
+}
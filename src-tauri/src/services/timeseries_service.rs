@@ -1 +1,277 @@
-// assemble channel payloads
+use crate::domain::atlas::ChannelRegions;
+use crate::domain::error::NWError;
+use crate::domain::nirs_view::{DataKind, NirsView};
+use crate::domain::snirf::NirsEntry;
+use crate::io::timeseries_exporter::{
+    write_delimited, write_events_delimited, write_events_parquet, write_homer_nirs,
+    write_regions_delimited, write_regions_parquet, write_timeseries_parquet, RegionRow,
+    TimeseriesTable,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Serialize, Debug)]
+pub struct ChannelPayload {
+    pub id: usize,
+    pub name: String,
+    /// For processed data: HbO timeseries. For raw: wavelength 1 data.
+    pub series_a: Vec<f64>,
+    /// For processed data: HbR timeseries. For raw: wavelength 2 data.
+    pub series_b: Vec<f64>,
+    /// Labels for the two series, so the frontend knows what to display.
+    pub series_a_label: String,
+    pub series_b_label: String,
+}
+
+/// Name of a block's data kind as the frontend knows it.
+pub fn data_kind_name(data_kind: DataKind) -> &'static str {
+    match data_kind {
+        DataKind::RawCW => "raw_cw",
+        DataKind::OpticalDensity => "optical_density",
+        DataKind::ProcessedHemoglobin => "processed_hemoglobin",
+        DataKind::Empty => "empty",
+    }
+}
+
+/// The two displayed series of every channel of block `block_idx`:
+/// HbO/HbR for haemoglobin blocks, longer/shorter wavelength otherwise.
+pub fn channel_payloads(view: &NirsView, block_idx: usize) -> Vec<ChannelPayload> {
+    let data_kind = view.data_kind_at(block_idx);
+    view.channels_at(block_idx)
+        .iter()
+        .map(|ch| match data_kind {
+            DataKind::ProcessedHemoglobin => {
+                // Try label-based HbO/HbR lookup first (labelled files).
+                // Fall back to positional for files that store processed data with
+                // empty labels: derive HbO position from the wavelength ordering in
+                // the reference (OD/raw) block so we assign the correct series.
+                let hbo_pos = view.hbo_position_from_reference().unwrap_or(0);
+                let hbr_pos = 1 - hbo_pos;
+                let hbo = view
+                    .hbo_data_at(block_idx, ch)
+                    .or_else(|| view.channel_data_at(block_idx, ch, hbo_pos))
+                    .unwrap_or(&[])
+                    .to_vec();
+                let hbr = view
+                    .hbr_data_at(block_idx, ch)
+                    .or_else(|| view.channel_data_at(block_idx, ch, hbr_pos))
+                    .unwrap_or(&[])
+                    .to_vec();
+                ChannelPayload {
+                    id: ch.id,
+                    name: ch.name.clone(),
+                    series_a: hbo,
+                    series_b: hbr,
+                    series_a_label: "HbO".into(),
+                    series_b_label: "HbR".into(),
+                }
+            }
+            // RawCW and OpticalDensity both carry two series per wavelength.
+            // HbO always has the longer wavelength → series_a (red).
+            // HbR always has the shorter wavelength → series_b (blue).
+            DataKind::RawCW | DataKind::OpticalDensity => {
+                let m0 = view.channel_measurement_at(block_idx, ch, 0);
+                let m1 = view.channel_measurement_at(block_idx, ch, 1);
+                let wl0 = m0
+                    .and_then(|m| m.wavelength_index)
+                    .and_then(|i| view.wavelength_nm(i))
+                    .unwrap_or(0.0);
+                let wl1 = m1
+                    .and_then(|m| m.wavelength_index)
+                    .and_then(|i| view.wavelength_nm(i))
+                    .unwrap_or(0.0);
+                let d0 = view
+                    .channel_data_at(block_idx, ch, 0)
+                    .unwrap_or(&[])
+                    .to_vec();
+                let d1 = view
+                    .channel_data_at(block_idx, ch, 1)
+                    .unwrap_or(&[])
+                    .to_vec();
+
+                // Longer wavelength = HbO-sensitive (series_a / red)
+                let (a_data, a_wl, b_data, b_wl) = if wl0 >= wl1 {
+                    (d0, wl0, d1, wl1)
+                } else {
+                    (d1, wl1, d0, wl0)
+                };
+
+                let prefix = if data_kind == DataKind::OpticalDensity {
+                    "dOD "
+                } else {
+                    ""
+                };
+                ChannelPayload {
+                    id: ch.id,
+                    name: ch.name.clone(),
+                    series_a: a_data,
+                    series_b: b_data,
+                    series_a_label: format!("{}{:.0} nm", prefix, a_wl),
+                    series_b_label: format!("{}{:.0} nm", prefix, b_wl),
+                }
+            }
+            DataKind::Empty => ChannelPayload {
+                id: ch.id,
+                name: ch.name.clone(),
+                series_a: vec![],
+                series_b: vec![],
+                series_a_label: String::new(),
+                series_b_label: String::new(),
+            },
+        })
+        .collect()
+}
+
+/// File formats for [`export_timeseries`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeseriesFormat {
+    Csv,
+    Tsv,
+    Parquet,
+    /// Homer2/3 `.nirs` (MATLAB v5 MAT-file).
+    Nirs,
+}
+
+/// `<stem>_<suffix>.<ext>` beside `path`; `ext` defaults to the path's.
+fn sidecar_path(path: &str, suffix: &str, ext: Option<&str>) -> String {
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("export");
+    let ext = ext
+        .or_else(|| p.extension().and_then(|s| s.to_str()))
+        .unwrap_or("csv");
+    p.with_file_name(format!("{stem}_{suffix}.{ext}"))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// One row per channel and atlas region, for the wanted channels of block
+/// `block_idx`.
+pub fn region_rows(
+    view: &NirsView,
+    block_idx: usize,
+    regions: &[ChannelRegions],
+    wanted: impl Fn(usize) -> bool,
+) -> Vec<RegionRow> {
+    let channels = view.channels_at(block_idx);
+    regions
+        .iter()
+        .filter(|row| wanted(row.channel))
+        .flat_map(|row| {
+            let channel = channels
+                .iter()
+                .find(|ch| ch.id == row.channel)
+                .map_or_else(|| row.channel.to_string(), |ch| ch.name.clone());
+            row.regions.iter().map(move |r| RegionRow {
+                channel: channel.clone(),
+                position: row.position,
+                label: r.label,
+                region: r.name.clone(),
+                percent: r.percent,
+            })
+        })
+        .collect()
+}
+
+/// The displayed series of the wanted channels, named `<channel> <label>`.
+fn series_table(
+    view: &NirsView,
+    block_idx: usize,
+    wanted: impl Fn(usize) -> bool,
+) -> TimeseriesTable {
+    let time = view.time_at(block_idx).to_vec();
+    let mut columns = Vec::new();
+    for ch in channel_payloads(view, block_idx)
+        .into_iter()
+        .filter(|ch| wanted(ch.id))
+    {
+        for (label, data) in [
+            (ch.series_a_label, ch.series_a),
+            (ch.series_b_label, ch.series_b),
+        ] {
+            if data.len() == time.len() {
+                columns.push((format!("{} {label}", ch.name), data));
+            }
+        }
+    }
+    TimeseriesTable { time, columns }
+}
+
+/// Export channels `channel_ids` of block `block_idx` (every channel when
+/// empty) to `path`. Delimited and Parquet exports hold the series shown in
+/// the timeseries view plus the events in a separate `<stem>_events` table;
+/// a `.nirs` file holds the channels' measurements, stims and aux. When
+/// channels have been labelled from an atlas, their regions are written to
+/// `<stem>_regions` (a `.tsv` beside a `.nirs`). Returns the files written.
+pub fn export_timeseries(
+    entry: &NirsEntry,
+    block_idx: usize,
+    channel_ids: &[usize],
+    regions: &[ChannelRegions],
+    format: TimeseriesFormat,
+    path: &str,
+) -> Result<Vec<String>, NWError> {
+    let view = NirsView::new(entry);
+    if block_idx >= view.block_count() {
+        return Err(NWError::BlockOutOfRange(block_idx));
+    }
+    let n_channels = view.channels_at(block_idx).len();
+    if let Some(&missing) = channel_ids.iter().find(|&&id| id >= n_channels) {
+        return Err(NWError::ChannelNotFound(missing));
+    }
+    let wanted = |id: usize| channel_ids.is_empty() || channel_ids.contains(&id);
+    let events = sidecar_path(path, "events", None);
+
+    let mut files = match format {
+        TimeseriesFormat::Csv | TimeseriesFormat::Tsv => {
+            let delim = if format == TimeseriesFormat::Csv {
+                ','
+            } else {
+                '\t'
+            };
+            write_delimited(path, &series_table(&view, block_idx, wanted), delim)?;
+            write_events_delimited(&events, &entry.events, delim)?;
+            vec![path.to_string(), events]
+        }
+        TimeseriesFormat::Parquet => {
+            write_timeseries_parquet(path, &series_table(&view, block_idx, wanted))?;
+            write_events_parquet(&events, &entry.events)?;
+            vec![path.to_string(), events]
+        }
+        TimeseriesFormat::Nirs => {
+            let channels: Vec<Vec<usize>> = view
+                .channels_at(block_idx)
+                .iter()
+                .filter(|ch| wanted(ch.id))
+                .map(|ch| ch.measurement_indices.clone())
+                .collect();
+            write_homer_nirs(path, entry, block_idx, &channels)?;
+            vec![path.to_string()]
+        }
+    };
+
+    let rows = region_rows(&view, block_idx, regions, wanted);
+    if !rows.is_empty() {
+        let regions_path = match format {
+            TimeseriesFormat::Csv => {
+                let p = sidecar_path(path, "regions", None);
+                write_regions_delimited(&p, &rows, ',')?;
+                p
+            }
+            TimeseriesFormat::Parquet => {
+                let p = sidecar_path(path, "regions", None);
+                write_regions_parquet(&p, &rows)?;
+                p
+            }
+            TimeseriesFormat::Tsv | TimeseriesFormat::Nirs => {
+                let p = sidecar_path(path, "regions", Some("tsv"));
+                write_regions_delimited(&p, &rows, '\t')?;
+                p
+            }
+        };
+        files.push(regions_path);
+    }
+    info!("Exported block {block_idx} to {path}");
+    Ok(files)
+}
@@ -0,0 +1,250 @@
+use crate::domain::error::NWError;
+use crate::domain::snirf::{AuxiliaryData, Event, EventMarker, NirsEntry};
+use crate::services::event_service::ImportMode;
+use serde::{Deserialize, Serialize};
+
+/// Which auxiliary signal(s) carry the triggers and how a sample becomes a
+/// condition code (0 = idle).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TriggerSource {
+    /// A plain pulse train: high above `threshold` (default: halfway between
+    /// the signal's extremes).
+    Pulse { aux: usize, threshold: Option<f64> },
+    /// One signal whose level encodes the code, e.g. a parallel port summed
+    /// into one analog input: code = round(level / step).
+    Level { aux: usize, step: f64 },
+    /// One signal per TTL line, least significant bit first, each
+    /// thresholded like [`TriggerSource::Pulse`].
+    Bits {
+        aux: Vec<usize>,
+        threshold: Option<f64>,
+    },
+}
+
+/// Which edge of a pulse marks the event.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TriggerOptions {
+    pub source: TriggerSource,
+    #[serde(default)]
+    pub edge: Edge,
+    /// Seconds; pulses starting sooner than this after the previous one are
+    /// treated as bounce and dropped.
+    #[serde(default)]
+    pub min_gap: f64,
+    /// Condition name, or prefix of `<name>_<code>` for coded triggers.
+    /// Defaults to the (first) auxiliary's name.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+fn aux_at(entry: &NirsEntry, index: usize) -> Result<&AuxiliaryData, NWError> {
+    entry
+        .auxiliaries
+        .get(index)
+        .ok_or_else(|| NWError::InvalidInput(format!("no auxiliary signal {index}")))
+}
+
+/// Halfway between the finite extremes of `data`.
+fn midpoint(data: &[f64]) -> f64 {
+    let (lo, hi) = data
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| {
+            (lo.min(x), hi.max(x))
+        });
+    if lo <= hi {
+        (lo + hi) / 2.0
+    } else {
+        0.0
+    }
+}
+
+fn high(data: &[f64], threshold: Option<f64>) -> Vec<bool> {
+    let threshold = threshold.unwrap_or_else(|| midpoint(data));
+    data.iter().map(|&x| x > threshold).collect()
+}
+
+/// Per-sample codes of `source`, and the auxiliary whose time base they use.
+fn code_series<'a>(
+    entry: &'a NirsEntry,
+    source: &TriggerSource,
+) -> Result<(Vec<u32>, &'a AuxiliaryData), NWError> {
+    match source {
+        TriggerSource::Pulse { aux, threshold } => {
+            let aux = aux_at(entry, *aux)?;
+            let codes = high(&aux.data, *threshold)
+                .into_iter()
+                .map(u32::from)
+                .collect();
+            Ok((codes, aux))
+        }
+        TriggerSource::Level { aux, step } => {
+            if step.is_nan() || *step <= 0.0 {
+                return Err(NWError::InvalidInput(
+                    "the level step must be positive".into(),
+                ));
+            }
+            let aux = aux_at(entry, *aux)?;
+            let codes = aux
+                .data
+                .iter()
+                .map(|&x| {
+                    let code = (x / step).round();
+                    if code.is_finite() && code > 0.0 {
+                        code as u32
+                    } else {
+                        0
+                    }
+                })
+                .collect();
+            Ok((codes, aux))
+        }
+        TriggerSource::Bits { aux, threshold } => {
+            if aux.is_empty() || aux.len() > 16 {
+                return Err(NWError::InvalidInput(
+                    "a TTL decoder needs between 1 and 16 lines".into(),
+                ));
+            }
+            let lines = aux
+                .iter()
+                .map(|&i| aux_at(entry, i))
+                .collect::<Result<Vec<_>, _>>()?;
+            let first = lines[0];
+            if lines.iter().any(|l| l.data.len() != first.data.len()) {
+                return Err(NWError::InvalidInput(
+                    "TTL lines must have the same number of samples".into(),
+                ));
+            }
+            let mut codes = vec![0u32; first.data.len()];
+            for (bit, line) in lines.iter().enumerate() {
+                for (code, is_high) in codes.iter_mut().zip(high(&line.data, *threshold)) {
+                    *code |= u32::from(is_high) << bit;
+                }
+            }
+            Ok((codes, first))
+        }
+    }
+}
+
+/// The code held longest in `run`, so lines that switch a sample apart do
+/// not produce spurious intermediate codes.
+fn dominant_code(run: &[u32]) -> u32 {
+    let mut counts: Vec<(u32, usize)> = Vec::new();
+    for &c in run {
+        match counts.iter_mut().find(|(code, _)| *code == c) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((c, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(_, n)| n)
+        .map_or(0, |(c, _)| c)
+}
+
+/// Derive events from trigger pulses in the entry's auxiliary signals.
+/// Onsets are in the data's time base: the auxiliary's `time_offset` is
+/// added. Plain pulses form one condition (value 1); coded triggers one
+/// condition per code (value = code), in order of first appearance.
+pub fn extract_trigger_events(
+    entry: &NirsEntry,
+    options: &TriggerOptions,
+) -> Result<Vec<Event>, NWError> {
+    let (codes, aux) = code_series(entry, &options.source)?;
+    if aux.time.len() != codes.len() {
+        return Err(NWError::InvalidInput(format!(
+            "auxiliary '{}' has {} samples but {} time points",
+            aux.name,
+            codes.len(),
+            aux.time.len()
+        )));
+    }
+    let offset = aux.time_offset.unwrap_or(0.0);
+    let name = options.name.clone().unwrap_or_else(|| aux.name.clone());
+    let coded = !matches!(options.source, TriggerSource::Pulse { .. });
+
+    let mut events: Vec<Event> = Vec::new();
+    let mut last_onset = f64::NEG_INFINITY;
+    let mut i = 0;
+    while i < codes.len() {
+        if codes[i] == 0 {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < codes.len() && codes[i] != 0 {
+            i += 1;
+        }
+        let (onset, duration) = match options.edge {
+            Edge::Rising => {
+                let end = aux.time[i.min(codes.len() - 1)];
+                (aux.time[start], end - aux.time[start])
+            }
+            // A pulse still high at the end of the recording has no falling edge.
+            Edge::Falling if i < codes.len() => (aux.time[i], 0.0),
+            Edge::Falling => continue,
+        };
+        if onset - last_onset < options.min_gap {
+            continue;
+        }
+        last_onset = onset;
+
+        let code = dominant_code(&codes[start..i]);
+        let (condition, value) = if coded {
+            (format!("{name}_{code}"), code as f64)
+        } else {
+            (name.clone(), 1.0)
+        };
+        let marker = EventMarker {
+            onset: onset + offset,
+            duration,
+            value,
+        };
+        match events.iter_mut().find(|e| e.name == condition) {
+            Some(event) => event.markers.push(marker),
+            None => events.push(Event {
+                name: condition,
+                markers: vec![marker],
+            }),
+        }
+    }
+    Ok(events)
+}
+
+/// An auxiliary signal as offered for trigger extraction.
+#[derive(Serialize, Clone, Debug)]
+pub struct AuxiliaryInfo {
+    pub index: usize,
+    pub name: String,
+    pub unit: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+pub fn auxiliary_info(entry: &NirsEntry) -> Vec<AuxiliaryInfo> {
+    entry
+        .auxiliaries
+        .iter()
+        .enumerate()
+        .map(|(index, aux)| {
+            let finite = aux.data.iter().copied().filter(|x| x.is_finite());
+            AuxiliaryInfo {
+                index,
+                name: aux.name.clone(),
+                unit: aux.unit.clone(),
+                min: finite.clone().fold(f64::INFINITY, f64::min),
+                max: finite.fold(f64::NEG_INFINITY, f64::max),
+            }
+        })
+        .collect()
+}
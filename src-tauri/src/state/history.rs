@@ -0,0 +1,411 @@
+use crate::domain::annotation::Annotations;
+use crate::domain::channel::ChannelIndex;
+use crate::domain::error::NWError;
+use crate::domain::provenance::ProvenanceRecord;
+use crate::domain::scene::Transform;
+use crate::domain::snirf::{AuxiliaryData, DataBlock, Event, MetadataTag, Snirf};
+use crate::state::session::SessionInner;
+use log::warn;
+use nalgebra::{Vector2, Vector3};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Upper bound on the data held by undo and redo entries together.
+const MAX_HISTORY_BYTES: usize = 512 * 1024 * 1024;
+/// Upper bound on the number of undoable operations.
+const MAX_HISTORY_DEPTH: usize = 100;
+
+/// Which part of the session an undo or redo touched, so the caller can
+/// notify the matching views.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeScope {
+    Recording,
+    Blocks,
+    Annotations,
+    Events,
+    Probe,
+}
+
+/// Derived data taken off the end of the first entry by an undo.
+pub struct EntryTail {
+    blocks: Vec<DataBlock>,
+    channel_indices: Vec<ChannelIndex>,
+    auxiliaries: Vec<AuxiliaryData>,
+    provenance: Vec<ProvenanceRecord>,
+}
+
+/// Everything replaced when a file is loaded.
+pub struct Recording {
+    snirf: Option<Snirf>,
+    channel_indices: Vec<ChannelIndex>,
+    annotations: Annotations,
+}
+
+/// An optode's 3D and 2D position.
+type OptodePosition = (Vector3<f64>, Vector2<f64>);
+/// A landmark's 3D and 2D position, where given.
+type LandmarkPosition = (Option<[f64; 3]>, Option<[f64; 2]>);
+
+/// Where the first entry's probe sits in space, and its 2D layout.
+pub struct ProbePlacement {
+    sources: Vec<OptodePosition>,
+    detectors: Vec<OptodePosition>,
+    landmarks: Vec<LandmarkPosition>,
+    coordinate_system: Option<String>,
+    coordinate_system_description: Option<String>,
+    length_unit: Option<String>,
+    /// The workspace probe transform, for changes that also reset it.
+    transform: Option<Transform>,
+}
+
+/// A reversible session change. Applying a change restores the state it
+/// describes and returns the change that reverses it, so the same value
+/// moves between the undo and redo stacks.
+pub enum Change {
+    /// Items were appended to the first entry; the list lengths before.
+    Appended {
+        blocks: usize,
+        auxiliaries: usize,
+        provenance: usize,
+    },
+    /// Items an undo removed from the first entry.
+    Removed(Box<EntryTail>),
+    /// The annotations before the change.
+    Annotations(Annotations),
+    /// The first entry's events before the change.
+    Events(Vec<Event>),
+    /// The session before a file was loaded.
+    Recording(Box<Recording>),
+    /// The probe's placement before it was moved, e.g. by registration.
+    Probe(Box<ProbePlacement>),
+}
+
+fn block_bytes(block: &DataBlock) -> usize {
+    let samples = block.time.len()
+        + block
+            .measurements
+            .iter()
+            .map(|m| m.data.len())
+            .sum::<usize>();
+    samples * std::mem::size_of::<f64>()
+}
+
+fn aux_bytes(aux: &AuxiliaryData) -> usize {
+    (aux.time.len() + aux.data.len()) * std::mem::size_of::<f64>()
+}
+
+impl Change {
+    /// Take the loaded recording out of the session, e.g. before a file
+    /// replaces it.
+    pub fn recording(inner: &mut SessionInner) -> Change {
+        Change::Recording(Box::new(Recording {
+            snirf: inner.snirf.take(),
+            channel_indices: std::mem::take(&mut inner.channel_indices),
+            annotations: std::mem::take(&mut inner.annotations),
+        }))
+    }
+
+    /// Lengths of the first entry's lists, to be recorded once processing
+    /// has appended to them.
+    pub fn appended(inner: &SessionInner) -> Result<Change, NWError> {
+        let entry = inner.entry()?;
+        Ok(Change::Appended {
+            blocks: entry.data_blocks.len(),
+            auxiliaries: entry.auxiliaries.len(),
+            provenance: entry.provenance.len(),
+        })
+    }
+
+    /// The first entry's probe placement, to be recorded before it moves.
+    pub fn probe(inner: &SessionInner) -> Result<Change, NWError> {
+        Change::placement(inner, None)
+    }
+
+    /// As [`Change::probe`], for a change that also replaces the workspace
+    /// probe `transform`.
+    pub fn probe_and_transform(
+        inner: &SessionInner,
+        transform: &Transform,
+    ) -> Result<Change, NWError> {
+        Change::placement(inner, Some(transform.clone()))
+    }
+
+    fn placement(inner: &SessionInner, transform: Option<Transform>) -> Result<Change, NWError> {
+        let entry = inner.entry()?;
+        let probe = &entry.probe;
+        Ok(Change::Probe(Box::new(ProbePlacement {
+            sources: probe.sources.iter().map(|o| (o.pos_3d, o.pos_2d)).collect(),
+            detectors: probe
+                .detectors
+                .iter()
+                .map(|o| (o.pos_3d, o.pos_2d))
+                .collect(),
+            landmarks: probe
+                .landmarks
+                .iter()
+                .flatten()
+                .map(|l| (l.pos_3d, l.pos_2d))
+                .collect(),
+            coordinate_system: probe.coordinate_system.clone(),
+            coordinate_system_description: probe.coordinate_system_description.clone(),
+            length_unit: entry
+                .metadata
+                .iter()
+                .find(|t| t.name == "LengthUnit")
+                .map(|t| t.value.clone()),
+            transform,
+        })))
+    }
+
+    pub fn scope(&self) -> ChangeScope {
+        match self {
+            Change::Appended { .. } | Change::Removed(_) => ChangeScope::Blocks,
+            Change::Annotations(_) => ChangeScope::Annotations,
+            Change::Events(_) => ChangeScope::Events,
+            Change::Recording(_) => ChangeScope::Recording,
+            Change::Probe(_) => ChangeScope::Probe,
+        }
+    }
+
+    /// Rough memory held by this change.
+    fn size_bytes(&self) -> usize {
+        match self {
+            Change::Appended { .. }
+            | Change::Annotations(_)
+            | Change::Events(_)
+            | Change::Probe(_) => 0,
+            Change::Removed(tail) => {
+                tail.blocks.iter().map(block_bytes).sum::<usize>()
+                    + tail.auxiliaries.iter().map(aux_bytes).sum::<usize>()
+            }
+            Change::Recording(rec) => rec.snirf.as_ref().map_or(0, |s| {
+                s.nirs_entries
+                    .iter()
+                    .map(|e| {
+                        e.data_blocks.iter().map(block_bytes).sum::<usize>()
+                            + e.auxiliaries.iter().map(aux_bytes).sum::<usize>()
+                    })
+                    .sum()
+            }),
+        }
+    }
+
+    fn apply(
+        self,
+        inner: &mut SessionInner,
+        probe_transform: &mut Transform,
+    ) -> Result<Change, NWError> {
+        Ok(match self {
+            Change::Appended {
+                blocks,
+                auxiliaries,
+                provenance,
+            } => {
+                let at = blocks.min(inner.channel_indices.len());
+                let channel_indices = inner.channel_indices.split_off(at);
+                let entry = inner.entry_mut()?;
+                Change::Removed(Box::new(EntryTail {
+                    blocks: entry
+                        .data_blocks
+                        .split_off(blocks.min(entry.data_blocks.len())),
+                    channel_indices,
+                    auxiliaries: entry
+                        .auxiliaries
+                        .split_off(auxiliaries.min(entry.auxiliaries.len())),
+                    provenance: entry
+                        .provenance
+                        .split_off(provenance.min(entry.provenance.len())),
+                }))
+            }
+            Change::Removed(tail) => {
+                let EntryTail {
+                    blocks,
+                    channel_indices,
+                    auxiliaries,
+                    provenance,
+                } = *tail;
+                let undo = Change::appended(inner)?;
+                let entry = inner.entry_mut()?;
+                entry.data_blocks.extend(blocks);
+                entry.auxiliaries.extend(auxiliaries);
+                entry.provenance.extend(provenance);
+                inner.channel_indices.extend(channel_indices);
+                undo
+            }
+            Change::Annotations(annotations) => {
+                Change::Annotations(std::mem::replace(&mut inner.annotations, annotations))
+            }
+            Change::Events(events) => {
+                Change::Events(std::mem::replace(&mut inner.entry_mut()?.events, events))
+            }
+            Change::Recording(rec) => {
+                let undo = Change::recording(inner);
+                let Recording {
+                    snirf,
+                    channel_indices,
+                    annotations,
+                } = *rec;
+                inner.snirf = snirf;
+                inner.channel_indices = channel_indices;
+                inner.annotations = annotations;
+                undo
+            }
+            Change::Probe(placement) => {
+                let ProbePlacement {
+                    sources,
+                    detectors,
+                    landmarks,
+                    coordinate_system,
+                    coordinate_system_description,
+                    length_unit,
+                    transform,
+                } = *placement;
+                let current = transform.as_ref().map(|_| probe_transform.clone());
+                let undo = Change::placement(inner, current)?;
+                if let Some(transform) = transform {
+                    *probe_transform = transform;
+                }
+                let entry = inner.entry_mut()?;
+                let probe = &mut entry.probe;
+                for (optode, (pos_3d, pos_2d)) in probe.sources.iter_mut().zip(sources) {
+                    optode.pos_3d = pos_3d;
+                    optode.pos_2d = pos_2d;
+                }
+                for (optode, (pos_3d, pos_2d)) in probe.detectors.iter_mut().zip(detectors) {
+                    optode.pos_3d = pos_3d;
+                    optode.pos_2d = pos_2d;
+                }
+                for (landmark, (pos_3d, pos_2d)) in
+                    probe.landmarks.iter_mut().flatten().zip(landmarks)
+                {
+                    landmark.pos_3d = pos_3d;
+                    landmark.pos_2d = pos_2d;
+                }
+                probe.coordinate_system = coordinate_system;
+                probe.coordinate_system_description = coordinate_system_description;
+                entry.metadata.retain(|t| t.name != "LengthUnit");
+                if let Some(value) = length_unit {
+                    entry.metadata.push(MetadataTag {
+                        name: "LengthUnit".into(),
+                        value,
+                    });
+                }
+                undo
+            }
+        })
+    }
+}
+
+struct HistoryEntry {
+    label: String,
+    change: Change,
+    bytes: usize,
+}
+
+/// State of the history for the frontend's Undo/Redo controls.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct HistoryStatus {
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+}
+
+/// Undo and redo stacks of session changes, bounded in depth and memory.
+/// The oldest undo entries are dropped first.
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    bytes: usize,
+}
+
+impl History {
+    /// Record a change that was just made; clears the redo stack.
+    pub fn record(&mut self, label: impl Into<String>, change: Change) {
+        self.bytes -= self.redo.drain(..).map(|e| e.bytes).sum::<usize>();
+        self.push_undo(HistoryEntry {
+            label: label.into(),
+            bytes: change.size_bytes(),
+            change,
+        });
+    }
+
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        if entry.bytes > MAX_HISTORY_BYTES {
+            warn!(
+                "'{}' holds {} MB, more than the history limit; it cannot be undone",
+                entry.label,
+                entry.bytes / (1024 * 1024)
+            );
+            return;
+        }
+        self.bytes += entry.bytes;
+        self.undo.push_back(entry);
+        while self.undo.len() > MAX_HISTORY_DEPTH || self.bytes > MAX_HISTORY_BYTES {
+            match self.undo.pop_front() {
+                Some(oldest) => self.bytes -= oldest.bytes,
+                None => break,
+            }
+        }
+    }
+
+    pub fn status(&self) -> HistoryStatus {
+        HistoryStatus {
+            undo_label: self.undo.back().map(|e| e.label.clone()),
+            redo_label: self.redo.last().map(|e| e.label.clone()),
+        }
+    }
+}
+
+impl SessionInner {
+    /// Record `change` (from [`Change::appended`]) unless nothing was appended.
+    pub fn record_appended(&mut self, label: impl Into<String>, change: Change) {
+        if let (Change::Appended { blocks, .. }, Ok(entry)) = (&change, self.entry()) {
+            if entry.data_blocks.len() == *blocks {
+                return;
+            }
+        }
+        self.history.record(label, change);
+    }
+
+    /// Revert the most recent change. `None` when there is nothing to undo.
+    /// `probe_transform` is the workspace's, which probe changes may have
+    /// replaced.
+    pub fn undo(
+        &mut self,
+        probe_transform: &mut Transform,
+    ) -> Result<Option<ChangeScope>, NWError> {
+        let Some(entry) = self.history.undo.pop_back() else {
+            return Ok(None);
+        };
+        self.history.bytes -= entry.bytes;
+        let scope = entry.change.scope();
+        let redo = entry.change.apply(self, probe_transform)?;
+        let bytes = redo.size_bytes();
+        self.history.bytes += bytes;
+        self.history.redo.push(HistoryEntry {
+            label: entry.label,
+            change: redo,
+            bytes,
+        });
+        Ok(Some(scope))
+    }
+
+    /// Re-apply the most recently undone change.
+    pub fn redo(
+        &mut self,
+        probe_transform: &mut Transform,
+    ) -> Result<Option<ChangeScope>, NWError> {
+        let Some(entry) = self.history.redo.pop() else {
+            return Ok(None);
+        };
+        self.history.bytes -= entry.bytes;
+        let scope = entry.change.scope();
+        let undo = entry.change.apply(self, probe_transform)?;
+        self.history.push_undo(HistoryEntry {
+            label: entry.label,
+            bytes: undo.size_bytes(),
+            change: undo,
+        });
+        Ok(Some(scope))
+    }
+}
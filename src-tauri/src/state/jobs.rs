@@ -0,0 +1,90 @@
+use crate::domain::anatomy::{AnatomyJobStatus, StepLog, StepProgress};
+use crate::domain::error::NWError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Lines of log an [`AnatomyJob`] keeps for views opened late.
+const LOG_TAIL: usize = 500;
+
+/// The anatomy job last started, running or finished.
+#[derive(Default)]
+pub struct JobState {
+    anatomy: Mutex<Option<Arc<AnatomyJob>>>,
+}
+
+/// A background anatomy pipeline run, shared between the thread running
+/// it and the commands that watch or cancel it.
+#[derive(Default)]
+pub struct AnatomyJob {
+    cancel: AtomicBool,
+    status: Mutex<AnatomyJobStatus>,
+}
+
+impl JobState {
+    /// Register a new anatomy job, unless one is still running.
+    pub fn start_anatomy(&self) -> Result<Arc<AnatomyJob>, NWError> {
+        let mut current = self.anatomy.lock().map_err(|_| NWError::LockPoisoned)?;
+        if current.as_ref().is_some_and(|job| job.status().running) {
+            return Err(NWError::InvalidInput(
+                "an anatomy job is already running".into(),
+            ));
+        }
+        let job = Arc::new(AnatomyJob::default());
+        job.update(|s| s.running = true);
+        *current = Some(job.clone());
+        Ok(job)
+    }
+
+    pub fn anatomy(&self) -> Result<Option<Arc<AnatomyJob>>, NWError> {
+        Ok(self
+            .anatomy
+            .lock()
+            .map_err(|_| NWError::LockPoisoned)?
+            .clone())
+    }
+}
+
+impl AnatomyJob {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> AnatomyJobStatus {
+        self.status.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut AnatomyJobStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+
+    pub fn record_progress(&self, progress: StepProgress) {
+        self.update(
+            |s| match s.steps.iter_mut().find(|p| p.step == progress.step) {
+                Some(p) => *p = progress,
+                None => s.steps.push(progress),
+            },
+        );
+    }
+
+    pub fn record_log(&self, log: StepLog) {
+        self.update(|s| {
+            if s.log.len() == LOG_TAIL {
+                s.log.remove(0);
+            }
+            s.log.push(log);
+        });
+    }
+
+    pub fn finish(&self, error: Option<String>) {
+        self.update(|s| {
+            s.running = false;
+            s.error = error;
+        });
+    }
+}
@@ -1,2 +1,5 @@
+pub mod history;
+pub mod jobs;
 pub mod selection;
 pub mod session;
+pub mod workspace;
@@ -1,14 +1,19 @@
+use crate::domain::annotation::Annotations;
 use crate::domain::channel::ChannelIndex;
-use crate::domain::snirf::Snirf;
+use crate::domain::error::NWError;
+use crate::domain::snirf::{DataBlock, NirsEntry, Snirf};
+use crate::state::history::{Change, History};
 use std::sync::RwLock;
 
 pub struct SessionState {
     inner: RwLock<SessionInner>,
 }
 
-struct SessionInner {
-    snirf: Option<Snirf>,
-    channel_indices: Vec<ChannelIndex>,
+pub struct SessionInner {
+    pub snirf: Option<Snirf>,
+    pub channel_indices: Vec<ChannelIndex>,
+    pub annotations: Annotations,
+    pub history: History,
 }
 
 impl Default for SessionState {
@@ -17,15 +22,72 @@ impl Default for SessionState {
             inner: RwLock::new(SessionInner {
                 snirf: None,
                 channel_indices: Vec::new(),
+                annotations: Annotations::default(),
+                history: History::default(),
             }),
         }
     }
 }
 
 impl SessionState {
+    /// Replace the loaded recording. Loading over an open file can be undone.
     pub fn load(&self, snirf: Snirf, indices: Vec<ChannelIndex>) {
         let mut inner = self.inner.write().unwrap();
+        let label = format!("Open {}", snirf.file_descriptor.filename);
+        let had_data = inner.snirf.is_some();
+        let previous = Change::recording(&mut inner);
         inner.snirf = Some(snirf);
         inner.channel_indices = indices;
+        if had_data {
+            inner.history.record(label, previous);
+        }
+    }
+
+    pub fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, SessionInner>, NWError> {
+        self.inner.read().map_err(|_| NWError::LockPoisoned)
+    }
+
+    pub fn write(&self) -> Result<std::sync::RwLockWriteGuard<'_, SessionInner>, NWError> {
+        self.inner.write().map_err(|_| NWError::LockPoisoned)
+    }
+}
+
+impl SessionInner {
+    pub fn snirf(&self) -> Result<&Snirf, NWError> {
+        self.snirf.as_ref().ok_or(NWError::NoData)
+    }
+
+    /// The first NIRS entry — the one every view operates on.
+    pub fn entry(&self) -> Result<&NirsEntry, NWError> {
+        self.snirf()?.nirs_entries.first().ok_or(NWError::NoEntries)
+    }
+
+    pub fn entry_mut(&mut self) -> Result<&mut NirsEntry, NWError> {
+        self.snirf
+            .as_mut()
+            .ok_or(NWError::NoData)?
+            .nirs_entries
+            .first_mut()
+            .ok_or(NWError::NoEntries)
+    }
+
+    pub fn block(&self, index: usize) -> Result<&DataBlock, NWError> {
+        self.entry()?
+            .data_blocks
+            .get(index)
+            .ok_or(NWError::BlockOutOfRange(index))
+    }
+
+    /// Index the channels of blocks appended to the first entry since the
+    /// last call (e.g. by a processing step).
+    pub fn sync_channel_indices(&mut self) {
+        let Some(entry) = self.snirf.as_ref().and_then(|s| s.nirs_entries.first()) else {
+            return;
+        };
+        let start = self.channel_indices.len();
+        if let Some(new_blocks) = entry.data_blocks.get(start..) {
+            self.channel_indices
+                .extend(new_blocks.iter().map(ChannelIndex::build));
+        }
     }
 }
@@ -0,0 +1,56 @@
+use crate::domain::anatomy::{AnatomyPaths, NamedPoint, SegmentationBackend};
+use crate::domain::atlas::ChannelRegions;
+use crate::domain::dataset::Dataset;
+use crate::domain::error::NWError;
+use crate::domain::optics::{Sensitivity, Simulation};
+use crate::domain::probe::ProbeDisplaySettings;
+use crate::domain::scene::Transform;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Session-wide settings that are not part of the recording but are saved
+/// with a project.
+pub struct WorkspaceState {
+    inner: RwLock<WorkspaceInner>,
+}
+
+#[derive(Default)]
+pub struct WorkspaceInner {
+    /// The project file last opened or saved.
+    pub project_path: Option<PathBuf>,
+    pub anatomy: AnatomyPaths,
+    /// How the anatomy pipeline last segmented the subject, to run it the
+    /// same way again.
+    pub anatomy_backend: Option<SegmentationBackend>,
+    /// Placement of the probe on the anatomy (`OptodeLayout::transform`).
+    pub probe_transform: Transform,
+    pub probe_settings: ProbeDisplaySettings,
+    /// 10-20/10-10/10-5 positions last computed on the scalp, mm.
+    pub head_positions: Vec<NamedPoint>,
+    /// Atlas regions under each channel, from the last atlas labelling.
+    pub channel_regions: Vec<ChannelRegions>,
+    /// Fluence from the last photon simulation.
+    pub simulation: Option<Simulation>,
+    /// Channel sensitivities from that fluence, or imported.
+    pub sensitivity: Option<Sensitivity>,
+    /// The dataset folder last indexed.
+    pub dataset: Option<Dataset>,
+}
+
+impl Default for WorkspaceState {
+    fn default() -> Self {
+        WorkspaceState {
+            inner: RwLock::new(WorkspaceInner::default()),
+        }
+    }
+}
+
+impl WorkspaceState {
+    pub fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, WorkspaceInner>, NWError> {
+        self.inner.read().map_err(|_| NWError::LockPoisoned)
+    }
+
+    pub fn write(&self) -> Result<std::sync::RwLockWriteGuard<'_, WorkspaceInner>, NWError> {
+        self.inner.write().map_err(|_| NWError::LockPoisoned)
+    }
+}
@@ -47,3 +47,14 @@ export const defaultOptodeState = () => ({
   },
   visible: true,
 });
+
+// landmarkPickLabel: the landmark whose head position is being picked in the
+// viewport, { owner, label } (owner: the panel asking), null when not picking.
+export const landmarkPickLabel = writable(null);
+
+// pickedLandmark: the last point picked on the anatomy, { owner, label, position:[x,y,z] }
+export const pickedLandmark = writable(null);
+
+// reconstructionChromophore: which image of the last DOT reconstruction the
+// viewport shows, 'hbo' or 'hbr'.
+export const reconstructionChromophore = writable('hbo');
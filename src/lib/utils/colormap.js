@@ -47,3 +47,13 @@ export function labelToCss(label, maxLabel) {
   const [r, g, b] = VIRIDIS[Math.min(idx, 255)];
   return `rgb(${r},${g},${b})`;
 }
+
+/**
+ * Diverging blue–white–red for signed values: t in [-1, 1] to
+ * [r, g, b] in 0–1 (0 is white).
+ */
+export function divergingColor(t) {
+  const u = Math.max(-1, Math.min(1, t));
+  const fade = 1 - Math.abs(u);
+  return u >= 0 ? [1, fade, fade] : [fade, fade, 1];
+}